@@ -0,0 +1,277 @@
+//! Quick terminal analysis of an MT4/MT5 report: performance metrics, the
+//! Kelly suggestion and the challenge pass rate at a given risk.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::{NaiveDate, TimeDelta};
+use clap::{Parser, ValueEnum};
+use risk_optima_core::{
+    calculate_kelly_from_trades, compute_performance_metrics, monte_carlo_detailed, parse_report_file, BetaPrior,
+    ChallengeParams, DailyLossBasis, DrawdownMode, KellyResult, PerformanceMetrics, ProfitNormalization,
+    SimulationOptions, TradeFilter, TradeType,
+};
+use serde::Serialize;
+
+#[derive(Parser)]
+#[command(name = "risk-optima", version, about = "Analyze a trading report against a prop-firm challenge")]
+struct Args {
+    /// MT5/MT4 report: HTML, XML spreadsheet or CSV export
+    report: PathBuf,
+    /// Accept trades closed at their open price
+    #[arg(long)]
+    allow_break_even: bool,
+    /// First close date to analyze, as YYYY-MM-DD (UTC)
+    #[arg(long, value_parser = parse_date)]
+    from: Option<NaiveDate>,
+    /// Last close date to analyze, as YYYY-MM-DD (UTC)
+    #[arg(long, value_parser = parse_date)]
+    to: Option<NaiveDate>,
+    /// Only analyze this symbol; repeat for several
+    #[arg(long = "symbol")]
+    symbols: Vec<String>,
+    #[arg(long, value_enum)]
+    direction: Option<Direction>,
+    #[arg(long)]
+    min_volume: Option<f64>,
+    #[arg(long, default_value_t = 100_000.0)]
+    account_size: f64,
+    #[arg(long, default_value_t = 10.0)]
+    profit_target: f64,
+    #[arg(long, default_value_t = 5.0)]
+    max_daily_loss: f64,
+    #[arg(long, default_value_t = 10.0)]
+    max_overall_loss: f64,
+    #[arg(long, default_value_t = 4)]
+    min_trading_days: u32,
+    /// Measure the daily loss limit from each day's starting balance
+    #[arg(long)]
+    day_start_daily_loss: bool,
+    #[arg(long, value_enum, default_value_t = Drawdown::Static)]
+    drawdown: Drawdown,
+    /// Fraction of equity risked per trade, where an average losing trade loses exactly this fraction
+    #[arg(long, default_value_t = 0.01)]
+    risk_fraction: f64,
+    /// Multiplier on the Kelly suggestion, e.g. 0.5 for half Kelly
+    #[arg(long, default_value_t = 1.0)]
+    fractional_kelly: f64,
+    #[arg(long, default_value_t = 10_000)]
+    simulations: usize,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Drawdown {
+    Static,
+    TrailingBalance,
+    TrailingEquity,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Direction {
+    Buy,
+    Sell,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Report {
+    trades: usize,
+    metrics: PerformanceMetrics,
+    kelly: Option<KellyResult>, // None without both wins and losses
+    risk_fraction: f64,
+    pass_rate: f64,
+    daily_loss_failures: usize,
+    overall_loss_failures: usize,
+    timeouts: usize,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args, &mut std::io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("risk-optima: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args, out: &mut impl Write) -> anyhow::Result<()> {
+    let parsed = parse_report_file(args.report.clone(), args.allow_break_even)?;
+    for warning in &parsed.warnings {
+        eprintln!("warning: row {}: {}", warning.row, warning.reason);
+    }
+    let midnight = |date: NaiveDate| date.and_time(Default::default()).and_utc().timestamp_millis();
+    let filter = TradeFilter {
+        start: args.from.map(midnight),
+        end: args.to.map(|date| midnight(date + TimeDelta::days(1))),
+        symbols: (!args.symbols.is_empty()).then(|| args.symbols.clone()),
+        direction: args.direction.map(|direction| match direction {
+            Direction::Buy => TradeType::Buy,
+            Direction::Sell => TradeType::Sell,
+        }),
+        min_volume: args.min_volume,
+    };
+    let trades = filter.apply(parsed.trades)?;
+    let metrics =
+        compute_performance_metrics(trades.clone(), 0.0, None, ProfitNormalization::Raw, BetaPrior::default())?;
+    if !metrics.has_sufficient_sample() {
+        eprintln!(
+            "warning: {} more trades needed before the win rate supports a Kelly fraction",
+            metrics.additional_trades_needed
+        );
+    }
+    let kelly = calculate_kelly_from_trades(trades.clone(), args.fractional_kelly).ok();
+
+    let params = ChallengeParams {
+        daily_loss_basis: if args.day_start_daily_loss {
+            DailyLossBasis::DayStartBalance
+        } else {
+            DailyLossBasis::InitialBalance
+        },
+        drawdown_mode: match args.drawdown {
+            Drawdown::Static => DrawdownMode::Static,
+            Drawdown::TrailingBalance => DrawdownMode::TrailingBalance,
+            Drawdown::TrailingEquity => DrawdownMode::TrailingEquity,
+        },
+        ..ChallengeParams::new(
+            args.account_size,
+            args.profit_target,
+            args.max_daily_loss,
+            args.max_overall_loss,
+            args.min_trading_days,
+        )?
+    };
+    let simulation = monte_carlo_detailed(
+        trades.clone(),
+        params,
+        args.risk_fraction,
+        args.simulations,
+        SimulationOptions { seed: args.seed, ..SimulationOptions::default() },
+        None,
+    )?;
+
+    let report = Report {
+        trades: trades.len(),
+        metrics,
+        kelly,
+        risk_fraction: args.risk_fraction,
+        pass_rate: simulation.pass_rate,
+        daily_loss_failures: simulation.daily_loss_failures,
+        overall_loss_failures: simulation.overall_loss_failures,
+        timeouts: simulation.timeouts,
+    };
+    match args.format {
+        Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+        Format::Table => print_table(out, &report, args.simulations)?,
+    }
+    Ok(())
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("expected YYYY-MM-DD: {e}"))
+}
+
+fn print_table(out: &mut impl Write, report: &Report, simulations: usize) -> std::io::Result<()> {
+    let optional = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{v:.2}"));
+    let kelly = |fraction: fn(&KellyResult) -> f64| {
+        report.kelly.as_ref().map_or("-".to_string(), |k| format!("{:.2}%", fraction(k) * 100.0))
+    };
+    let m = &report.metrics;
+    let rows = [
+        ("Trades", report.trades.to_string()),
+        ("Win rate", format!("{:.1}%", m.win_probability * 100.0)),
+        ("Average win", format!("{:.2}", m.avg_win)),
+        ("Average loss", format!("{:.2}", m.avg_loss)),
+        ("Profit factor", format!("{:.2}", m.profit_factor)),
+        ("Expectancy", format!("{:.2}", m.expectancy)),
+        ("Max drawdown", format!("{:.2}", m.max_drawdown)),
+        ("Sharpe ratio", optional(m.sharpe_ratio)),
+        ("SQN", optional(m.sqn)),
+        ("Kelly fraction", kelly(|k| k.kelly_fraction)),
+        ("Continuous Kelly", kelly(|k| k.continuous_kelly_fraction)),
+        ("Risk per trade", format!("{:.2}%", report.risk_fraction * 100.0)),
+        ("Pass rate", format!("{:.1}% of {}", report.pass_rate * 100.0, simulations)),
+        ("Daily loss failures", report.daily_loss_failures.to_string()),
+        ("Overall loss failures", report.overall_loss_failures.to_string()),
+        ("Timeouts", report.timeouts.to_string()),
+    ];
+    let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    for (label, value) in rows {
+        writeln!(out, "{label:<width$}  {value}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = "\
+Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap,Open Time,Close Time
+EURUSD,Buy,1.0,1.1000,1.1050,50.0,0.0,0.0,2024.01.15 10:00:00,2024.01.15 14:00:00
+EURUSD,Sell,1.0,1.1050,1.1070,-20.0,0.0,0.0,2024.01.16 10:00:00,2024.01.16 14:00:00
+GBPUSD,Buy,1.0,1.2700,1.2730,30.0,0.0,0.0,2024.01.16 11:00:00,2024.01.16 15:00:00
+GBPUSD,Sell,1.0,1.2730,1.2740,-10.0,0.0,0.0,2024.01.17 10:00:00,2024.01.17 14:00:00
+EURUSD,Buy,1.0,1.1000,1.1040,40.0,0.0,0.0,2024.01.18 10:00:00,2024.01.18 14:00:00
+";
+
+    /// Runs the CLI on `REPORT` with `flags` and returns what it printed.
+    fn run_on_report(name: &str, flags: &[&str]) -> String {
+        let path = std::env::temp_dir().join(format!("risk-optima-{}-{}.csv", std::process::id(), name));
+        std::fs::write(&path, REPORT).unwrap();
+        let mut argv = vec!["risk-optima", path.to_str().unwrap(), "--simulations", "200", "--seed", "7"];
+        argv.extend_from_slice(flags);
+        let mut out = Vec::new();
+        let result = run(&Args::parse_from(argv), &mut out);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn json_report(name: &str, flags: &[&str]) -> serde_json::Value {
+        let flags = [&["--format", "json"], flags].concat();
+        serde_json::from_str(&run_on_report(name, &flags)).unwrap()
+    }
+
+    #[test]
+    fn json_report_covers_every_trade() {
+        let report = json_report("json", &[]);
+        assert_eq!(report["trades"], 5);
+        assert_eq!(report["metrics"]["total_trades"], 5);
+        assert_eq!(report["risk_fraction"], 0.01);
+        let pass_rate = report["pass_rate"].as_f64().unwrap();
+        assert!((0.0..=1.0).contains(&pass_rate));
+    }
+
+    #[test]
+    fn filters_select_trades_by_close_date_and_symbol() {
+        assert_eq!(json_report("dates", &["--from", "2024-01-16", "--to", "2024-01-17"])["trades"], 3);
+        assert_eq!(json_report("symbol", &["--symbol", "EURUSD"])["trades"], 3);
+        assert_eq!(json_report("symbols", &["--symbol", "EURUSD", "--symbol", "GBPUSD"])["trades"], 5);
+        let both = json_report("both", &["--from", "2024-01-16", "--symbol", "GBPUSD"]);
+        assert_eq!(both["trades"], 2);
+        assert_eq!(both["metrics"]["win_probability"], 0.5);
+    }
+
+    #[test]
+    fn table_lists_one_labelled_row_per_figure() {
+        let table = run_on_report("table", &["--to", "2024-01-16"]);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows.len(), 16);
+        let row = |label: &str| rows.iter().find(|row| row.starts_with(label)).unwrap().split_whitespace().last();
+        assert_eq!(row("Trades "), Some("3"));
+        assert_eq!(row("Win rate "), Some("66.7%"));
+        assert_eq!(row("Pass rate "), Some("200"));
+    }
+}
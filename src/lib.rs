@@ -1,85 +1,708 @@
-use pyo3::prelude::*;
-use pyo3::exceptions::PyValueError;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::basic::CompareOp;
+#[cfg(feature = "python")]
+use pyo3::types::{PyDict, PyString, PyType};
+#[cfg(feature = "python")]
+use python::{
+    from_json, from_state, hash_repr, reduce_to_state, to_json, ParseError, PyRepr, SimulationError, ValidationError,
+};
+
+// The Python bindings; without the `python` feature this crate is a plain
+// Rust library with the same types and computations.
+#[cfg(feature = "python")]
+mod python;
+
+/// Errors returned by the engine. The Python bindings raise each kind as the
+/// matching subclass of `RiskOptimaError`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Parse(String), // A report, record or serialized state could not be read
+    #[error("{0}")]
+    Validation(String), // An argument or setting is out of range
+    #[error("{0}")]
+    InsufficientData(String), // The trade history is empty or too small for the requested statistic
+    #[error("{0}")]
+    Simulation(String), // A Monte Carlo run failed
+    #[error("{0}")]
+    Cancelled(String), // A Monte Carlo run was cancelled
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// A copy of the error, which `std::io::Error` does not offer; I/O
+    /// errors keep their kind and message.
+    fn duplicate(&self) -> Error {
+        match self {
+            Error::Parse(message) => Error::Parse(message.clone()),
+            Error::Validation(message) => Error::Validation(message.clone()),
+            Error::InsufficientData(message) => Error::InsufficientData(message.clone()),
+            Error::Simulation(message) => Error::Simulation(message.clone()),
+            Error::Cancelled(message) => Error::Cancelled(message.clone()),
+            Error::Io(error) => Error::Io(std::io::Error::new(error.kind(), error.to_string())),
+        }
+    }
+}
+
+/// Recently computed results keyed by their serialized inputs, so that
+/// dashboards re-running the same analysis (a Streamlit rerun, say) get the
+/// answer back instantly. The least recently used entry is dropped first.
+struct ResultCache {
+    capacity: usize,
+    max_bytes: usize,
+    bytes: usize, // Serialized size of the keys and results held
+    entries: std::collections::VecDeque<CacheEntry>, // Most recently used last
+}
+
+struct CacheEntry {
+    key: CacheKey,
+    size: usize, // Serialized size of the key and the result
+    value: Box<dyn std::any::Any + Send>,
+}
+
+/// Serialized inputs of a computation, with their hash to rule out most
+/// other keys without comparing the bytes.
+#[derive(PartialEq)]
+struct CacheKey {
+    hash: u64,
+    bytes: Vec<u8>,
+}
+
+/// Results kept by default, enough for a few pages of a dashboard.
+const DEFAULT_CACHE_ENTRIES: usize = 32;
+
+/// Memory the cached results may take by default, measured by their
+/// serialized size. Results larger than the whole budget are not cached.
+const DEFAULT_CACHE_BYTES: usize = 128 * 1024 * 1024;
+
+static RESULT_CACHE: std::sync::Mutex<ResultCache> = std::sync::Mutex::new(ResultCache {
+    capacity: DEFAULT_CACHE_ENTRIES,
+    max_bytes: DEFAULT_CACHE_BYTES,
+    bytes: 0,
+    entries: std::collections::VecDeque::new(),
+});
+
+impl ResultCache {
+    /// Drops the least recently used entries until `entries` entries of
+    /// `bytes` more bytes would fit.
+    fn evict(&mut self, entries: usize, bytes: usize) {
+        while self.entries.len() > self.capacity.saturating_sub(entries) || self.bytes + bytes > self.max_bytes {
+            let Some(entry) = self.entries.pop_front() else {
+                break;
+            };
+            self.bytes -= entry.size;
+        }
+    }
+}
+
+/// The cache key of a computation's inputs, which should start with the
+/// computation's name; `None` if the inputs cannot be serialized.
+fn cache_key(inputs: &impl Serialize) -> Option<CacheKey> {
+    use std::hash::Hasher;
+
+    let bytes = bincode::serialize(inputs).ok()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hasher.write(&bytes);
+    Some(CacheKey { hash: hasher.finish(), bytes })
+}
+
+/// The cached result under `key`, or the result of `compute`, which is
+/// cached when it succeeds and fits the cache. A `None` key always computes.
+fn cached<T: Clone + Serialize + Send + 'static>(
+    key: Option<CacheKey>,
+    compute: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let Some(key) = key else {
+        return compute();
+    };
+    {
+        let mut cache = RESULT_CACHE.lock().expect("result cache lock");
+        if let Some(index) = cache.entries.iter().position(|entry| entry.key == key && entry.value.is::<T>()) {
+            let entry = cache.entries.remove(index).expect("cached entry");
+            let value = entry.value.downcast_ref::<T>().expect("cached type").clone();
+            cache.entries.push_back(entry);
+            return Ok(value);
+        }
+    }
+    // Computed without the lock, so other threads can use the cache meanwhile
+    let value = compute()?;
+    let Ok(result_size) = bincode::serialized_size(&value) else {
+        return Ok(value);
+    };
+    let size = key.bytes.len().saturating_add(result_size as usize);
+    let mut cache = RESULT_CACHE.lock().expect("result cache lock");
+    if cache.capacity > 0 && size <= cache.max_bytes {
+        cache.evict(1, size);
+        cache.bytes += size;
+        cache.entries.push_back(CacheEntry { key, size, value: Box::new(value.clone()) });
+    }
+    Ok(value)
+}
+
+/// Drops every cached metrics and simulation result.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn clear_result_cache() {
+    let mut cache = RESULT_CACHE.lock().expect("result cache lock");
+    cache.entries.clear();
+    cache.bytes = 0;
+}
+
+/// Sets how many results the cache keeps (32 by default) and how many bytes
+/// they may take (128 MiB by default); 0 for either turns caching off.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (entries, max_bytes=DEFAULT_CACHE_BYTES)))]
+pub fn set_result_cache_size(entries: usize, max_bytes: usize) {
+    let mut cache = RESULT_CACHE.lock().expect("result cache lock");
+    cache.capacity = entries;
+    cache.max_bytes = max_bytes;
+    cache.evict(0, 0);
+}
 
 // Data structures
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[pyclass]
+/// Direction of a position. Compares equal to its name, so `trade_type == "Buy"` keeps working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum TradeType {
+    Buy,
+    Sell,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl TradeType {
+    /// Parses "buy", "Sell", "buy limit", ... case-insensitively.
+    #[pyo3(name = "parse")]
+    #[staticmethod]
+    fn py_parse(raw: &str) -> Result<Self> {
+        Self::parse(raw)
+    }
+
+    #[getter(direction)]
+    fn py_direction(&self) -> f64 {
+        self.direction()
+    }
+
+    #[pyo3(name = "opposite")]
+    fn py_opposite(&self) -> Self {
+        self.opposite()
+    }
+
+    fn __str__(&self) -> &'static str {
+        self.as_str()
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __richcmp__(&self, other: &Bound<'_, PyAny>, op: CompareOp, py: Python<'_>) -> PyObject {
+        let equal = if let Ok(other) = other.extract::<TradeType>() {
+            *self == other
+        } else if let Ok(other) = other.extract::<String>() {
+            self.as_str() == other
+        } else {
+            return py.NotImplemented();
+        };
+        match op {
+            CompareOp::Eq => equal.into_py(py),
+            CompareOp::Ne => (!equal).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+
+    /// Hashes like the name, consistent with comparing equal to it.
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        PyString::new_bound(py, self.as_str()).hash()
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+}
+
+impl TradeType {
+    /// Parses "buy", "Sell", "buy limit", ... case-insensitively.
+    pub fn parse(raw: &str) -> Result<Self> {
+        normalize_trade_type(raw)
+            .ok_or_else(|| Error::Validation(format!("Invalid trade type {:?}, expected Buy or Sell", raw)))
+    }
+
+    pub fn direction(&self) -> f64 {
+        match self {
+            TradeType::Buy => 1.0,
+            TradeType::Sell => -1.0,
+        }
+    }
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            TradeType::Buy => TradeType::Sell,
+            TradeType::Sell => TradeType::Buy,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeType::Buy => "Buy",
+            TradeType::Sell => "Sell",
+        }
+    }
+
+    /// Accepts a `TradeType` or any string `parse` understands.
+    #[cfg(feature = "python")]
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(trade_type) = value.extract::<TradeType>() {
+            return Ok(trade_type);
+        }
+        let raw: String =
+            value.extract().map_err(|_| ValidationError::new_err("trade_type must be a TradeType or str"))?;
+        Ok(TradeType::parse(&raw)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
 pub struct Trade {
-    #[pyo3(get, set)]
     pub symbol: String,
-    #[pyo3(get, set)]
-    pub trade_type: String, // "Buy" or "Sell"
-    #[pyo3(get, set)]
+    pub trade_type: TradeType,
     pub volume: f64,
-    #[pyo3(get, set)]
     pub open_price: f64,
-    #[pyo3(get, set)]
     pub close_price: f64,
-    #[pyo3(get, set)]
     pub profit: f64,
-    #[pyo3(get, set)]
     pub commission: Option<f64>,
-    #[pyo3(get, set)]
     pub swap: Option<f64>,
+    pub open_time: Option<i64>, // Unix epoch milliseconds
+    pub close_time: Option<i64>, // Unix epoch milliseconds
+    pub risk_amount: Option<f64>, // Money lost if the initial stop is hit (1R)
+    #[serde(default)]
+    pub tag: Option<String>, // Setup, session or market regime label
+    #[serde(default)]
+    pub max_favorable_excursion: Option<f64>, // Best open profit while the trade ran, in account currency
+    #[serde(default)]
+    pub max_adverse_excursion: Option<f64>, // Worst open loss while the trade ran, as a positive amount
+    #[serde(default)]
+    pub ticket: Option<u64>, // Broker position ID, identifying the trade across overlapping reports
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl Trade {
     #[new]
+    #[pyo3(signature = (symbol, trade_type, volume, open_price, close_price, profit, commission, swap, open_time=None, close_time=None, risk_amount=None, tag=None, max_favorable_excursion=None, max_adverse_excursion=None, ticket=None, allow_break_even=false))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         symbol: String,
-        trade_type: String,
+        trade_type: &Bound<'_, PyAny>,
         volume: f64,
         open_price: f64,
         close_price: f64,
         profit: f64,
         commission: Option<f64>,
         swap: Option<f64>,
-    ) -> Self {
-        Trade {
+        open_time: Option<i64>,
+        close_time: Option<i64>,
+        risk_amount: Option<f64>,
+        tag: Option<String>,
+        max_favorable_excursion: Option<f64>,
+        max_adverse_excursion: Option<f64>,
+        ticket: Option<u64>,
+        allow_break_even: bool,
+    ) -> PyResult<Self> {
+        let trade = Trade {
             symbol,
-            trade_type,
+            trade_type: TradeType::from_py(trade_type)?,
             volume,
             open_price,
             close_price,
             profit,
             commission,
             swap,
+            open_time,
+            close_time,
+            risk_amount,
+            tag,
+            max_favorable_excursion,
+            max_adverse_excursion,
+            ticket,
+        };
+        trade.check(allow_break_even).map_err(ValidationError::new_err)?;
+        Ok(trade)
+    }
+
+    /// Re-checks the constructor's rules, which setters bypass. With
+    /// `strict`, also rejects a profit whose sign contradicts the price move.
+    #[pyo3(name = "validate")]
+    #[pyo3(signature = (strict=false, allow_break_even=false))]
+    fn py_validate(&self, strict: bool, allow_break_even: bool) -> Result<()> {
+        self.validate(strict, allow_break_even)
+    }
+
+    /// Profit in multiples of the initial risk, if the risk is known.
+    #[pyo3(name = "r_multiple")]
+    fn py_r_multiple(&self) -> Option<f64> {
+        self.r_multiple()
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("symbol", &self.symbol)?;
+        dict.set_item("trade_type", self.trade_type.as_str())?;
+        dict.set_item("volume", self.volume)?;
+        dict.set_item("open_price", self.open_price)?;
+        dict.set_item("close_price", self.close_price)?;
+        dict.set_item("profit", self.profit)?;
+        dict.set_item("commission", self.commission)?;
+        dict.set_item("swap", self.swap)?;
+        dict.set_item("open_time", self.open_time)?;
+        dict.set_item("close_time", self.close_time)?;
+        dict.set_item("risk_amount", self.risk_amount)?;
+        dict.set_item("tag", &self.tag)?;
+        dict.set_item("max_favorable_excursion", self.max_favorable_excursion)?;
+        dict.set_item("max_adverse_excursion", self.max_adverse_excursion)?;
+        dict.set_item("ticket", self.ticket)?;
+        Ok(dict)
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (record, allow_break_even=false))]
+    fn from_dict(record: &Bound<'_, PyAny>, allow_break_even: bool) -> PyResult<Self> {
+        Trade::from_record(record, 0, allow_break_even)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+/// Epoch milliseconds from an integer, a float, or an object with a
+/// `timestamp()` method such as `datetime` or `pandas.Timestamp`.
+#[cfg(feature = "python")]
+fn epoch_millis(value: &Bound<'_, PyAny>) -> Option<i64> {
+    if let Ok(millis) = value.extract::<i64>() {
+        return Some(millis);
+    }
+    match value.call_method0("timestamp") {
+        Ok(seconds) => seconds.extract::<f64>().ok().map(|seconds| (seconds * 1000.0).round() as i64),
+        Err(_) => value.extract::<f64>().ok().map(|ms| ms as i64),
+    }
+}
+
+impl Trade {
+    /// Re-checks the constructor's rules, which setters bypass. With
+    /// `strict`, also rejects a profit whose sign contradicts the price move.
+    pub fn validate(&self, strict: bool, allow_break_even: bool) -> Result<()> {
+        self.check(allow_break_even).map_err(Error::Validation)?;
+        if strict {
+            self.check_consistency().map_err(Error::Validation)?;
+        }
+        Ok(())
+    }
+
+    /// Profit in multiples of the initial risk, if the risk is known.
+    pub fn r_multiple(&self) -> Option<f64> {
+        self.risk_amount
+            .map(f64::abs)
+            .filter(|&risk| risk > 0.0)
+            .map(|risk| self.profit / risk)
+    }
+
+    /// Reads a trade from any mapping with a `get` method (dict, pandas
+    /// Series) keyed by the `Trade` field names. Missing optional fields,
+    /// None and NaN become None; times may be epoch milliseconds or objects
+    /// with a `timestamp()` method such as `datetime` or `pandas.Timestamp`.
+    #[cfg(feature = "python")]
+    fn from_record(record: &Bound<'_, PyAny>, index: usize, allow_break_even: bool) -> PyResult<Self> {
+        let invalid = |field: &str| ParseError::new_err(format!("Record {} has an invalid {} value", index, field));
+        let field = |name: &str| -> PyResult<Option<Bound<'_, PyAny>>> {
+            let value = record.call_method1("get", (name,))?;
+            let is_nan = value.extract::<f64>().is_ok_and(f64::is_nan);
+            Ok((!value.is_none() && !is_nan).then_some(value))
+        };
+        let required = |name: &str| {
+            field(name)?.ok_or_else(|| ParseError::new_err(format!("Record {} is missing {}", index, name)))
+        };
+        let number = |name: &str| required(name)?.extract::<f64>().map_err(|_| invalid(name));
+        let optional_number = |name: &str| -> PyResult<Option<f64>> {
+            field(name)?.map(|v| v.extract::<f64>().map_err(|_| invalid(name))).transpose()
+        };
+        let time = |name: &str| -> PyResult<Option<i64>> {
+            field(name)?.map(|value| epoch_millis(&value).ok_or_else(|| invalid(name))).transpose()
+        };
+
+        let trade_type = required("trade_type")?;
+        let trade_type = match trade_type.extract::<TradeType>() {
+            Ok(trade_type) => Some(trade_type),
+            Err(_) => trade_type.extract::<String>().ok().and_then(|raw| normalize_trade_type(&raw)),
+        };
+        let trade = Trade {
+            symbol: required("symbol")?.str()?.to_string(),
+            trade_type: trade_type.ok_or_else(|| invalid("trade_type"))?,
+            volume: number("volume")?,
+            open_price: number("open_price")?,
+            close_price: number("close_price")?,
+            profit: number("profit")?,
+            commission: optional_number("commission")?,
+            swap: optional_number("swap")?,
+            open_time: time("open_time")?,
+            close_time: time("close_time")?,
+            risk_amount: optional_number("risk_amount")?,
+            tag: field("tag")?.map(|v| v.str().map(|s| s.to_string())).transpose()?,
+            max_favorable_excursion: optional_number("max_favorable_excursion")?,
+            max_adverse_excursion: optional_number("max_adverse_excursion")?,
+            ticket: field("ticket")?
+                .map(|v| match (v.extract::<u64>(), v.extract::<f64>()) {
+                    (Ok(ticket), _) => Ok(ticket),
+                    // Integer columns with gaps come out of pandas as floats
+                    (_, Ok(t)) if t >= 0.0 && t.fract() == 0.0 => Ok(t as u64),
+                    _ => Err(invalid("ticket")),
+                })
+                .transpose()?,
+        };
+        trade
+            .check(allow_break_even)
+            .map_err(|reason| ValidationError::new_err(format!("Record {}: {}", index, reason)))?;
+        Ok(trade)
+    }
+
+    /// Rules every trade must satisfy: finite numbers, a positive volume,
+    /// positive prices, non-negative excursions and a close no earlier than
+    /// the open. Equal open and close prices are rejected unless
+    /// `allow_break_even` admits scratch trades. The parsers apply the rules
+    /// to every row they read.
+    fn check(&self, allow_break_even: bool) -> Result<(), String> {
+        let numbers = [("volume", Some(self.volume)), ("open_price", Some(self.open_price)),
+            ("close_price", Some(self.close_price)), ("profit", Some(self.profit)), ("commission", self.commission),
+            ("swap", self.swap), ("risk_amount", self.risk_amount),
+            ("max_favorable_excursion", self.max_favorable_excursion),
+            ("max_adverse_excursion", self.max_adverse_excursion)];
+        if let Some((name, _)) = numbers.iter().find(|(_, value)| value.is_some_and(|v| !v.is_finite())) {
+            return Err(format!("{} must be finite", name));
+        }
+        let excursions = [("max_favorable_excursion", self.max_favorable_excursion),
+            ("max_adverse_excursion", self.max_adverse_excursion)];
+        if let Some((name, Some(value))) = excursions.iter().find(|(_, value)| value.is_some_and(|v| v < 0.0)) {
+            return Err(format!("{} must be non-negative, got {}", name, value));
         }
+        if self.volume <= 0.0 {
+            return Err(format!("volume must be positive, got {}", self.volume));
+        }
+        if self.open_price <= 0.0 || self.close_price <= 0.0 {
+            return Err(format!("prices must be positive, got {} and {}", self.open_price, self.close_price));
+        }
+        if self.open_price == self.close_price && !allow_break_even {
+            let reason = "allow break-even trades to accept it";
+            return Err(format!("open and close prices are both {}; {}", self.open_price, reason));
+        }
+        if let (Some(open), Some(close)) = (self.open_time, self.close_time) {
+            if close < open {
+                return Err(format!("close_time {} is before open_time {}", close, open));
+            }
+        }
+        Ok(())
+    }
+
+    /// A trade whose price moved in its favour should not have lost money,
+    /// and one closed at its open price should not have made or lost any.
+    fn check_consistency(&self) -> Result<(), String> {
+        let moved = self.trade_type.direction() * (self.close_price - self.open_price);
+        if moved == 0.0 && self.profit != 0.0 {
+            return Err(format!("profit {} on a trade closed at its open price", self.profit));
+        }
+        if moved * self.profit < 0.0 {
+            return Err(format!("profit {} contradicts the {} price move", self.profit, self.trade_type.as_str()));
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[pyclass]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
 pub struct PerformanceMetrics {
-    #[pyo3(get)]
     pub total_trades: usize,
-    #[pyo3(get)]
     pub win_probability: f64,
-    #[pyo3(get)]
     pub loss_probability: f64,
-    #[pyo3(get)]
     pub avg_win: f64,
-    #[pyo3(get)]
     pub avg_loss: f64,
-    #[pyo3(get)]
     pub win_loss_ratio: f64,
-    #[pyo3(get)]
     pub profit_factor: f64,
-    #[pyo3(get)]
     pub expectancy: f64,
-    #[pyo3(get)]
     pub max_drawdown: f64,
-    #[pyo3(get)]
     pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    pub calmar_ratio: Option<f64>,
+    pub mar_ratio: Option<f64>,
+    #[serde(default)]
+    pub bayesian_win_probability: f64, // Posterior mean win rate under a Beta prior
+    #[serde(default)]
+    pub sqn: Option<f64>, // Van Tharp's System Quality Number, trade count capped at 100
+    #[serde(default)]
+    pub expectancy_t_stat: Option<f64>,
+    #[serde(default)]
+    pub expectancy_p_value: Option<f64>, // One-sided, against an expectancy of zero or less
+    #[serde(default)]
+    pub additional_trades_needed: usize, // Before the Kelly fraction is known to within 0.1 at 95%, 0 if enough
+    #[serde(default)]
+    pub outlier_adjustment: Option<OutlierAdjustment>, // Edge statistics with the tails winsorized or trimmed
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl PerformanceMetrics {
     #[new]
-    fn new(
+    #[pyo3(signature = (total_trades, win_probability, loss_probability, avg_win, avg_loss, win_loss_ratio, profit_factor, expectancy, max_drawdown, sharpe_ratio, sortino_ratio=None, calmar_ratio=None, mar_ratio=None, bayesian_win_probability=None, sqn=None, expectancy_t_stat=None, expectancy_p_value=None, outlier_adjustment=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        total_trades: usize,
+        win_probability: f64,
+        loss_probability: f64,
+        avg_win: f64,
+        avg_loss: f64,
+        win_loss_ratio: f64,
+        profit_factor: f64,
+        expectancy: f64,
+        max_drawdown: f64,
+        sharpe_ratio: Option<f64>,
+        sortino_ratio: Option<f64>,
+        calmar_ratio: Option<f64>,
+        mar_ratio: Option<f64>,
+        bayesian_win_probability: Option<f64>,
+        sqn: Option<f64>,
+        expectancy_t_stat: Option<f64>,
+        expectancy_p_value: Option<f64>,
+        outlier_adjustment: Option<OutlierAdjustment>,
+    ) -> Self {
+        Self::new(
+            total_trades,
+            win_probability,
+            loss_probability,
+            avg_win,
+            avg_loss,
+            win_loss_ratio,
+            profit_factor,
+            expectancy,
+            max_drawdown,
+            sharpe_ratio,
+            sortino_ratio,
+            calmar_ratio,
+            mar_ratio,
+            bayesian_win_probability,
+            sqn,
+            expectancy_t_stat,
+            expectancy_p_value,
+            outlier_adjustment,
+        )
+    }
+
+    /// Whether the sample is large enough for the win rate behind the Kelly
+    /// fraction to be meaningful.
+    #[pyo3(name = "has_sufficient_sample")]
+    fn py_has_sufficient_sample(&self) -> bool {
+        self.has_sufficient_sample()
+    }
+
+    /// Whether the expectancy is significantly above zero. If not, the
+    /// sample cannot justify any positive Kelly fraction.
+    #[pyo3(name = "has_significant_edge")]
+    #[pyo3(signature = (significance=0.05))]
+    fn py_has_significant_edge(&self, significance: f64) -> bool {
+        self.has_significant_edge(significance)
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("total_trades", self.total_trades)?;
+        dict.set_item("win_probability", self.win_probability)?;
+        dict.set_item("loss_probability", self.loss_probability)?;
+        dict.set_item("avg_win", self.avg_win)?;
+        dict.set_item("avg_loss", self.avg_loss)?;
+        dict.set_item("win_loss_ratio", self.win_loss_ratio)?;
+        dict.set_item("profit_factor", self.profit_factor)?;
+        dict.set_item("expectancy", self.expectancy)?;
+        dict.set_item("max_drawdown", self.max_drawdown)?;
+        dict.set_item("sharpe_ratio", self.sharpe_ratio)?;
+        dict.set_item("sortino_ratio", self.sortino_ratio)?;
+        dict.set_item("calmar_ratio", self.calmar_ratio)?;
+        dict.set_item("mar_ratio", self.mar_ratio)?;
+        dict.set_item("bayesian_win_probability", self.bayesian_win_probability)?;
+        dict.set_item("sqn", self.sqn)?;
+        dict.set_item("expectancy_t_stat", self.expectancy_t_stat)?;
+        dict.set_item("expectancy_p_value", self.expectancy_p_value)?;
+        dict.set_item("additional_trades_needed", self.additional_trades_needed)?;
+        dict.set_item("outlier_adjustment", self.outlier_adjustment.clone().map(|a| a.into_py(py)))?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        hash_repr(&self.py_repr())
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl PerformanceMetrics {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
         total_trades: usize,
         win_probability: f64,
         loss_probability: f64,
@@ -90,6 +713,14 @@ impl PerformanceMetrics {
         expectancy: f64,
         max_drawdown: f64,
         sharpe_ratio: Option<f64>,
+        sortino_ratio: Option<f64>,
+        calmar_ratio: Option<f64>,
+        mar_ratio: Option<f64>,
+        bayesian_win_probability: Option<f64>,
+        sqn: Option<f64>,
+        expectancy_t_stat: Option<f64>,
+        expectancy_p_value: Option<f64>,
+        outlier_adjustment: Option<OutlierAdjustment>,
     ) -> Self {
         PerformanceMetrics {
             total_trades,
@@ -102,392 +733,11622 @@ impl PerformanceMetrics {
             expectancy,
             max_drawdown,
             sharpe_ratio,
+            sortino_ratio,
+            calmar_ratio,
+            mar_ratio,
+            bayesian_win_probability: bayesian_win_probability.unwrap_or(win_probability),
+            sqn,
+            expectancy_t_stat,
+            expectancy_p_value,
+            additional_trades_needed: additional_trades_needed(total_trades, win_probability, win_loss_ratio),
+            outlier_adjustment,
         }
     }
+
+    /// Whether the sample is large enough for the win rate behind the Kelly
+    /// fraction to be meaningful.
+    pub fn has_sufficient_sample(&self) -> bool {
+        self.additional_trades_needed == 0
+    }
+
+    /// Whether the expectancy is significantly above zero. If not, the
+    /// sample cannot justify any positive Kelly fraction.
+    pub fn has_significant_edge(&self, significance: f64) -> bool {
+        self.expectancy_p_value.is_some_and(|p| p < significance)
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[pyclass]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
 pub struct ChallengeParams {
-    #[pyo3(get, set)]
     pub account_size: f64,
-    #[pyo3(get, set)]
     pub profit_target_percent: f64,
-    #[pyo3(get, set)]
     pub max_daily_loss_percent: f64,
-    #[pyo3(get, set)]
     pub max_overall_loss_percent: f64,
-    #[pyo3(get, set)]
     pub min_trading_days: u32,
+    pub daily_loss_basis: DailyLossBasis,
+    pub drawdown_mode: DrawdownMode,
+    pub lock_trailing_at_breakeven: bool,
+    pub phases: Vec<ChallengePhase>, // Empty for a single-phase challenge
+    #[serde(default)]
+    pub max_day_profit_percent: Option<f64>, // Consistency: best day's share of total profit, e.g. 30
+    #[serde(default)]
+    pub min_profitable_days_percent: Option<f64>, // Share of trading days that must end in profit
+    #[serde(default)]
+    pub no_weekend_holding: bool, // Positions may not stay open over a Saturday (UTC)
+    #[serde(default)]
+    pub restricted_windows: Vec<(i64, i64)>, // (start, end) epoch ms, e.g. high-impact news, with no opens or closes
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl ChallengeParams {
     #[new]
-    fn new(
+    #[pyo3(signature = (account_size, profit_target_percent, max_daily_loss_percent, max_overall_loss_percent, min_trading_days, daily_loss_basis=DailyLossBasis::InitialBalance, drawdown_mode=DrawdownMode::Static, lock_trailing_at_breakeven=true, phases=Vec::new(), max_day_profit_percent=None, min_profitable_days_percent=None, no_weekend_holding=false, restricted_windows=Vec::new()))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
         account_size: f64,
         profit_target_percent: f64,
         max_daily_loss_percent: f64,
         max_overall_loss_percent: f64,
         min_trading_days: u32,
-    ) -> Self {
-        ChallengeParams {
+        daily_loss_basis: DailyLossBasis,
+        drawdown_mode: DrawdownMode,
+        lock_trailing_at_breakeven: bool,
+        phases: Vec<ChallengePhase>,
+        max_day_profit_percent: Option<f64>,
+        min_profitable_days_percent: Option<f64>,
+        no_weekend_holding: bool,
+        restricted_windows: Vec<(i64, i64)>,
+    ) -> Result<Self> {
+        let params = ChallengeParams {
             account_size,
             profit_target_percent,
             max_daily_loss_percent,
             max_overall_loss_percent,
             min_trading_days,
-        }
+            daily_loss_basis,
+            drawdown_mode,
+            lock_trailing_at_breakeven,
+            phases,
+            max_day_profit_percent,
+            min_profitable_days_percent,
+            no_weekend_holding,
+            restricted_windows,
+        };
+        params.validate()?;
+        Ok(params)
     }
-}
 
-// Core computational functions
-#[pyfunction]
-fn parse_mt5_csv(content: &str) -> PyResult<Vec<Trade>> {
-    let mut trades = Vec::new();
-    let mut reader = csv::Reader::from_reader(content.as_bytes());
-
-    for result in reader.records() {
-        let record = result.map_err(|e| PyValueError::new_err(format!("CSV parsing error: {}", e)))?;
+    /// Re-checks the constructor's rules, which setters bypass.
+    #[pyo3(name = "validate")]
+    fn py_validate(&self) -> Result<()> {
+        self.validate()
+    }
 
-        // Skip header and non-trade rows
-        if record.len() < 8 || record.get(0).unwrap_or("").contains("Positions") {
-            continue;
-        }
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
 
-        let trade = Trade {
-            symbol: record.get(0).unwrap_or("").to_string(),
-            trade_type: record.get(1).unwrap_or("").to_string(),
-            volume: record.get(2).unwrap_or("0").parse().unwrap_or(0.0),
-            open_price: record.get(3).unwrap_or("0").parse().unwrap_or(0.0),
-            close_price: record.get(4).unwrap_or("0").parse().unwrap_or(0.0),
-            profit: record.get(5).unwrap_or("0").parse().unwrap_or(0.0),
-            commission: record.get(6).and_then(|s| s.parse().ok()),
-            swap: record.get(7).and_then(|s| s.parse().ok()),
-        };
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
 
-        trades.push(trade);
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
     }
 
-    Ok(trades)
-}
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
 
-#[pyfunction]
-fn parse_mt5_xml(content: &str) -> PyResult<Vec<Trade>> {
-    let mut trades = Vec::new();
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
 
-    // Simple XML parsing for MT5 format
-    // This is a simplified implementation - in production, use a proper XML parser
-    let positions_start = content.find("<Positions>").unwrap_or(0);
-    let positions_end = content.find("</Positions>").unwrap_or(content.len());
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
 
-    if positions_start == 0 {
-        return Err(PyValueError::new_err("Invalid MT5 XML format: Positions section not found"));
+    /// A built-in preset such as `ftmo-100k`, with any fields replaced by
+    /// keyword overrides.
+    #[pyo3(name = "preset", signature = (name, **overrides))]
+    #[classmethod]
+    fn py_preset(cls: &Bound<'_, PyType>, name: &str, overrides: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        override_challenge_params(cls.py(), Self::preset(name)?, overrides)
     }
+}
 
-    let positions_content = &content[positions_start..positions_end];
+impl ChallengeParams {
+    /// A single-phase challenge with a static drawdown, the daily loss taken
+    /// of the initial balance and no consistency or trading restrictions.
+    /// Change the other fields with struct update syntax.
+    pub fn new(
+        account_size: f64,
+        profit_target_percent: f64,
+        max_daily_loss_percent: f64,
+        max_overall_loss_percent: f64,
+        min_trading_days: u32,
+    ) -> Result<Self> {
+        let params = ChallengeParams {
+            account_size,
+            profit_target_percent,
+            max_daily_loss_percent,
+            max_overall_loss_percent,
+            min_trading_days,
+            daily_loss_basis: DailyLossBasis::InitialBalance,
+            drawdown_mode: DrawdownMode::Static,
+            lock_trailing_at_breakeven: true,
+            phases: Vec::new(),
+            max_day_profit_percent: None,
+            min_profitable_days_percent: None,
+            no_weekend_holding: false,
+            restricted_windows: Vec::new(),
+        };
+        params.validate()?;
+        Ok(params)
+    }
 
-    // Parse individual position entries
-    // This is a basic implementation - enhance for production use
-    for line in positions_content.lines() {
-        if line.contains("<Position>") && line.contains("</Position>") {
-            // Extract trade data from XML line
-            // Simplified parsing - use proper XML parsing in production
-            let trade = Trade {
-                symbol: "EURUSD".to_string(), // Placeholder
-                trade_type: "Buy".to_string(), // Placeholder
-                volume: 1.0,
-                open_price: 1.0,
-                close_price: 1.0,
-                profit: 0.0,
-                commission: None,
-                swap: None,
-            };
-            trades.push(trade);
+    /// Rules every challenge must satisfy: a positive account size, positive
+    /// profit targets, loss limits above 0 and at most 100%, a profitable
+    /// days share within 0 to 100% and restricted windows that end after
+    /// they start.
+    pub fn validate(&self) -> Result<()> {
+        if !(self.account_size.is_finite() && self.account_size > 0.0) {
+            return Err(Error::Validation(format!("Account size must be positive, got {}", self.account_size)));
+        }
+        for phase in self.phase_rules() {
+            if !(phase.profit_target_percent.is_finite() && phase.profit_target_percent > 0.0) {
+                return Err(Error::Validation(format!(
+                    "Profit target must be a positive percentage, got {}",
+                    phase.profit_target_percent
+                )));
+            }
+        }
+        let limits = [
+            ("max_daily_loss_percent", Some(self.max_daily_loss_percent)),
+            ("max_overall_loss_percent", Some(self.max_overall_loss_percent)),
+            ("max_day_profit_percent", self.max_day_profit_percent),
+        ];
+        if let Some((name, Some(value))) =
+            limits.iter().find(|(_, value)| value.is_some_and(|v| !(v > 0.0 && v <= 100.0)))
+        {
+            return Err(Error::Validation(format!("{} must be above 0 and at most 100, got {}", name, value)));
+        }
+        if let Some(minimum) = self.min_profitable_days_percent.filter(|m| !(0.0..=100.0).contains(m)) {
+            return Err(Error::Validation(format!(
+                "min_profitable_days_percent must be between 0 and 100, got {}",
+                minimum
+            )));
         }
+        if let Some((start, end)) = self.restricted_windows.iter().find(|(start, end)| end < start) {
+            return Err(Error::Validation(format!("Restricted window ends at {} before it starts at {}", end, start)));
+        }
+        Ok(())
     }
 
-    Ok(trades)
-}
+    /// A built-in preset such as `ftmo-100k`; see `ChallengePresets`.
+    pub fn preset(name: &str) -> Result<Self> {
+        ChallengePresets::builtin().params(name)
+    }
 
-#[pyfunction]
-fn calculate_performance_metrics(trades: Vec<Trade>) -> PyResult<PerformanceMetrics> {
-    if trades.is_empty() {
-        return Err(PyValueError::new_err("No trades provided"));
+    /// The phases to pass in order. Without explicit phases the challenge is
+    /// a single phase using `profit_target_percent` and `min_trading_days`.
+    fn phase_rules(&self) -> Vec<ChallengePhase> {
+        if self.phases.is_empty() {
+            vec![ChallengePhase {
+                profit_target_percent: self.profit_target_percent,
+                min_trading_days: self.min_trading_days,
+            }]
+        } else {
+            self.phases.clone()
+        }
     }
 
-    let total_trades = trades.len();
+    /// Raises a trailing loss floor to follow a new high-water mark, never
+    /// past the starting balance when the trail locks at breakeven.
+    fn trail_floor(&self, floor: f64, high_water: f64) -> f64 {
+        let trailed = floor.max(high_water - self.account_size * self.max_overall_loss_percent / 100.0);
+        if self.lock_trailing_at_breakeven {
+            trailed.min(self.account_size.max(floor))
+        } else {
+            trailed
+        }
+    }
 
-    let winning_trades: Vec<_> = trades.iter().filter(|t| t.profit > 0.0).collect();
-    let losing_trades: Vec<_> = trades.iter().filter(|t| t.profit < 0.0).collect();
+    /// Balance the daily loss limit is a percentage of, for a day that
+    /// starts at `day_start_equity`.
+    fn daily_loss_basis(&self, day_start_equity: f64) -> f64 {
+        match self.daily_loss_basis {
+            DailyLossBasis::InitialBalance => self.account_size,
+            DailyLossBasis::DayStartBalance => day_start_equity,
+        }
+    }
 
-    let win_probability = winning_trades.len() as f64 / total_trades as f64;
-    let loss_probability = losing_trades.len() as f64 / total_trades as f64;
+    /// The holding or timing rule `trade` breaks, if any. Trades without
+    /// timestamps cannot be checked and never break them.
+    fn trading_restriction(&self, trade: &Trade) -> Option<&'static str> {
+        if self.no_weekend_holding {
+            if let (Some(open), Some(close)) = (trade.open_time, trade.close_time) {
+                // Day 0 of the Unix epoch was a Thursday, so Saturdays are day 2 mod 7
+                let open_day = open.div_euclid(MILLIS_PER_DAY);
+                let next_saturday = open_day + (2 - open_day).rem_euclid(7);
+                if next_saturday <= close.div_euclid(MILLIS_PER_DAY) {
+                    return Some("position held over the weekend");
+                }
+            }
+        }
+        let restricted = |time: Option<i64>| {
+            time.is_some_and(|t| self.restricted_windows.iter().any(|&(start, end)| start <= t && t <= end))
+        };
+        if restricted(trade.open_time) || restricted(trade.close_time) {
+            return Some("trade opened or closed in a restricted window");
+        }
+        None
+    }
 
-    let avg_win = if !winning_trades.is_empty() {
-        winning_trades.iter().map(|t| t.profit).sum::<f64>() / winning_trades.len() as f64
-    } else {
-        0.0
-    };
+    /// The first consistency rule broken by these daily P&Ls, if any.
+    fn consistency_violation(&self, day_profits: &[f64]) -> Option<String> {
+        let total: f64 = day_profits.iter().sum();
+        // The best day is a share of the total profit, so the rule needs one
+        if let Some(limit) = self.max_day_profit_percent.filter(|_| total > 0.0) {
+            let best = day_profits.iter().copied().fold(0.0, f64::max);
+            if best > total * limit / 100.0 {
+                let share = best / total * 100.0;
+                return Some(format!("best day is {:.1}% of total profit, above the {}% limit", share, limit));
+            }
+        }
+        if let Some(minimum) = self.min_profitable_days_percent {
+            let profitable = day_profits.iter().filter(|&&p| p > 0.0).count();
+            let percent = profitable as f64 / day_profits.len().max(1) as f64 * 100.0;
+            if percent < minimum {
+                return Some(format!("{:.1}% of trading days were profitable, below the {}% minimum", percent, minimum));
+            }
+        }
+        None
+    }
+}
 
-    let avg_loss = if !losing_trades.is_empty() {
-        losing_trades.iter().map(|t| t.profit).sum::<f64>() / losing_trades.len() as f64
-    } else {
-        0.0
-    };
+/// One stage of a multi-phase challenge (e.g. evaluation, then verification).
+/// Every phase starts again from the initial account size and shares the
+/// loss limits of its `ChallengeParams`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct ChallengePhase {
+    pub profit_target_percent: f64,
+    pub min_trading_days: u32,
+}
 
-    // Robust Win/Loss Ratio using median
-    let mut win_amounts: Vec<f64> = winning_trades.iter().map(|t| t.profit).collect();
-    let mut loss_amounts: Vec<f64> = losing_trades.iter().map(|t| t.profit.abs()).collect();
+#[cfg(feature = "python")]
+#[pymethods]
+impl ChallengePhase {
+    #[new]
+    #[pyo3(signature = (profit_target_percent, min_trading_days=0))]
+    fn py_new(profit_target_percent: f64, min_trading_days: u32) -> Self {
+        Self::new(profit_target_percent, min_trading_days)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
 
-    win_amounts.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    loss_amounts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
 
-    let median_win = if !win_amounts.is_empty() {
-        let mid = win_amounts.len() / 2;
-        if win_amounts.len() % 2 == 0 {
-            (win_amounts[mid - 1] + win_amounts[mid]) / 2.0
-        } else {
-            win_amounts[mid]
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl ChallengePhase {
+    pub fn new(profit_target_percent: f64, min_trading_days: u32) -> Self {
+        ChallengePhase {
+            profit_target_percent,
+            min_trading_days,
         }
-    } else {
-        0.0
-    };
+    }
+}
+
+/// One firm's published rules as percentages of the account size, offered at
+/// each of `account_sizes`. A single phase uses the top-level target and days.
+struct FirmRules {
+    firm: &'static str,
+    account_sizes: &'static [f64],
+    phases: &'static [(f64, u32)], // (profit target %, minimum trading days) per phase
+    max_daily_loss_percent: f64,
+    max_overall_loss_percent: f64,
+    daily_loss_basis: DailyLossBasis,
+    drawdown_mode: DrawdownMode,
+    max_day_profit_percent: Option<f64>,
+}
+
+/// Rules as the firms published them at the time of writing. Firms revise
+/// their rules often, so check a preset against the current terms before
+/// relying on it.
+const BUILTIN_FIRM_RULES: &[FirmRules] = &[
+    FirmRules {
+        firm: "ftmo",
+        account_sizes: &[10_000.0, 25_000.0, 50_000.0, 100_000.0, 200_000.0],
+        phases: &[(10.0, 4), (5.0, 4)],
+        max_daily_loss_percent: 5.0,
+        max_overall_loss_percent: 10.0,
+        daily_loss_basis: DailyLossBasis::InitialBalance,
+        drawdown_mode: DrawdownMode::Static,
+        max_day_profit_percent: None,
+    },
+    FirmRules {
+        firm: "mff",
+        account_sizes: &[5_000.0, 10_000.0, 25_000.0, 50_000.0, 100_000.0, 200_000.0],
+        phases: &[(8.0, 5), (5.0, 5)],
+        max_daily_loss_percent: 5.0,
+        max_overall_loss_percent: 12.0,
+        daily_loss_basis: DailyLossBasis::DayStartBalance,
+        drawdown_mode: DrawdownMode::Static,
+        max_day_profit_percent: None,
+    },
+    FirmRules {
+        firm: "the5ers",
+        account_sizes: &[5_000.0, 10_000.0, 20_000.0, 60_000.0, 100_000.0],
+        phases: &[(8.0, 3), (5.0, 3)],
+        max_daily_loss_percent: 5.0,
+        max_overall_loss_percent: 10.0,
+        daily_loss_basis: DailyLossBasis::DayStartBalance,
+        drawdown_mode: DrawdownMode::Static,
+        max_day_profit_percent: None,
+    },
+    FirmRules {
+        firm: "fundednext",
+        account_sizes: &[6_000.0, 15_000.0, 25_000.0, 50_000.0, 100_000.0, 200_000.0],
+        phases: &[(8.0, 5), (5.0, 5)],
+        max_daily_loss_percent: 5.0,
+        max_overall_loss_percent: 10.0,
+        daily_loss_basis: DailyLossBasis::DayStartBalance,
+        drawdown_mode: DrawdownMode::Static,
+        max_day_profit_percent: None,
+    },
+    // Topstep's trailing loss is a fixed dollar amount, a larger share of the smallest account
+    FirmRules {
+        firm: "topstep",
+        account_sizes: &[50_000.0],
+        phases: &[(6.0, 2)],
+        max_daily_loss_percent: 2.0,
+        max_overall_loss_percent: 4.0,
+        daily_loss_basis: DailyLossBasis::DayStartBalance,
+        drawdown_mode: DrawdownMode::TrailingBalance,
+        max_day_profit_percent: Some(50.0),
+    },
+    FirmRules {
+        firm: "topstep",
+        account_sizes: &[100_000.0, 150_000.0],
+        phases: &[(6.0, 2)],
+        max_daily_loss_percent: 2.0,
+        max_overall_loss_percent: 3.0,
+        daily_loss_basis: DailyLossBasis::DayStartBalance,
+        drawdown_mode: DrawdownMode::TrailingBalance,
+        max_day_profit_percent: Some(50.0),
+    },
+];
+
+/// Named challenge rules, e.g. `ftmo-100k`. Starts from the built-in firm
+/// presets and can be extended with user presets from JSON files. Names are
+/// case-insensitive.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct ChallengePresets {
+    presets: HashMap<String, ChallengeParams>,
+}
 
-    let median_loss = if !loss_amounts.is_empty() {
-        let mid = loss_amounts.len() / 2;
-        if loss_amounts.len() % 2 == 0 {
-            (loss_amounts[mid - 1] + loss_amounts[mid]) / 2.0
+#[cfg(feature = "python")]
+#[pymethods]
+impl ChallengePresets {
+    #[new]
+    #[pyo3(signature = (builtin=true))]
+    fn py_new(builtin: bool) -> Self {
+        if builtin {
+            Self::builtin()
         } else {
-            loss_amounts[mid]
+            Self::default()
         }
-    } else {
-        0.0
-    };
+    }
 
-    let win_loss_ratio = if median_loss != 0.0 { median_win / median_loss } else { 0.0 };
+    /// The named preset with any fields replaced by keyword overrides, e.g.
+    /// `get("ftmo-100k", max_daily_loss_percent=4.0)`.
+    #[pyo3(name = "get", signature = (name, **overrides))]
+    fn py_get(&self, py: Python<'_>, name: &str, overrides: Option<&Bound<'_, PyDict>>) -> PyResult<ChallengeParams> {
+        override_challenge_params(py, self.params(name)?, overrides)
+    }
 
-    let gross_profit: f64 = winning_trades.iter().map(|t| t.profit).sum();
-    let gross_loss: f64 = losing_trades.iter().map(|t| t.profit.abs()).sum();
-    let profit_factor = if gross_loss != 0.0 { gross_profit / gross_loss } else { 0.0 };
+    /// Adds a preset, replacing any existing one with the same name.
+    #[pyo3(name = "add")]
+    fn py_add(&mut self, name: &str, params: ChallengeParams) {
+        self.add(name, params)
+    }
 
-    let expectancy = win_probability * avg_win - loss_probability * avg_loss.abs();
+    #[pyo3(name = "names")]
+    fn py_names(&self) -> Vec<String> {
+        self.names()
+    }
+
+    fn __len__(&self) -> usize {
+        self.presets.len()
+    }
 
-    // Calculate equity curve for drawdown
-    let mut equity = 0.0;
-    let mut peak = 0.0;
-    let mut max_drawdown = 0.0;
+    fn __contains__(&self, name: &str) -> bool {
+        self.presets.contains_key(&preset_key(name))
+    }
 
-    for trade in &trades {
-        equity += trade.profit;
-        if equity > peak {
-            peak = equity;
+    /// Adds the presets in a JSON object of name to parameters.
+    #[pyo3(name = "load_json")]
+    fn py_load_json(&mut self, content: &str) -> Result<()> {
+        self.load_json(content)
+    }
+
+    /// Adds the presets in a JSON file.
+    #[pyo3(name = "load")]
+    fn py_load(&mut self, path: std::path::PathBuf) -> Result<()> {
+        self.load(&path)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    /// Writes the presets in the format `load_json` reads.
+    fn to_json(&self) -> PyResult<String> {
+        let presets: std::collections::BTreeMap<&String, &ChallengeParams> = self.presets.iter().collect();
+        to_json(&presets)
+    }
+}
+
+impl ChallengePresets {
+    /// The built-in presets, one per firm and account size.
+    pub fn builtin() -> Self {
+        let mut presets = ChallengePresets::default();
+        for rules in BUILTIN_FIRM_RULES {
+            let (profit_target_percent, min_trading_days) = rules.phases[0];
+            let phases = if rules.phases.len() > 1 {
+                rules.phases.iter().map(|&(target, days)| ChallengePhase::new(target, days)).collect()
+            } else {
+                Vec::new()
+            };
+            for &account_size in rules.account_sizes {
+                let base = ChallengeParams::new(
+                    account_size,
+                    profit_target_percent,
+                    rules.max_daily_loss_percent,
+                    rules.max_overall_loss_percent,
+                    min_trading_days,
+                )
+                .expect("built-in firm rules are valid");
+                let params = ChallengeParams {
+                    daily_loss_basis: rules.daily_loss_basis,
+                    drawdown_mode: rules.drawdown_mode,
+                    phases: phases.clone(),
+                    max_day_profit_percent: rules.max_day_profit_percent,
+                    ..base
+                };
+                presets.add(&format!("{}-{}k", rules.firm, account_size / 1000.0), params);
+            }
+        }
+        presets
+    }
+
+    /// Adds a preset, replacing any existing one with the same name.
+    pub fn add(&mut self, name: &str, params: ChallengeParams) {
+        self.presets.insert(preset_key(name), params);
+    }
+
+    pub fn get(&self, name: &str) -> Option<ChallengeParams> {
+        self.presets.get(&preset_key(name)).cloned()
+    }
+
+    /// The named preset, or a validation error naming the unknown preset.
+    pub fn params(&self, name: &str) -> Result<ChallengeParams> {
+        self.get(name).ok_or_else(|| Error::Validation(format!("No challenge preset named {}", name)))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.presets.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Adds the presets in a JSON object of name to parameters. An entry with
+    /// a `"base"` preset name only needs the fields it changes, e.g.
+    /// `{"my-ftmo": {"base": "ftmo-100k", "max_daily_loss_percent": 4}}`.
+    /// Bases must already be in the registry before the file is loaded.
+    pub fn load_json(&mut self, content: &str) -> Result<()> {
+        let invalid = |name: &str, e: String| Error::Parse(format!("Invalid challenge preset {}: {}", name, e));
+        let entries: std::collections::BTreeMap<String, serde_json::Value> = serde_json::from_str(content)
+            .map_err(|e| Error::Parse(format!("Invalid challenge preset JSON: {}", e)))?;
+        let mut loaded = Vec::with_capacity(entries.len());
+        for (name, entry) in entries {
+            let serde_json::Value::Object(mut fields) = entry else {
+                return Err(invalid(&name, "expected an object".into()));
+            };
+            let params = match fields.remove("base") {
+                Some(serde_json::Value::String(base)) => {
+                    let base = self.get(&base).ok_or_else(|| invalid(&name, format!("unknown base preset {}", base)))?;
+                    let mut merged = match serde_json::to_value(base) {
+                        Ok(serde_json::Value::Object(merged)) => merged,
+                        _ => unreachable!("ChallengeParams serializes to an object"),
+                    };
+                    merged.extend(fields);
+                    serde_json::Value::Object(merged)
+                }
+                Some(_) => return Err(invalid(&name, "base must be a preset name".into())),
+                None => serde_json::Value::Object(fields),
+            };
+            let params: ChallengeParams = serde_json::from_value(params).map_err(|e| invalid(&name, e.to_string()))?;
+            params.validate().map_err(|e| invalid(&name, e.to_string()))?;
+            loaded.push((name, params));
         }
-        let drawdown = peak - equity;
-        if drawdown > max_drawdown {
-            max_drawdown = drawdown;
+        for (name, params) in loaded {
+            self.add(&name, params);
         }
+        Ok(())
     }
 
-    // Sharpe ratio calculation (simplified - requires daily returns)
-    let sharpe_ratio = None; // Placeholder for future implementation
+    /// Adds the presets in a JSON file.
+    pub fn load(&mut self, path: &std::path::Path) -> Result<()> {
+        self.load_json(&std::fs::read_to_string(path)?)
+    }
+}
 
-    Ok(PerformanceMetrics::new(
-        total_trades,
-        win_probability,
-        loss_probability,
-        avg_win,
-        avg_loss,
-        win_loss_ratio,
-        profit_factor,
-        expectancy,
-        max_drawdown,
-        sharpe_ratio,
-    ))
+fn preset_key(name: &str) -> String {
+    name.trim().to_ascii_lowercase()
 }
 
-#[pyfunction]
-fn calculate_kelly_criterion(win_prob: f64, win_loss_ratio: f64, fractional_multiplier: f64) -> PyResult<f64> {
-    if win_prob <= 0.0 || win_prob >= 1.0 {
-        return Err(PyValueError::new_err("Win probability must be between 0 and 1"));
+/// Replaces fields of `params` with the keyword arguments of the same name.
+#[cfg(feature = "python")]
+fn override_challenge_params(
+    py: Python<'_>,
+    params: ChallengeParams,
+    overrides: Option<&Bound<'_, PyDict>>,
+) -> PyResult<ChallengeParams> {
+    let Some(overrides) = overrides else {
+        return Ok(params);
+    };
+    let fields = match serde_json::to_value(&params) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => unreachable!("ChallengeParams serializes to an object"),
+    };
+    let params = Bound::new(py, params)?;
+    for (key, value) in overrides.iter() {
+        let key: String = key.extract()?;
+        if !fields.contains_key(&key) {
+            return Err(Error::Validation(format!("Unknown challenge parameter: {}", key)).into());
+        }
+        params.setattr(key.as_str(), value)?;
     }
-    if win_loss_ratio <= 0.0 {
-        return Err(PyValueError::new_err("Win/loss ratio must be positive"));
+    let params = params.borrow().clone();
+    params.validate()?;
+    Ok(params)
+}
+
+/// What the daily loss percentage is taken of. Either way the loss itself is
+/// measured from the balance at the start of the trading day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum DailyLossBasis {
+    InitialBalance,  // e.g. 5% of the initial account size (FTMO style)
+    DayStartBalance, // e.g. 5% of the balance at the start of each day
+}
+
+/// How the overall loss limit is anchored. Trailing modes keep the floor a
+/// fixed `max_overall_loss_percent` of the account size below the
+/// high-water mark instead of below the initial balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum DrawdownMode {
+    Static,          // Floor fixed relative to the initial balance
+    TrailingBalance, // Floor trails the highest end-of-day balance
+    TrailingEquity,  // Floor trails the highest equity after any trade
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct KellyResult {
+    pub win_probability: f64,
+    pub win_loss_ratio: f64,
+    pub mean_return: f64, // Per-trade return in units of the average loss
+    pub return_variance: f64,
+    pub fractional_multiplier: f64,
+    pub kelly_fraction: f64, // Binary win/loss Kelly
+    pub continuous_kelly_fraction: f64, // Thorp's mean/variance Kelly
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct KellyDistribution {
+    pub point_estimate: f64,
+    pub iterations: usize,
+    pub valid_samples: usize, // Resamples with at least one win and one loss
+    pub mean: f64,
+    pub median: f64,
+    pub percentile_5: f64,
+    pub percentile_95: f64,
+    pub conservative_kelly: f64, // Lower quartile of the bootstrap distribution
+}
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// Calendar day (days since the Unix epoch, UTC) on which a trade closed.
+fn trade_day(trade: &Trade) -> Option<i64> {
+    trade.close_time.map(|t| t.div_euclid(MILLIS_PER_DAY))
+}
+
+/// Orders trades by close time when every trade carries one; otherwise the
+/// input order is assumed to already be chronological.
+fn chronological(trades: &[Trade]) -> Vec<&Trade> {
+    let mut ordered: Vec<&Trade> = trades.iter().collect();
+    if ordered.iter().all(|t| t.close_time.is_some()) {
+        ordered.sort_by_key(|t| t.close_time);
     }
+    ordered
+}
 
-    let kelly_fraction = win_prob - ((1.0 - win_prob) / win_loss_ratio);
-    let optimal_fraction = kelly_fraction * fractional_multiplier;
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
 
-    Ok(optimal_fraction)
+/// Whether a day since the Unix epoch falls on a Saturday or Sunday. Day 0
+/// was a Thursday, so Monday maps to 0.
+fn is_weekend(day: i64) -> bool {
+    (day + 3).rem_euclid(7) >= 5
 }
 
-#[pyfunction]
-fn calculate_optimal_f(trades: Vec<Trade>, max_iterations: usize, tolerance: f64) -> PyResult<f64> {
-    if trades.is_empty() {
-        return Err(PyValueError::new_err("No trades provided"));
+/// Net P&L for every trading day from the first to the last trade close.
+/// Weekdays without trades count as flat days; weekends only appear if
+/// something was traded on them. Returns `None` without close timestamps.
+fn daily_pnl_series(trades: &[Trade]) -> Option<Vec<f64>> {
+    let mut pnl_by_day: HashMap<i64, f64> = HashMap::new();
+    for trade in trades {
+        *pnl_by_day.entry(trade_day(trade)?).or_insert(0.0) += trade.profit;
     }
 
-    // Find the largest loss
-    let largest_loss = trades.iter()
-        .map(|t| t.profit)
-        .filter(|&p| p < 0.0)
-        .min_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap_or(-1.0)
-        .abs();
+    let first = *pnl_by_day.keys().min()?;
+    let last = *pnl_by_day.keys().max()?;
 
-    if largest_loss == 0.0 {
-        return Ok(0.0); // No losses, no risk management needed
+    Some(
+        (first..=last)
+            .filter_map(|day| match pnl_by_day.get(&day) {
+                Some(&pnl) => Some(pnl),
+                None if is_weekend(day) => None,
+                None => Some(0.0),
+            })
+            .collect(),
+    )
+}
+
+/// Daily returns as a fraction of start-of-day equity when an initial
+/// balance is known, otherwise the raw daily P&L.
+fn daily_returns(trades: &[Trade], initial_balance: Option<f64>) -> Option<Vec<f64>> {
+    let pnl = daily_pnl_series(trades)?;
+    let Some(balance) = initial_balance else {
+        return Some(pnl);
+    };
+
+    let mut equity = balance;
+    let mut returns = Vec::with_capacity(pnl.len());
+    for day_pnl in pnl {
+        if equity <= 0.0 {
+            break;
+        }
+        returns.push(day_pnl / equity);
+        equity += day_pnl;
+    }
+    Some(returns)
+}
+
+/// Mean and sample standard deviation.
+fn mean_and_std(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance.sqrt())
+}
+
+/// Annualized Sharpe ratio of a daily return series. `risk_free_rate` is an
+/// annual rate, spread evenly over the trading days of a year.
+fn sharpe_ratio(daily_returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    if daily_returns.len() < 2 {
+        return None;
+    }
+    let daily_risk_free = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    let excess: Vec<f64> = daily_returns.iter().map(|r| r - daily_risk_free).collect();
+    let (mean, std) = mean_and_std(&excess);
+    if std == 0.0 {
+        return None;
     }
+    Some(mean / std * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// Annualized Sortino ratio: excess return over the downside deviation,
+/// where only returns below the daily risk-free rate count as risk.
+fn sortino_ratio(daily_returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    if daily_returns.len() < 2 {
+        return None;
+    }
+    let daily_risk_free = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    let n = daily_returns.len() as f64;
+    let mean_excess = daily_returns.iter().map(|r| r - daily_risk_free).sum::<f64>() / n;
+    let downside_deviation = (daily_returns
+        .iter()
+        .map(|r| (r - daily_risk_free).min(0.0).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    if downside_deviation == 0.0 {
+        return None;
+    }
+    Some(mean_excess / downside_deviation * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// Calmar ratio only looks at the most recent 36 months of trading days.
+const CALMAR_WINDOW_DAYS: usize = 36 * 21;
+
+/// Trading days in a month, for converting funded horizons in months.
+const TRADING_DAYS_PER_MONTH: u32 = 21;
+
+/// Van Tharp caps the trade count in the SQN so large samples do not inflate it.
+const SQN_MAX_TRADES: f64 = 100.0;
 
-    // Grid search for optimal f
-    let mut best_f = 0.0;
-    let mut best_twr = f64::NEG_INFINITY;
+/// Confidence and Kelly-fraction margin behind `additional_trades_needed`.
+const SAMPLE_SIZE_CONFIDENCE: f64 = 0.95;
+const SAMPLE_SIZE_MARGIN: f64 = 0.1;
 
-    for i in 0..1000 {
-        let f = (i as f64) / 10000.0; // f from 0.000 to 0.100
+/// Compound annual growth rate divided by the maximum percentage drawdown of
+/// a daily P&L series starting from `start_equity`. This is the MAR ratio
+/// over the full series and the Calmar ratio over its last 36 months.
+fn return_over_drawdown(daily_pnl: &[f64], start_equity: f64) -> Option<f64> {
+    if daily_pnl.is_empty() || start_equity <= 0.0 {
+        return None;
+    }
+
+    let mut equity = start_equity;
+    let mut peak = start_equity;
+    let mut max_drawdown = 0.0_f64;
+    for pnl in daily_pnl {
+        equity += pnl;
+        peak = peak.max(equity);
+        max_drawdown = max_drawdown.max((peak - equity) / peak);
+    }
+    if equity <= 0.0 || max_drawdown == 0.0 {
+        return None;
+    }
 
-        let twr: f64 = trades.iter()
-            .map(|trade| 1.0 + f * (-trade.profit / largest_loss))
-            .product();
+    let years = daily_pnl.len() as f64 / TRADING_DAYS_PER_YEAR;
+    let cagr = (equity / start_equity).powf(1.0 / years) - 1.0;
+    Some(cagr / max_drawdown)
+}
 
-        if twr > best_twr {
-            best_twr = twr;
-            best_f = f;
+/// Sample covariance matrix of equally long series.
+fn covariance_matrix(series: &[&[f64]], means: &[f64]) -> Vec<Vec<f64>> {
+    let n = series[0].len() as f64;
+    let mut covariance = vec![vec![0.0; series.len()]; series.len()];
+    for i in 0..series.len() {
+        for j in i..series.len() {
+            let cov = series[i]
+                .iter()
+                .zip(series[j])
+                .map(|(a, b)| (a - means[i]) * (b - means[j]))
+                .sum::<f64>()
+                / (n - 1.0);
+            covariance[i][j] = cov;
+            covariance[j][i] = cov;
         }
     }
+    covariance
+}
 
-    // Refine with gradient ascent
-    let mut f = best_f;
-    let learning_rate = 0.001;
+/// Solves `a · x = b` by Gaussian elimination with partial pivoting.
+/// Returns `None` when the matrix is (numerically) singular.
+fn solve_linear_system(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    let scale = a.iter().flatten().fold(0.0_f64, |m, v| m.max(v.abs()));
+    if scale == 0.0 {
+        return None;
+    }
 
-    for _ in 0..max_iterations {
-        let mut gradient = 0.0;
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() <= scale * 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
 
-        for trade in &trades {
-            let term = 1.0 + f * (-trade.profit / largest_loss);
-            if term > 0.0 {
-                gradient += (-trade.profit / largest_loss) / term;
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot_value) in a[row].iter_mut().zip(&pivot_row).skip(col) {
+                *value -= factor * pivot_value;
             }
+            b[row] -= factor * b[col];
         }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Median of an ascending-sorted slice, 0 when empty.
+fn median(sorted: &[f64]) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Linearly interpolated percentile (`p` in 0..=100) of an ascending-sorted slice.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
 
-        let twr: f64 = trades.iter()
-            .map(|trade| 1.0 + f * (-trade.profit / largest_loss))
-            .product();
+/// Natural log of the gamma function (Lanczos approximation, x > 0).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series: f64 = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
 
-        gradient *= twr;
+/// Regularized incomplete beta function I_x(a, b), the Beta(a, b) CDF,
+/// evaluated with Lentz's continued fraction.
+fn regularized_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x > (a + 1.0) / (a + b + 2.0) {
+        return 1.0 - regularized_beta(1.0 - x, b, a);
+    }
 
-        let new_f = f + learning_rate * gradient;
-        if new_f < 0.0 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    d = 1.0 / if d.abs() < TINY { TINY } else { d };
+    let mut fraction = d;
+    for m in 1..=200 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            d = 1.0 / if d.abs() < TINY { TINY } else { d };
+            c = 1.0 + numerator / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            fraction *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
             break;
         }
+    }
+    front * fraction / a
+}
 
-        let new_twr: f64 = trades.iter()
-            .map(|trade| 1.0 + new_f * (-trade.profit / largest_loss))
-            .product();
+/// Upper tail probability P(T > t) of Student's t with `df` degrees of freedom.
+fn student_t_upper_tail(t: f64, df: f64) -> f64 {
+    let tail = 0.5 * regularized_beta(df / (df + t * t), df / 2.0, 0.5);
+    if t >= 0.0 {
+        tail
+    } else {
+        1.0 - tail
+    }
+}
 
-        if (new_twr - twr).abs() < tolerance {
-            f = new_f;
-            break;
+/// Quantile `q` (0..1) of the standard normal distribution, found by bisection.
+fn normal_quantile(q: f64) -> f64 {
+    let (mut lower, mut upper) = (-10.0, 10.0);
+    for _ in 0..100 {
+        let mid = (lower + upper) / 2.0;
+        if 0.5 * erfc(-mid / std::f64::consts::SQRT_2) < q {
+            lower = mid;
+        } else {
+            upper = mid;
         }
+    }
+    (lower + upper) / 2.0
+}
 
-        if new_twr > twr {
-            f = new_f;
+/// Quantile `q` (0..=1) of a Beta(a, b) distribution, found by bisection.
+fn beta_quantile(q: f64, a: f64, b: f64) -> f64 {
+    let (mut lower, mut upper) = (0.0, 1.0);
+    for _ in 0..100 {
+        let mid = (lower + upper) / 2.0;
+        if regularized_beta(mid, a, b) < q {
+            lower = mid;
         } else {
-            break;
+            upper = mid;
         }
     }
+    (lower + upper) / 2.0
+}
 
-    Ok(f)
+/// Beta prior on the win rate, written as a prior mean and a strength in
+/// pseudo-trades (alpha + beta). The default is the uniform Beta(1, 1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BetaPrior {
+    alpha: f64,
+    beta: f64,
 }
 
-#[pyfunction]
-fn run_monte_carlo_simulation(
-    trades: Vec<Trade>,
-    challenge_params: ChallengeParams,
-    risk_fraction: f64,
-    num_simulations: usize,
-) -> PyResult<HashMap<String, f64>> {
-    use rand::prelude::*;
-    use rayon::prelude::*;
+impl Default for BetaPrior {
+    fn default() -> Self {
+        BetaPrior { alpha: 1.0, beta: 1.0 }
+    }
+}
 
-    if trades.is_empty() {
-        return Err(PyValueError::new_err("No trades provided"));
+impl BetaPrior {
+    pub fn new(win_rate: f64, strength: f64) -> Result<Self> {
+        if win_rate <= 0.0 || win_rate >= 1.0 {
+            return Err(Error::Validation("Prior win rate must be between 0 and 1".into()));
+        }
+        if strength <= 0.0 {
+            return Err(Error::Validation("Prior strength must be positive".into()));
+        }
+        Ok(BetaPrior {
+            alpha: win_rate * strength,
+            beta: (1.0 - win_rate) * strength,
+        })
     }
 
-    let returns: Vec<f64> = trades.iter().map(|t| t.profit).collect();
-    let mut rng = rand::thread_rng();
+    /// Posterior parameters after `wins` out of `trials` trades.
+    fn posterior(&self, wins: usize, trials: usize) -> (f64, f64) {
+        (self.alpha + wins as f64, self.beta + (trials - wins) as f64)
+    }
 
-    let results: Vec<bool> = (0..num_simulations)
-        .into_par_iter()
-        .map(|_| {
-            // Bootstrap resampling
-            let mut simulation_returns = Vec::new();
-            for _ in 0..trades.len() {
-                let idx = rng.gen_range(0..returns.len());
-                simulation_returns.push(returns[idx]);
-            }
-
-            // Run simulation
-            let mut equity = challenge_params.account_size;
-            let mut peak_equity = equity;
-            let mut daily_pl = 0.0;
-            let mut passed = true;
-
-            for &ret in &simulation_returns {
-                let position_size = equity * risk_fraction;
-                let trade_pl = position_size * ret; // ret is already a profit/loss value
-                daily_pl += trade_pl;
-                equity += trade_pl;
-
-                // Check daily loss limit
-                if daily_pl / challenge_params.account_size < -challenge_params.max_daily_loss_percent / 100.0 {
-                    passed = false;
-                    break;
-                }
+    fn posterior_mean(&self, wins: usize, trials: usize) -> f64 {
+        let (alpha, beta) = self.posterior(wins, trials);
+        alpha / (alpha + beta)
+    }
+}
 
-                // Check overall loss limit
-                if equity < challenge_params.account_size * (1.0 - challenge_params.max_overall_loss_percent / 100.0) {
-                    passed = false;
-                    break;
-                }
+/// Median win over median loss, which is less sensitive to a single
+/// outsized trade than the ratio of averages. 0 when either side is empty.
+fn robust_win_loss_ratio(profits: &[f64]) -> f64 {
+    let mut wins: Vec<f64> = profits.iter().copied().filter(|&p| p > 0.0).collect();
+    let mut losses: Vec<f64> = profits.iter().filter(|&&p| p < 0.0).map(|p| p.abs()).collect();
+    wins.sort_by(f64::total_cmp);
+    losses.sort_by(f64::total_cmp);
 
-                // Check profit target
-                if equity >= challenge_params.account_size * (1.0 + challenge_params.profit_target_percent / 100.0) {
-                    break; // Success
-                }
+    let median_loss = median(&losses);
+    if median_loss != 0.0 {
+        median(&wins) / median_loss
+    } else {
+        0.0
+    }
+}
 
-                // Reset daily P&L at end of day (simplified)
-                if simulation_returns.len() > 100 { // Arbitrary day length
-                    daily_pl = 0.0;
-                }
-            }
+/// Full binary Kelly fraction (p - q / b) of a P&L sample using the robust
+/// win/loss ratio, or `None` unless the sample has both wins and losses.
+fn binary_kelly(profits: &[f64]) -> Option<f64> {
+    let wins = profits.iter().filter(|&&p| p > 0.0).count();
+    let losses = profits.iter().filter(|&&p| p < 0.0).count();
+    if wins == 0 || losses == 0 {
+        return None;
+    }
+    let win_prob = wins as f64 / profits.len() as f64;
+    let loss_prob = losses as f64 / profits.len() as f64;
+    Some(win_prob - loss_prob / robust_win_loss_ratio(profits))
+}
 
-            passed && equity >= challenge_params.account_size * (1.0 + challenge_params.profit_target_percent / 100.0)
-        })
-        .collect();
+/// Mean log growth `E[ln(1 + f * r)]` of a return sample; `-inf` once any
+/// outcome would wipe out the account.
+fn log_growth(returns: &[f64], f: f64) -> f64 {
+    let mut total = 0.0;
+    for r in returns {
+        let factor = 1.0 + f * r;
+        if factor <= 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        total += factor.ln();
+    }
+    total / returns.len() as f64
+}
+
+/// Maximizes `log_growth` over `f` in `[lower, upper]` by golden-section
+/// search. The growth is concave in `f`, so this finds the global maximum.
+/// Returns `(f, growth)`.
+fn maximize_log_growth(returns: &[f64], lower: f64, upper: f64, tolerance: f64) -> (f64, f64) {
+    let inv_phi = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut a, mut b) = (lower, upper);
+    let mut c = b - inv_phi * (b - a);
+    let mut d = a + inv_phi * (b - a);
+    let (mut gc, mut gd) = (log_growth(returns, c), log_growth(returns, d));
 
-    let pass_count = results.iter().filter(|&&p| p).count();
-    let pass_rate = pass_count as f64 / num_simulations as f64;
+    while b - a > tolerance {
+        if gc >= gd {
+            b = d;
+            d = c;
+            gd = gc;
+            c = b - inv_phi * (b - a);
+            gc = log_growth(returns, c);
+        } else {
+            a = c;
+            c = d;
+            gc = gd;
+            d = a + inv_phi * (b - a);
+            gd = log_growth(returns, d);
+        }
+    }
 
-    let mut result = HashMap::new();
-    result.insert("pass_rate".to_string(), pass_rate);
-    result.insert("total_simulations".to_string(), num_simulations as f64);
-    result.insert("passed_simulations".to_string(), pass_count as f64);
+    // The optimum may sit on the boundary, e.g. f = 0 without an edge
+    [(a + b) / 2.0, lower, upper]
+        .into_iter()
+        .map(|f| (f, log_growth(returns, f)))
+        .fold((lower, f64::NEG_INFINITY), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+}
 
-    Ok(result)
+/// SplitMix64 finalizer, used to turn nearby seeds into unrelated ones.
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
 }
 
-/// A Python module implemented in Rust.
-#[pymodule]
-fn risk_optima_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_class::<Trade>()?;
-    m.add_class::<PerformanceMetrics>()?;
-    m.add_class::<ChallengeParams>()?;
-    m.add_function(wrap_pyfunction!(parse_mt5_csv, m)?)?;
-    m.add_function(wrap_pyfunction!(parse_mt5_xml, m)?)?;
-    m.add_function(wrap_pyfunction!(calculate_performance_metrics, m)?)?;
-    m.add_function(wrap_pyfunction!(calculate_kelly_criterion, m)?)?;
-    m.add_function(wrap_pyfunction!(calculate_optimal_f, m)?)?;
-    m.add_function(wrap_pyfunction!(run_monte_carlo_simulation, m)?)?;
-    Ok(())
+/// Independent RNG stream for one simulation path, so results depend only
+/// on the seed and not on how rayon schedules the paths across threads.
+fn simulation_rng(seed: u64, index: u64) -> rand::rngs::StdRng {
+    use rand::SeedableRng;
+    rand::rngs::StdRng::seed_from_u64(splitmix64(seed ^ splitmix64(index)))
+}
+
+// Core computational functions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ParseWarning {
+    pub row: usize, // 1-based line of the file, counting the header
+    pub field: Option<String>, // None when the whole row is unusable
+    pub raw_value: String,
+    pub reason: String,
+    pub row_skipped: bool, // False when only an optional field was left empty
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ParseResult {
+    pub trades: Vec<Trade>,
+    pub warnings: Vec<ParseWarning>,
+    pub rows_read: usize,
+    pub rows_skipped: usize,
+    pub format: String, // e.g. "mt5_csv", "mt5_html"
+    pub encoding: String,
+    #[serde(default)]
+    pub operations: Vec<AccountOperation>, // Balance rows, kept out of the trades and the skipped rows
+}
+
+/// Kind of a non-trading balance change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum OperationKind {
+    Deposit,
+    Withdrawal,
+    Credit,     // Broker credit, which is not the trader's money
+    Adjustment, // Corrections, bonuses and charges
+}
+
+/// A deposit, withdrawal, credit or balance adjustment row of a report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct AccountOperation {
+    pub time: Option<i64>, // Unix epoch milliseconds
+    pub kind: OperationKind,
+    pub amount: f64, // Signed change of the balance
+    pub comment: Option<String>,
+}
+
+impl AccountOperation {
+    /// Recognises a balance row by its type column. MT5 writes deposits and
+    /// withdrawals alike as "balance", told apart by the sign of the amount.
+    /// A balance row whose amount could not be read is `Some(Err("amount"))`.
+    fn from_row(
+        raw_type: &str,
+        amount: Option<f64>,
+        time: Option<i64>,
+        comment: Option<&str>,
+    ) -> Option<Result<Self, &'static str>> {
+        let raw_type = raw_type.trim().to_ascii_lowercase();
+        let kind = match raw_type.as_str() {
+            "balance" | "deposit" => OperationKind::Deposit,
+            "withdrawal" | "withdraw" => OperationKind::Withdrawal,
+            "credit" => OperationKind::Credit,
+            "correction" | "adjustment" | "bonus" | "charge" => OperationKind::Adjustment,
+            _ => return None,
+        };
+        let Some(amount) = amount else {
+            return Some(Err("amount"));
+        };
+        let (kind, amount) = match kind {
+            OperationKind::Deposit if raw_type == "balance" && amount < 0.0 => (OperationKind::Withdrawal, amount),
+            OperationKind::Withdrawal => (kind, -amount.abs()),
+            _ => (kind, amount),
+        };
+        Some(Ok(AccountOperation {
+            time,
+            kind,
+            amount,
+            comment: comment.map(str::trim).filter(|c| !c.is_empty()).map(str::to_string),
+        }))
+    }
+}
+
+impl ParseResult {
+    fn from_trades(trades: Vec<Trade>, format: ReportFormat) -> Self {
+        ParseResult {
+            rows_read: trades.len(),
+            rows_skipped: 0,
+            trades,
+            warnings: Vec::new(),
+            format: format.as_str().to_string(),
+            encoding: "utf-8".to_string(),
+            operations: Vec::new(),
+        }
+    }
+}
+
+/// Reads the positional MT5 CSV layout. Rows with an unparseable required
+/// field are skipped; an unparseable optional field is left empty. Both are
+/// reported as warnings, which `parse_mt5_csv` turns into errors.
+fn read_mt5_csv(content: &str, allow_break_even: bool) -> Result<ParseResult> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let mut result = ParseResult {
+        trades: Vec::new(),
+        warnings: Vec::new(),
+        rows_read: 0,
+        rows_skipped: 0,
+        format: ReportFormat::Mt5Csv.as_str().to_string(),
+        encoding: "utf-8".to_string(),
+        operations: Vec::new(),
+    };
+
+    for (offset, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| Error::Parse(format!("CSV parsing error: {}", e)))?;
+        let row = offset + 2;
+        result.rows_read += 1;
+
+        // Skip section titles such as "Positions"
+        if record.get(0).unwrap_or("").contains("Positions") {
+            result.rows_skipped += 1;
+            continue;
+        }
+        let field = |index: usize| record.get(index).unwrap_or("").trim();
+        let time = parse_timestamp(field(9)).or_else(|| parse_timestamp(field(8)));
+        match AccountOperation::from_row(field(1), parse_number(field(5), None), time, None) {
+            Some(Ok(operation)) => {
+                result.operations.push(operation);
+                continue;
+            }
+            Some(Err(field_name)) => {
+                result.rows_skipped += 1;
+                result.warnings.push(ParseWarning {
+                    row,
+                    field: Some(field_name.to_string()),
+                    raw_value: field(5).to_string(),
+                    reason: "invalid number".to_string(),
+                    row_skipped: true,
+                });
+                continue;
+            }
+            None => {}
+        }
+
+        if record.len() < 8 {
+            result.rows_skipped += 1;
+            if record.iter().any(|field| !field.trim().is_empty()) {
+                result.warnings.push(ParseWarning {
+                    row,
+                    field: None,
+                    raw_value: record.iter().collect::<Vec<_>>().join(","),
+                    reason: format!("expected at least 8 columns, found {}", record.len()),
+                    row_skipped: true,
+                });
+            }
+            continue;
+        }
+
+        let mut problems = Vec::new();
+        let trade_type = read_csv_field(&record, 1, "type", true, normalize_trade_type, &mut problems);
+        let mut number = |index: usize, name: &str, required: bool| {
+            read_csv_field(&record, index, name, required, |raw| parse_number(raw, None), &mut problems)
+        };
+        let volume = number(2, "volume", true);
+        let open_price = number(3, "open price", true);
+        let close_price = number(4, "close price", true);
+        let profit = number(5, "profit", true);
+        let commission = number(6, "commission", false);
+        let swap = number(7, "swap", false);
+        let open_time = read_csv_field(&record, 8, "open time", false, parse_timestamp, &mut problems);
+        let close_time = read_csv_field(&record, 9, "close time", false, parse_timestamp, &mut problems);
+
+        let skip = problems.iter().any(|(_, _, required)| *required);
+        for (field, raw_value, required) in problems {
+            let reason = match field.as_str() {
+                "type" => "invalid trade type",
+                field if field.ends_with("time") => "invalid timestamp",
+                _ => "invalid number",
+            };
+            result.warnings.push(ParseWarning {
+                row,
+                field: Some(field),
+                raw_value,
+                reason: reason.to_string(),
+                row_skipped: required,
+            });
+        }
+
+        match (trade_type, volume, open_price, close_price, profit) {
+            (Some(trade_type), Some(volume), Some(open_price), Some(close_price), Some(profit)) if !skip => {
+                let trade = Trade {
+                    symbol: record.get(0).unwrap_or("").to_string(),
+                    trade_type,
+                    volume,
+                    open_price,
+                    close_price,
+                    profit,
+                    commission,
+                    swap,
+                    open_time,
+                    close_time,
+                    risk_amount: None,
+                    tag: None,
+                    max_favorable_excursion: None,
+                    max_adverse_excursion: None,
+                    ticket: None,
+                };
+                match trade.check(allow_break_even) {
+                    Ok(()) => result.trades.push(trade),
+                    Err(reason) => {
+                        result.rows_skipped += 1;
+                        result.warnings.push(ParseWarning {
+                            row,
+                            field: Some("trade".to_string()),
+                            raw_value: record.iter().collect::<Vec<_>>().join(","),
+                            reason,
+                            row_skipped: true,
+                        });
+                    }
+                }
+            }
+            _ => result.rows_skipped += 1,
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parses one CSV field, noting `(field, raw value, required)` in `problems`
+/// when a present (or required) value cannot be parsed.
+fn read_csv_field<T>(
+    record: &csv::StringRecord,
+    index: usize,
+    name: &str,
+    required: bool,
+    parse: impl Fn(&str) -> Option<T>,
+    problems: &mut Vec<(String, String, bool)>,
+) -> Option<T> {
+    let raw = record.get(index).unwrap_or("").trim();
+    if raw.is_empty() && !required {
+        return None;
+    }
+    let value = parse(raw);
+    if value.is_none() {
+        problems.push((name.to_string(), raw.to_string(), required));
+    }
+    value
+}
+
+/// Reads an MT5 CSV export. `allow_break_even` admits trades closed at
+/// their open price, which every parser rejects by default.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, allow_break_even=false)))]
+pub fn parse_mt5_csv(content: &str, allow_break_even: bool) -> Result<Vec<Trade>> {
+    let result = read_mt5_csv(content, allow_break_even)?;
+
+    // Short rows are tolerated, but a broken value is an error, even in an optional field
+    let broken = result.warnings.iter().find(|w| w.field.is_some());
+    if let Some(warning) = broken {
+        return Err(Error::Parse(match warning.field.as_deref() {
+            Some("trade") => format!("Invalid MT5 CSV format: row {}: {}", warning.row, warning.reason),
+            field => format!(
+                "Invalid MT5 CSV format: row {} has an invalid {} value",
+                warning.row,
+                field.unwrap_or_default()
+            ),
+        }));
+    }
+
+    Ok(result.trades)
+}
+
+/// Like `parse_mt5_csv`, but skips broken rows, leaves unparseable optional
+/// fields empty and reports both as warnings.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, allow_break_even=false)))]
+pub fn parse_mt5_csv_detailed(content: &str, allow_break_even: bool) -> Result<ParseResult> {
+    read_mt5_csv(content, allow_break_even)
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, allow_break_even=false)))]
+pub fn parse_mt5_xml(content: &str, allow_break_even: bool) -> Result<Vec<Trade>> {
+    // MT5 exports its report as an Excel 2003 SpreadsheetML workbook
+    let rows = read_spreadsheet_rows(content)
+        .map_err(|e| Error::Parse(format!("XML parsing error: {}", e)))?;
+    Ok(read_mt5_report_rows(&rows, ReportFormat::Mt5Xml, allow_break_even)?.trades)
+}
+
+/// Text or raw bytes of a report file. MT5 writes its HTML reports as UTF-16.
+#[cfg_attr(feature = "python", derive(FromPyObject))]
+pub enum ReportContent {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, allow_break_even=false)))]
+pub fn parse_mt5_html(content: ReportContent, allow_break_even: bool) -> Result<Vec<Trade>> {
+    let content = match content {
+        ReportContent::Text(text) => text,
+        ReportContent::Bytes(bytes) => decode_report_bytes(&bytes).0,
+    };
+    Ok(read_mt5_report_rows(&read_html_rows(&content), ReportFormat::Mt5Html, allow_break_even)?.trades)
+}
+
+/// Reads the trades of an MT5 HTML or XML report from its "Positions"
+/// section, or for HTML from its "Deals" section when there is none, and
+/// the balance operations listed among the deals. Positions netted without
+/// their entry fills open at their close price, so they count as break-even.
+fn read_mt5_report_rows(rows: &[Vec<String>], format: ReportFormat, allow_break_even: bool) -> Result<ParseResult> {
+    let name = if format == ReportFormat::Mt5Xml { "XML" } else { "HTML" };
+    let (trades, operations) = match positions_from_rows(rows, name, allow_break_even)? {
+        // The deals only matter for their balance rows here, so a Deals table that does not parse is ignored
+        Some(trades) => (trades, deals_from_rows(rows, name).ok().flatten().map(|(_, ops)| ops).unwrap_or_default()),
+        None if format == ReportFormat::Mt5Html => {
+            let (deals, operations) = deals_from_rows(rows, name)?.ok_or_else(|| {
+                Error::Parse("Invalid MT5 HTML format: neither Positions nor Deals section found".into())
+            })?;
+            let trades = aggregate_deals(deals);
+            for trade in &trades {
+                trade.check(allow_break_even).map_err(|reason| {
+                    let netted = format!("{} trade netted from the Deals", trade.symbol);
+                    Error::Parse(format!("Invalid MT5 HTML format: {}: {}", netted, reason))
+                })?;
+            }
+            (trades, operations)
+        }
+        None => return Err(Error::Parse(format!("Invalid MT5 {} format: Positions section not found", name))),
+    };
+    Ok(ParseResult { operations, ..ParseResult::from_trades(trades, format) })
+}
+
+/// Reads the individual fills of the "Deals" section of an MT5 HTML or XML
+/// report, in report order. Balance operations and the totals line are
+/// skipped. `aggregate_deals` nets the fills into round-trip trades.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn parse_mt5_deals(content: ReportContent) -> Result<Vec<Deal>> {
+    let content = match content {
+        ReportContent::Text(text) => text,
+        ReportContent::Bytes(bytes) => decode_report_bytes(&bytes).0,
+    };
+    let content = content.trim_start_matches('\u{feff}');
+    let (rows, format) = match ReportFormat::detect(content) {
+        ReportFormat::Mt5Xml => (
+            read_spreadsheet_rows(content).map_err(|e| Error::Parse(format!("XML parsing error: {}", e)))?,
+            "XML",
+        ),
+        _ => (read_html_rows(content), "HTML"),
+    };
+    deals_from_rows(&rows, format)?
+        .map(|(deals, _)| deals)
+        .ok_or_else(|| Error::Parse(format!("Invalid MT5 {} format: Deals section not found", format)))
+}
+
+/// Report layouts that `parse_report` recognises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    Mt5Csv,
+    Mt5Xml,
+    Mt5Html,
+    CTraderCsv,
+}
+
+impl ReportFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReportFormat::Mt5Csv => "mt5_csv",
+            ReportFormat::Mt5Xml => "mt5_xml",
+            ReportFormat::Mt5Html => "mt5_html",
+            ReportFormat::CTraderCsv => "ctrader_csv",
+        }
+    }
+
+    /// Guesses the layout from the start of the document and its CSV header.
+    fn detect(content: &str) -> Self {
+        let head: String = content.trim_start().chars().take(4096).collect::<String>().to_ascii_lowercase();
+        if head.contains("<workbook") {
+            return ReportFormat::Mt5Xml;
+        }
+        if head.contains("<html") || head.contains("<table") {
+            return ReportFormat::Mt5Html;
+        }
+        if head.starts_with("<?xml") {
+            return ReportFormat::Mt5Xml;
+        }
+        let header = head.lines().next().unwrap_or("");
+        if header.contains("opening direction") || (header.contains("closing price") && header.contains("net ")) {
+            return ReportFormat::CTraderCsv;
+        }
+        ReportFormat::Mt5Csv
+    }
+}
+
+/// Detects the encoding (for bytes) and layout of a report and parses it.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, allow_break_even=false)))]
+pub fn parse_report(content: ReportContent, allow_break_even: bool) -> Result<ParseResult> {
+    let (text, encoding) = match content {
+        ReportContent::Text(text) => (text, "utf-8"),
+        ReportContent::Bytes(bytes) => decode_report_bytes(&bytes),
+    };
+    let text = text.trim_start_matches('\u{feff}');
+
+    let format = ReportFormat::detect(text);
+    let mut result = match format {
+        ReportFormat::Mt5Csv => read_mt5_csv(text, allow_break_even)?,
+        ReportFormat::Mt5Xml => read_mt5_report_rows(
+            &read_spreadsheet_rows(text).map_err(|e| Error::Parse(format!("XML parsing error: {}", e)))?,
+            format,
+            allow_break_even,
+        )?,
+        ReportFormat::Mt5Html => read_mt5_report_rows(&read_html_rows(text), format, allow_break_even)?,
+        ReportFormat::CTraderCsv => {
+            let (trades, operations) = read_ctrader_csv(text, allow_break_even)?;
+            ParseResult { operations, ..ParseResult::from_trades(trades, format) }
+        }
+    };
+    result.encoding = encoding.to_string();
+    Ok(result)
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (path, allow_break_even=false)))]
+pub fn parse_report_file(path: std::path::PathBuf, allow_break_even: bool) -> Result<ParseResult> {
+    let bytes = std::fs::read(&path)?;
+    parse_report(ReportContent::Bytes(bytes), allow_break_even)
+}
+
+/// Builds trades from an iterable of mappings, e.g. the output of
+/// `DataFrame.to_dict("records")`. See `Trade.from_dict` for the fields.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (records, allow_break_even=false))]
+fn trades_from_records(records: &Bound<'_, PyAny>, allow_break_even: bool) -> PyResult<Vec<Trade>> {
+    records
+        .iter()?
+        .enumerate()
+        .map(|(index, record)| Trade::from_record(&record?, index, allow_break_even))
+        .collect()
+}
+
+/// The inverse of `trades_from_records`, ready for `pandas.DataFrame(...)`.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn trades_to_records(py: Python<'_>, trades: Vec<Trade>) -> PyResult<Vec<Bound<'_, PyDict>>> {
+    trades.iter().map(|trade| trade.to_dict(py)).collect()
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, allow_break_even=false)))]
+pub fn parse_ctrader_csv(content: &str, allow_break_even: bool) -> Result<Vec<Trade>> {
+    Ok(read_ctrader_csv(content, allow_break_even)?.0)
+}
+
+/// Reads the trades of a cTrader history export, and apart from them the
+/// deposit and withdrawal rows.
+fn read_ctrader_csv(content: &str, allow_break_even: bool) -> Result<(Vec<Trade>, Vec<AccountOperation>)> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let header: Vec<String> = reader
+        .headers()
+        .map_err(|e| Error::Parse(format!("CSV parsing error: {}", e)))?
+        .iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    let columns = CTraderColumns::from_header(&header)?;
+
+    let mut trades = Vec::new();
+    let mut operations = Vec::new();
+    for (offset, result) in reader.records().enumerate() {
+        let record = result.map_err(|e| Error::Parse(format!("CSV parsing error: {}", e)))?;
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let row: Vec<String> = record.iter().map(|field| field.trim().to_string()).collect();
+        let invalid = |field: &str| {
+            Error::Parse(format!(
+                "Invalid cTrader CSV format: row {} has an invalid {} value",
+                offset + 2,
+                field
+            ))
+        };
+        if let Some(operation) = columns.to_operation(&row) {
+            operations.push(operation.map_err(invalid)?);
+            continue;
+        }
+        let trade = columns.to_trade(&row).map_err(invalid)?;
+        trade
+            .check(allow_break_even)
+            .map_err(|reason| Error::Parse(format!("Invalid cTrader CSV format: row {}: {}", offset + 2, reason)))?;
+        trades.push(trade);
+    }
+
+    Ok((trades, operations))
+}
+
+/// Column indices of a cTrader history export, resolved from its header.
+/// Money columns carry the account currency ("Net USD", "Gross EUR").
+struct CTraderColumns {
+    symbol: usize,
+    direction: usize,
+    volume: usize,
+    open_price: usize,
+    close_price: usize,
+    open_time: Option<usize>,
+    close_time: Option<usize>,
+    commission: Option<usize>,
+    swap: Option<usize>,
+    profit: CTraderProfit,
+    ticket: Option<usize>,
+}
+
+enum CTraderProfit {
+    Gross(usize),
+    Net(usize), // Net of commission and swap
+}
+
+impl CTraderColumns {
+    fn from_header(header: &[String]) -> Result<Self> {
+        let find = |names: &[&str]| {
+            header
+                .iter()
+                .position(|cell| names.iter().any(|name| cell.eq_ignore_ascii_case(name)))
+        };
+        let find_prefix = |prefix: &str| {
+            header.iter().position(|cell| {
+                cell.len() > prefix.len() && cell[..prefix.len()].eq_ignore_ascii_case(prefix)
+            })
+        };
+        let require = |names: &[&str]| {
+            find(names).ok_or_else(|| {
+                Error::Parse(format!("Invalid cTrader CSV format: column '{}' not found", names[0]))
+            })
+        };
+
+        let profit = match (find_prefix("Gross "), find_prefix("Net ")) {
+            (Some(gross), _) => CTraderProfit::Gross(gross),
+            (None, Some(net)) => CTraderProfit::Net(net),
+            (None, None) => {
+                return Err(Error::Parse(
+                    "Invalid cTrader CSV format: neither a Gross nor a Net profit column found".into(),
+                ))
+            }
+        };
+
+        Ok(CTraderColumns {
+            symbol: require(&["Symbol"])?,
+            direction: require(&["Opening Direction", "Direction", "Side"])?,
+            volume: require(&["Closing Quantity", "Quantity", "Volume"])?,
+            open_price: require(&["Entry Price", "Opening Price"])?,
+            close_price: require(&["Closing Price", "Exit Price"])?,
+            open_time: find(&["Opening Time", "Entry Time", "Open Time"]),
+            close_time: find(&["Closing Time", "Exit Time", "Close Time"]),
+            commission: find(&["Commission", "Commissions"]),
+            swap: find(&["Swap"]),
+            profit,
+            ticket: find(&["Position ID", "ID"]),
+        })
+    }
+
+    /// A balance row, recognised by an operation name in the direction column.
+    fn to_operation(&self, row: &[String]) -> Option<Result<AccountOperation, &'static str>> {
+        let cell = |index: usize| row.get(index).map(String::as_str).unwrap_or("");
+        let amount = match self.profit {
+            CTraderProfit::Gross(index) | CTraderProfit::Net(index) => parse_report_number(cell(index)),
+        };
+        let time = self.close_time.or(self.open_time).and_then(|i| parse_ctrader_timestamp(cell(i)));
+        AccountOperation::from_row(cell(self.direction), amount, time, None)
+    }
+
+    /// Builds a trade from a row. Like MT5, `profit` excludes commission and
+    /// swap, so it is rebuilt from the net result when there is no gross column.
+    fn to_trade(&self, row: &[String]) -> Result<Trade, &'static str> {
+        let cell = |index: usize| row.get(index).map(String::as_str).unwrap_or("");
+        let number = |index: usize, field: &'static str| parse_report_number(cell(index)).ok_or(field);
+        let optional = |index: Option<usize>, field: &'static str| match index.map(cell) {
+            Some(raw) if !raw.trim().is_empty() => parse_report_number(raw).map(Some).ok_or(field),
+            _ => Ok(None),
+        };
+        let time = |index: Option<usize>, field: &'static str| match index.map(cell) {
+            Some(raw) if !raw.is_empty() => parse_ctrader_timestamp(raw).map(Some).ok_or(field),
+            _ => Ok(None),
+        };
+
+        let symbol = cell(self.symbol);
+        if symbol.is_empty() {
+            return Err("symbol");
+        }
+        // Quantities read like "1.00 Lots"
+        let volume = cell(self.volume)
+            .trim_end_matches(|c: char| c.is_alphabetic() || c.is_whitespace())
+            .to_string();
+
+        let commission = optional(self.commission, "commission")?;
+        let swap = optional(self.swap, "swap")?;
+        let profit = match self.profit {
+            CTraderProfit::Gross(gross) => number(gross, "gross profit")?,
+            CTraderProfit::Net(net) => number(net, "net profit")? - commission.unwrap_or(0.0) - swap.unwrap_or(0.0),
+        };
+
+        Ok(Trade {
+            symbol: symbol.to_string(),
+            trade_type: normalize_trade_type(cell(self.direction)).ok_or("direction")?,
+            volume: parse_report_number(&volume).ok_or("volume")?,
+            open_price: number(self.open_price, "entry price")?,
+            close_price: number(self.close_price, "closing price")?,
+            profit,
+            commission,
+            swap,
+            open_time: time(self.open_time, "opening time")?,
+            close_time: time(self.close_time, "closing time")?,
+            risk_amount: None,
+            tag: None,
+            max_favorable_excursion: None,
+            max_adverse_excursion: None,
+            ticket: optional_ticket(self.ticket.map(cell)).ok_or("position ID")?,
+        })
+    }
+}
+
+/// cTrader prints times day first ("15/01/2024 10:30:00.123").
+fn parse_ctrader_timestamp(raw: &str) -> Option<i64> {
+    use chrono::NaiveDateTime;
+
+    ["%d/%m/%Y %H:%M:%S%.f", "%d/%m/%Y %H:%M", "%d.%m.%Y %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(raw.trim(), format).ok())
+        .map(|dt| dt.and_utc().timestamp_millis())
+        .or_else(|| parse_timestamp(raw))
+}
+
+/// A CSV column given by its header name or by its 0-based position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", derive(FromPyObject))]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+#[cfg(feature = "python")]
+impl IntoPy<PyObject> for ColumnRef {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            ColumnRef::Index(index) => index.into_py(py),
+            ColumnRef::Name(name) => name.into_py(py),
+        }
+    }
+}
+
+impl ColumnRef {
+    fn resolve(&self, header: Option<&[String]>) -> Result<usize> {
+        match (self, header) {
+            (ColumnRef::Index(index), _) => Ok(*index),
+            (ColumnRef::Name(name), Some(header)) => header
+                .iter()
+                .position(|cell| cell.trim().eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| Error::Parse(format!("Invalid CSV format: column '{}' not found", name))),
+            (ColumnRef::Name(name), None) => Err(Error::Parse(format!(
+                "Column '{}' is given by name but the schema has no header row",
+                name
+            ))),
+        }
+    }
+}
+
+/// Maps the columns of an arbitrary broker export onto `Trade` fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct CsvSchema {
+    pub symbol: ColumnRef,
+    pub trade_type: ColumnRef,
+    pub volume: ColumnRef,
+    pub open_price: ColumnRef,
+    pub close_price: ColumnRef,
+    pub profit: ColumnRef,
+    pub commission: Option<ColumnRef>,
+    pub swap: Option<ColumnRef>,
+    pub open_time: Option<ColumnRef>,
+    pub close_time: Option<ColumnRef>,
+    pub risk_amount: Option<ColumnRef>,
+    pub date_format: Option<String>, // chrono format, e.g. "%d/%m/%Y %H:%M"; None tries the usual layouts
+    pub decimal_separator: char,
+    pub delimiter: char,
+    pub has_header: bool,
+    #[serde(default)]
+    pub tag: Option<ColumnRef>,
+    #[serde(default)]
+    pub max_favorable_excursion: Option<ColumnRef>,
+    #[serde(default)]
+    pub max_adverse_excursion: Option<ColumnRef>,
+    #[serde(default)]
+    pub ticket: Option<ColumnRef>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CsvSchema {
+    #[new]
+    #[pyo3(signature = (
+        symbol=ColumnRef::Name("Symbol".to_string()),
+        trade_type=ColumnRef::Name("Type".to_string()),
+        volume=ColumnRef::Name("Volume".to_string()),
+        open_price=ColumnRef::Name("Open Price".to_string()),
+        close_price=ColumnRef::Name("Close Price".to_string()),
+        profit=ColumnRef::Name("Profit".to_string()),
+        commission=None,
+        swap=None,
+        open_time=None,
+        close_time=None,
+        risk_amount=None,
+        date_format=None,
+        decimal_separator='.',
+        delimiter=',',
+        has_header=true,
+        tag=None,
+        max_favorable_excursion=None,
+        max_adverse_excursion=None,
+        ticket=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        symbol: ColumnRef,
+        trade_type: ColumnRef,
+        volume: ColumnRef,
+        open_price: ColumnRef,
+        close_price: ColumnRef,
+        profit: ColumnRef,
+        commission: Option<ColumnRef>,
+        swap: Option<ColumnRef>,
+        open_time: Option<ColumnRef>,
+        close_time: Option<ColumnRef>,
+        risk_amount: Option<ColumnRef>,
+        date_format: Option<String>,
+        decimal_separator: char,
+        delimiter: char,
+        has_header: bool,
+        tag: Option<ColumnRef>,
+        max_favorable_excursion: Option<ColumnRef>,
+        max_adverse_excursion: Option<ColumnRef>,
+        ticket: Option<ColumnRef>,
+    ) -> Self {
+        Self::new(
+            symbol,
+            trade_type,
+            volume,
+            open_price,
+            close_price,
+            profit,
+            commission,
+            swap,
+            open_time,
+            close_time,
+            risk_amount,
+            date_format,
+            decimal_separator,
+            delimiter,
+            has_header,
+            tag,
+            max_favorable_excursion,
+            max_adverse_excursion,
+            ticket,
+        )
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl CsvSchema {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        symbol: ColumnRef,
+        trade_type: ColumnRef,
+        volume: ColumnRef,
+        open_price: ColumnRef,
+        close_price: ColumnRef,
+        profit: ColumnRef,
+        commission: Option<ColumnRef>,
+        swap: Option<ColumnRef>,
+        open_time: Option<ColumnRef>,
+        close_time: Option<ColumnRef>,
+        risk_amount: Option<ColumnRef>,
+        date_format: Option<String>,
+        decimal_separator: char,
+        delimiter: char,
+        has_header: bool,
+        tag: Option<ColumnRef>,
+        max_favorable_excursion: Option<ColumnRef>,
+        max_adverse_excursion: Option<ColumnRef>,
+        ticket: Option<ColumnRef>,
+    ) -> Self {
+        CsvSchema {
+            symbol,
+            trade_type,
+            volume,
+            open_price,
+            close_price,
+            profit,
+            commission,
+            swap,
+            open_time,
+            close_time,
+            risk_amount,
+            date_format,
+            decimal_separator,
+            delimiter,
+            has_header,
+            tag,
+            max_favorable_excursion,
+            max_adverse_excursion,
+            ticket,
+        }
+    }
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (content, schema, allow_break_even=false)))]
+pub fn parse_csv_with_schema(content: &str, schema: &CsvSchema, allow_break_even: bool) -> Result<Vec<Trade>> {
+    if !schema.delimiter.is_ascii() {
+        return Err(Error::Validation("CSV delimiter must be an ASCII character".into()));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(schema.delimiter as u8)
+        .has_headers(schema.has_header)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let header: Option<Vec<String>> = match schema.has_header {
+        true => Some(
+            reader
+                .headers()
+                .map_err(|e| Error::Parse(format!("CSV parsing error: {}", e)))?
+                .iter()
+                .map(str::to_string)
+                .collect(),
+        ),
+        false => None,
+    };
+    let header = header.as_deref();
+    let optional = |column: &Option<ColumnRef>| column.as_ref().map(|c| c.resolve(header)).transpose();
+
+    let symbol = schema.symbol.resolve(header)?;
+    let trade_type = schema.trade_type.resolve(header)?;
+    let volume = schema.volume.resolve(header)?;
+    let open_price = schema.open_price.resolve(header)?;
+    let close_price = schema.close_price.resolve(header)?;
+    let profit = schema.profit.resolve(header)?;
+    let commission = optional(&schema.commission)?;
+    let swap = optional(&schema.swap)?;
+    let open_time = optional(&schema.open_time)?;
+    let close_time = optional(&schema.close_time)?;
+    let risk_amount = optional(&schema.risk_amount)?;
+    let tag = optional(&schema.tag)?;
+    let max_favorable_excursion = optional(&schema.max_favorable_excursion)?;
+    let max_adverse_excursion = optional(&schema.max_adverse_excursion)?;
+    let ticket = optional(&schema.ticket)?;
+
+    let first_row = if schema.has_header { 2 } else { 1 };
+    let mut trades = Vec::new();
+    for (offset, result) in reader.records().enumerate() {
+        let record = result.map_err(|e| Error::Parse(format!("CSV parsing error: {}", e)))?;
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let invalid = |field: &str| {
+            Error::Parse(format!(
+                "Invalid CSV format: row {} has an invalid {} value",
+                first_row + offset,
+                field
+            ))
+        };
+        let cell = |index: usize| record.get(index).unwrap_or("").trim();
+        let number = |index: usize| parse_number(cell(index), Some(schema.decimal_separator));
+        let optional_number = |index: Option<usize>, field: &str| match index.map(cell) {
+            Some(raw) if !raw.is_empty() => {
+                parse_number(raw, Some(schema.decimal_separator)).map(Some).ok_or_else(|| invalid(field))
+            }
+            _ => Ok(None),
+        };
+        let time = |index: Option<usize>, field: &str| match index.map(cell) {
+            Some(raw) if !raw.is_empty() => parse_timestamp_with_format(raw, schema.date_format.as_deref())
+                .map(Some)
+                .ok_or_else(|| invalid(field)),
+            _ => Ok(None),
+        };
+
+        // Balance rows are not trades
+        if AccountOperation::from_row(cell(trade_type), Some(0.0), None, None).is_some() {
+            continue;
+        }
+        if cell(symbol).is_empty() {
+            return Err(invalid("symbol"));
+        }
+        let trade = Trade {
+            symbol: cell(symbol).to_string(),
+            trade_type: normalize_trade_type(cell(trade_type)).ok_or_else(|| invalid("type"))?,
+            volume: number(volume).ok_or_else(|| invalid("volume"))?,
+            open_price: number(open_price).ok_or_else(|| invalid("open price"))?,
+            close_price: number(close_price).ok_or_else(|| invalid("close price"))?,
+            profit: number(profit).ok_or_else(|| invalid("profit"))?,
+            commission: optional_number(commission, "commission")?,
+            swap: optional_number(swap, "swap")?,
+            open_time: time(open_time, "open time")?,
+            close_time: time(close_time, "close time")?,
+            risk_amount: optional_number(risk_amount, "risk amount")?,
+            tag: tag.map(cell).filter(|t| !t.is_empty()).map(str::to_string),
+            max_favorable_excursion: optional_number(max_favorable_excursion, "max favorable excursion")?.map(f64::abs),
+            max_adverse_excursion: optional_number(max_adverse_excursion, "max adverse excursion")?.map(f64::abs),
+            ticket: ticket
+                .map(cell)
+                .filter(|t| !t.is_empty())
+                .map(|t| t.parse().map_err(|_| invalid("ticket")))
+                .transpose()?,
+        };
+        trade
+            .check(allow_break_even)
+            .map_err(|reason| Error::Parse(format!("Invalid CSV format: row {}: {}", first_row + offset, reason)))?;
+        trades.push(trade);
+    }
+
+    Ok(trades)
+}
+
+/// Parses a timestamp with an explicit chrono format (date-only formats are
+/// taken as midnight), falling back to the layouts `parse_timestamp` knows.
+fn parse_timestamp_with_format(raw: &str, format: Option<&str>) -> Option<i64> {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    let Some(format) = format else {
+        return parse_timestamp(raw);
+    };
+    NaiveDateTime::parse_from_str(raw, format)
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(raw, format).ok().and_then(|d| d.and_hms_opt(0, 0, 0)))
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+/// Index of the single-cell title row that opens a report section.
+fn find_section(rows: &[Vec<String>], title: &str) -> Option<usize> {
+    rows.iter()
+        .position(|row| row.iter().any(|cell| cell == title) && non_empty_cells(row) == 1)
+}
+
+/// Reads the trades of the report's "Positions" section, which starts with
+/// a single "Positions" title row followed by the column header row, and
+/// ends at the next section title or the totals line. Returns `None` when
+/// the report has no such section.
+fn positions_from_rows(rows: &[Vec<String>], format: &str, allow_break_even: bool) -> Result<Option<Vec<Trade>>> {
+    let Some(section_start) = find_section(rows, "Positions") else {
+        return Ok(None);
+    };
+
+    let header = rows.get(section_start + 1).ok_or_else(|| {
+        Error::Parse(format!("Invalid MT5 {} format: Positions header row missing", format))
+    })?;
+    let columns = PositionColumns::from_header(header, format)?;
+
+    let mut trades = Vec::new();
+    for (offset, row) in rows[section_start + 2..].iter().enumerate() {
+        if non_empty_cells(row) <= 1 || row.get(columns.symbol).is_none_or(|s| s.is_empty()) {
+            break;
+        }
+
+        let row_number = section_start + 3 + offset;
+        let trade = columns.to_trade(row).map_err(|field| {
+            Error::Parse(format!("Invalid MT5 {} format: row {} has an invalid {} value", format, row_number, field))
+        })?;
+        trade
+            .check(allow_break_even)
+            .map_err(|reason| Error::Parse(format!("Invalid MT5 {} format: row {}: {}", format, row_number, reason)))?;
+        trades.push(trade);
+    }
+
+    Ok(Some(trades))
+}
+
+/// Whether a deal opens, closes or reverses a position, from the report's
+/// "Direction" column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum DealEntry {
+    In,
+    Out,
+    InOut, // Netting reversal: closes the position and opens the opposite one
+    OutBy, // Closed by an opposite position
+}
+
+impl DealEntry {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "in" => Some(DealEntry::In),
+            "out" => Some(DealEntry::Out),
+            "in/out" => Some(DealEntry::InOut),
+            "out by" => Some(DealEntry::OutBy),
+            _ => None,
+        }
+    }
+}
+
+/// One fill from the "Deals" section of an MT5 report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct Deal {
+    pub time: Option<i64>, // Unix epoch milliseconds
+    pub ticket: Option<u64>, // Of the deal itself
+    pub position: Option<u64>, // ID of the position the fill opens or closes, when the report shows it
+    pub order: Option<u64>, // Ticket of the order that placed the fill
+    pub symbol: String,
+    pub trade_type: TradeType, // Side of the fill; a sell deal closes a long position
+    pub entry: DealEntry,
+    pub volume: f64,
+    pub price: f64,
+    pub commission: Option<f64>, // Including the report's separate fee column
+    pub swap: Option<f64>,
+    pub profit: f64,
+}
+
+/// Reads the fills and the balance operations of the report's "Deals"
+/// section, skipping the totals line. Returns `None` when the report has no
+/// such section.
+fn deals_from_rows(rows: &[Vec<String>], format: &str) -> Result<Option<(Vec<Deal>, Vec<AccountOperation>)>> {
+    let Some(section_start) = find_section(rows, "Deals") else {
+        return Ok(None);
+    };
+    let header = rows
+        .get(section_start + 1)
+        .ok_or_else(|| Error::Parse(format!("Invalid MT5 {} format: Deals header row missing", format)))?;
+    let column = |name: &str| header.iter().position(|cell| cell.eq_ignore_ascii_case(name));
+    let require = |name: &str| {
+        column(name).ok_or_else(|| {
+            Error::Parse(format!("Invalid MT5 {} format: Deals column '{}' not found", format, name))
+        })
+    };
+    let (time, symbol, deal_type) = (require("Time")?, require("Symbol")?, require("Type")?);
+    let (direction, volume) = (require("Direction")?, require("Volume")?);
+    let (price, profit) = (require("Price")?, require("Profit")?);
+    let (ticket, commission, fee, swap) = (column("Deal"), column("Commission"), column("Fee"), column("Swap"));
+    let (position, order) = (column("Position"), column("Order"));
+    let comment = column("Comment");
+
+    let mut deals = Vec::new();
+    let mut operations = Vec::new();
+    for (offset, row) in rows[section_start + 2..].iter().enumerate() {
+        if non_empty_cells(row) <= 1 {
+            break;
+        }
+        let cell = |index: usize| row.get(index).map(String::as_str).unwrap_or("");
+        let invalid = |field: &str| {
+            Error::Parse(format!(
+                "Invalid MT5 {} format: row {} has an invalid {} value",
+                format,
+                section_start + 3 + offset,
+                field
+            ))
+        };
+
+        let amount = parse_report_number(cell(profit));
+        let operation =
+            AccountOperation::from_row(cell(deal_type), amount, parse_timestamp(cell(time)), comment.map(cell));
+        if let Some(operation) = operation {
+            operations.push(operation.map_err(invalid)?);
+            continue;
+        }
+        let (Some(side), Some(entry)) = (normalize_trade_type(cell(deal_type)), DealEntry::parse(cell(direction)))
+        else {
+            continue;
+        };
+        if cell(symbol).is_empty() {
+            continue;
+        }
+        let optional = |index: Option<usize>, field: &str| match index.map(cell) {
+            Some(raw) if !raw.trim().is_empty() => parse_report_number(raw).map(Some).ok_or_else(|| invalid(field)),
+            _ => Ok(None),
+        };
+        let charges: Vec<f64> =
+            [optional(commission, "commission")?, optional(fee, "fee")?].into_iter().flatten().collect();
+        deals.push(Deal {
+            time: parse_timestamp(cell(time)),
+            ticket: optional_ticket(ticket.map(cell)).ok_or_else(|| invalid("deal"))?,
+            position: optional_ticket(position.map(cell)).ok_or_else(|| invalid("position"))?,
+            order: optional_ticket(order.map(cell)).ok_or_else(|| invalid("order"))?,
+            symbol: cell(symbol).to_string(),
+            trade_type: side,
+            entry,
+            volume: parse_report_number(cell(volume)).ok_or_else(|| invalid("volume"))?,
+            price: parse_report_number(cell(price)).ok_or_else(|| invalid("price"))?,
+            commission: (!charges.is_empty()).then(|| charges.iter().sum()),
+            swap: optional(swap, "swap")?,
+            profit: parse_report_number(cell(profit)).ok_or_else(|| invalid("profit"))?,
+        });
+    }
+
+    Ok(Some((deals, operations)))
+}
+
+/// Fills of one open position while `aggregate_deals` nets them.
+struct NettedPosition {
+    open_volume: f64,
+    entry_volume: f64,
+    entry_value: f64, // Sum of volume * price over the entry fills
+    exit_volume: f64,
+    exit_value: f64,
+    profit: f64,
+    commission: Option<f64>,
+    swap: Option<f64>,
+    open_time: Option<i64>,
+    close_time: Option<i64>,
+    ticket: Option<u64>, // Position ID, as in a Positions report of the same trade
+}
+
+impl NettedPosition {
+    fn new(time: Option<i64>) -> Self {
+        NettedPosition {
+            open_volume: 0.0,
+            entry_volume: 0.0,
+            entry_value: 0.0,
+            exit_volume: 0.0,
+            exit_value: 0.0,
+            profit: 0.0,
+            commission: None,
+            swap: None,
+            open_time: time,
+            close_time: None,
+            ticket: None,
+        }
+    }
+
+    fn charge(&mut self, deal: &Deal) {
+        let add = |total: Option<f64>, amount: Option<f64>| match (total, amount) {
+            (Some(total), Some(amount)) => Some(total + amount),
+            (total, amount) => total.or(amount),
+        };
+        self.profit += deal.profit;
+        self.commission = add(self.commission, deal.commission);
+        self.swap = add(self.swap, deal.swap);
+    }
+
+    fn into_trade(self, symbol: &str, trade_type: TradeType) -> Trade {
+        let close_price = self.exit_value / self.exit_volume;
+        Trade {
+            symbol: symbol.to_string(),
+            trade_type,
+            volume: if self.entry_volume > 0.0 { self.entry_volume } else { self.exit_volume },
+            // A position opened before the report starts has no entry fills
+            open_price: if self.entry_volume > 0.0 { self.entry_value / self.entry_volume } else { close_price },
+            close_price,
+            profit: self.profit,
+            commission: self.commission,
+            swap: self.swap,
+            open_time: self.open_time,
+            close_time: self.close_time,
+            risk_amount: None,
+            tag: None,
+            max_favorable_excursion: None,
+            max_adverse_excursion: None,
+            ticket: self.ticket,
+        }
+    }
+}
+
+/// Nets fills into round-trip trades. Fills with a position ID belong to
+/// that position, so the concurrent positions of a hedging account stay
+/// separate; without one, each symbol and direction is one position.
+/// Entries add to a position, exits reduce it, and a trade is recorded
+/// once it is flat again, at the volume-weighted entry and exit prices
+/// with the profit, commission and swap of all its fills. A reversal closes the position and opens the
+/// opposite one with the remaining volume. Positions still open at the end
+/// are left out. Trades are ordered by the deal that closed them.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn aggregate_deals(deals: Vec<Deal>) -> Vec<Trade> {
+    let mut netting = DealNetting::default();
+    deals.iter().filter_map(|deal| netting.add(deal)).collect()
+}
+
+/// The open positions of `aggregate_deals`, kept between fills so that
+/// deals can also be netted one at a time as they arrive.
+#[derive(Default)]
+struct DealNetting {
+    positions: HashMap<(PositionKey, TradeType), NettedPosition>, // By position and side
+}
+
+/// The position a fill belongs to: its position ID when the deal carries
+/// one, so concurrent positions of a hedging account stay apart, or else
+/// its symbol, as on a netting account.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum PositionKey {
+    Id(u64),
+    Symbol(String),
+}
+
+impl DealNetting {
+    /// Books one fill and returns the trade it completes, if any.
+    fn add(&mut self, deal: &Deal) -> Option<Trade> {
+        const FLAT: f64 = 1e-9;
+
+        let side = deal.trade_type.opposite();
+        let position_key = match deal.position {
+            Some(id) => PositionKey::Id(id),
+            None => PositionKey::Symbol(deal.symbol.clone()),
+        };
+        let key = (position_key.clone(), side);
+        // A reversal keeps the position ID of the position it closes
+        let reversed = match deal.entry {
+            DealEntry::InOut => self.positions.get(&key).and_then(|p| p.ticket),
+            _ => None,
+        };
+        let closed = match deal.entry {
+            DealEntry::In => 0.0,
+            // Only the open volume is closed; the rest opens the opposite position
+            DealEntry::InOut => self.positions.get(&key).map_or(0.0, |p| p.open_volume.min(deal.volume)),
+            DealEntry::Out | DealEntry::OutBy => deal.volume,
+        };
+        let mut trade = None;
+        if closed > 0.0 {
+            let position = self.positions.entry(key.clone()).or_insert_with(|| NettedPosition::new(None));
+            position.open_volume -= closed;
+            position.exit_volume += closed;
+            position.exit_value += closed * deal.price;
+            position.close_time = deal.time;
+            position.ticket = position.ticket.or(deal.position);
+            position.charge(deal);
+            if position.open_volume <= FLAT {
+                trade = self.positions.remove(&key).map(|position| position.into_trade(&deal.symbol, side));
+            }
+        }
+
+        let opened = deal.volume - closed;
+        if opened > FLAT {
+            let position = self
+                .positions
+                .entry((position_key, deal.trade_type))
+                .or_insert_with(|| NettedPosition::new(deal.time));
+            // MT5 gives a new position the ticket of the order that opened it
+            let opening_order = deal.order.filter(|_| deal.entry == DealEntry::In);
+            position.ticket = position.ticket.or(deal.position).or(reversed).or(opening_order);
+            position.open_volume += opened;
+            position.entry_volume += opened;
+            position.entry_value += opened * deal.price;
+            if closed == 0.0 {
+                position.charge(deal);
+            }
+        }
+        trade
+    }
+}
+
+/// The union of several trade imports with the trades they share removed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct TradeMerge {
+    pub trades: Vec<Trade>, // By close time; trades without one follow in input order
+    pub duplicates_removed: usize,
+    pub duplicates_by_set: Vec<usize>, // Trades dropped from each input set, in input order
+    pub conflicting_tickets: Vec<u64>, // Duplicated tickets whose copies differ; the first copy is kept
+}
+
+/// Merges overlapping imports, e.g. monthly reports and a full-history
+/// export, into one chronological list. A trade is a duplicate when an
+/// earlier set, or earlier in its own set, already has its ticket and close
+/// time, so the partial closes of one position stay separate. Trades without
+/// a ticket are matched on every field, keeping as many identical copies as
+/// the set that has the most of them.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn merge_trade_sets(trade_sets: Vec<Vec<Trade>>) -> TradeMerge {
+    let mut trades: Vec<Trade> = Vec::new();
+    let mut ticketed: HashMap<(u64, Option<i64>), usize> = HashMap::new(); // Index into `trades`
+    let mut untracked: HashMap<Vec<u8>, usize> = HashMap::new(); // Copies kept of each ticketless trade
+    let mut duplicates_by_set = Vec::with_capacity(trade_sets.len());
+    let mut conflicting_tickets = Vec::new();
+
+    for set in trade_sets {
+        let mut duplicates = 0;
+        let mut copies_in_set: HashMap<Vec<u8>, usize> = HashMap::new();
+        for trade in set {
+            if let Some(ticket) = trade.ticket {
+                match ticketed.get(&(ticket, trade.close_time)) {
+                    Some(&index) => {
+                        if trades[index] != trade && !conflicting_tickets.contains(&ticket) {
+                            conflicting_tickets.push(ticket);
+                        }
+                        duplicates += 1;
+                    }
+                    None => {
+                        ticketed.insert((ticket, trade.close_time), trades.len());
+                        trades.push(trade);
+                    }
+                }
+                continue;
+            }
+            let key = bincode::serialize(&trade).expect("trades serialize");
+            let copies = copies_in_set.entry(key.clone()).or_insert(0);
+            *copies += 1;
+            let kept = untracked.entry(key).or_insert(0);
+            if *copies > *kept {
+                *kept += 1;
+                trades.push(trade);
+            } else {
+                duplicates += 1;
+            }
+        }
+        duplicates_by_set.push(duplicates);
+    }
+
+    trades.sort_by_key(|t| (t.close_time.is_none(), t.close_time));
+    TradeMerge {
+        trades,
+        duplicates_removed: duplicates_by_set.iter().sum(),
+        duplicates_by_set,
+        conflicting_tickets,
+    }
+}
+
+/// Which trades to keep when slicing a history. Unset criteria match every
+/// trade; dates apply to the close time, or the open time without one, so a
+/// trade with neither fails any date bound.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TradeFilter {
+    pub start: Option<i64>, // Epoch ms, inclusive
+    pub end: Option<i64>, // Epoch ms, exclusive
+    pub symbols: Option<Vec<String>>, // Matched ignoring case
+    pub direction: Option<TradeType>,
+    pub min_volume: Option<f64>,
+}
+
+impl TradeFilter {
+    pub fn matches(&self, trade: &Trade) -> bool {
+        let time = trade.close_time.or(trade.open_time);
+        let after_start = self.start.is_none_or(|start| time.is_some_and(|t| t >= start));
+        let before_end = self.end.is_none_or(|end| time.is_some_and(|t| t < end));
+        let symbol = self
+            .symbols
+            .as_ref()
+            .is_none_or(|symbols| symbols.iter().any(|s| s.eq_ignore_ascii_case(&trade.symbol)));
+        after_start
+            && before_end
+            && symbol
+            && self.direction.is_none_or(|direction| trade.trade_type == direction)
+            && self.min_volume.is_none_or(|min| trade.volume >= min)
+    }
+
+    /// The matching trades, in their original order.
+    pub fn apply(&self, trades: Vec<Trade>) -> Result<Vec<Trade>> {
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if start > end {
+                return Err(Error::Validation("The filter's start is after its end".into()));
+            }
+        }
+        Ok(trades.into_iter().filter(|trade| self.matches(trade)).collect())
+    }
+}
+
+/// Slices a history by close date (`start` inclusive, `end` exclusive, as
+/// epoch ms or datetimes), symbols, direction and minimum volume.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, start=None, end=None, symbols=None, direction=None, min_volume=None))]
+fn filter_trades(
+    trades: Vec<Trade>,
+    start: Option<&Bound<'_, PyAny>>,
+    end: Option<&Bound<'_, PyAny>>,
+    symbols: Option<Vec<String>>,
+    direction: Option<&Bound<'_, PyAny>>,
+    min_volume: Option<f64>,
+) -> PyResult<Vec<Trade>> {
+    let time = |value: Option<&Bound<'_, PyAny>>, name: &str| {
+        value
+            .map(|v| epoch_millis(v).ok_or_else(|| ValidationError::new_err(format!("Invalid {} time", name))))
+            .transpose()
+    };
+    let filter = TradeFilter {
+        start: time(start, "start")?,
+        end: time(end, "end")?,
+        symbols,
+        direction: direction.map(TradeType::from_py).transpose()?,
+        min_volume,
+    };
+    Ok(filter.apply(trades)?)
+}
+
+/// Decodes a report file, honouring a UTF-8 or UTF-16 byte order mark and
+/// recognising BOM-less UTF-16 by the zero bytes of its ASCII characters.
+fn decode_report_bytes(bytes: &[u8]) -> (String, &'static str) {
+    let utf16 = |bytes: &[u8], little_endian: bool| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| match little_endian {
+                true => u16::from_le_bytes([pair[0], pair[1]]),
+                false => u16::from_be_bytes([pair[0], pair[1]]),
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => (utf16(rest, true), "utf-16le"),
+        [0xFE, 0xFF, rest @ ..] => (utf16(rest, false), "utf-16be"),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => (String::from_utf8_lossy(rest).into_owned(), "utf-8"),
+        [_, 0, ..] => (utf16(bytes, true), "utf-16le"),
+        [0, _, ..] => (utf16(bytes, false), "utf-16be"),
+        _ => (String::from_utf8_lossy(bytes).into_owned(), "utf-8"),
+    }
+}
+
+/// Reads every `<tr>` of an HTML document as a list of cell texts. A cell
+/// spanning several columns is followed by empty cells so that header and
+/// data rows line up. Tolerates the unclosed tags browsers accept.
+fn read_html_rows(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row: Option<Vec<String>> = None;
+    let mut cell: Option<(String, usize)> = None;
+
+    let close_cell = |row: &mut Option<Vec<String>>, cell: &mut Option<(String, usize)>| {
+        if let (Some(row), Some((text, span))) = (row.as_mut(), cell.take()) {
+            let text = decode_html_entities(&text);
+            row.push(text.split_whitespace().collect::<Vec<_>>().join(" "));
+            row.extend(std::iter::repeat_n(String::new(), span.saturating_sub(1)));
+        }
+    };
+
+    let mut rest = content;
+    while let Some(open) = rest.find('<') {
+        if let Some((text, _)) = cell.as_mut() {
+            text.push_str(&rest[..open]);
+        }
+        rest = &rest[open..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = rest[1..end].to_ascii_lowercase();
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+
+        match (name.as_str(), closing) {
+            ("tr", false) => {
+                close_cell(&mut row, &mut cell);
+                rows.extend(row.replace(Vec::new()));
+            }
+            ("td" | "th", false) => {
+                close_cell(&mut row, &mut cell);
+                let span = html_attribute(&tag, "colspan").and_then(|v| v.parse().ok()).unwrap_or(1);
+                cell = Some((String::new(), span));
+            }
+            ("td" | "th", true) => close_cell(&mut row, &mut cell),
+            ("tr" | "table", true) => {
+                close_cell(&mut row, &mut cell);
+                rows.extend(row.take());
+            }
+            ("br", _) => {
+                if let Some((text, _)) = cell.as_mut() {
+                    text.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+    close_cell(&mut row, &mut cell);
+    rows.extend(row);
+    rows
+}
+
+/// Value of an attribute inside a lowercased tag, with or without quotes.
+fn html_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split(|c: char| c.is_whitespace() || c == '/').next(),
+    }
+}
+
+fn decode_html_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Reads every `<Row>` of a SpreadsheetML document as a list of cell texts.
+fn read_spreadsheet_rows(content: &str) -> Result<Vec<Vec<String>>, xml::reader::Error> {
+    use xml::reader::{EventReader, XmlEvent};
+
+    let mut rows = Vec::new();
+    let mut current_row: Option<Vec<String>> = None;
+    let mut current_cell: Option<String> = None;
+
+    for event in EventReader::from_str(content) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => match name.local_name.as_str() {
+                "Row" => current_row = Some(Vec::new()),
+                "Cell" => {
+                    // ss:Index is 1-based and skips over empty cells
+                    if let Some(row) = current_row.as_mut() {
+                        let index = attributes
+                            .iter()
+                            .find(|a| a.name.local_name == "Index")
+                            .and_then(|a| a.value.parse::<usize>().ok());
+                        if let Some(index) = index {
+                            while row.len() + 1 < index {
+                                row.push(String::new());
+                            }
+                        }
+                    }
+                    current_cell = Some(String::new());
+                }
+                _ => {}
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(cell) = current_cell.as_mut() {
+                    cell.push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "Cell" => {
+                    if let (Some(row), Some(cell)) = (current_row.as_mut(), current_cell.take()) {
+                        row.push(cell.trim().to_string());
+                    }
+                }
+                "Row" => {
+                    if let Some(row) = current_row.take() {
+                        rows.push(row);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    Ok(rows)
+}
+
+fn non_empty_cells(row: &[String]) -> usize {
+    row.iter().filter(|cell| !cell.is_empty()).count()
+}
+
+/// Column indices of the MT5 "Positions" table, resolved from its header row.
+struct PositionColumns {
+    symbol: usize,
+    trade_type: usize,
+    volume: usize,
+    open_price: usize,
+    close_price: usize,
+    commission: Option<usize>,
+    swap: Option<usize>,
+    profit: usize,
+    open_time: Option<usize>,
+    close_time: Option<usize>,
+    ticket: Option<usize>,
+}
+
+impl PositionColumns {
+    fn from_header(header: &[String], format: &str) -> Result<Self> {
+        let find_all = |name: &str| -> Vec<usize> {
+            header
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| cell.eq_ignore_ascii_case(name))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let require = |name: &str| -> Result<usize> {
+            find_all(name).first().copied().ok_or_else(|| {
+                Error::Parse(format!("Invalid MT5 {} format: Positions column '{}' not found", format, name))
+            })
+        };
+
+        // The header repeats "Time" and "Price" for the open and the close of the position
+        let times = find_all("Time");
+        let prices = find_all("Price");
+        if prices.len() < 2 {
+            return Err(Error::Parse(format!(
+                "Invalid MT5 {} format: Positions table needs open and close Price columns",
+                format
+            )));
+        }
+
+        Ok(PositionColumns {
+            symbol: require("Symbol")?,
+            trade_type: require("Type")?,
+            volume: require("Volume")?,
+            open_price: prices[0],
+            close_price: prices[1],
+            commission: find_all("Commission").first().copied(),
+            swap: find_all("Swap").first().copied(),
+            profit: require("Profit")?,
+            open_time: times.first().copied(),
+            close_time: times.get(1).copied(),
+            ticket: find_all("Position").first().copied(),
+        })
+    }
+
+    /// Builds a trade from a table row, returning the name of the first field
+    /// that could not be parsed.
+    fn to_trade(&self, row: &[String]) -> Result<Trade, &'static str> {
+        let cell = |index: usize| row.get(index).map(String::as_str).unwrap_or("");
+        let number = |index: usize, field: &'static str| parse_report_number(cell(index)).ok_or(field);
+        let optional = |index: Option<usize>, field: &'static str| match index.map(cell) {
+            Some(raw) if !raw.trim().is_empty() => parse_report_number(raw).map(Some).ok_or(field),
+            _ => Ok(None),
+        };
+        let time = |index: Option<usize>, field: &'static str| match index.map(cell) {
+            Some(raw) if !raw.is_empty() => parse_timestamp(raw).map(Some).ok_or(field),
+            _ => Ok(None),
+        };
+
+        let symbol = cell(self.symbol);
+        if symbol.is_empty() {
+            return Err("symbol");
+        }
+
+        Ok(Trade {
+            symbol: symbol.to_string(),
+            trade_type: normalize_trade_type(cell(self.trade_type)).ok_or("type")?,
+            volume: number(self.volume, "volume")?,
+            open_price: number(self.open_price, "open price")?,
+            close_price: number(self.close_price, "close price")?,
+            profit: number(self.profit, "profit")?,
+            commission: optional(self.commission, "commission")?,
+            swap: optional(self.swap, "swap")?,
+            open_time: time(self.open_time, "open time")?,
+            close_time: time(self.close_time, "close time")?,
+            risk_amount: None,
+            tag: None,
+            max_favorable_excursion: None,
+            max_adverse_excursion: None,
+            ticket: optional_ticket(self.ticket.map(cell)).ok_or("position")?,
+        })
+    }
+}
+
+/// Reads an optional ticket cell: `Some(None)` when the cell is missing or
+/// empty, `None` when it holds something other than a ticket number.
+fn optional_ticket(raw: Option<&str>) -> Option<Option<u64>> {
+    match raw.map(str::trim) {
+        Some(raw) if !raw.is_empty() => raw.parse().ok().map(Some),
+        _ => Some(None),
+    }
+}
+
+/// Parses a number as printed in MT5 reports, where volumes are shown as
+/// "filled / requested" and the digits follow the terminal's locale.
+fn parse_report_number(raw: &str) -> Option<f64> {
+    parse_number(raw.split('/').next().unwrap_or(""), None)
+}
+
+/// Parses a locale-formatted number such as "1 234,56", "1.234,56",
+/// "1,234.56" or "1'234.56". Without an explicit decimal separator the last
+/// of '.' and ',' is taken as the decimal point, unless that character
+/// appears more than once; a lone ',' counts as a decimal comma. Grouping
+/// separators must mark thousands.
+fn parse_number(raw: &str, decimal_separator: Option<char>) -> Option<f64> {
+    let digits: String = raw
+        .chars()
+        .filter(|&c| !c.is_whitespace() && c != '\'')
+        .map(|c| if c == '\u{2212}' { '-' } else { c })
+        .collect();
+    if digits.is_empty() {
+        return None;
+    }
+
+    let decimal = decimal_separator.or_else(|| {
+        let last = digits.rfind(['.', ','])?;
+        let candidate = digits[last..].chars().next()?;
+        (digits.matches(candidate).count() == 1).then_some(candidate)
+    });
+    // "12,3,4" is no number
+    let (whole, fraction) = match decimal.and_then(|d| digits.split_once(d)) {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (digits.as_str(), ""),
+    };
+    if fraction.contains(['.', ',']) || whole.split(['.', ',']).skip(1).any(|group| group.len() != 3) {
+        return None;
+    }
+    let cleaned: String = digits
+        .chars()
+        .filter_map(|c| match c {
+            '.' | ',' if Some(c) == decimal => Some('.'),
+            '.' | ',' => None,
+            c => Some(c),
+        })
+        .collect();
+    cleaned.parse().ok()
+}
+
+/// Parses a report timestamp into Unix epoch milliseconds. Accepts the MT5
+/// "2024.01.15 10:30:00" layout, ISO-8601 variants and raw epoch millis.
+fn parse_timestamp(raw: &str) -> Option<i64> {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    const FORMATS: [&str; 6] = [
+        "%Y.%m.%d %H:%M:%S%.f",
+        "%Y.%m.%d %H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ];
+
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    if let Ok(millis) = raw.parse::<i64>() {
+        return Some(millis);
+    }
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(raw) {
+        return Some(dt.timestamp_millis());
+    }
+    for format in FORMATS {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(raw, format) {
+            return Some(dt.and_utc().timestamp_millis());
+        }
+    }
+    ["%Y.%m.%d", "%Y-%m-%d"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(raw, format).ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+/// Maps MT5 deal types ("buy", "sell", "buy limit", ...) to a `TradeType`.
+fn normalize_trade_type(raw: &str) -> Option<TradeType> {
+    let lower = raw.trim().to_ascii_lowercase();
+    if lower.starts_with("buy") {
+        Some(TradeType::Buy)
+    } else if lower.starts_with("sell") {
+        Some(TradeType::Sell)
+    } else {
+        None
+    }
+}
+
+/// Units in which `calculate_performance_metrics` measures each trade's
+/// result, so that past position sizing does not distort the edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum ProfitNormalization {
+    Raw,              // Account currency
+    PerLot,           // Profit divided by volume
+    PerRiskUnit,      // Profit divided by risk_amount (R-multiples)
+    PercentOfBalance, // Profit as a percent of the balance before the trade
+}
+
+/// How `calculate_performance_metrics` tames the most extreme trades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum OutlierMode {
+    Winsorize, // Clip each tail to the most extreme outcome that is kept
+    Trim,      // Drop each tail from the sample
+}
+
+/// Edge statistics recomputed after winsorizing or trimming the top and
+/// bottom `percent` of trade outcomes, next to the untouched metrics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct OutlierAdjustment {
+    pub mode: OutlierMode,
+    pub percent: f64, // Share of trades treated in each tail
+    pub trades_adjusted: usize, // Both tails combined
+    pub win_probability: f64,
+    pub avg_win: f64,
+    pub avg_loss: f64,
+    pub win_loss_ratio: f64,
+    pub profit_factor: f64,
+    pub expectancy: f64,
+    pub raw_kelly_fraction: Option<f64>, // Full binary Kelly before the adjustment
+    pub kelly_fraction: Option<f64>,
+}
+
+/// Winsorizes or trims the top and bottom `percent` (0 to below 50) of the
+/// trade outcomes, measured in the units of `normalization`, and recomputes
+/// the edge statistics on what is left.
+pub fn outlier_adjustment(
+    trades: &[Trade],
+    normalization: ProfitNormalization,
+    initial_balance: Option<f64>,
+    mode: OutlierMode,
+    percent: f64,
+) -> Result<OutlierAdjustment> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if percent.is_nan() || !(0.0..50.0).contains(&percent) {
+        return Err(Error::Validation("Outlier percent must be at least 0 and below 50".into()));
+    }
+    let profits = normalized_profits(trades, normalization, initial_balance)?;
+    let mut sorted = profits.clone();
+    sorted.sort_by(f64::total_cmp);
+    let tail = (profits.len() as f64 * percent / 100.0).floor() as usize;
+    let adjusted = match mode {
+        OutlierMode::Winsorize => {
+            let (lower, upper) = (sorted[tail], sorted[sorted.len() - 1 - tail]);
+            profits.iter().map(|p| p.clamp(lower, upper)).collect()
+        }
+        OutlierMode::Trim => sorted[tail..sorted.len() - tail].to_vec(),
+    };
+
+    let wins: Vec<f64> = adjusted.iter().copied().filter(|&p| p > 0.0).collect();
+    let losses: Vec<f64> = adjusted.iter().copied().filter(|&p| p < 0.0).collect();
+    let average = |values: &[f64]| {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+    };
+    let win_probability = wins.len() as f64 / adjusted.len() as f64;
+    let loss_probability = losses.len() as f64 / adjusted.len() as f64;
+    let (avg_win, avg_loss) = (average(&wins), average(&losses));
+    let gross_profit: f64 = wins.iter().sum();
+    let gross_loss: f64 = -losses.iter().sum::<f64>();
+    Ok(OutlierAdjustment {
+        mode,
+        percent,
+        trades_adjusted: 2 * tail,
+        win_probability,
+        avg_win,
+        avg_loss,
+        win_loss_ratio: robust_win_loss_ratio(&adjusted),
+        profit_factor: if gross_loss != 0.0 { gross_profit / gross_loss } else { 0.0 },
+        expectancy: win_probability * avg_win - loss_probability * avg_loss.abs(),
+        raw_kelly_fraction: binary_kelly(&profits),
+        kelly_fraction: binary_kelly(&adjusted),
+    })
+}
+
+/// Per-trade results in the units of `normalization`, in input order.
+fn normalized_profits(
+    trades: &[Trade],
+    normalization: ProfitNormalization,
+    initial_balance: Option<f64>,
+) -> Result<Vec<f64>> {
+    match normalization {
+        ProfitNormalization::Raw => Ok(trades.iter().map(|t| t.profit).collect()),
+        ProfitNormalization::PerLot => trades
+            .iter()
+            .map(|t| match t.volume > 0.0 {
+                true => Ok(t.profit / t.volume),
+                false => Err(Error::Validation("Per-lot normalization needs a positive volume on every trade".into())),
+            })
+            .collect(),
+        ProfitNormalization::PerRiskUnit => trades
+            .iter()
+            .map(|t| {
+                t.r_multiple().ok_or_else(|| {
+                    Error::Validation("Per-risk-unit normalization needs a risk_amount on every trade".into())
+                })
+            })
+            .collect(),
+        ProfitNormalization::PercentOfBalance => {
+            let mut balance = initial_balance.ok_or_else(|| {
+                Error::Validation("Percent-of-balance normalization requires an initial balance".into())
+            })?;
+            let mut order: Vec<usize> = (0..trades.len()).collect();
+            if trades.iter().all(|t| t.close_time.is_some()) {
+                order.sort_by_key(|&i| trades[i].close_time);
+            }
+            let mut percents = vec![0.0; trades.len()];
+            for i in order {
+                if balance <= 0.0 {
+                    return Err(Error::Validation("Balance fell to zero before the last trade".into()));
+                }
+                percents[i] = trades[i].profit / balance * 100.0;
+                balance += trades[i].profit;
+            }
+            Ok(percents)
+        }
+    }
+}
+
+/// `exchange_rates` converts the trades to the account currency first.
+/// With `outliers` set, the result also carries the edge statistics after
+/// winsorizing or trimming `outlier_percent` of the trades in each tail.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (
+    trades,
+    risk_free_rate=0.0,
+    initial_balance=None,
+    normalization=ProfitNormalization::Raw,
+    prior_win_rate=0.5,
+    prior_strength=2.0,
+    exchange_rates=None,
+    outliers=None,
+    outlier_percent=5.0
+))]
+#[allow(clippy::too_many_arguments)]
+fn calculate_performance_metrics(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    normalization: ProfitNormalization,
+    prior_win_rate: f64,
+    prior_strength: f64,
+    exchange_rates: Option<ExchangeRates>,
+    outliers: Option<OutlierMode>,
+    outlier_percent: f64,
+) -> Result<PerformanceMetrics> {
+    let prior = BetaPrior::new(prior_win_rate, prior_strength)?;
+    let trades = match exchange_rates {
+        Some(rates) => rates.convert(&trades)?,
+        None => trades,
+    };
+    py.allow_threads(|| {
+        let adjustment = match outliers {
+            Some(mode) => Some(outlier_adjustment(&trades, normalization, initial_balance, mode, outlier_percent)?),
+            None => None,
+        };
+        let metrics = cached_performance_metrics(trades, risk_free_rate, initial_balance, normalization, prior)?;
+        Ok(PerformanceMetrics { outlier_adjustment: adjustment, ..metrics })
+    })
+}
+
+/// `compute_performance_metrics` through the result cache, for entry points
+/// called directly by users; internal loops compute without it.
+pub(crate) fn cached_performance_metrics(
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    normalization: ProfitNormalization,
+    prior: BetaPrior,
+) -> Result<PerformanceMetrics> {
+    let key = cache_key(&(
+        "performance_metrics",
+        &trades,
+        risk_free_rate,
+        initial_balance,
+        normalization,
+        (prior.alpha, prior.beta),
+    ));
+    cached(key, || compute_performance_metrics(trades, risk_free_rate, initial_balance, normalization, prior))
+}
+
+pub fn compute_performance_metrics(
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    normalization: ProfitNormalization,
+    prior: BetaPrior,
+) -> Result<PerformanceMetrics> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if initial_balance.is_some_and(|b| b <= 0.0) {
+        return Err(Error::Validation("Initial balance must be positive".into()));
+    }
+    if risk_free_rate != 0.0 && initial_balance.is_none() {
+        return Err(Error::Validation(
+            "A risk-free rate requires an initial balance to express daily P&L as returns".into(),
+        ));
+    }
+
+    let total_trades = trades.len();
+
+    // Edge statistics use the normalized profits; drawdown and the ratios
+    // below describe the account itself and keep using raw profits.
+    let profits = normalized_profits(&trades, normalization, initial_balance)?;
+    let winning_trades: Vec<f64> = profits.iter().copied().filter(|&p| p > 0.0).collect();
+    let losing_trades: Vec<f64> = profits.iter().copied().filter(|&p| p < 0.0).collect();
+
+    let win_probability = winning_trades.len() as f64 / total_trades as f64;
+    let loss_probability = losing_trades.len() as f64 / total_trades as f64;
+
+    let avg_win = if !winning_trades.is_empty() {
+        winning_trades.iter().sum::<f64>() / winning_trades.len() as f64
+    } else {
+        0.0
+    };
+
+    let avg_loss = if !losing_trades.is_empty() {
+        losing_trades.iter().sum::<f64>() / losing_trades.len() as f64
+    } else {
+        0.0
+    };
+
+    // Robust Win/Loss Ratio using median
+    let win_loss_ratio = robust_win_loss_ratio(&profits);
+
+    let gross_profit: f64 = winning_trades.iter().sum();
+    let gross_loss: f64 = losing_trades.iter().map(|p| p.abs()).sum();
+    let profit_factor = if gross_loss != 0.0 { gross_profit / gross_loss } else { 0.0 };
+
+    let expectancy = win_probability * avg_win - loss_probability * avg_loss.abs();
+    let (sqn, expectancy_t_stat, expectancy_p_value) = match expectancy_t_test(&profits) {
+        Some((sqn, t_stat, p_value)) => (Some(sqn), Some(t_stat), Some(p_value)),
+        None => (None, None, None),
+    };
+
+    let max_drawdown = EquityCurve::from_trades(&trades, 0.0).drawdown.into_iter().fold(0.0, f64::max);
+
+    // Annualized Sharpe and Sortino ratios from daily returns (requires close timestamps)
+    let returns = daily_returns(&trades, initial_balance);
+    let sharpe_ratio = returns.as_deref().and_then(|r| sharpe_ratio(r, risk_free_rate));
+    let sortino_ratio = returns.as_deref().and_then(|r| sortino_ratio(r, risk_free_rate));
+
+    // Calmar and MAR ratios need a starting balance to express CAGR and drawdown in percent
+    let daily_pnl = daily_pnl_series(&trades);
+    let (calmar_ratio, mar_ratio) = match (daily_pnl, initial_balance) {
+        (Some(pnl), Some(balance)) => {
+            let calmar_window = pnl.len().saturating_sub(CALMAR_WINDOW_DAYS);
+            let calmar_start = balance + pnl[..calmar_window].iter().sum::<f64>();
+            (
+                return_over_drawdown(&pnl[calmar_window..], calmar_start),
+                return_over_drawdown(&pnl, balance),
+            )
+        }
+        _ => (None, None),
+    };
+
+    Ok(PerformanceMetrics {
+        total_trades,
+        win_probability,
+        loss_probability,
+        avg_win,
+        avg_loss,
+        win_loss_ratio,
+        profit_factor,
+        expectancy,
+        max_drawdown,
+        sharpe_ratio,
+        sortino_ratio,
+        calmar_ratio,
+        mar_ratio,
+        bayesian_win_probability: prior.posterior_mean(winning_trades.len(), total_trades),
+        sqn,
+        expectancy_t_stat,
+        expectancy_p_value,
+        additional_trades_needed: additional_trades_needed(total_trades, win_probability, win_loss_ratio),
+        outlier_adjustment: None,
+    })
+}
+
+/// Number of trades needed to pin the binary Kelly fraction
+/// `p - (1 - p) / b` down to within `margin` at the given two-sided
+/// confidence. The fraction moves by `1 + 1 / b` per unit of win rate, so
+/// this is the usual binomial sample size for a win-rate margin of
+/// `margin / (1 + 1 / b)`.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (win_prob, win_loss_ratio, confidence=0.95, margin=0.1)))]
+pub fn required_sample_size(win_prob: f64, win_loss_ratio: f64, confidence: f64, margin: f64) -> Result<usize> {
+    if !(0.0..=1.0).contains(&win_prob) {
+        return Err(Error::Validation("Win probability must be between 0 and 1".into()));
+    }
+    if win_loss_ratio.is_nan() || win_loss_ratio <= 0.0 {
+        return Err(Error::Validation("Win/loss ratio must be positive".into()));
+    }
+    check_confidence(confidence)?;
+    if margin.is_nan() || margin <= 0.0 {
+        return Err(Error::Validation("Margin must be positive".into()));
+    }
+    let z = normal_quantile(0.5 + confidence / 2.0);
+    let sensitivity = 1.0 + 1.0 / win_loss_ratio;
+    let n = (z * sensitivity / margin).powi(2) * win_prob * (1.0 - win_prob);
+    Ok(n.ceil() as usize)
+}
+
+/// Trades still missing before `required_sample_size` is met at the default
+/// confidence and margin. A sample without both wins and losses says nothing
+/// about the edge, so it is judged as an even-odds coin flip instead.
+fn additional_trades_needed(total_trades: usize, win_probability: f64, win_loss_ratio: f64) -> usize {
+    let degenerate =
+        win_probability <= 0.0 || win_probability >= 1.0 || win_loss_ratio.is_nan() || win_loss_ratio <= 0.0;
+    let (p, b) = if degenerate { (0.5, 1.0) } else { (win_probability, win_loss_ratio) };
+    required_sample_size(p, b, SAMPLE_SIZE_CONFIDENCE, SAMPLE_SIZE_MARGIN)
+        .map_or(0, |needed| needed.saturating_sub(total_trades))
+}
+
+/// SQN, t-statistic and one-sided p-value of the mean profit, or None with
+/// fewer than two trades or no variation between them.
+fn expectancy_t_test(profits: &[f64]) -> Option<(f64, f64, f64)> {
+    let n = profits.len() as f64;
+    if profits.len() < 2 {
+        return None;
+    }
+    let mean = profits.iter().sum::<f64>() / n;
+    let std_dev = (profits.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    if std_dev == 0.0 {
+        return None;
+    }
+    let t_stat = mean / std_dev * n.sqrt();
+    let sqn = mean / std_dev * n.min(SQN_MAX_TRADES).sqrt();
+    Some((sqn, t_stat, student_t_upper_tail(t_stat, n - 1.0)))
+}
+
+/// Running performance statistics updated one trade at a time, for live
+/// monitoring where recomputing over the full history on every fill is too
+/// slow. Trades are assumed to arrive in close order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct IncrementalMetrics {
+    pub initial_balance: f64,
+    pub total_trades: usize,
+    pub winning_trades: usize,
+    pub losing_trades: usize,
+    pub gross_profit: f64,
+    pub gross_loss: f64, // Sum of losing trades as a positive number
+    pub equity: f64,
+    pub peak: f64, // High-water mark, starting from the initial balance
+    pub max_drawdown: f64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl IncrementalMetrics {
+    #[new]
+    #[pyo3(signature = (initial_balance=0.0))]
+    fn py_new(initial_balance: f64) -> Self {
+        Self::new(initial_balance)
+    }
+
+    #[pyo3(name = "add_trade")]
+    fn py_add_trade(&mut self, trade: &Trade) {
+        self.add_trade(trade)
+    }
+
+    #[pyo3(name = "add_trades")]
+    fn py_add_trades(&mut self, trades: Vec<Trade>) {
+        self.add_trades(trades)
+    }
+
+    #[getter(win_probability)]
+    fn py_win_probability(&self) -> f64 {
+        self.win_probability()
+    }
+
+    #[getter(loss_probability)]
+    fn py_loss_probability(&self) -> f64 {
+        self.loss_probability()
+    }
+
+    #[getter(avg_win)]
+    fn py_avg_win(&self) -> f64 {
+        self.avg_win()
+    }
+
+    /// Average losing trade, negative like `PerformanceMetrics.avg_loss`.
+    #[getter(avg_loss)]
+    fn py_avg_loss(&self) -> f64 {
+        self.avg_loss()
+    }
+
+    /// Average win over average loss. The batch metrics use medians, which
+    /// cannot be maintained in constant time, so the two can differ.
+    #[getter(win_loss_ratio)]
+    fn py_win_loss_ratio(&self) -> f64 {
+        self.win_loss_ratio()
+    }
+
+    #[getter(profit_factor)]
+    fn py_profit_factor(&self) -> f64 {
+        self.profit_factor()
+    }
+
+    #[getter(expectancy)]
+    fn py_expectancy(&self) -> f64 {
+        self.expectancy()
+    }
+
+    #[getter(current_drawdown)]
+    fn py_current_drawdown(&self) -> f64 {
+        self.current_drawdown()
+    }
+
+    /// Binary Kelly fraction (p - q / b) from the running averages, or `None`
+    /// until at least one win and one loss have been recorded.
+    #[pyo3(name = "kelly_fraction")]
+    #[pyo3(signature = (fractional_multiplier=1.0))]
+    fn py_kelly_fraction(&self, fractional_multiplier: f64) -> Option<f64> {
+        self.kelly_fraction(fractional_multiplier)
+    }
+
+    /// Snapshot as `PerformanceMetrics`; the ratios that need daily returns
+    /// are left as `None`.
+    #[pyo3(name = "to_metrics")]
+    fn py_to_metrics(&self) -> PerformanceMetrics {
+        self.to_metrics()
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl IncrementalMetrics {
+    pub fn new(initial_balance: f64) -> Self {
+        IncrementalMetrics {
+            initial_balance,
+            total_trades: 0,
+            winning_trades: 0,
+            losing_trades: 0,
+            gross_profit: 0.0,
+            gross_loss: 0.0,
+            equity: initial_balance,
+            peak: initial_balance,
+            max_drawdown: 0.0,
+        }
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) {
+        self.add_profit(trade.profit);
+    }
+
+    pub fn add_trades(&mut self, trades: Vec<Trade>) {
+        trades.iter().for_each(|trade| self.add_profit(trade.profit));
+    }
+
+    pub fn win_probability(&self) -> f64 {
+        self.rate(self.winning_trades)
+    }
+
+    pub fn loss_probability(&self) -> f64 {
+        self.rate(self.losing_trades)
+    }
+
+    pub fn avg_win(&self) -> f64 {
+        if self.winning_trades > 0 { self.gross_profit / self.winning_trades as f64 } else { 0.0 }
+    }
+
+    /// Average losing trade, negative like `PerformanceMetrics.avg_loss`.
+    pub fn avg_loss(&self) -> f64 {
+        if self.losing_trades > 0 { -self.gross_loss / self.losing_trades as f64 } else { 0.0 }
+    }
+
+    /// Average win over average loss. The batch metrics use medians, which
+    /// cannot be maintained in constant time, so the two can differ.
+    pub fn win_loss_ratio(&self) -> f64 {
+        if self.losing_trades > 0 { self.avg_win() / self.avg_loss().abs() } else { 0.0 }
+    }
+
+    pub fn profit_factor(&self) -> f64 {
+        if self.gross_loss != 0.0 { self.gross_profit / self.gross_loss } else { 0.0 }
+    }
+
+    pub fn expectancy(&self) -> f64 {
+        self.rate(1) * (self.gross_profit - self.gross_loss)
+    }
+
+    pub fn current_drawdown(&self) -> f64 {
+        self.peak - self.equity
+    }
+
+    /// Binary Kelly fraction (p - q / b) from the running averages, or `None`
+    /// until at least one win and one loss have been recorded.
+    pub fn kelly_fraction(&self, fractional_multiplier: f64) -> Option<f64> {
+        if self.winning_trades == 0 || self.losing_trades == 0 {
+            return None;
+        }
+        Some((self.win_probability() - self.loss_probability() / self.win_loss_ratio()) * fractional_multiplier)
+    }
+
+    /// Snapshot as `PerformanceMetrics`; the ratios that need daily returns
+    /// are left as `None`.
+    pub fn to_metrics(&self) -> PerformanceMetrics {
+        PerformanceMetrics::new(
+            self.total_trades,
+            self.win_probability(),
+            self.loss_probability(),
+            self.avg_win(),
+            self.avg_loss(),
+            self.win_loss_ratio(),
+            self.profit_factor(),
+            self.expectancy(),
+            self.max_drawdown,
+            None,
+            None,
+            None,
+            None,
+            Some(BetaPrior::default().posterior_mean(self.winning_trades, self.total_trades)),
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn add_profit(&mut self, profit: f64) {
+        self.total_trades += 1;
+        if profit > 0.0 {
+            self.winning_trades += 1;
+            self.gross_profit += profit;
+        } else if profit < 0.0 {
+            self.losing_trades += 1;
+            self.gross_loss -= profit;
+        }
+        self.equity += profit;
+        self.peak = f64::max(self.peak, self.equity);
+        self.max_drawdown = f64::max(self.max_drawdown, self.peak - self.equity);
+    }
+
+    fn rate(&self, count: usize) -> f64 {
+        if self.total_trades > 0 { count as f64 / self.total_trades as f64 } else { 0.0 }
+    }
+}
+
+/// Raised by `LiveRiskMonitor` when the recommended risk fraction first
+/// becomes available or moves by more than the monitor's threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct RiskFractionChange {
+    pub previous: Option<f64>, // Recommendation at the last event; None for the first one
+    pub risk_fraction: f64,
+    pub total_trades: usize, // Closed trades seen when the change happened
+    pub close_time: Option<i64>, // Close time of the trade that caused it
+}
+
+/// Follows a live account: closed trades or raw fills are fed in as they
+/// happen, by hand or from a Python `source` callable polled periodically
+/// (e.g. wrapping `MetaTrader5.history_deals_get`), and the running metrics
+/// and the recommended (fractional Kelly) risk fraction are kept up to
+/// date. Deals are netted into trades as their positions close and are
+/// counted once per ticket, and closed trades once per ticket and close
+/// time, so overlapping history windows are harmless. Trades without a
+/// ticket are counted every time they are fed in.
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct LiveRiskMonitor {
+    metrics: IncrementalMetrics,
+    fractional_multiplier: f64,
+    change_threshold: f64, // Move in the risk fraction that raises an event
+    announced: Option<f64>, // Recommendation at the last event
+    netting: DealNetting,
+    seen_tickets: std::collections::HashSet<u64>, // Of the deals booked
+    seen_trades: std::collections::HashSet<(u64, Option<i64>)>, // Ticket and close time of the trades booked
+    #[cfg(feature = "python")]
+    source: Option<PyObject>, // Called with no arguments for the newly closed deals or trades
+    #[cfg(feature = "python")]
+    on_change: Option<PyObject>, // Called with each RiskFractionChange
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl LiveRiskMonitor {
+    #[new]
+    #[pyo3(signature = (source=None, initial_balance=0.0, fractional_multiplier=1.0, change_threshold=0.0025, on_change=None))]
+    fn py_new(
+        source: Option<PyObject>,
+        initial_balance: f64,
+        fractional_multiplier: f64,
+        change_threshold: f64,
+        on_change: Option<PyObject>,
+    ) -> Result<Self> {
+        Ok(LiveRiskMonitor {
+            source,
+            on_change,
+            ..Self::new(initial_balance, fractional_multiplier, change_threshold)?
+        })
+    }
+
+    #[getter(metrics)]
+    fn py_metrics(&self) -> IncrementalMetrics {
+        self.metrics.clone()
+    }
+
+    #[getter(recommended_risk_fraction)]
+    fn py_recommended_risk_fraction(&self) -> Option<f64> {
+        self.recommended_risk_fraction()
+    }
+
+    #[getter(fractional_multiplier)]
+    fn py_fractional_multiplier(&self) -> f64 {
+        self.fractional_multiplier
+    }
+
+    #[getter(change_threshold)]
+    fn py_change_threshold(&self) -> f64 {
+        self.change_threshold
+    }
+
+    /// Books closed trades, `Deal`s or MetaTrader5 `TradeDeal` records
+    /// (balance operations among them are skipped) and returns the changes
+    /// they caused, after passing each to `on_change`. A batch with an item
+    /// that cannot be read is rejected whole, so it can be retried.
+    #[pyo3(name = "ingest")]
+    fn py_ingest(slf: &Bound<'_, Self>, items: &Bound<'_, PyAny>) -> PyResult<Vec<RiskFractionChange>> {
+        enum Item {
+            Trade(Trade),
+            Deal(Deal),
+        }
+
+        let mut batch = Vec::new();
+        for item in items.iter()? {
+            let item = item?;
+            if let Ok(trade) = item.extract::<Trade>() {
+                batch.push(Item::Trade(trade));
+            } else if let Ok(deal) = item.extract::<Deal>() {
+                batch.push(Item::Deal(deal));
+            } else if let Some(deal) = deal_from_mt5(&item)? {
+                batch.push(Item::Deal(deal));
+            }
+        }
+        let events: Vec<RiskFractionChange> = {
+            let mut monitor = slf.borrow_mut();
+            batch
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Trade(trade) => monitor.add_trade(trade),
+                    Item::Deal(deal) => monitor.add_deal(deal),
+                })
+                .collect()
+        };
+        // Callbacks run without the monitor borrowed, so they may read it
+        let on_change = slf.borrow().on_change.as_ref().map(|callback| callback.clone_ref(slf.py()));
+        if let Some(callback) = on_change {
+            for event in &events {
+                callback.call1(slf.py(), (event.clone(),))?;
+            }
+        }
+        Ok(events)
+    }
+
+    /// Calls `source` once and ingests what it returns; `None` counts as
+    /// nothing new.
+    #[pyo3(name = "poll")]
+    fn py_poll(slf: &Bound<'_, Self>) -> PyResult<Vec<RiskFractionChange>> {
+        let source = slf.borrow().source.as_ref().map(|source| source.clone_ref(slf.py()));
+        let Some(source) = source else {
+            return Err(ValidationError::new_err("The monitor has no source to poll"));
+        };
+        let items = source.call0(slf.py())?;
+        if items.is_none(slf.py()) {
+            return Ok(Vec::new());
+        }
+        Self::py_ingest(slf, items.bind(slf.py()))
+    }
+
+    /// Polls `source` every `interval` seconds, `max_polls` times or until
+    /// Ctrl-C or an exception from `source` or `on_change`; the GIL is
+    /// released while waiting. Returns every change raised.
+    #[pyo3(name = "run")]
+    #[pyo3(signature = (interval=1.0, max_polls=None))]
+    fn py_run(slf: &Bound<'_, Self>, interval: f64, max_polls: Option<usize>) -> PyResult<Vec<RiskFractionChange>> {
+        if !interval.is_finite() || interval < 0.0 {
+            return Err(ValidationError::new_err("The polling interval must be a non-negative number of seconds"));
+        }
+        let interval = std::time::Duration::from_secs_f64(interval);
+        let mut events = Vec::new();
+        let mut polls = 0;
+        loop {
+            events.extend(Self::py_poll(slf)?);
+            polls += 1;
+            if max_polls.is_some_and(|max| polls >= max) {
+                return Ok(events);
+            }
+            let deadline = std::time::Instant::now() + interval;
+            while let Some(left) = deadline.checked_duration_since(std::time::Instant::now()) {
+                if left.is_zero() {
+                    break;
+                }
+                slf.py().allow_threads(|| std::thread::sleep(left.min(JOB_POLL_INTERVAL)));
+                slf.py().check_signals()?;
+            }
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "LiveRiskMonitor(total_trades={}, recommended_risk_fraction={:?})",
+            self.metrics.total_trades,
+            self.recommended_risk_fraction()
+        )
+    }
+}
+
+/// A deal from a MetaTrader5 `TradeDeal` record, or `None` for balance,
+/// credit and other non-trading operations.
+#[cfg(feature = "python")]
+fn deal_from_mt5(record: &Bound<'_, PyAny>) -> PyResult<Option<Deal>> {
+    let invalid = || {
+        let repr = record.repr().map(|r| r.to_string()).unwrap_or_default();
+        ParseError::new_err(format!("Expected a Trade, a Deal or a MetaTrader5 TradeDeal, got {}", repr))
+    };
+    let field = |name: &str| record.getattr(name).map_err(|_| invalid());
+    let number = |name: &str| field(name)?.extract::<f64>().map_err(|_| invalid());
+    let trade_type = match field("type")?.extract::<i64>().map_err(|_| invalid())? {
+        0 => TradeType::Buy,
+        1 => TradeType::Sell,
+        _ => return Ok(None),
+    };
+    let entry = match field("entry")?.extract::<i64>().map_err(|_| invalid())? {
+        0 => DealEntry::In,
+        1 => DealEntry::Out,
+        2 => DealEntry::InOut,
+        3 => DealEntry::OutBy,
+        _ => return Ok(None),
+    };
+    let time = match record.getattr("time_msc").and_then(|t| t.extract::<i64>()) {
+        Ok(millis) => Some(millis),
+        Err(_) => record.getattr("time").and_then(|t| t.extract::<i64>()).ok().map(|seconds| seconds * 1000),
+    };
+    // Newer terminals report fees separately from the commission
+    let fee = record.getattr("fee").and_then(|f| f.extract::<f64>()).unwrap_or(0.0);
+    Ok(Some(Deal {
+        time,
+        ticket: record.getattr("ticket").and_then(|t| t.extract::<u64>()).ok(),
+        position: record.getattr("position_id").and_then(|t| t.extract::<u64>()).ok(),
+        order: record.getattr("order").and_then(|t| t.extract::<u64>()).ok(),
+        symbol: field("symbol")?.str()?.to_string(),
+        trade_type,
+        entry,
+        volume: number("volume")?,
+        price: number("price")?,
+        commission: Some(number("commission")? + fee),
+        swap: Some(number("swap")?),
+        profit: number("profit")?,
+    }))
+}
+
+impl LiveRiskMonitor {
+    pub fn new(initial_balance: f64, fractional_multiplier: f64, change_threshold: f64) -> Result<Self> {
+        if fractional_multiplier <= 0.0 {
+            return Err(Error::Validation("Fractional multiplier must be positive".into()));
+        }
+        if change_threshold.is_nan() || change_threshold < 0.0 {
+            return Err(Error::Validation("Change threshold must not be negative".into()));
+        }
+        Ok(LiveRiskMonitor {
+            metrics: IncrementalMetrics::new(initial_balance),
+            fractional_multiplier,
+            change_threshold,
+            announced: None,
+            netting: DealNetting::default(),
+            seen_tickets: Default::default(),
+            seen_trades: Default::default(),
+            #[cfg(feature = "python")]
+            source: None,
+            #[cfg(feature = "python")]
+            on_change: None,
+        })
+    }
+
+    pub fn metrics(&self) -> &IncrementalMetrics {
+        &self.metrics
+    }
+
+    /// Fractional Kelly from the running averages, floored at zero, or
+    /// `None` until there is at least one win and one loss.
+    pub fn recommended_risk_fraction(&self) -> Option<f64> {
+        self.metrics.kelly_fraction(self.fractional_multiplier).map(|f| f.max(0.0))
+    }
+
+    /// Books a closed trade, ignoring one already booked with the same
+    /// ticket and close time, and returns the change it caused, if any.
+    pub fn add_trade(&mut self, trade: &Trade) -> Option<RiskFractionChange> {
+        if trade.ticket.is_some_and(|ticket| !self.seen_trades.insert((ticket, trade.close_time))) {
+            return None;
+        }
+        self.book_trade(trade)
+    }
+
+    fn book_trade(&mut self, trade: &Trade) -> Option<RiskFractionChange> {
+        self.metrics.add_trade(trade);
+        let risk_fraction = self.recommended_risk_fraction()?;
+        if self.announced.is_some_and(|announced| (risk_fraction - announced).abs() <= self.change_threshold) {
+            return None;
+        }
+        let previous = self.announced.replace(risk_fraction);
+        Some(RiskFractionChange {
+            previous,
+            risk_fraction,
+            total_trades: self.metrics.total_trades,
+            close_time: trade.close_time,
+        })
+    }
+
+    /// Books a fill, ignoring tickets already seen, and returns the change
+    /// caused by the trade it completes, if any.
+    pub fn add_deal(&mut self, deal: &Deal) -> Option<RiskFractionChange> {
+        if deal.ticket.is_some_and(|ticket| !self.seen_tickets.insert(ticket)) {
+            return None;
+        }
+        let trade = self.netting.add(deal)?;
+        self.book_trade(&trade)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct EquityCurve {
+    pub close_times: Vec<Option<i64>>,
+    pub equity: Vec<f64>, // Equity after each trade, in chronological order
+    pub peak: Vec<f64>, // Running high-water mark, starting from the initial balance
+    pub drawdown: Vec<f64>, // Peak minus equity
+    pub drawdown_percent: Vec<f64>, // Drawdown relative to the peak; 0 while the peak is not positive
+}
+
+impl EquityCurve {
+    fn from_trades(trades: &[Trade], initial_balance: f64) -> Self {
+        let ordered = chronological(trades);
+        let mut curve = EquityCurve {
+            close_times: Vec::with_capacity(ordered.len()),
+            equity: Vec::with_capacity(ordered.len()),
+            peak: Vec::with_capacity(ordered.len()),
+            drawdown: Vec::with_capacity(ordered.len()),
+            drawdown_percent: Vec::with_capacity(ordered.len()),
+        };
+
+        let mut equity = initial_balance;
+        let mut peak = initial_balance;
+        for trade in ordered {
+            equity += trade.profit;
+            peak = f64::max(peak, equity);
+            let drawdown = peak - equity;
+
+            curve.close_times.push(trade.close_time);
+            curve.equity.push(equity);
+            curve.peak.push(peak);
+            curve.drawdown.push(drawdown);
+            curve.drawdown_percent.push(if peak > 0.0 { drawdown / peak * 100.0 } else { 0.0 });
+        }
+        curve
+    }
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, initial_balance=0.0)))]
+pub fn calculate_equity_curve(trades: Vec<Trade>, initial_balance: f64) -> Result<EquityCurve> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    Ok(EquityCurve::from_trades(&trades, initial_balance))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct DrawdownPeriod {
+    pub start_index: usize, // First trade below the previous peak
+    pub trough_index: usize,
+    pub recovery_index: Option<usize>, // First trade back at the peak; None if still underwater
+    pub depth: f64,
+    pub depth_percent: f64,
+    pub duration_trades: usize, // Trades spent underwater
+    pub recovery_trades: Option<usize>, // Trades from the trough back to the peak
+    pub duration_days: Option<f64>, // Peak to recovery (or last trade); needs close timestamps
+    pub recovery_days: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct DrawdownAnalysis {
+    pub drawdowns: Vec<DrawdownPeriod>, // Largest first, at most `top_n`
+    pub num_drawdowns: usize,
+    pub max_drawdown: f64,
+    pub average_drawdown: f64, // Mean depth over every drawdown period
+    pub time_underwater_percent: f64, // Share of trades closed below the running peak
+}
+
+/// Splits an equity curve into its drawdown periods, in chronological order.
+fn drawdown_periods(curve: &EquityCurve) -> Vec<DrawdownPeriod> {
+    let days_between = |from: Option<i64>, to: Option<i64>| {
+        from.zip(to).map(|(from, to)| (to - from) as f64 / MILLIS_PER_DAY as f64)
+    };
+
+    let mut periods = Vec::new();
+    let mut current: Option<DrawdownPeriod> = None;
+    let mut peak_time = curve.close_times.first().copied().flatten();
+
+    for (i, &drawdown) in curve.drawdown.iter().enumerate() {
+        if drawdown > 0.0 {
+            let period = current.get_or_insert(DrawdownPeriod {
+                start_index: i,
+                trough_index: i,
+                recovery_index: None,
+                depth: 0.0,
+                depth_percent: 0.0,
+                duration_trades: 0,
+                recovery_trades: None,
+                duration_days: None,
+                recovery_days: None,
+            });
+            if drawdown > period.depth {
+                period.trough_index = i;
+                period.depth = drawdown;
+                period.depth_percent = curve.drawdown_percent[i];
+            }
+        } else {
+            if let Some(mut period) = current.take() {
+                period.recovery_index = Some(i);
+                period.duration_trades = i - period.start_index;
+                period.recovery_trades = Some(i - period.trough_index);
+                period.duration_days = days_between(peak_time, curve.close_times[i]);
+                period.recovery_days = days_between(curve.close_times[period.trough_index], curve.close_times[i]);
+                periods.push(period);
+            }
+            peak_time = curve.close_times[i];
+        }
+    }
+
+    if let Some(mut period) = current {
+        period.duration_trades = curve.drawdown.len() - period.start_index;
+        period.duration_days = days_between(peak_time, curve.close_times.last().copied().flatten());
+        periods.push(period);
+    }
+    periods
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, initial_balance=0.0, top_n=5)))]
+pub fn calculate_drawdown_analysis(trades: Vec<Trade>, initial_balance: f64, top_n: usize) -> Result<DrawdownAnalysis> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    let curve = EquityCurve::from_trades(&trades, initial_balance);
+    let mut periods = drawdown_periods(&curve);
+    let num_drawdowns = periods.len();
+    let average_drawdown = if periods.is_empty() {
+        0.0
+    } else {
+        periods.iter().map(|p| p.depth).sum::<f64>() / num_drawdowns as f64
+    };
+    let underwater = curve.drawdown.iter().filter(|&&d| d > 0.0).count();
+
+    periods.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    let max_drawdown = periods.first().map_or(0.0, |p| p.depth);
+    periods.truncate(top_n);
+
+    Ok(DrawdownAnalysis {
+        drawdowns: periods,
+        num_drawdowns,
+        max_drawdown,
+        average_drawdown,
+        time_underwater_percent: underwater as f64 / curve.drawdown.len() as f64 * 100.0,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct StreakAnalysis {
+    pub longest_winning_streak: usize,
+    pub longest_losing_streak: usize,
+    pub current_streak: i64, // Positive for wins, negative for losses, 0 after a breakeven trade
+    pub winning_streaks: HashMap<usize, usize>, // Streak length -> number of streaks
+    pub losing_streaks: HashMap<usize, usize>,
+    pub loss_probability: f64,
+    pub total_trades: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl StreakAnalysis {
+    /// Probability of at least one run of `length` consecutive losses within
+    /// `num_trades` independent trades (defaults to the history length).
+    #[pyo3(name = "losing_streak_probability")]
+    #[pyo3(signature = (length, num_trades=None))]
+    fn py_losing_streak_probability(&self, length: usize, num_trades: Option<usize>) -> f64 {
+        self.losing_streak_probability(length, num_trades)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        hash_repr(&self.py_repr())
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl StreakAnalysis {
+    /// Probability of at least one run of `length` consecutive losses within
+    /// `num_trades` independent trades (defaults to the history length).
+    pub fn losing_streak_probability(&self, length: usize, num_trades: Option<usize>) -> f64 {
+        if length == 0 {
+            return 1.0;
+        }
+        let q = self.loss_probability;
+        // state[k]: probability the run has not happened yet and the last k trades were losses
+        let mut state = vec![0.0; length];
+        state[0] = 1.0;
+        for _ in 0..num_trades.unwrap_or(self.total_trades) {
+            let alive: f64 = state.iter().sum();
+            state.rotate_right(1);
+            state[0] = alive * (1.0 - q);
+            state[1..].iter_mut().for_each(|p| *p *= q);
+        }
+        1.0 - state.iter().sum::<f64>()
+    }
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn calculate_streaks(trades: Vec<Trade>) -> Result<StreakAnalysis> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    let mut winning_streaks = HashMap::new();
+    let mut losing_streaks = HashMap::new();
+    let mut current: i64 = 0;
+    let mut close_streak = |streak: i64| match streak {
+        0 => {}
+        n if n > 0 => *winning_streaks.entry(n as usize).or_insert(0) += 1,
+        n => *losing_streaks.entry(n.unsigned_abs() as usize).or_insert(0) += 1,
+    };
+
+    for trade in chronological(&trades) {
+        let next = if trade.profit > 0.0 {
+            current.max(0) + 1
+        } else if trade.profit < 0.0 {
+            current.min(0) - 1
+        } else {
+            0
+        };
+        if next.signum() != current.signum() {
+            close_streak(current);
+        }
+        current = next;
+    }
+    close_streak(current);
+
+    let losses = trades.iter().filter(|t| t.profit < 0.0).count();
+    Ok(StreakAnalysis {
+        longest_winning_streak: winning_streaks.keys().copied().max().unwrap_or(0),
+        longest_losing_streak: losing_streaks.keys().copied().max().unwrap_or(0),
+        current_streak: current,
+        winning_streaks,
+        losing_streaks,
+        loss_probability: losses as f64 / trades.len() as f64,
+        total_trades: trades.len(),
+    })
+}
+
+/// Calendar bucket for `aggregate_pnl`, in UTC. Weeks start on Monday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum PnlPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl PnlPeriod {
+    /// First day of the bucket containing `date`, and the bucket's label.
+    fn bucket(&self, date: chrono::NaiveDate) -> (chrono::NaiveDate, String) {
+        use chrono::Datelike;
+        match self {
+            PnlPeriod::Day => (date, date.format("%Y-%m-%d").to_string()),
+            PnlPeriod::Week => {
+                let monday = date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64);
+                (monday, date.format("%G-W%V").to_string())
+            }
+            PnlPeriod::Month => (date.with_day(1).unwrap_or(date), date.format("%Y-%m").to_string()),
+        }
+    }
+}
+
+/// P&L of one calendar period, as a row of a calendar-style report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct PeriodPnl {
+    pub label: String, // "2024-01-15", "2024-W03" or "2024-01"
+    pub start_time: i64, // Unix epoch milliseconds at the start of the period
+    pub profit: f64,
+    pub trades: usize,
+    pub win_rate: f64,
+    pub max_drawdown: f64, // Deepest fall from the period's running peak, which starts at zero
+}
+
+/// Groups trades into day, week or month buckets by close time. Only
+/// periods with trades are returned, oldest first.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn aggregate_pnl(trades: Vec<Trade>, period: PnlPeriod) -> Result<Vec<PeriodPnl>> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    // Each period with its winning trade count and running peak profit
+    let mut buckets: std::collections::BTreeMap<chrono::NaiveDate, (PeriodPnl, usize, f64)> =
+        std::collections::BTreeMap::new();
+    for trade in chronological(&trades) {
+        let date = trade
+            .close_time
+            .and_then(chrono::DateTime::from_timestamp_millis)
+            .ok_or_else(|| Error::InsufficientData("Aggregating P&L by period requires close timestamps".into()))?
+            .date_naive();
+        let (start, label) = period.bucket(date);
+        let (pnl, wins, peak) = buckets.entry(start).or_insert_with(|| {
+            let start_time = start.and_hms_opt(0, 0, 0).map_or(0, |dt| dt.and_utc().timestamp_millis());
+            let pnl = PeriodPnl { label, start_time, profit: 0.0, trades: 0, win_rate: 0.0, max_drawdown: 0.0 };
+            (pnl, 0, 0.0)
+        });
+        pnl.profit += trade.profit;
+        pnl.trades += 1;
+        *wins += usize::from(trade.profit > 0.0);
+        *peak = peak.max(pnl.profit);
+        pnl.max_drawdown = pnl.max_drawdown.max(*peak - pnl.profit);
+    }
+
+    Ok(buckets
+        .into_values()
+        .map(|(pnl, wins, _)| PeriodPnl { win_rate: wins as f64 / pnl.trades as f64, ..pnl })
+        .collect())
+}
+
+/// Trades entered in one hour of the day or on one day of the week.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct TimeBucket {
+    pub label: String, // "14:00" or "Monday"
+    pub index: u32, // Hour 0-23, or weekday from 0 = Monday
+    pub trades: usize,
+    pub win_rate: f64,
+    pub expectancy: f64,
+    pub profit: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct TimeBucketAnalysis {
+    pub by_hour: Vec<TimeBucket>, // Only hours with trades, in order
+    pub by_weekday: Vec<TimeBucket>, // Only weekdays with trades, Monday first
+    pub trades_analyzed: usize, // Trades with an open or close time
+    pub utc_offset_hours: f64,
+}
+
+/// Win rate and expectancy by hour of day and day of week, taken at each
+/// trade's open time (its close time if the open is unknown) shifted by
+/// `utc_offset_hours`, e.g. 2.0 to read UTC timestamps in broker time.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, utc_offset_hours=0.0)))]
+pub fn analyze_time_buckets(trades: Vec<Trade>, utc_offset_hours: f64) -> Result<TimeBucketAnalysis> {
+    use chrono::{Datelike, Timelike};
+
+    if utc_offset_hours.is_nan() || utc_offset_hours.abs() >= 24.0 {
+        return Err(Error::Validation("UTC offset must be within 24 hours".into()));
+    }
+    let offset = (utc_offset_hours * 3_600_000.0).round() as i64;
+
+    // Trade count, wins and profit per hour and per weekday
+    let mut hours = [(0usize, 0usize, 0.0f64); 24];
+    let mut weekdays = [(0usize, 0usize, 0.0f64); 7];
+    let mut trades_analyzed = 0;
+    for trade in &trades {
+        let Some(time) = trade
+            .open_time
+            .or(trade.close_time)
+            .and_then(|t| chrono::DateTime::from_timestamp_millis(t + offset))
+        else {
+            continue;
+        };
+        let weekday = time.weekday().num_days_from_monday() as usize;
+        for bucket in [&mut hours[time.hour() as usize], &mut weekdays[weekday]] {
+            bucket.0 += 1;
+            bucket.1 += usize::from(trade.profit > 0.0);
+            bucket.2 += trade.profit;
+        }
+        trades_analyzed += 1;
+    }
+    if trades_analyzed == 0 {
+        return Err(Error::InsufficientData("Time bucket analysis requires trade timestamps".into()));
+    }
+
+    let buckets = |counts: &[(usize, usize, f64)], label: &dyn Fn(usize) -> String| -> Vec<TimeBucket> {
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, (trades, _, _))| *trades > 0)
+            .map(|(index, &(trades, wins, profit))| TimeBucket {
+                label: label(index),
+                index: index as u32,
+                trades,
+                win_rate: wins as f64 / trades as f64,
+                expectancy: profit / trades as f64,
+                profit,
+            })
+            .collect()
+    };
+    const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+    Ok(TimeBucketAnalysis {
+        by_hour: buckets(&hours, &|hour| format!("{:02}:00", hour)),
+        by_weekday: buckets(&weekdays, &|day| WEEKDAYS[day].to_string()),
+        trades_analyzed,
+        utc_offset_hours,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct RMultipleMetrics {
+    pub trades_with_risk: usize, // Trades with a known, non-zero risk_amount
+    pub r_multiples: Vec<f64>, // In chronological order
+    pub win_probability: f64,
+    pub expectancy_r: f64,
+    pub avg_win_r: f64,
+    pub avg_loss_r: f64, // Negative, e.g. -0.9
+    pub r_std: f64,
+    pub kelly_fraction: Option<f64>, // Fraction of equity to risk per trade
+    pub histogram: Vec<(f64, usize)>, // (lower bin edge in R, trade count)
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, bin_width=0.5, fractional_multiplier=1.0)))]
+pub fn calculate_r_multiple_metrics(
+    trades: Vec<Trade>,
+    bin_width: f64,
+    fractional_multiplier: f64,
+) -> Result<RMultipleMetrics> {
+    if bin_width <= 0.0 {
+        return Err(Error::Validation("Bin width must be positive".into()));
+    }
+    let r_multiples: Vec<f64> = chronological(&trades).iter().filter_map(|t| t.r_multiple()).collect();
+    if r_multiples.is_empty() {
+        return Err(Error::InsufficientData("No trades with a risk amount provided".into()));
+    }
+
+    let wins: Vec<f64> = r_multiples.iter().copied().filter(|&r| r > 0.0).collect();
+    let losses: Vec<f64> = r_multiples.iter().copied().filter(|&r| r < 0.0).collect();
+    let average = |values: &[f64]| match values.len() {
+        0 => 0.0,
+        n => values.iter().sum::<f64>() / n as f64,
+    };
+    let win_probability = wins.len() as f64 / r_multiples.len() as f64;
+    let avg_win_r = average(&wins);
+    let avg_loss_r = average(&losses);
+    let (expectancy_r, r_std) = mean_and_std(&r_multiples);
+
+    // Binary Kelly on R, where a loss of 1R is the amount risked
+    let kelly_fraction = if avg_loss_r < 0.0 {
+        calculate_kelly_criterion(win_probability, avg_win_r / avg_loss_r.abs(), fractional_multiplier).ok()
+    } else {
+        None
+    };
+
+    let mut bins: std::collections::BTreeMap<i64, usize> = std::collections::BTreeMap::new();
+    for r in &r_multiples {
+        *bins.entry((r / bin_width).floor() as i64).or_insert(0) += 1;
+    }
+
+    Ok(RMultipleMetrics {
+        trades_with_risk: r_multiples.len(),
+        win_probability,
+        expectancy_r,
+        avg_win_r,
+        avg_loss_r,
+        r_std,
+        kelly_fraction,
+        histogram: bins.into_iter().map(|(bin, count)| (bin as f64 * bin_width, count)).collect(),
+        r_multiples,
+    })
+}
+
+/// Shape of the per-trade return distribution: an equal-width histogram,
+/// the sample moments and the Jarque-Bera test of normality. A normal
+/// sample suits a parametric simulation; fat tails or skew favor the
+/// empirical Kelly and a bootstrap.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ReturnDistribution {
+    pub trades: usize,
+    pub mean: f64,
+    pub std_dev: f64, // Sample standard deviation
+    pub skewness: f64,
+    pub excess_kurtosis: f64, // 0 for a normal distribution
+    pub jarque_bera: f64,
+    pub jarque_bera_p_value: f64, // Chi-square with two degrees of freedom
+    pub bin_edges: Vec<f64>, // One more than the counts, the last bin includes its upper edge
+    pub counts: Vec<usize>,
+}
+
+/// Histogram, moments and normality test of the trade results in the units
+/// of `normalization`.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, bins=20, normalization=ProfitNormalization::Raw, initial_balance=None)))]
+pub fn analyze_return_distribution(
+    trades: Vec<Trade>,
+    bins: usize,
+    normalization: ProfitNormalization,
+    initial_balance: Option<f64>,
+) -> Result<ReturnDistribution> {
+    if bins == 0 {
+        return Err(Error::Validation("At least one bin is required".into()));
+    }
+    let returns = normalized_profits(&trades, normalization, initial_balance)?;
+    let (mean, std_dev) = mean_and_std(&returns);
+    if std_dev == 0.0 {
+        return Err(Error::InsufficientData("The distribution needs at least two distinct trade results".into()));
+    }
+
+    let n = returns.len() as f64;
+    let moment = |k: i32| returns.iter().map(|r| (r - mean).powi(k)).sum::<f64>() / n;
+    let variance = moment(2);
+    let skewness = moment(3) / variance.powf(1.5);
+    let excess_kurtosis = moment(4) / variance.powi(2) - 3.0;
+    let jarque_bera = n / 6.0 * (skewness.powi(2) + excess_kurtosis.powi(2) / 4.0);
+
+    let min = returns.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = returns.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (max - min) / bins as f64;
+    let mut counts = vec![0; bins];
+    for r in &returns {
+        counts[(((r - min) / width) as usize).min(bins - 1)] += 1;
+    }
+
+    Ok(ReturnDistribution {
+        trades: returns.len(),
+        mean,
+        std_dev,
+        skewness,
+        excess_kurtosis,
+        jarque_bera,
+        jarque_bera_p_value: (-jarque_bera / 2.0).exp(),
+        bin_edges: (0..=bins).map(|i| min + width * i as f64).collect(),
+        counts,
+    })
+}
+
+/// Maximum favorable and adverse excursion statistics, with the fixed stop
+/// and target that would have maximized expectancy had every trade been
+/// replayed with them. A trade whose adverse excursion reaches the stop is
+/// taken as stopped out even if it also reached the target, since the order
+/// of the two is unknown.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ExcursionMetrics {
+    pub trades_with_excursions: usize, // Trades with both excursions recorded
+    pub avg_mfe: f64,
+    pub avg_mae: f64,
+    pub edge_ratio: f64, // Average MFE over average MAE; above 1 when trades run further for than against
+    pub avg_mae_winners: f64, // Heat winners took before closing in profit
+    pub avg_mfe_losers: f64, // Open profit losers gave back
+    pub suggested_stop: Option<f64>, // Adverse excursion to exit at, in account currency; None to keep the exits
+    pub suggested_target: Option<f64>,
+    pub win_probability: f64, // As traded
+    pub win_loss_ratio: f64,
+    pub expectancy: f64,
+    pub kelly_fraction: Option<f64>,
+    pub suggested_win_probability: f64, // Replayed with the suggested stop and target
+    pub suggested_win_loss_ratio: f64,
+    pub suggested_expectancy: f64,
+    pub suggested_kelly_fraction: Option<f64>,
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, fractional_multiplier=1.0)))]
+pub fn calculate_excursion_metrics(trades: Vec<Trade>, fractional_multiplier: f64) -> Result<ExcursionMetrics> {
+    let excursions: Vec<(f64, f64, f64)> = trades
+        .iter()
+        .filter_map(|t| Some((t.profit, t.max_favorable_excursion?, t.max_adverse_excursion?)))
+        .collect();
+    if excursions.is_empty() {
+        return Err(Error::InsufficientData("No trades with both excursions provided".into()));
+    }
+
+    let average = |values: &mut dyn Iterator<Item = f64>| {
+        let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+        if count == 0 { 0.0 } else { sum / count as f64 }
+    };
+    let avg_mfe = average(&mut excursions.iter().map(|e| e.1));
+    let avg_mae = average(&mut excursions.iter().map(|e| e.2));
+
+    // Outcome of a trade exited at the stop or target, if reached
+    let replay = |(profit, mfe, mae): (f64, f64, f64), stop: Option<f64>, target: Option<f64>| match (stop, target) {
+        (Some(stop), _) if mae >= stop => -stop,
+        (_, Some(target)) if mfe >= target => target,
+        _ => profit,
+    };
+    let outcomes = |stop: Option<f64>, target: Option<f64>| -> Vec<f64> {
+        excursions.iter().map(|&e| replay(e, stop, target)).collect()
+    };
+
+    // Candidate levels at every fifth percentile of each excursion
+    let levels = |values: Vec<f64>| -> Vec<Option<f64>> {
+        let mut sorted: Vec<f64> = values.into_iter().filter(|&v| v > 0.0).collect();
+        sorted.sort_by(f64::total_cmp);
+        let mut levels: Vec<Option<f64>> = (1..=20).map(|i| Some(percentile(&sorted, i as f64 * 5.0))).collect();
+        levels.dedup();
+        levels.retain(|level| level.is_some_and(|l| l > 0.0));
+        levels.insert(0, None);
+        levels
+    };
+    let stops = levels(excursions.iter().map(|e| e.2).collect());
+    let targets = levels(excursions.iter().map(|e| e.1).collect());
+    let expectancy_of = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+
+    let mut best = (None, None, expectancy_of(&outcomes(None, None)));
+    for &stop in &stops {
+        for &target in &targets {
+            let expectancy = expectancy_of(&outcomes(stop, target));
+            if expectancy > best.2 {
+                best = (stop, target, expectancy);
+            }
+        }
+    }
+    let (suggested_stop, suggested_target, _) = best;
+
+    // Win rate, payoff ratio, expectancy and Kelly of a set of outcomes
+    let edge = |values: &[f64]| {
+        let wins: Vec<f64> = values.iter().copied().filter(|&v| v > 0.0).collect();
+        let losses: Vec<f64> = values.iter().copied().filter(|&v| v < 0.0).collect();
+        let win_probability = wins.len() as f64 / values.len() as f64;
+        let avg_win = average(&mut wins.iter().copied());
+        let avg_loss = average(&mut losses.iter().copied()).abs();
+        let win_loss_ratio = if avg_loss > 0.0 { avg_win / avg_loss } else { 0.0 };
+        let kelly_fraction = (!wins.is_empty() && !losses.is_empty())
+            .then(|| calculate_kelly_criterion(win_probability, win_loss_ratio, fractional_multiplier).ok())
+            .flatten();
+        (win_probability, win_loss_ratio, expectancy_of(values), kelly_fraction)
+    };
+    let (win_probability, win_loss_ratio, expectancy, kelly_fraction) = edge(&outcomes(None, None));
+    let (suggested_win_probability, suggested_win_loss_ratio, suggested_expectancy, suggested_kelly_fraction) =
+        edge(&outcomes(suggested_stop, suggested_target));
+
+    Ok(ExcursionMetrics {
+        trades_with_excursions: excursions.len(),
+        avg_mfe,
+        avg_mae,
+        edge_ratio: if avg_mae > 0.0 { avg_mfe / avg_mae } else { f64::INFINITY },
+        avg_mae_winners: average(&mut excursions.iter().filter(|e| e.0 > 0.0).map(|e| e.2)),
+        avg_mfe_losers: average(&mut excursions.iter().filter(|e| e.0 < 0.0).map(|e| e.1)),
+        suggested_stop,
+        suggested_target,
+        win_probability,
+        win_loss_ratio,
+        expectancy,
+        kelly_fraction,
+        suggested_win_probability,
+        suggested_win_loss_ratio,
+        suggested_expectancy,
+        suggested_kelly_fraction,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct SymbolMetrics {
+    pub symbol: String,
+    pub metrics: PerformanceMetrics,
+    pub kelly_fraction: Option<f64>, // None unless the symbol has both wins and losses
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, risk_free_rate=0.0, initial_balance=None, fractional_multiplier=1.0))]
+fn calculate_metrics_by_symbol(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    fractional_multiplier: f64,
+) -> Result<HashMap<String, SymbolMetrics>> {
+    py.allow_threads(|| metrics_by_symbol(trades, risk_free_rate, initial_balance, fractional_multiplier))
+}
+
+pub fn metrics_by_symbol(
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    fractional_multiplier: f64,
+) -> Result<HashMap<String, SymbolMetrics>> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    let mut by_symbol: HashMap<String, Vec<Trade>> = HashMap::new();
+    for trade in trades {
+        by_symbol.entry(trade.symbol.clone()).or_default().push(trade);
+    }
+
+    by_symbol
+        .into_iter()
+        .map(|(symbol, trades)| {
+            let metrics = compute_performance_metrics(
+                trades,
+                risk_free_rate,
+                initial_balance,
+                ProfitNormalization::Raw,
+                BetaPrior::default(),
+            )?;
+            let kelly_fraction =
+                calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, fractional_multiplier).ok();
+            let breakdown = SymbolMetrics {
+                symbol: symbol.clone(),
+                metrics,
+                kelly_fraction,
+            };
+            Ok((symbol, breakdown))
+        })
+        .collect()
+}
+
+/// Metrics and Kelly suggestion for buys and sells separately, since an
+/// edge often exists on one side only. A side without trades is `None`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct DirectionMetrics {
+    pub long: Option<PerformanceMetrics>,
+    pub short: Option<PerformanceMetrics>,
+    pub long_kelly_fraction: Option<f64>, // None unless the side has both wins and losses
+    pub short_kelly_fraction: Option<f64>,
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, risk_free_rate=0.0, initial_balance=None, fractional_multiplier=1.0))]
+fn calculate_metrics_by_direction(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    fractional_multiplier: f64,
+) -> Result<DirectionMetrics> {
+    py.allow_threads(|| metrics_by_direction(trades, risk_free_rate, initial_balance, fractional_multiplier))
+}
+
+pub fn metrics_by_direction(
+    trades: Vec<Trade>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    fractional_multiplier: f64,
+) -> Result<DirectionMetrics> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    let (long, short): (Vec<Trade>, Vec<Trade>) = trades.into_iter().partition(|t| t.trade_type == TradeType::Buy);
+    let side = |trades: Vec<Trade>| -> Result<(Option<PerformanceMetrics>, Option<f64>)> {
+        if trades.is_empty() {
+            return Ok((None, None));
+        }
+        let metrics = compute_performance_metrics(
+            trades,
+            risk_free_rate,
+            initial_balance,
+            ProfitNormalization::Raw,
+            BetaPrior::default(),
+        )?;
+        let kelly_fraction =
+            calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, fractional_multiplier).ok();
+        Ok((Some(metrics), kelly_fraction))
+    };
+    let (long, long_kelly_fraction) = side(long)?;
+    let (short, short_kelly_fraction) = side(short)?;
+
+    Ok(DirectionMetrics {
+        long,
+        short,
+        long_kelly_fraction,
+        short_kelly_fraction,
+    })
+}
+
+/// Trades whose holding time falls in `[min_hours, max_hours)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct DurationBucket {
+    pub label: String, // "< 1h", "1h to 24h", ">= 24h"
+    pub min_hours: f64,
+    pub max_hours: Option<f64>, // None for the open-ended last bucket
+    pub metrics: PerformanceMetrics,
+    pub kelly_fraction: Option<f64>, // None unless the bucket has both wins and losses
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct HoldingTimeMetrics {
+    pub trades_analyzed: usize, // Trades with both open and close times
+    pub avg_holding_hours: f64,
+    pub median_holding_hours: f64,
+    pub avg_winner_holding_hours: f64,
+    pub avg_loser_holding_hours: f64,
+    pub buckets: Vec<DurationBucket>, // Only buckets with trades, shortest first
+}
+
+/// Holding time statistics and metrics per duration bucket. The default
+/// `boundaries_hours` of `[1, 24]` separate scalps, intraday and swing trades.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, boundaries_hours=None, fractional_multiplier=1.0))]
+fn calculate_holding_time_metrics(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    boundaries_hours: Option<Vec<f64>>,
+    fractional_multiplier: f64,
+) -> Result<HoldingTimeMetrics> {
+    py.allow_threads(|| holding_time_metrics(trades, boundaries_hours, fractional_multiplier))
+}
+
+pub fn holding_time_metrics(
+    trades: Vec<Trade>,
+    boundaries_hours: Option<Vec<f64>>,
+    fractional_multiplier: f64,
+) -> Result<HoldingTimeMetrics> {
+    let boundaries = boundaries_hours.unwrap_or_else(|| vec![1.0, 24.0]);
+    if boundaries.iter().any(|&b| !(b > 0.0 && b.is_finite())) || boundaries.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(Error::Validation("Duration boundaries must be positive and increasing".into()));
+    }
+
+    let timed: Vec<(f64, Trade)> = trades
+        .into_iter()
+        .filter_map(|t| Some(((t.close_time? - t.open_time?) as f64 / 3_600_000.0, t)))
+        .collect();
+    if timed.is_empty() {
+        return Err(Error::InsufficientData("Holding times require open and close timestamps".into()));
+    }
+
+    let average = |hours: Vec<f64>| if hours.is_empty() { 0.0 } else { hours.iter().sum::<f64>() / hours.len() as f64 };
+    let mut sorted: Vec<f64> = timed.iter().map(|(hours, _)| *hours).collect();
+    sorted.sort_by(f64::total_cmp);
+    let avg_winner_holding_hours = average(timed.iter().filter(|(_, t)| t.profit > 0.0).map(|(h, _)| *h).collect());
+    let avg_loser_holding_hours = average(timed.iter().filter(|(_, t)| t.profit < 0.0).map(|(h, _)| *h).collect());
+
+    let mut grouped: Vec<Vec<Trade>> = vec![Vec::new(); boundaries.len() + 1];
+    for (hours, trade) in timed {
+        grouped[boundaries.partition_point(|&b| b <= hours)].push(trade);
+    }
+    let buckets = grouped
+        .into_iter()
+        .enumerate()
+        .filter(|(_, trades)| !trades.is_empty())
+        .map(|(i, trades)| {
+            let min_hours = if i == 0 { 0.0 } else { boundaries[i - 1] };
+            let max_hours = boundaries.get(i).copied();
+            let label = match max_hours {
+                Some(max) if i == 0 => format!("< {}h", max),
+                Some(max) => format!("{}h to {}h", min_hours, max),
+                None => format!(">= {}h", min_hours),
+            };
+            let metrics =
+                compute_performance_metrics(trades, 0.0, None, ProfitNormalization::Raw, BetaPrior::default())?;
+            let kelly_fraction =
+                calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, fractional_multiplier).ok();
+            Ok(DurationBucket { label, min_hours, max_hours, metrics, kelly_fraction })
+        })
+        .collect::<Result<Vec<DurationBucket>>>()?;
+
+    Ok(HoldingTimeMetrics {
+        trades_analyzed: sorted.len(),
+        avg_holding_hours: average(sorted.clone()),
+        median_holding_hours: median(&sorted),
+        avg_winner_holding_hours,
+        avg_loser_holding_hours,
+        buckets,
+    })
+}
+
+/// Metrics over a sliding window of consecutive trades, one entry per window
+/// in chronological order, to show whether the edge is stable or decaying.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct RollingMetrics {
+    pub window: usize,
+    pub step: usize,
+    pub end_indices: Vec<usize>, // Chronological index of the last trade in each window
+    pub close_times: Vec<Option<i64>>, // Close time of the last trade in each window
+    pub metrics: Vec<PerformanceMetrics>,
+    pub kelly_fraction: Vec<Option<f64>>, // None for windows without both wins and losses
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, window, step=1, risk_free_rate=0.0, initial_balance=None, fractional_multiplier=1.0))]
+fn rolling_metrics(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    window: usize,
+    step: usize,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    fractional_multiplier: f64,
+) -> Result<RollingMetrics> {
+    py.allow_threads(|| {
+        rolling_windows(trades, window, step, risk_free_rate, initial_balance, fractional_multiplier)
+    })
+}
+
+pub fn rolling_windows(
+    trades: Vec<Trade>,
+    window: usize,
+    step: usize,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+    fractional_multiplier: f64,
+) -> Result<RollingMetrics> {
+    use rayon::prelude::*;
+
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if window == 0 || step == 0 {
+        return Err(Error::Validation("Window and step must be positive".into()));
+    }
+    if window > trades.len() {
+        return Err(Error::InsufficientData(format!(
+            "Window of {} trades is longer than the history of {} trades",
+            window,
+            trades.len()
+        )));
+    }
+
+    let ordered: Vec<Trade> = chronological(&trades).into_iter().cloned().collect();
+    // Balance before each trade, so every window's ratios start from the equity it actually had
+    let balances: Option<Vec<f64>> = initial_balance.map(|balance| {
+        ordered
+            .iter()
+            .scan(balance, |equity, trade| {
+                let before = *equity;
+                *equity += trade.profit;
+                Some(before)
+            })
+            .collect()
+    });
+
+    let starts: Vec<usize> = (0..=ordered.len() - window).step_by(step).collect();
+    let results: Vec<(PerformanceMetrics, Option<f64>)> = starts
+        .par_iter()
+        .map(|&start| {
+            let slice = ordered[start..start + window].to_vec();
+            let balance = balances.as_ref().map(|b| b[start]);
+            let metrics = compute_performance_metrics(
+                slice,
+                risk_free_rate,
+                balance,
+                ProfitNormalization::Raw,
+                BetaPrior::default(),
+            )?;
+            let kelly_fraction =
+                calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, fractional_multiplier).ok();
+            Ok((metrics, kelly_fraction))
+        })
+        .collect::<Result<_>>()?;
+
+    let end_indices: Vec<usize> = starts.iter().map(|start| start + window - 1).collect();
+    let (metrics, kelly_fraction) = results.into_iter().unzip();
+    Ok(RollingMetrics {
+        window,
+        step,
+        close_times: end_indices.iter().map(|&i| ordered[i].close_time).collect(),
+        end_indices,
+        metrics,
+        kelly_fraction,
+    })
+}
+
+/// Trades without a `tag` are grouped under this label.
+const UNTAGGED: &str = "untagged";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct TagMetrics {
+    pub tag: String,
+    pub metrics: PerformanceMetrics,
+    pub kelly_fraction: Option<f64>, // None unless the tag has both wins and losses
+    pub frequency: f64, // Share of all trades carrying this tag
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct RegimeKelly {
+    pub tags: HashMap<String, TagMetrics>,
+    pub blended_kelly_fraction: f64, // Frequency-weighted Kelly; tags without a positive edge count as 0
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, fractional_multiplier=1.0, risk_free_rate=0.0, initial_balance=None))]
+fn calculate_kelly_by_tag(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    fractional_multiplier: f64,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+) -> Result<RegimeKelly> {
+    py.allow_threads(|| kelly_by_tag(trades, fractional_multiplier, risk_free_rate, initial_balance))
+}
+
+pub fn kelly_by_tag(
+    trades: Vec<Trade>,
+    fractional_multiplier: f64,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+) -> Result<RegimeKelly> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    let total_trades = trades.len() as f64;
+    let mut by_tag: HashMap<String, Vec<Trade>> = HashMap::new();
+    for trade in trades {
+        let tag = trade.tag.clone().unwrap_or_else(|| UNTAGGED.to_string());
+        by_tag.entry(tag).or_default().push(trade);
+    }
+
+    let tags = by_tag
+        .into_iter()
+        .map(|(tag, trades)| {
+            let frequency = trades.len() as f64 / total_trades;
+            let metrics = compute_performance_metrics(
+                trades,
+                risk_free_rate,
+                initial_balance,
+                ProfitNormalization::Raw,
+                BetaPrior::default(),
+            )?;
+            let kelly_fraction =
+                calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, fractional_multiplier).ok();
+            let breakdown = TagMetrics {
+                tag: tag.clone(),
+                metrics,
+                kelly_fraction,
+                frequency,
+            };
+            Ok((tag, breakdown))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    // Sizing is only applied in regimes with an edge, so the others contribute nothing
+    let blended_kelly_fraction = tags
+        .values()
+        .map(|t| t.frequency * t.kelly_fraction.unwrap_or(0.0).max(0.0))
+        .sum();
+    Ok(RegimeKelly {
+        tags,
+        blended_kelly_fraction,
+    })
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn calculate_kelly_criterion(win_prob: f64, win_loss_ratio: f64, fractional_multiplier: f64) -> Result<f64> {
+    if win_prob <= 0.0 || win_prob >= 1.0 {
+        return Err(Error::Validation("Win probability must be between 0 and 1".into()));
+    }
+    if win_loss_ratio <= 0.0 {
+        return Err(Error::Validation("Win/loss ratio must be positive".into()));
+    }
+
+    let kelly_fraction = win_prob - ((1.0 - win_prob) / win_loss_ratio);
+    let optimal_fraction = kelly_fraction * fractional_multiplier;
+
+    Ok(optimal_fraction)
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, fractional_multiplier=1.0)))]
+pub fn calculate_kelly_from_trades(trades: Vec<Trade>, fractional_multiplier: f64) -> Result<KellyResult> {
+    let metrics =
+        compute_performance_metrics(trades.clone(), 0.0, None, ProfitNormalization::Raw, BetaPrior::default())?;
+    let kelly_fraction =
+        calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, fractional_multiplier)?;
+
+    // Express every trade in units of the average loss so the continuous
+    // estimate is, like the binary one, a fraction of equity to risk.
+    let unit = metrics.avg_loss.abs();
+    let returns: Vec<f64> = trades.iter().map(|t| t.profit / unit).collect();
+    let (mean_return, std) = mean_and_std(&returns);
+    let return_variance = std * std;
+    if return_variance == 0.0 {
+        return Err(Error::InsufficientData("Trade returns have zero variance".into()));
+    }
+
+    Ok(KellyResult {
+        win_probability: metrics.win_probability,
+        win_loss_ratio: metrics.win_loss_ratio,
+        mean_return,
+        return_variance,
+        fractional_multiplier,
+        kelly_fraction,
+        continuous_kelly_fraction: mean_return / return_variance * fractional_multiplier,
+    })
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, fractional_multiplier=1.0)))]
+pub fn calculate_kelly_empirical(trades: Vec<Trade>, fractional_multiplier: f64) -> Result<f64> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+
+    // Returns per unit risked: R-multiples when every trade carries its risk,
+    // otherwise multiples of the average loss as in calculate_kelly_from_trades
+    let returns: Vec<f64> = match trades.iter().map(|t| t.r_multiple()).collect::<Option<Vec<f64>>>() {
+        Some(r_multiples) => r_multiples,
+        None => {
+            let losses: Vec<f64> = trades.iter().map(|t| t.profit).filter(|&p| p < 0.0).collect();
+            let unit = losses.iter().sum::<f64>().abs() / losses.len() as f64;
+            trades.iter().map(|t| t.profit / unit).collect()
+        }
+    };
+
+    let worst = returns.iter().copied().fold(0.0, f64::min);
+    if worst >= 0.0 {
+        return Err(Error::InsufficientData("Trade history needs at least one loss".into()));
+    }
+    // Stop just short of the fraction at which the worst trade is a total loss
+    let upper = 1.0 / worst.abs() * (1.0 - 1e-9);
+    let (kelly, _) = maximize_log_growth(&returns, 0.0, upper, 1e-10);
+
+    Ok(kelly * fractional_multiplier)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct BayesianKellyResult {
+    pub win_probability: f64, // Raw sample win rate
+    pub posterior_win_probability: f64,
+    pub posterior_alpha: f64,
+    pub posterior_beta: f64,
+    pub credible_interval: (f64, f64), // 5th and 95th percentiles of the posterior win rate
+    pub win_loss_ratio: f64,
+    pub fractional_multiplier: f64,
+    pub raw_kelly_fraction: f64, // Kelly from the raw win rate
+    pub kelly_fraction: f64, // Kelly maximizing growth averaged over the posterior
+    pub conservative_kelly_fraction: f64, // Kelly at the lower end of the credible interval
+}
+
+/// Kelly fraction that maximizes log growth averaged over a Beta posterior on
+/// the win rate. Expected log growth is linear in the win rate, so the
+/// average is exactly the growth at the posterior mean; small samples are
+/// pulled towards the prior instead of trusting a lucky streak.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, fractional_multiplier=1.0, prior_win_rate=0.5, prior_strength=2.0)))]
+pub fn calculate_kelly_bayesian(
+    trades: Vec<Trade>,
+    fractional_multiplier: f64,
+    prior_win_rate: f64,
+    prior_strength: f64,
+) -> Result<BayesianKellyResult> {
+    let prior = BetaPrior::new(prior_win_rate, prior_strength)?;
+    let profits: Vec<f64> = trades.iter().map(|t| t.profit).collect();
+    let wins = profits.iter().filter(|&&p| p > 0.0).count();
+    if wins == 0 || !profits.iter().any(|&p| p < 0.0) {
+        return Err(Error::InsufficientData(
+            "Bayesian Kelly needs at least one winning and one losing trade".into(),
+        ));
+    }
+
+    let win_loss_ratio = robust_win_loss_ratio(&profits);
+    let kelly = |p: f64| (p - (1.0 - p) / win_loss_ratio) * fractional_multiplier;
+    let (alpha, beta) = prior.posterior(wins, profits.len());
+    let win_probability = wins as f64 / profits.len() as f64;
+    let posterior_win_probability = alpha / (alpha + beta);
+    let credible_interval = (beta_quantile(0.05, alpha, beta), beta_quantile(0.95, alpha, beta));
+
+    Ok(BayesianKellyResult {
+        win_probability,
+        posterior_win_probability,
+        posterior_alpha: alpha,
+        posterior_beta: beta,
+        credible_interval,
+        win_loss_ratio,
+        fractional_multiplier,
+        raw_kelly_fraction: kelly(win_probability),
+        kelly_fraction: kelly(posterior_win_probability),
+        conservative_kelly_fraction: kelly(credible_interval.0),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct WalkForwardFold {
+    pub train_start: usize, // Chronological index of the first training trade
+    pub test_start: usize,
+    pub test_end: usize, // Exclusive
+    pub kelly_fraction: f64, // Fitted on the training window; 0 without a measurable edge
+    pub in_sample_growth: f64, // Mean log growth per trade
+    pub out_of_sample_growth: f64,
+    pub in_sample_drawdown_percent: f64,
+    pub out_of_sample_drawdown_percent: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct WalkForwardResult {
+    pub folds: Vec<WalkForwardFold>,
+    pub in_sample_growth: f64, // Mean over folds
+    pub out_of_sample_growth: f64,
+    pub out_of_sample_twr: f64, // Terminal wealth relative of all test windows traded back to back
+    pub out_of_sample_drawdown_percent: f64, // Max drawdown of the same stitched sequence
+    pub efficiency: Option<f64>, // Out-of-sample over in-sample growth; None without in-sample growth
+}
+
+/// Refits Kelly on a rolling training window and trades the next
+/// `test_window` trades with it, so the sizing is judged on data it was not
+/// fitted to. Returns are measured in units of the training average loss.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, train_window, test_window, fractional_multiplier=1.0)))]
+pub fn walk_forward_kelly(
+    trades: Vec<Trade>,
+    train_window: usize,
+    test_window: usize,
+    fractional_multiplier: f64,
+) -> Result<WalkForwardResult> {
+    if train_window == 0 || test_window == 0 {
+        return Err(Error::Validation("Training and test windows must be positive".into()));
+    }
+    if train_window + test_window > trades.len() {
+        return Err(Error::InsufficientData(format!(
+            "Walk-forward needs at least {} trades, got {}",
+            train_window + test_window,
+            trades.len()
+        )));
+    }
+
+    let ordered: Vec<Trade> = chronological(&trades).into_iter().cloned().collect();
+    let mut folds = Vec::new();
+    let mut out_of_sample_steps = Vec::new();
+    let mut train_start = 0;
+    while train_start + train_window + test_window <= ordered.len() {
+        let test_start = train_start + train_window;
+        let test_end = test_start + test_window;
+        let train = &ordered[train_start..test_start];
+
+        let metrics =
+            compute_performance_metrics(train.to_vec(), 0.0, None, ProfitNormalization::Raw, BetaPrior::default())?;
+        let kelly_fraction = calculate_kelly_criterion(metrics.win_probability, metrics.win_loss_ratio, 1.0)
+            .map_or(0.0, |k| (k * fractional_multiplier).max(0.0));
+        let unit = metrics.avg_loss.abs();
+        let returns = |window: &[Trade]| -> Vec<f64> {
+            window.iter().map(|t| if unit > 0.0 { t.profit / unit } else { 0.0 }).collect()
+        };
+        let (train_returns, test_returns) = (returns(train), returns(&ordered[test_start..test_end]));
+
+        out_of_sample_steps.extend(test_returns.iter().map(|r| kelly_fraction * r));
+        folds.push(WalkForwardFold {
+            train_start,
+            test_start,
+            test_end,
+            kelly_fraction,
+            in_sample_growth: log_growth(&train_returns, kelly_fraction),
+            out_of_sample_growth: log_growth(&test_returns, kelly_fraction),
+            in_sample_drawdown_percent: compounded_max_drawdown(train_returns.into_iter(), kelly_fraction),
+            out_of_sample_drawdown_percent: compounded_max_drawdown(test_returns.into_iter(), kelly_fraction),
+        });
+        train_start += test_window;
+    }
+
+    let mean = |growth: fn(&WalkForwardFold) -> f64| folds.iter().map(growth).sum::<f64>() / folds.len() as f64;
+    let in_sample_growth = mean(|f| f.in_sample_growth);
+    let out_of_sample_growth = mean(|f| f.out_of_sample_growth);
+    Ok(WalkForwardResult {
+        in_sample_growth,
+        out_of_sample_growth,
+        out_of_sample_twr: out_of_sample_steps.iter().map(|step| (1.0 + step).max(0.0)).product(),
+        out_of_sample_drawdown_percent: compounded_max_drawdown(out_of_sample_steps.into_iter(), 1.0),
+        efficiency: (in_sample_growth > 0.0).then(|| out_of_sample_growth / in_sample_growth),
+        folds,
+    })
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, iterations=1000, seed=None, fractional_multiplier=1.0))]
+fn calculate_kelly_confidence(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    iterations: usize,
+    seed: Option<u64>,
+    fractional_multiplier: f64,
+) -> Result<KellyDistribution> {
+    py.allow_threads(|| kelly_confidence(trades, iterations, seed, fractional_multiplier))
+}
+
+pub fn kelly_confidence(
+    trades: Vec<Trade>,
+    iterations: usize,
+    seed: Option<u64>,
+    fractional_multiplier: f64,
+) -> Result<KellyDistribution> {
+    use rand::Rng;
+    use rayon::prelude::*;
+
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if iterations == 0 {
+        return Err(Error::Validation("Iterations must be positive".into()));
+    }
+
+    let profits: Vec<f64> = trades.iter().map(|t| t.profit).collect();
+    let point_estimate = binary_kelly(&profits)
+        .ok_or_else(|| Error::InsufficientData("Trade history needs at least one win and one loss".into()))?
+        * fractional_multiplier;
+
+    // Each resample draws from its own stream, so the result depends only on the seed
+    let base_seed = seed.unwrap_or_else(rand::random);
+    let mut estimates: Vec<f64> = (0..iterations)
+        .into_par_iter()
+        .filter_map(|index| {
+            let mut rng = simulation_rng(base_seed, index as u64);
+            let sample: Vec<f64> = profits.iter().map(|_| profits[rng.gen_range(0..profits.len())]).collect();
+            // Resamples that happen to contain only wins or only losses have no defined Kelly
+            binary_kelly(&sample).map(|kelly| kelly * fractional_multiplier)
+        })
+        .collect();
+
+    if estimates.is_empty() {
+        return Err(Error::InsufficientData("No bootstrap sample contained both wins and losses".into()));
+    }
+    estimates.sort_by(f64::total_cmp);
+
+    Ok(KellyDistribution {
+        point_estimate,
+        iterations,
+        valid_samples: estimates.len(),
+        mean: estimates.iter().sum::<f64>() / estimates.len() as f64,
+        median: median(&estimates),
+        percentile_5: percentile(&estimates, 5.0),
+        percentile_95: percentile(&estimates, 95.0),
+        conservative_kelly: percentile(&estimates, 25.0),
+    })
+}
+
+/// Correlation of the symbols' daily P&L over the days from the first to the
+/// last trade close, skipping weekends nothing was traded on. `daily_returns`
+/// holds the aligned series, ready for `calculate_kelly_portfolio`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct SymbolCorrelation {
+    pub symbols: Vec<String>, // Sorted; the row and column order of `matrix`
+    pub matrix: Vec<Vec<f64>>, // Pearson correlation; 0 against a symbol with constant daily P&L
+    pub days: usize,
+    pub daily_returns: HashMap<String, Vec<f64>>, // Daily P&L, over the initial balance when given
+    pub correlated_pairs: Vec<(String, String, f64)>, // Pairs at or above the threshold in absolute value
+    pub threshold: f64,
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, initial_balance=None, threshold=0.7)))]
+pub fn calculate_symbol_correlation(
+    trades: Vec<Trade>,
+    initial_balance: Option<f64>,
+    threshold: f64,
+) -> Result<SymbolCorrelation> {
+    if initial_balance.is_some_and(|b| b <= 0.0) {
+        return Err(Error::Validation("Initial balance must be positive".into()));
+    }
+    let mut pnl_by_symbol: HashMap<String, HashMap<i64, f64>> = HashMap::new();
+    for trade in &trades {
+        let day = trade_day(trade)
+            .ok_or_else(|| Error::InsufficientData("Symbol correlation requires close timestamps".into()))?;
+        *pnl_by_symbol.entry(trade.symbol.clone()).or_default().entry(day).or_insert(0.0) += trade.profit;
+    }
+    let traded_days: std::collections::HashSet<i64> = pnl_by_symbol.values().flat_map(|d| d.keys().copied()).collect();
+    let (Some(&first), Some(&last)) = (traded_days.iter().min(), traded_days.iter().max()) else {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    };
+    let days: Vec<i64> = (first..=last).filter(|day| !is_weekend(*day) || traded_days.contains(day)).collect();
+    if days.len() < 2 {
+        return Err(Error::InsufficientData("At least two trading days are required".into()));
+    }
+
+    let mut symbols: Vec<String> = pnl_by_symbol.keys().cloned().collect();
+    symbols.sort();
+    let scale = initial_balance.unwrap_or(1.0);
+    let series: Vec<Vec<f64>> = symbols
+        .iter()
+        .map(|symbol| days.iter().map(|day| pnl_by_symbol[symbol].get(day).copied().unwrap_or(0.0) / scale).collect())
+        .collect();
+
+    let matrix = correlation_matrix(&series);
+
+    let mut correlated_pairs = Vec::new();
+    for i in 0..symbols.len() {
+        for j in i + 1..symbols.len() {
+            if matrix[i][j].abs() >= threshold {
+                correlated_pairs.push((symbols[i].clone(), symbols[j].clone(), matrix[i][j]));
+            }
+        }
+    }
+
+    Ok(SymbolCorrelation {
+        daily_returns: symbols.iter().cloned().zip(series).collect(),
+        symbols,
+        matrix,
+        days: days.len(),
+        correlated_pairs,
+        threshold,
+    })
+}
+
+/// Pearson correlation of equally long series; 0 against a constant series.
+fn correlation_matrix(series: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let slices: Vec<&[f64]> = series.iter().map(Vec::as_slice).collect();
+    let means: Vec<f64> = slices.iter().map(|s| s.iter().sum::<f64>() / s.len() as f64).collect();
+    let covariance = covariance_matrix(&slices, &means);
+    (0..series.len())
+        .map(|i| {
+            (0..series.len())
+                .map(|j| match (i == j, (covariance[i][i] * covariance[j][j]).sqrt()) {
+                    (true, _) => 1.0,
+                    (false, scale) if scale > 0.0 => covariance[i][j] / scale,
+                    _ => 0.0,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (returns_by_symbol, fractional_multiplier=1.0)))]
+pub fn calculate_kelly_portfolio(
+    returns_by_symbol: HashMap<String, Vec<f64>>,
+    fractional_multiplier: f64,
+) -> Result<HashMap<String, f64>> {
+    let PortfolioMoments { symbols, means, covariance } = portfolio_moments(&returns_by_symbol)?;
+
+    // Multivariate Kelly: f* = Σ⁻¹ μ
+    let fractions = solve_linear_system(covariance, means).ok_or_else(singular_covariance)?;
+
+    Ok(symbols
+        .into_iter()
+        .zip(fractions)
+        .map(|(symbol, f)| (symbol, f * fractional_multiplier))
+        .collect())
+}
+
+/// Symbols in sorted order with the mean and covariance of their returns.
+struct PortfolioMoments {
+    symbols: Vec<String>,
+    means: Vec<f64>,
+    covariance: Vec<Vec<f64>>,
+}
+
+fn portfolio_moments(returns_by_symbol: &HashMap<String, Vec<f64>>) -> Result<PortfolioMoments> {
+    if returns_by_symbol.is_empty() {
+        return Err(Error::InsufficientData("No symbols provided".into()));
+    }
+
+    let mut symbols: Vec<String> = returns_by_symbol.keys().cloned().collect();
+    symbols.sort();
+    let series: Vec<&[f64]> = symbols.iter().map(|s| returns_by_symbol[s].as_slice()).collect();
+
+    let observations = series[0].len();
+    if series.iter().any(|s| s.len() != observations) {
+        return Err(Error::Validation("Return series must all have the same length".into()));
+    }
+    if observations < 2 {
+        return Err(Error::InsufficientData("At least two return observations are required".into()));
+    }
+
+    let means: Vec<f64> = series.iter().map(|s| s.iter().sum::<f64>() / observations as f64).collect();
+    let covariance = covariance_matrix(&series, &means);
+    Ok(PortfolioMoments { symbols, means, covariance })
+}
+
+fn singular_covariance() -> Error {
+    Error::Validation("Covariance matrix is singular; remove duplicate or constant series".into())
+}
+
+/// Multivariate Kelly on instrument price returns, expressed as leverage:
+/// signed notional exposure over equity. Each symbol's `SymbolSpec` sets the
+/// margin one unit of leverage ties up (`margin_rate / leverage`); when the
+/// allocation needs more than `max_margin_fraction` of equity as margin it is
+/// re-solved under that budget rather than scaled down uniformly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct LeveragedKelly {
+    pub leverage: HashMap<String, f64>, // Executable allocation; negative for short exposure
+    pub unconstrained_leverage: HashMap<String, f64>, // Fractional Kelly ignoring margin
+    pub margin_fraction: HashMap<String, f64>, // Share of equity each position ties up as margin
+    pub total_margin_fraction: f64,
+    pub max_margin_fraction: f64,
+    pub constrained: bool, // The unconstrained allocation needed more margin than allowed
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (returns_by_symbol, symbol_specs, fractional_multiplier=1.0, max_margin_fraction=1.0)))]
+pub fn calculate_kelly_portfolio_with_margin(
+    returns_by_symbol: HashMap<String, Vec<f64>>,
+    symbol_specs: SymbolRegistry,
+    fractional_multiplier: f64,
+    max_margin_fraction: f64,
+) -> Result<LeveragedKelly> {
+    if fractional_multiplier <= 0.0 {
+        return Err(Error::Validation("Fractional multiplier must be positive".into()));
+    }
+    if max_margin_fraction <= 0.0 {
+        return Err(Error::Validation("Max margin fraction must be positive".into()));
+    }
+    let PortfolioMoments { symbols, means, covariance } = portfolio_moments(&returns_by_symbol)?;
+    let margins = symbols
+        .iter()
+        .map(|symbol| symbol_specs.lookup(symbol).map(|spec| spec.margin_rate / spec.leverage))
+        .collect::<Result<Vec<f64>>>()?;
+    let margin_used = |leverage: &[f64]| leverage.iter().zip(&margins).map(|(f, m)| f.abs() * m).sum::<f64>();
+
+    let kelly = solve_linear_system(covariance.clone(), means.clone()).ok_or_else(singular_covariance)?;
+    let unconstrained: Vec<f64> = kelly.iter().map(|f| f * fractional_multiplier).collect();
+    let constrained = margin_used(&unconstrained) > max_margin_fraction;
+    let leverage = if constrained {
+        // Fractional Kelly c maximizes μ'f - f'Σf / 2c, so the full-Kelly
+        // problem with the budget divided by c has the same solution over c
+        let budget = max_margin_fraction / fractional_multiplier;
+        let full = margin_budget_kelly(&covariance, &means, &margins, &kelly, budget).ok_or_else(singular_covariance)?;
+        full.iter().map(|f| f * fractional_multiplier).collect()
+    } else {
+        unconstrained.clone()
+    };
+
+    let by_symbol = |values: Vec<f64>| symbols.iter().cloned().zip(values).collect::<HashMap<String, f64>>();
+    Ok(LeveragedKelly {
+        total_margin_fraction: margin_used(&leverage),
+        margin_fraction: by_symbol(leverage.iter().zip(&margins).map(|(f, m)| f.abs() * m).collect()),
+        leverage: by_symbol(leverage),
+        unconstrained_leverage: by_symbol(unconstrained),
+        max_margin_fraction,
+        constrained,
+    })
+}
+
+/// Maximizes the quadratic growth approximation μ'f - f'Σf / 2 subject to
+/// Σ margin_i |f_i| = budget. With the signs of the positions fixed the
+/// optimum is f = Σ⁻¹(μ - λ w) for w_i = sign_i * margin_i; positions whose
+/// sign flips are closed and the rest re-solved until the signs agree.
+fn margin_budget_kelly(
+    covariance: &[Vec<f64>],
+    means: &[f64],
+    margins: &[f64],
+    unconstrained: &[f64],
+    budget: f64,
+) -> Option<Vec<f64>> {
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+    let mut signs: Vec<f64> = unconstrained.iter().map(|&f| if f == 0.0 { 0.0 } else { f.signum() }).collect();
+    loop {
+        let active: Vec<usize> = (0..means.len()).filter(|&i| signs[i] != 0.0).collect();
+        let mut leverage = vec![0.0; means.len()];
+        if active.is_empty() {
+            return Some(leverage);
+        }
+        let sub_covariance: Vec<Vec<f64>> =
+            active.iter().map(|&i| active.iter().map(|&j| covariance[i][j]).collect()).collect();
+        let sub_means: Vec<f64> = active.iter().map(|&i| means[i]).collect();
+        let weights: Vec<f64> = active.iter().map(|&i| signs[i] * margins[i]).collect();
+        let growth = solve_linear_system(sub_covariance.clone(), sub_means)?;
+        let penalty = solve_linear_system(sub_covariance, weights.clone())?;
+        let lambda = ((dot(&weights, &growth) - budget) / dot(&weights, &penalty)).max(0.0);
+
+        let mut flipped = false;
+        for (k, &i) in active.iter().enumerate() {
+            leverage[i] = growth[k] - lambda * penalty[k];
+            if leverage[i] * signs[i] < 0.0 {
+                signs[i] = 0.0;
+                flipped = true;
+            }
+        }
+        if !flipped {
+            return Some(leverage);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct OptimalFResult {
+    pub optimal_f: f64,
+    pub twr: f64, // Terminal wealth relative at the optimal f
+    pub geometric_mean: f64, // Per-trade growth factor, TWR^(1/N)
+    pub largest_loss: f64, // Absolute size of the worst trade; 0 without losses
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, max_iterations=1000, tolerance=1e-6, f_min=0.0, f_max=1.0, golden_section=true))]
+fn calculate_optimal_f(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    max_iterations: usize,
+    tolerance: f64,
+    f_min: f64,
+    f_max: f64,
+    golden_section: bool,
+) -> Result<OptimalFResult> {
+    py.allow_threads(|| optimal_f(trades, max_iterations, tolerance, f_min, f_max, golden_section))
+}
+
+pub fn optimal_f(
+    trades: Vec<Trade>,
+    max_iterations: usize,
+    tolerance: f64,
+    f_min: f64,
+    f_max: f64,
+    golden_section: bool,
+) -> Result<OptimalFResult> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if !(0.0..=1.0).contains(&f_min) || !(0.0..=1.0).contains(&f_max) || f_min >= f_max {
+        return Err(Error::Validation("Search range must satisfy 0 <= f_min < f_max <= 1".into()));
+    }
+    if tolerance <= 0.0 {
+        return Err(Error::Validation("Tolerance must be positive".into()));
+    }
+
+    // Find the largest loss
+    let largest_loss = trades.iter().map(|t| t.profit).fold(0.0, f64::min).abs();
+
+    if largest_loss == 0.0 {
+        // No losses, no risk management needed
+        return Ok(OptimalFResult {
+            optimal_f: 0.0,
+            twr: 1.0,
+            geometric_mean: 1.0,
+            largest_loss,
+        });
+    }
+
+    // Holding period returns are 1 + f * profit / |largest loss|
+    let returns: Vec<f64> = trades.iter().map(|t| t.profit / largest_loss).collect();
+    let (optimal_f, growth) = if golden_section {
+        maximize_log_growth(&returns, f_min, f_max, tolerance)
+    } else {
+        let steps = max_iterations.max(2);
+        (0..steps)
+            .map(|i| f_min + (f_max - f_min) * i as f64 / (steps - 1) as f64)
+            .map(|f| (f, log_growth(&returns, f)))
+            .fold((f_min, f64::NEG_INFINITY), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+    };
+
+    Ok(OptimalFResult {
+        optimal_f,
+        twr: (growth * returns.len() as f64).exp(),
+        geometric_mean: growth.exp(),
+        largest_loss,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct SecureFResult {
+    pub secure_f: f64,
+    pub optimal_f: f64, // Upper bound of the search
+    pub drawdown_percent: f64, // Simulated max drawdown at secure_f, at the confidence percentile
+    pub historical_drawdown_percent: f64, // Max drawdown of the actual trade sequence at secure_f
+    pub twr: f64, // Terminal wealth relative of the actual sequence at secure_f
+}
+
+/// Largest percentage drop from a running peak when every holding period
+/// return `r` compounds equity by `1 + f * r`.
+fn compounded_max_drawdown(returns: impl Iterator<Item = f64>, f: f64) -> f64 {
+    let (mut equity, mut peak, mut max_drawdown) = (1.0_f64, 1.0_f64, 0.0_f64);
+    for r in returns {
+        equity *= (1.0 + f * r).max(0.0);
+        peak = peak.max(equity);
+        max_drawdown = max_drawdown.max((peak - equity) / peak * 100.0);
+    }
+    max_drawdown
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, max_drawdown_percent, num_simulations=1000, confidence_percent=95.0, seed=None))]
+fn calculate_secure_f(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    max_drawdown_percent: f64,
+    num_simulations: usize,
+    confidence_percent: f64,
+    seed: Option<u64>,
+) -> Result<SecureFResult> {
+    py.allow_threads(|| secure_f(trades, max_drawdown_percent, num_simulations, confidence_percent, seed))
+}
+
+pub fn secure_f(
+    trades: Vec<Trade>,
+    max_drawdown_percent: f64,
+    num_simulations: usize,
+    confidence_percent: f64,
+    seed: Option<u64>,
+) -> Result<SecureFResult> {
+    use rand::Rng;
+    use rayon::prelude::*;
+
+    if max_drawdown_percent <= 0.0 || max_drawdown_percent >= 100.0 {
+        return Err(Error::Validation("Max drawdown must be between 0 and 100 percent".into()));
+    }
+    if num_simulations == 0 {
+        return Err(Error::Validation("Number of simulations must be positive".into()));
+    }
+    if !(0.0..=100.0).contains(&confidence_percent) {
+        return Err(Error::Validation("Confidence must be between 0 and 100 percent".into()));
+    }
+    let optimal = optimal_f(trades.clone(), 1000, 1e-9, 0.0, 1.0, true)?;
+    if optimal.optimal_f == 0.0 {
+        return Err(Error::Validation("Trade history has no positive optimal f to constrain".into()));
+    }
+
+    let returns: Vec<f64> = chronological(&trades).iter().map(|t| t.profit / optimal.largest_loss).collect();
+    let base_seed = seed.unwrap_or_else(rand::random);
+    let paths: Vec<Vec<usize>> = (0..num_simulations)
+        .map(|index| {
+            let mut rng = simulation_rng(base_seed, index as u64);
+            (0..returns.len()).map(|_| rng.gen_range(0..returns.len())).collect()
+        })
+        .collect();
+
+    // Every path reuses the same resampled order for each f, so the
+    // drawdown percentile grows with f and bisection applies
+    let simulated_drawdown = |f: f64| {
+        let drawdowns = paths
+            .par_iter()
+            .map(|path| compounded_max_drawdown(path.iter().map(|&i| returns[i]), f));
+        sorted_percentile(drawdowns.collect::<Vec<f64>>().into_iter(), confidence_percent)
+    };
+
+    let (mut safe, mut unsafe_f) = (0.0, optimal.optimal_f);
+    if simulated_drawdown(unsafe_f) <= max_drawdown_percent {
+        safe = unsafe_f;
+    } else {
+        while unsafe_f - safe > 1e-6 {
+            let mid = (safe + unsafe_f) / 2.0;
+            if simulated_drawdown(mid) <= max_drawdown_percent {
+                safe = mid;
+            } else {
+                unsafe_f = mid;
+            }
+        }
+    }
+
+    Ok(SecureFResult {
+        secure_f: safe,
+        optimal_f: optimal.optimal_f,
+        drawdown_percent: simulated_drawdown(safe),
+        historical_drawdown_percent: compounded_max_drawdown(returns.iter().copied(), safe),
+        twr: (log_growth(&returns, safe) * returns.len() as f64).exp(),
+    })
+}
+
+/// Growth and drawdown of betting one multiple of full Kelly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct KellyTradeoffRow {
+    pub multiplier: f64, // Multiple of full Kelly, e.g. 0.5 for half Kelly
+    pub fraction: f64, // Fraction of equity lost on an average losing trade
+    pub growth_rate: f64, // Geometric mean return per trade; -1 once any path is wiped out
+    pub relative_growth: f64, // Expected log growth as a share of full Kelly's
+    pub median_max_drawdown_percent: f64,
+    pub drawdown_probability: f64, // Share of paths whose max drawdown reaches the threshold
+}
+
+/// How growth and drawdown trade off across multiples of full Kelly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct KellyTradeoff {
+    pub full_kelly_fraction: f64, // Growth-optimal fraction, in units of the average loss
+    pub drawdown_threshold_percent: f64,
+    pub num_simulations: usize,
+    pub horizon_trades: usize,
+    pub rows: Vec<KellyTradeoffRow>,
+}
+
+/// Every trade in units of the average loss, like `KellyResult`, and the
+/// growth-optimal fraction of those returns.
+fn kelly_returns(trades: &[Trade]) -> Result<(Vec<f64>, f64)> {
+    let metrics =
+        compute_performance_metrics(trades.to_vec(), 0.0, None, ProfitNormalization::Raw, BetaPrior::default())?;
+    if metrics.avg_loss == 0.0 || metrics.avg_win == 0.0 {
+        return Err(Error::InsufficientData("Kelly needs both winning and losing trades".into()));
+    }
+    let returns: Vec<f64> = trades.iter().map(|t| t.profit / metrics.avg_loss.abs()).collect();
+    let largest_loss = returns.iter().fold(0.0f64, |worst, &r| worst.min(r));
+    let (full_kelly, _) = maximize_log_growth(&returns, 0.0, 0.999 / -largest_loss, 1e-9);
+    if full_kelly == 0.0 {
+        return Err(Error::Validation("Trade history has no edge to size with Kelly".into()));
+    }
+    Ok((returns, full_kelly))
+}
+
+/// `num_simulations` sequences of `horizon` returns drawn with replacement.
+fn resampled_paths(
+    returns: &[f64],
+    horizon: usize,
+    num_simulations: usize,
+    seed: Option<u64>,
+) -> Result<Vec<Vec<f64>>> {
+    use rand::Rng;
+
+    if num_simulations == 0 {
+        return Err(Error::Validation("Number of simulations must be positive".into()));
+    }
+    if horizon == 0 {
+        return Err(Error::Validation("Horizon must be at least one trade".into()));
+    }
+    let base_seed = seed.unwrap_or_else(rand::random);
+    Ok((0..num_simulations)
+        .map(|index| {
+            let mut rng = simulation_rng(base_seed, index as u64);
+            (0..horizon).map(|_| returns[rng.gen_range(0..returns.len())]).collect()
+        })
+        .collect())
+}
+
+/// 0.1x to 1.5x Kelly in steps of 0.1.
+fn default_kelly_multipliers() -> Vec<f64> {
+    (1..=15).map(|i| i as f64 / 10.0).collect()
+}
+
+/// Simulates `num_simulations` resampled sequences of `horizon_trades`
+/// trades (the history's length by default) at each multiple of full Kelly,
+/// 0.1x to 1.5x by default. Every multiple reuses the same paths.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, multipliers=None, num_simulations=1000, horizon_trades=None, drawdown_threshold_percent=20.0, seed=None))]
+fn calculate_kelly_tradeoff(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    multipliers: Option<Vec<f64>>,
+    num_simulations: usize,
+    horizon_trades: Option<usize>,
+    drawdown_threshold_percent: f64,
+    seed: Option<u64>,
+) -> Result<KellyTradeoff> {
+    py.allow_threads(|| {
+        kelly_tradeoff(trades, multipliers, num_simulations, horizon_trades, drawdown_threshold_percent, seed)
+    })
+}
+
+pub fn kelly_tradeoff(
+    trades: Vec<Trade>,
+    multipliers: Option<Vec<f64>>,
+    num_simulations: usize,
+    horizon_trades: Option<usize>,
+    drawdown_threshold_percent: f64,
+    seed: Option<u64>,
+) -> Result<KellyTradeoff> {
+    use rayon::prelude::*;
+
+    let multipliers = multipliers.unwrap_or_else(default_kelly_multipliers);
+    if multipliers.is_empty() || multipliers.iter().any(|m| !m.is_finite() || *m <= 0.0) {
+        return Err(Error::Validation("Kelly multipliers must be positive".into()));
+    }
+    if drawdown_threshold_percent <= 0.0 || drawdown_threshold_percent >= 100.0 {
+        return Err(Error::Validation("Drawdown threshold must be between 0 and 100 percent".into()));
+    }
+    let (returns, full_kelly) = kelly_returns(&trades)?;
+    let horizon = horizon_trades.unwrap_or(returns.len());
+    let paths = resampled_paths(&returns, horizon, num_simulations, seed)?;
+    let mean_log_growth =
+        |f: f64| paths.par_iter().map(|path| log_growth(path, f)).sum::<f64>() / num_simulations as f64;
+    let full_growth = mean_log_growth(full_kelly);
+
+    let rows = multipliers
+        .iter()
+        .map(|&multiplier| {
+            let fraction = full_kelly * multiplier;
+            let growth = mean_log_growth(fraction);
+            let drawdowns: Vec<f64> =
+                paths.par_iter().map(|path| compounded_max_drawdown(path.iter().copied(), fraction)).collect();
+            let breaches = drawdowns.iter().filter(|&&d| d >= drawdown_threshold_percent).count();
+            KellyTradeoffRow {
+                multiplier,
+                fraction,
+                growth_rate: growth.exp() - 1.0,
+                relative_growth: growth / full_growth,
+                median_max_drawdown_percent: sorted_percentile(drawdowns.into_iter(), 50.0),
+                drawdown_probability: breaches as f64 / num_simulations as f64,
+            }
+        })
+        .collect();
+
+    Ok(KellyTradeoff {
+        full_kelly_fraction: full_kelly,
+        drawdown_threshold_percent,
+        num_simulations,
+        horizon_trades: horizon,
+        rows,
+    })
+}
+
+/// Multiples of full Kelly on a sizing card unless others are given.
+const SIZING_LADDER_MULTIPLIERS: [f64; 3] = [0.25, 0.5, 1.0];
+
+/// Risk per trade at one account balance and multiple of Kelly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct KellySizingRow {
+    pub account_balance: f64,
+    pub kelly_multiplier: f64,
+    pub risk_fraction: f64, // Full Kelly times the multiplier
+    pub risk_amount: f64, // Money lost at the stop at exactly `risk_fraction`
+    pub lots: Option<f64>, // Tradable size at the stop distance; None without a sizer
+    pub lot_risk_amount: Option<f64>, // Money lost at the stop with `lots`
+    pub limited_by: Option<String>, // "min_lot", "max_lot" or "lot_step" when the lots were adjusted
+}
+
+/// Recommended risk per trade across account balances and multiples of
+/// Kelly, printable as a sizing card with `to_markdown`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct KellySizingLadder {
+    pub full_kelly_fraction: f64,
+    pub account_balances: Vec<f64>,
+    pub kelly_multipliers: Vec<f64>,
+    pub stop_loss_points: Option<f64>,
+    pub rows: Vec<KellySizingRow>, // Every multiplier for the first balance, then for the next
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl KellySizingLadder {
+    /// A Markdown table with a row per balance and a column per multiple of Kelly.
+    #[pyo3(name = "to_markdown")]
+    fn py_to_markdown(&self) -> String {
+        self.to_markdown()
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        hash_repr(&self.py_repr())
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl KellySizingLadder {
+    /// A Markdown table with a row per balance and a column per multiple of
+    /// Kelly. Cells show the money at risk and, with a sizer, the lots.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("| Balance |");
+        for multiplier in &self.kelly_multipliers {
+            out.push_str(&format!(" {}× Kelly |", multiplier));
+        }
+        out.push_str(&format!("\n|---:|{}\n", "---:|".repeat(self.kelly_multipliers.len())));
+        for balance_rows in self.rows.chunks(self.kelly_multipliers.len()) {
+            out.push_str(&format!("| {:.2} |", balance_rows[0].account_balance));
+            for row in balance_rows {
+                match row.lots {
+                    Some(lots) => out.push_str(&format!(" {:.2} ({:.2} lots) |", row.risk_amount, lots)),
+                    None => out.push_str(&format!(" {:.2} |", row.risk_amount)),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Risk per trade at each of `account_balances` for each multiple of
+/// `kelly_fraction` (0.25, 0.5 and 1 by default). With a `sizer` and the
+/// stop distance in points, every entry is also expressed in tradable lots.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (kelly_fraction, account_balances, kelly_multipliers=None, sizer=None, stop_loss_points=None)))]
+pub fn kelly_sizing_ladder(
+    kelly_fraction: f64,
+    account_balances: Vec<f64>,
+    kelly_multipliers: Option<Vec<f64>>,
+    sizer: Option<PositionSizer>,
+    stop_loss_points: Option<f64>,
+) -> Result<KellySizingLadder> {
+    if !kelly_fraction.is_finite() || kelly_fraction <= 0.0 {
+        return Err(Error::Validation(
+            "The Kelly fraction must be positive; the trades show no edge to size from".into(),
+        ));
+    }
+    if account_balances.is_empty() || account_balances.iter().any(|b| !b.is_finite() || *b <= 0.0) {
+        return Err(Error::Validation("Account balances must be positive".into()));
+    }
+    let kelly_multipliers = kelly_multipliers.unwrap_or_else(|| SIZING_LADDER_MULTIPLIERS.to_vec());
+    if kelly_multipliers.is_empty() || kelly_multipliers.iter().any(|m| !m.is_finite() || *m <= 0.0) {
+        return Err(Error::Validation("Kelly multipliers must be positive".into()));
+    }
+    let sizing = match (&sizer, stop_loss_points) {
+        (Some(sizer), Some(points)) => Some((sizer, points)),
+        (None, None) => None,
+        _ => return Err(Error::Validation("Sizing in lots needs both a sizer and a stop-loss distance".into())),
+    };
+
+    let mut rows = Vec::with_capacity(account_balances.len() * kelly_multipliers.len());
+    for &account_balance in &account_balances {
+        for &kelly_multiplier in &kelly_multipliers {
+            let risk_fraction = kelly_fraction * kelly_multiplier;
+            let size = sizing.map(|(sizer, points)| sizer.size(account_balance, risk_fraction, points)).transpose()?;
+            rows.push(KellySizingRow {
+                account_balance,
+                kelly_multiplier,
+                risk_fraction,
+                risk_amount: account_balance * risk_fraction,
+                lots: size.as_ref().map(|s| s.lots),
+                lot_risk_amount: size.as_ref().map(|s| s.risk_amount),
+                limited_by: size.and_then(|s| s.limited_by),
+            });
+        }
+    }
+
+    Ok(KellySizingLadder {
+        full_kelly_fraction: kelly_fraction,
+        account_balances,
+        kelly_multipliers,
+        stop_loss_points,
+        rows,
+    })
+}
+
+/// The largest risk fraction whose simulated max drawdown stays under a cap.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct DrawdownConstrainedKelly {
+    pub risk_fraction: f64, // Fraction of equity lost on an average losing trade
+    pub full_kelly_fraction: f64, // Upper bound of the search
+    pub kelly_multiplier: f64, // risk_fraction as a multiple of full Kelly
+    pub drawdown_percent: f64, // Simulated max drawdown at risk_fraction, at the confidence quantile
+    pub max_drawdown_percent: f64,
+    pub confidence: f64,
+    pub num_simulations: usize,
+    pub horizon_trades: usize,
+}
+
+/// Searches up to full Kelly for the largest risk fraction whose max
+/// drawdown over `horizon_trades` resampled trades (the history's length by
+/// default) stays within `max_drawdown_percent` on `confidence` of paths.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, max_drawdown_percent, confidence=0.95, num_simulations=1000, horizon_trades=None, seed=None))]
+fn kelly_with_drawdown_constraint(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    max_drawdown_percent: f64,
+    confidence: f64,
+    num_simulations: usize,
+    horizon_trades: Option<usize>,
+    seed: Option<u64>,
+) -> Result<DrawdownConstrainedKelly> {
+    py.allow_threads(|| {
+        drawdown_constrained_kelly(trades, max_drawdown_percent, confidence, num_simulations, horizon_trades, seed)
+    })
+}
+
+pub fn drawdown_constrained_kelly(
+    trades: Vec<Trade>,
+    max_drawdown_percent: f64,
+    confidence: f64,
+    num_simulations: usize,
+    horizon_trades: Option<usize>,
+    seed: Option<u64>,
+) -> Result<DrawdownConstrainedKelly> {
+    use rayon::prelude::*;
+
+    if max_drawdown_percent <= 0.0 || max_drawdown_percent >= 100.0 {
+        return Err(Error::Validation("Max drawdown must be between 0 and 100 percent".into()));
+    }
+    check_confidence(confidence)?;
+    let (returns, full_kelly) = kelly_returns(&trades)?;
+    let horizon = horizon_trades.unwrap_or(returns.len());
+    let paths = resampled_paths(&returns, horizon, num_simulations, seed)?;
+
+    // The same paths serve every fraction, so the drawdown quantile grows
+    // with the fraction and bisection applies
+    let simulated_drawdown = |f: f64| {
+        let drawdowns = paths.par_iter().map(|path| compounded_max_drawdown(path.iter().copied(), f));
+        sorted_percentile(drawdowns.collect::<Vec<f64>>().into_iter(), confidence * 100.0)
+    };
+
+    let (mut safe, mut unsafe_f) = (0.0, full_kelly);
+    if simulated_drawdown(unsafe_f) <= max_drawdown_percent {
+        safe = unsafe_f;
+    } else {
+        while unsafe_f - safe > 1e-6 {
+            let mid = (safe + unsafe_f) / 2.0;
+            if simulated_drawdown(mid) <= max_drawdown_percent {
+                safe = mid;
+            } else {
+                unsafe_f = mid;
+            }
+        }
+    }
+
+    Ok(DrawdownConstrainedKelly {
+        risk_fraction: safe,
+        full_kelly_fraction: full_kelly,
+        kelly_multiplier: safe / full_kelly,
+        drawdown_percent: simulated_drawdown(safe),
+        max_drawdown_percent,
+        confidence,
+        num_simulations,
+        horizon_trades: horizon,
+    })
+}
+
+/// Contract economics of one tradable instrument, as listed in the broker's
+/// symbol specification. Prices and tick values are in `currency`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct SymbolSpec {
+    pub symbol: String,
+    pub contract_size: f64, // Units of the underlying per lot
+    pub tick_size: f64, // Smallest price change (one point)
+    pub tick_value: f64, // Value of one tick per lot
+    pub currency: String,
+    pub leverage: f64,
+    pub margin_rate: f64, // Multiplier on the leveraged margin (1.0 = standard)
+    pub min_lot: f64,
+    pub lot_step: f64,
+    pub max_lot: f64,
+}
+
+/// On-disk form of a `SymbolSpec`; everything but the symbol, contract size
+/// and tick size may be left out.
+#[derive(Deserialize)]
+struct SymbolSpecRecord {
+    symbol: String,
+    contract_size: f64,
+    tick_size: f64,
+    tick_value: Option<f64>,
+    currency: Option<String>,
+    leverage: Option<f64>,
+    margin_rate: Option<f64>,
+    min_lot: Option<f64>,
+    lot_step: Option<f64>,
+    max_lot: Option<f64>,
+}
+
+impl TryFrom<SymbolSpecRecord> for SymbolSpec {
+    type Error = String;
+
+    fn try_from(record: SymbolSpecRecord) -> Result<Self, String> {
+        let spec = SymbolSpec {
+            tick_value: record.tick_value.unwrap_or(record.contract_size * record.tick_size),
+            currency: record.currency.filter(|c| !c.trim().is_empty()).unwrap_or_else(|| "USD".to_string()),
+            leverage: record.leverage.unwrap_or(100.0),
+            margin_rate: record.margin_rate.unwrap_or(1.0),
+            min_lot: record.min_lot.unwrap_or(0.01),
+            lot_step: record.lot_step.unwrap_or(0.01),
+            max_lot: record.max_lot.unwrap_or(100.0),
+            symbol: record.symbol,
+            contract_size: record.contract_size,
+            tick_size: record.tick_size,
+        };
+        if spec.symbol.trim().is_empty() {
+            return Err("Symbol specification has an empty symbol".to_string());
+        }
+        if spec.contract_size <= 0.0 || spec.tick_size <= 0.0 || spec.tick_value <= 0.0 {
+            return Err(format!("{}: contract size, tick size and tick value must be positive", spec.symbol));
+        }
+        if spec.leverage <= 0.0 || spec.margin_rate < 0.0 {
+            return Err(format!("{}: leverage must be positive and margin rate non-negative", spec.symbol));
+        }
+        if spec.lot_step <= 0.0 || spec.min_lot < 0.0 || spec.max_lot < spec.min_lot {
+            return Err(format!("{}: lot limits must satisfy 0 <= min_lot <= max_lot and lot_step > 0", spec.symbol));
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SymbolSpec {
+    #[new]
+    #[pyo3(signature = (symbol, contract_size, tick_size, tick_value=None, currency=None, leverage=None, margin_rate=None, min_lot=None, lot_step=None, max_lot=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        symbol: String,
+        contract_size: f64,
+        tick_size: f64,
+        tick_value: Option<f64>,
+        currency: Option<String>,
+        leverage: Option<f64>,
+        margin_rate: Option<f64>,
+        min_lot: Option<f64>,
+        lot_step: Option<f64>,
+        max_lot: Option<f64>,
+    ) -> Result<Self> {
+        Self::new(
+            symbol,
+            contract_size,
+            tick_size,
+            tick_value,
+            currency,
+            leverage,
+            margin_rate,
+            min_lot,
+            lot_step,
+            max_lot,
+        )
+    }
+
+    /// Margin needed to hold one lot opened at `price`.
+    #[pyo3(name = "margin_per_lot")]
+    fn py_margin_per_lot(&self, price: f64) -> f64 {
+        self.margin_per_lot(price)
+    }
+
+    /// Profit of one lot moving from `open_price` to `close_price`.
+    fn profit_per_lot(&self, trade_type: &Bound<'_, PyAny>, open_price: f64, close_price: f64) -> PyResult<f64> {
+        Ok(self.lot_profit(TradeType::from_py(trade_type)?, open_price, close_price))
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl SymbolSpec {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        symbol: String,
+        contract_size: f64,
+        tick_size: f64,
+        tick_value: Option<f64>,
+        currency: Option<String>,
+        leverage: Option<f64>,
+        margin_rate: Option<f64>,
+        min_lot: Option<f64>,
+        lot_step: Option<f64>,
+        max_lot: Option<f64>,
+    ) -> Result<Self> {
+        SymbolSpecRecord {
+            symbol,
+            contract_size,
+            tick_size,
+            tick_value,
+            currency,
+            leverage,
+            margin_rate,
+            min_lot,
+            lot_step,
+            max_lot,
+        }
+        .try_into()
+        .map_err(Error::Validation)
+    }
+
+    /// Margin needed to hold one lot opened at `price`.
+    pub fn margin_per_lot(&self, price: f64) -> f64 {
+        self.contract_size * price * self.margin_rate / self.leverage
+    }
+
+    /// Profit of one lot moving from `open_price` to `close_price`.
+    fn lot_profit(&self, trade_type: TradeType, open_price: f64, close_price: f64) -> f64 {
+        trade_type.direction() * (close_price - open_price) / self.tick_size * self.tick_value
+    }
+}
+
+/// Symbol specifications keyed by symbol, loaded from JSON or CSV files.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct SymbolRegistry {
+    specs: HashMap<String, SymbolSpec>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SymbolRegistry {
+    #[new]
+    #[pyo3(signature = (specs=Vec::new()))]
+    fn py_new(specs: Vec<SymbolSpec>) -> Self {
+        Self::new(specs)
+    }
+
+    /// Adds a specification, replacing any existing one for the same symbol.
+    #[pyo3(name = "add")]
+    fn py_add(&mut self, spec: SymbolSpec) {
+        self.add(spec)
+    }
+
+    #[pyo3(name = "get")]
+    fn py_get(&self, symbol: &str) -> Option<SymbolSpec> {
+        self.get(symbol)
+    }
+
+    #[pyo3(name = "symbols")]
+    fn py_symbols(&self) -> Vec<String> {
+        self.symbols()
+    }
+
+    fn __len__(&self) -> usize {
+        self.specs.len()
+    }
+
+    fn __contains__(&self, symbol: &str) -> bool {
+        self.specs.contains_key(symbol)
+    }
+
+    /// Reads a JSON array of specification objects.
+    #[pyo3(name = "from_json")]
+    #[staticmethod]
+    fn py_from_json(content: &str) -> Result<Self> {
+        Self::from_json(content)
+    }
+
+    /// Reads a CSV file whose header names the specification fields.
+    #[pyo3(name = "from_csv")]
+    #[staticmethod]
+    fn py_from_csv(content: &str) -> Result<Self> {
+        Self::from_csv(content)
+    }
+
+    /// Loads a `.json` or `.csv` file, chosen by its extension.
+    #[pyo3(name = "load")]
+    #[staticmethod]
+    fn py_load(path: std::path::PathBuf) -> Result<Self> {
+        Self::load(&path)
+    }
+
+    /// A position sizer for `symbol` using its contract economics and lot limits.
+    #[pyo3(name = "sizer")]
+    fn py_sizer(&self, symbol: &str) -> Result<PositionSizer> {
+        self.sizer(symbol)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    /// Writes the specifications in the format `from_json` reads.
+    fn to_json(&self) -> PyResult<String> {
+        let specs: Vec<&SymbolSpec> = self.symbols().iter().map(|symbol| &self.specs[symbol]).collect();
+        to_json(&specs)
+    }
+}
+
+impl SymbolRegistry {
+    pub fn new(specs: Vec<SymbolSpec>) -> Self {
+        let mut registry = SymbolRegistry::default();
+        for spec in specs {
+            registry.add(spec);
+        }
+        registry
+    }
+
+    /// Adds a specification, replacing any existing one for the same symbol.
+    pub fn add(&mut self, spec: SymbolSpec) {
+        self.specs.insert(spec.symbol.clone(), spec);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<SymbolSpec> {
+        self.specs.get(symbol).cloned()
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.specs.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Reads a JSON array of specification objects.
+    pub fn from_json(content: &str) -> Result<Self> {
+        let records: Vec<SymbolSpecRecord> = serde_json::from_str(content)
+            .map_err(|e| Error::Parse(format!("Invalid symbol specification JSON: {}", e)))?;
+        let specs = records
+            .into_iter()
+            .map(|record| SymbolSpec::try_from(record).map_err(Error::Parse))
+            .collect::<Result<Vec<SymbolSpec>>>()?;
+        Ok(SymbolRegistry::new(specs))
+    }
+
+    /// Reads a CSV file whose header names the specification fields.
+    pub fn from_csv(content: &str) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(content.as_bytes());
+        let specs = reader
+            .deserialize()
+            .enumerate()
+            .map(|(i, record)| {
+                let invalid =
+                    |e: String| Error::Parse(format!("Invalid symbol specification CSV: row {} {}", i + 1, e));
+                let record: SymbolSpecRecord = record.map_err(|e| invalid(e.to_string()))?;
+                SymbolSpec::try_from(record).map_err(invalid)
+            })
+            .collect::<Result<Vec<SymbolSpec>>>()?;
+        Ok(SymbolRegistry::new(specs))
+    }
+
+    /// Loads a `.json` or `.csv` file, chosen by its extension.
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("json") => SymbolRegistry::from_json(&content),
+            Some("csv") => SymbolRegistry::from_csv(&content),
+            _ => Err(Error::Parse("Symbol specification files must be .json or .csv".into())),
+        }
+    }
+
+    /// A position sizer for `symbol` using its contract economics and lot limits.
+    pub fn sizer(&self, symbol: &str) -> Result<PositionSizer> {
+        self.lookup(symbol).map(PositionSizer::from_spec)
+    }
+
+    fn lookup(&self, symbol: &str) -> Result<&SymbolSpec> {
+        self.specs
+            .get(symbol)
+            .ok_or_else(|| Error::Validation(format!("No symbol specification for {}", symbol)))
+    }
+
+    /// Replaces each trade's profit with its price move valued per lot,
+    /// including commission and swap per lot, as a fraction of the margin
+    /// one lot ties up. The Monte Carlo engine then sizes every trade off
+    /// equity the same way whatever volume was traded historically.
+    fn margin_returns(&self, trades: &[Trade]) -> Result<Vec<Trade>> {
+        trades
+            .iter()
+            .map(|trade| {
+                let spec = self.lookup(&trade.symbol)?;
+                let margin = spec.margin_per_lot(trade.open_price);
+                if margin <= 0.0 {
+                    return Err(Error::Validation(format!(
+                        "{} trade has no margin at its open price",
+                        trade.symbol
+                    )));
+                }
+                let costs = trade.commission.unwrap_or(0.0) + trade.swap.unwrap_or(0.0);
+                let costs_per_lot = if trade.volume > 0.0 { costs / trade.volume } else { 0.0 };
+                let profit = spec.lot_profit(trade.trade_type, trade.open_price, trade.close_price) + costs_per_lot;
+                Ok(Trade {
+                    profit: profit / margin,
+                    ..trade.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct PositionSize {
+    pub lots: f64, // Tradable size after broker constraints; 0 if below the minimum lot
+    pub raw_lots: f64, // Exact size for the requested risk
+    pub risk_amount: f64, // Money lost at the stop with `lots`
+    pub risk_fraction: f64, // Actual fraction of equity at risk with `lots`
+    pub limited_by: Option<String>, // "min_lot", "max_lot" or "lot_step" when the size was adjusted
+}
+
+/// Converts a risk fraction into a lot size for one instrument. A point is
+/// one `tick_size` move in price; `tick_value` is what that move is worth
+/// per lot in account currency (defaults to `contract_size * tick_size`,
+/// i.e. an account in the quote currency).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct PositionSizer {
+    pub contract_size: f64,
+    pub tick_size: f64,
+    pub tick_value: f64,
+    pub min_lot: f64,
+    pub lot_step: f64,
+    pub max_lot: f64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PositionSizer {
+    #[new]
+    #[pyo3(signature = (contract_size=100_000.0, tick_size=0.00001, tick_value=None, min_lot=0.01, lot_step=0.01, max_lot=100.0))]
+    fn py_new(
+        contract_size: f64,
+        tick_size: f64,
+        tick_value: Option<f64>,
+        min_lot: f64,
+        lot_step: f64,
+        max_lot: f64,
+    ) -> Result<Self> {
+        PositionSizer::new(contract_size, tick_size, tick_value, min_lot, lot_step, max_lot)
+    }
+
+    /// A sizer using the contract economics and lot limits of `spec`.
+    #[pyo3(name = "from_spec")]
+    #[staticmethod]
+    fn py_from_spec(spec: &SymbolSpec) -> Self {
+        Self::from_spec(spec)
+    }
+
+    /// Money lost per lot when price moves `stop_loss_points` against the position.
+    #[pyo3(name = "risk_per_lot")]
+    fn py_risk_per_lot(&self, stop_loss_points: f64) -> f64 {
+        self.risk_per_lot(stop_loss_points)
+    }
+
+    /// Largest tradable size that risks at most `risk_fraction` of `equity`
+    /// at the stop. Sizes are rounded down to the lot step and capped at the
+    /// maximum lot; a size below the minimum lot cannot be traded at all.
+    #[pyo3(name = "size")]
+    fn py_size(&self, equity: f64, risk_fraction: f64, stop_loss_points: f64) -> Result<PositionSize> {
+        self.size(equity, risk_fraction, stop_loss_points)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl PositionSizer {
+    /// A sizer using the contract economics and lot limits of `spec`.
+    pub fn from_spec(spec: &SymbolSpec) -> Self {
+        PositionSizer {
+            contract_size: spec.contract_size,
+            tick_size: spec.tick_size,
+            tick_value: spec.tick_value,
+            min_lot: spec.min_lot,
+            lot_step: spec.lot_step,
+            max_lot: spec.max_lot,
+        }
+    }
+
+    /// Money lost per lot when price moves `stop_loss_points` against the position.
+    pub fn risk_per_lot(&self, stop_loss_points: f64) -> f64 {
+        stop_loss_points.abs() * self.tick_value
+    }
+
+    /// Largest tradable size that risks at most `risk_fraction` of `equity`
+    /// at the stop. Sizes are rounded down to the lot step and capped at the
+    /// maximum lot; a size below the minimum lot cannot be traded at all.
+    pub fn size(&self, equity: f64, risk_fraction: f64, stop_loss_points: f64) -> Result<PositionSize> {
+        if equity <= 0.0 || risk_fraction < 0.0 {
+            return Err(Error::Validation("Equity must be positive and risk fraction non-negative".into()));
+        }
+        let risk_per_lot = self.risk_per_lot(stop_loss_points);
+        if risk_per_lot == 0.0 {
+            return Err(Error::Validation("Stop-loss distance must be non-zero".into()));
+        }
+
+        let raw_lots = equity * risk_fraction / risk_per_lot;
+        // Tolerate float noise such as 0.3 / 0.1 = 2.9999999999999996 steps
+        let steps = (raw_lots / self.lot_step + 1e-9).floor();
+        let stepped = round_lots(steps * self.lot_step);
+
+        let (lots, limited_by) = if stepped > self.max_lot {
+            (self.max_lot, Some("max_lot"))
+        } else if stepped < self.min_lot || stepped == 0.0 {
+            (0.0, Some("min_lot"))
+        } else if stepped < raw_lots - 1e-9 {
+            (stepped, Some("lot_step"))
+        } else {
+            (stepped, None)
+        };
+
+        Ok(PositionSize {
+            lots,
+            raw_lots,
+            risk_amount: lots * risk_per_lot,
+            risk_fraction: lots * risk_per_lot / equity,
+            limited_by: limited_by.map(str::to_string),
+        })
+    }
+
+    pub fn new(
+        contract_size: f64,
+        tick_size: f64,
+        tick_value: Option<f64>,
+        min_lot: f64,
+        lot_step: f64,
+        max_lot: f64,
+    ) -> Result<Self> {
+        if contract_size <= 0.0 || tick_size <= 0.0 || tick_value.is_some_and(|v| v <= 0.0) {
+            return Err(Error::Validation("Contract size, tick size and tick value must be positive".into()));
+        }
+        if lot_step <= 0.0 || min_lot < 0.0 || max_lot < min_lot {
+            return Err(Error::Validation("Lot limits must satisfy 0 <= min_lot <= max_lot and lot_step > 0".into()));
+        }
+        Ok(PositionSizer {
+            contract_size,
+            tick_size,
+            tick_value: tick_value.unwrap_or(contract_size * tick_size),
+            min_lot,
+            lot_step,
+            max_lot,
+        })
+    }
+}
+
+/// Drops the float noise left after multiplying a step count by the lot step.
+fn round_lots(lots: f64) -> f64 {
+    (lots * 1e8).round() / 1e8
+}
+
+/// Audit of a real trade history against a challenge's rules.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ComplianceReport {
+    pub passed: bool, // Target reached on enough trading days without breaking any rule
+    pub violations: Vec<String>, // Every rule broken, in the order it first happened
+    pub target_reached: bool,
+    pub trading_days: u32,
+    pub profit_percent: f64, // Final profit, in percent of the account size
+    pub worst_daily_loss_percent: f64, // Deepest intraday loss, in percent of the daily loss basis
+    pub max_drawdown_percent: f64, // Deepest fall from peak equity, in percent
+    pub best_day_percent: Option<f64>, // Best day's share of total profit; None without a total profit
+    pub profitable_days_percent: f64,
+}
+
+/// Replays a trade history day by day against the loss limits and
+/// consistency rules of `params`, as the firm would. Multi-phase challenges
+/// are audited against their first phase's target.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn check_challenge_compliance(trades: Vec<Trade>, params: ChallengeParams) -> Result<ComplianceReport> {
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if trades.iter().any(|t| t.close_time.is_none()) {
+        return Err(Error::InsufficientData("Checking compliance requires close timestamps".into()));
+    }
+
+    let phase = &params.phase_rules()[0];
+    let target_equity = params.account_size * (1.0 + phase.profit_target_percent / 100.0);
+    let date = |day: i64| {
+        chrono::DateTime::from_timestamp(day * MILLIS_PER_DAY / 1000, 0)
+            .map_or_else(|| day.to_string(), |dt| dt.format("%Y-%m-%d").to_string())
+    };
+
+    let mut equity = params.account_size;
+    let mut peak_equity = equity;
+    let mut loss_floor = params.account_size * (1.0 - params.max_overall_loss_percent / 100.0);
+    let mut report = ComplianceReport {
+        passed: false,
+        violations: Vec::new(),
+        target_reached: false,
+        trading_days: 0,
+        profit_percent: 0.0,
+        worst_daily_loss_percent: 0.0,
+        max_drawdown_percent: 0.0,
+        best_day_percent: None,
+        profitable_days_percent: 0.0,
+    };
+    let (mut daily_breached, mut overall_breached) = (false, false);
+    let mut restrictions_broken: Vec<&str> = Vec::new();
+    let mut day_profits = Vec::new();
+
+    let ordered = chronological(&trades);
+    for day_trades in ordered.chunk_by(|a, b| trade_day(a) == trade_day(b)) {
+        let day = trade_day(day_trades[0]).unwrap_or_default();
+        let day_start_equity = equity;
+        let basis = params.daily_loss_basis(day_start_equity);
+
+        for trade in day_trades {
+            equity += trade.profit;
+            peak_equity = peak_equity.max(equity);
+            report.max_drawdown_percent = report.max_drawdown_percent.max((peak_equity - equity) / peak_equity * 100.0);
+            if params.drawdown_mode == DrawdownMode::TrailingEquity {
+                loss_floor = params.trail_floor(loss_floor, peak_equity);
+            }
+
+            let daily_loss_percent = (day_start_equity - equity) / basis * 100.0;
+            report.worst_daily_loss_percent = report.worst_daily_loss_percent.max(daily_loss_percent);
+            if daily_loss_percent > params.max_daily_loss_percent && !daily_breached {
+                daily_breached = true;
+                report.violations.push(format!(
+                    "daily loss of {:.2}% on {} exceeds the {}% limit",
+                    daily_loss_percent,
+                    date(day),
+                    params.max_daily_loss_percent
+                ));
+            }
+            if equity < loss_floor && !overall_breached {
+                overall_breached = true;
+                report.violations.push(format!("equity fell below the overall loss floor on {}", date(day)));
+            }
+            if let Some(rule) = params.trading_restriction(trade) {
+                if !restrictions_broken.contains(&rule) {
+                    restrictions_broken.push(rule);
+                    report.violations.push(format!("{} on {}", rule, date(day)));
+                }
+            }
+            report.target_reached |= equity >= target_equity;
+        }
+
+        if params.drawdown_mode == DrawdownMode::TrailingBalance {
+            loss_floor = params.trail_floor(loss_floor, equity);
+        }
+        day_profits.push(equity - day_start_equity);
+    }
+
+    let total_profit = equity - params.account_size;
+    report.violations.extend(params.consistency_violation(&day_profits));
+    report.trading_days = day_profits.len() as u32;
+    report.profit_percent = total_profit / params.account_size * 100.0;
+    report.best_day_percent =
+        (total_profit > 0.0).then(|| day_profits.iter().copied().fold(0.0, f64::max) / total_profit * 100.0);
+    report.profitable_days_percent =
+        day_profits.iter().filter(|&&p| p > 0.0).count() as f64 / day_profits.len() as f64 * 100.0;
+    report.passed =
+        report.violations.is_empty() && report.target_reached && report.trading_days >= phase.min_trading_days;
+    Ok(report)
+}
+
+/// Pre-trade check of a proposed risk against a challenge's loss limits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct NextTradeCheck {
+    pub allowed: bool, // Neither limit is breached even if the trade loses its full risk
+    pub breaches_daily_limit: bool,
+    pub breaches_overall_limit: bool,
+    pub proposed_loss: f64, // Worst-case loss of the proposed trade, in account currency
+    pub daily_loss_room: f64, // Further loss the daily limit allows today; negative once breached
+    pub overall_loss_room: f64, // Further loss before equity falls below the overall floor
+    pub max_risk_percent: f64, // Largest risk, in percent of current equity, that breaches neither limit
+}
+
+/// Checks whether a trade risking `proposed_risk_percent` of current equity
+/// could breach the daily or overall loss limit if it hits its stop, and
+/// how much risk the limits still allow, for expert advisors and trade
+/// copiers to call before each order. The trailing drawdown modes measure
+/// the overall floor from `peak_equity`, the high-water mark the mode
+/// tracks; without it the larger of the account size and the given
+/// equities is used, which can only understate the floor.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (current_equity, day_start_equity, challenge_params, proposed_risk_percent, peak_equity=None)))]
+pub fn evaluate_next_trade(
+    current_equity: f64,
+    day_start_equity: f64,
+    challenge_params: ChallengeParams,
+    proposed_risk_percent: f64,
+    peak_equity: Option<f64>,
+) -> Result<NextTradeCheck> {
+    if !(current_equity.is_finite() && current_equity > 0.0 && day_start_equity.is_finite() && day_start_equity > 0.0) {
+        return Err(Error::Validation("Current and day-start equity must be positive".into()));
+    }
+    if !proposed_risk_percent.is_finite() || proposed_risk_percent < 0.0 {
+        return Err(Error::Validation("Proposed risk must be a non-negative percentage".into()));
+    }
+    let params = &challenge_params;
+    let daily_limit = params.daily_loss_basis(day_start_equity) * params.max_daily_loss_percent / 100.0;
+    let daily_floor = day_start_equity - daily_limit;
+
+    let static_floor = params.account_size * (1.0 - params.max_overall_loss_percent / 100.0);
+    let overall_floor = match params.drawdown_mode {
+        DrawdownMode::Static => static_floor,
+        DrawdownMode::TrailingBalance | DrawdownMode::TrailingEquity => {
+            let peak = peak_equity.unwrap_or(params.account_size.max(day_start_equity).max(current_equity));
+            params.trail_floor(static_floor, peak)
+        }
+    };
+
+    // The simulations fail a day once its loss exceeds the limit, and the
+    // account once equity falls below the floor, so both edges are allowed
+    let daily_loss_room = current_equity - daily_floor;
+    let overall_loss_room = current_equity - overall_floor;
+    let proposed_loss = current_equity * proposed_risk_percent / 100.0;
+    let breaches_daily_limit = proposed_loss > daily_loss_room;
+    let breaches_overall_limit = proposed_loss > overall_loss_room;
+    Ok(NextTradeCheck {
+        allowed: !breaches_daily_limit && !breaches_overall_limit,
+        breaches_daily_limit,
+        breaches_overall_limit,
+        proposed_loss,
+        daily_loss_room,
+        overall_loss_room,
+        max_risk_percent: (daily_loss_room.min(overall_loss_room) / current_equity * 100.0).max(0.0),
+    })
+}
+
+/// An account copying a master account's trades, in its current state.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct CopyFollower {
+    pub name: String,
+    pub equity: f64,
+    pub challenge_params: ChallengeParams,
+    pub day_start_equity: Option<f64>, // Defaults to `equity`
+    pub peak_equity: Option<f64>, // High-water mark for trailing drawdowns; see `evaluate_next_trade`
+    pub symbol_specs: Option<SymbolRegistry>, // The follower broker's contracts, when they differ from the master's
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CopyFollower {
+    #[new]
+    #[pyo3(signature = (name, equity, challenge_params, day_start_equity=None, peak_equity=None, symbol_specs=None))]
+    fn py_new(
+        name: String,
+        equity: f64,
+        challenge_params: ChallengeParams,
+        day_start_equity: Option<f64>,
+        peak_equity: Option<f64>,
+        symbol_specs: Option<SymbolRegistry>,
+    ) -> Self {
+        Self::new(name, equity, challenge_params, day_start_equity, peak_equity, symbol_specs)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl CopyFollower {
+    pub fn new(
+        name: String,
+        equity: f64,
+        challenge_params: ChallengeParams,
+        day_start_equity: Option<f64>,
+        peak_equity: Option<f64>,
+        symbol_specs: Option<SymbolRegistry>,
+    ) -> Self {
+        CopyFollower {
+            name,
+            equity,
+            challenge_params,
+            day_start_equity,
+            peak_equity,
+            symbol_specs,
+        }
+    }
+}
+
+/// How much of a master account's size one follower should copy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct CopyScaling {
+    pub name: String,
+    pub multiplier: f64, // Follower lots per master lot
+    pub proportional_multiplier: f64, // Equity ratio: the master's risk fraction, unconstrained
+    pub risk_percent: f64, // Follower's loss on one master stop-out with `multiplier`, in % of its equity
+    pub max_risk_percent: f64, // Largest such loss its daily and overall limits allow
+    pub limited_by: Option<String>, // "daily_loss" or "overall_loss" when the limits cut the proportional size
+    pub symbol_multipliers: HashMap<String, f64>, // `multiplier` corrected for contract differences, by symbol
+}
+
+/// Per-follower lot multipliers for copying a master account that risks
+/// `master_risk_fraction` of `master_equity` per trade. Each follower copies
+/// proportionally to its equity unless that would let `max_losses_per_day`
+/// master stop-outs in a row break its own daily or overall loss limit, in
+/// which case it is scaled down to fit. Symbols both the master's and a
+/// follower's specifications define get a multiplier corrected for the
+/// value of a lot at each broker, e.g. 100 oz against 10 oz gold contracts.
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (master_equity, master_risk_fraction, followers, master_symbol_specs=None, max_losses_per_day=1)))]
+pub fn copy_trade_scaling(
+    master_equity: f64,
+    master_risk_fraction: f64,
+    followers: Vec<CopyFollower>,
+    master_symbol_specs: Option<SymbolRegistry>,
+    max_losses_per_day: u32,
+) -> Result<Vec<CopyScaling>> {
+    if !master_equity.is_finite() || master_equity <= 0.0 {
+        return Err(Error::Validation("Master equity must be positive".into()));
+    }
+    if !(master_risk_fraction > 0.0 && master_risk_fraction <= 1.0) {
+        return Err(Error::Validation("Master risk fraction must be in (0, 1]".into()));
+    }
+    if max_losses_per_day == 0 {
+        return Err(Error::Validation("Max losses per day must be at least 1".into()));
+    }
+    let master_loss = master_equity * master_risk_fraction;
+
+    followers
+        .into_iter()
+        .map(|follower| {
+            let check = evaluate_next_trade(
+                follower.equity,
+                follower.day_start_equity.unwrap_or(follower.equity),
+                follower.challenge_params.clone(),
+                0.0,
+                follower.peak_equity,
+            )?;
+            let losses = max_losses_per_day as f64;
+            let daily_multiplier = (check.daily_loss_room / losses / master_loss).max(0.0);
+            let overall_multiplier = (check.overall_loss_room / losses / master_loss).max(0.0);
+            let proportional_multiplier = follower.equity / master_equity;
+
+            let (multiplier, limited_by) = if proportional_multiplier <= daily_multiplier.min(overall_multiplier) {
+                (proportional_multiplier, None)
+            } else if daily_multiplier <= overall_multiplier {
+                (daily_multiplier, Some("daily_loss"))
+            } else {
+                (overall_multiplier, Some("overall_loss"))
+            };
+
+            let mut symbol_multipliers = HashMap::new();
+            if let (Some(master_specs), Some(follower_specs)) = (&master_symbol_specs, &follower.symbol_specs) {
+                for (symbol, spec) in &follower_specs.specs {
+                    if let Some(master_spec) = master_specs.specs.get(symbol) {
+                        let lot_value = |s: &SymbolSpec| s.tick_value / s.tick_size;
+                        let symbol_multiplier = multiplier * lot_value(master_spec) / lot_value(spec);
+                        symbol_multipliers.insert(symbol.clone(), symbol_multiplier);
+                    }
+                }
+            }
+
+            Ok(CopyScaling {
+                name: follower.name,
+                multiplier,
+                proportional_multiplier,
+                risk_percent: master_loss * multiplier / follower.equity * 100.0,
+                max_risk_percent: check.max_risk_percent / losses,
+                limited_by: limited_by.map(str::to_string),
+                symbol_multipliers,
+            })
+        })
+        .collect()
+}
+
+/// Why a simulated challenge attempt did not pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailureReason {
+    DailyLoss,
+    OverallLoss,
+    Timeout,     // Ran out of trades before passing
+    Consistency, // Reached the target but ran out of trades before meeting the consistency rules
+    Restricted,  // Drew a trade held over the weekend or placed in a restricted window
+}
+
+impl FailureReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            FailureReason::DailyLoss => "daily_loss",
+            FailureReason::OverallLoss => "overall_loss",
+            FailureReason::Timeout => "timeout",
+            FailureReason::Consistency => "consistency",
+            FailureReason::Restricted => "restricted_trading",
+        }
+    }
+}
+
+/// Outcome of one simulated challenge attempt.
+#[derive(Debug, Clone)]
+struct PathOutcome {
+    failure: Option<FailureReason>,
+    final_equity: f64,
+    max_drawdown_percent: f64, // Deepest fall from peak equity, in percent
+    days_to_target: Option<u32>,  // Trading days across all phases
+    target_before_min_days: bool, // Target was reached before min_trading_days
+    phases_passed: usize,
+    equity_path: Vec<f64>, // Starting equity, then equity after every trade; empty unless sampled
+    funded_payout: f64,              // Trader's share of funded-account payouts
+    funded_blowup_day: Option<u32>, // Funded trading day on which the account was lost
+}
+
+/// How historical sessions are resampled into a simulated sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum ResamplingMode {
+    Iid,        // Independent draws of single sessions
+    Block,      // Fixed-length blocks of consecutive sessions
+    Stationary, // Politis-Romano blocks with geometric lengths
+}
+
+/// Where simulated trade returns come from. The parametric models keep the
+/// resampled sessions' trade counts but draw every return from a
+/// distribution fitted to the history, which smooths over small samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum ReturnModel {
+    Bootstrap,       // Historical trades themselves
+    Normal,          // Fitted mean and standard deviation
+    StudentT,        // Location-scale Student-t with moment-matched degrees of freedom
+    GaussianMixture, // Mixture of normals fitted by expectation-maximization
+    MarkovChain,     // Historical wins and losses in a fitted two-state win/loss sequence
+    Garch,           // Normal returns whose variance follows a fitted GARCH(1,1), for clustered volatility
+}
+
+/// How the simulations read each trade's `profit`. A simulated trade gains
+/// or loses its converted return times the position size, so one unit is
+/// the amount risked. Modes other than R-multiples rescale their returns
+/// so that the average losing trade is one unit, and so need at least one
+/// losing trade. Simulations read account currency unless told otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum ReturnMode {
+    RiskUnits,        // Already in units of the amount risked, used as given
+    Currency,         // Account currency, e.g. MT5 profits
+    PerLot,           // Account currency per lot traded
+    PercentOfBalance, // Percent of the balance before the trade, starting from the account size
+    RMultiple,        // Profit over the trade's risk_amount
+}
+
+impl ReturnMode {
+    /// The trades with `profit` rewritten in risk units.
+    fn apply(self, trades: Vec<Trade>, account_size: f64) -> Result<Vec<Trade>> {
+        let normalization = match self {
+            ReturnMode::RiskUnits => return Ok(trades),
+            ReturnMode::Currency => ProfitNormalization::Raw,
+            ReturnMode::PerLot => ProfitNormalization::PerLot,
+            ReturnMode::PercentOfBalance => ProfitNormalization::PercentOfBalance,
+            ReturnMode::RMultiple => ProfitNormalization::PerRiskUnit,
+        };
+        let mut returns = normalized_profits(&trades, normalization, Some(account_size))?;
+        let losses: Vec<f64> = returns.iter().copied().filter(|&r| r < 0.0).collect();
+        if self != ReturnMode::RMultiple {
+            // Without a loss there is no amount risked to measure the profits in
+            if losses.is_empty() {
+                return Err(Error::InsufficientData(format!(
+                    "{:?} profits need at least one losing trade to be converted to risk units",
+                    self
+                )));
+            }
+            let unit = -losses.iter().sum::<f64>() / losses.len() as f64;
+            returns.iter_mut().for_each(|r| *r /= unit);
+        }
+        Ok(trades.into_iter().zip(returns).map(|(trade, profit)| Trade { profit, ..trade }).collect())
+    }
+}
+
+/// Money-management scheme deciding each simulated trade's position size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum SizingMode {
+    FixedFractional,   // `risk_fraction` of current equity
+    FixedLot,          // `risk_fraction` of the initial balance, never compounded
+    FixedRatio,        // Ryan Jones: one more unit for every `delta` of profit per unit held
+    VolatilityTarget,  // Scaled so each trade moves equity by `target_volatility`
+    KellyRecalculated, // Fractional Kelly re-estimated every `recalc_interval` trades
+    EquityCurve,       // `risk_fraction` of equity, cut by `equity_curve_multiplier` below its moving average
+}
+
+/// Position sizing for the Monte Carlo engine. Sizes are in the engine's
+/// units: a trade with profit `r` changes equity by `size * r`. The adaptive
+/// modes measure the last `lookback` trades, starting from the tail of the
+/// history, and never exceed `risk_fraction` of equity. Equity curve sizing
+/// averages the simulated equity over the last `lookback` trades instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct SizingStrategy {
+    pub mode: SizingMode,
+    pub risk_fraction: f64, // Fraction per unit, or the cap for adaptive modes
+    pub delta: Option<f64>, // Fixed ratio: profit per unit held needed to add a unit
+    pub target_volatility: Option<f64>, // Volatility target: per-trade equity change, as a fraction
+    pub lookback: usize,
+    pub recalc_interval: usize,
+    pub kelly_multiplier: f64,
+    pub equity_curve_multiplier: f64, // Equity curve: share of `risk_fraction` risked below the moving average
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SizingStrategy {
+    #[new]
+    #[pyo3(signature = (mode, risk_fraction, delta=None, target_volatility=None, lookback=50, recalc_interval=20, kelly_multiplier=0.5, equity_curve_multiplier=0.5))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        mode: SizingMode,
+        risk_fraction: f64,
+        delta: Option<f64>,
+        target_volatility: Option<f64>,
+        lookback: usize,
+        recalc_interval: usize,
+        kelly_multiplier: f64,
+        equity_curve_multiplier: f64,
+    ) -> Result<Self> {
+        Self::new(
+            mode,
+            risk_fraction,
+            delta,
+            target_volatility,
+            lookback,
+            recalc_interval,
+            kelly_multiplier,
+            equity_curve_multiplier,
+        )
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl SizingStrategy {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        mode: SizingMode,
+        risk_fraction: f64,
+        delta: Option<f64>,
+        target_volatility: Option<f64>,
+        lookback: usize,
+        recalc_interval: usize,
+        kelly_multiplier: f64,
+        equity_curve_multiplier: f64,
+    ) -> Result<Self> {
+        let strategy = SizingStrategy {
+            mode,
+            risk_fraction,
+            delta,
+            target_volatility,
+            lookback,
+            recalc_interval,
+            kelly_multiplier,
+            equity_curve_multiplier,
+        };
+        strategy.validate()?;
+        Ok(strategy)
+    }
+
+    fn fixed_fractional(risk_fraction: f64) -> Self {
+        SizingStrategy {
+            mode: SizingMode::FixedFractional,
+            risk_fraction,
+            delta: None,
+            target_volatility: None,
+            lookback: 50,
+            recalc_interval: 20,
+            kelly_multiplier: 0.5,
+            equity_curve_multiplier: 0.5,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.risk_fraction < 0.0 {
+            return Err(Error::Validation("Risk fraction must be non-negative".into()));
+        }
+        match self.mode {
+            SizingMode::FixedRatio if !self.delta.is_some_and(|d| d > 0.0) => {
+                Err(Error::Validation("Fixed ratio sizing needs a positive delta".into()))
+            }
+            SizingMode::VolatilityTarget if !self.target_volatility.is_some_and(|v| v > 0.0) => {
+                Err(Error::Validation("Volatility target sizing needs a positive target_volatility".into()))
+            }
+            SizingMode::VolatilityTarget | SizingMode::KellyRecalculated | SizingMode::EquityCurve
+                if self.lookback < 2 =>
+            {
+                Err(Error::Validation("Lookback must be at least 2 trades".into()))
+            }
+            SizingMode::KellyRecalculated if self.recalc_interval == 0 || self.kelly_multiplier < 0.0 => {
+                Err(Error::Validation(
+                    "Recalculation interval must be positive and Kelly multiplier non-negative".into(),
+                ))
+            }
+            SizingMode::EquityCurve if !(0.0..=1.0).contains(&self.equity_curve_multiplier) => {
+                Err(Error::Validation("Equity curve multiplier must be between 0 and 1".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Discipline rules a trader follows on top of the sizing strategy: daily
+/// stops that sit out the rest of the day, and caps on position size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct TradingRules {
+    pub max_losses_per_day: Option<u32>, // Stop for the day after this many losing trades
+    pub daily_stop_percent: Option<f64>, // Stop for the day once its loss uses this percent of the daily allowance
+    pub drawdown_threshold_percent: Option<f64>, // Cut risk while equity is this far below its high-water mark
+    pub drawdown_risk_multiplier: f64, // Scale on the position size during such a drawdown
+    pub max_risk_fraction: Option<f64>, // Cap on the position size as a fraction of equity
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl TradingRules {
+    #[new]
+    #[pyo3(signature = (
+        max_losses_per_day=None,
+        daily_stop_percent=None,
+        drawdown_threshold_percent=None,
+        drawdown_risk_multiplier=0.5,
+        max_risk_fraction=None
+    ))]
+    fn py_new(
+        max_losses_per_day: Option<u32>,
+        daily_stop_percent: Option<f64>,
+        drawdown_threshold_percent: Option<f64>,
+        drawdown_risk_multiplier: f64,
+        max_risk_fraction: Option<f64>,
+    ) -> Result<Self> {
+        TradingRules::new(
+            max_losses_per_day,
+            daily_stop_percent,
+            drawdown_threshold_percent,
+            drawdown_risk_multiplier,
+            max_risk_fraction,
+        )
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl TradingRules {
+    pub fn new(
+        max_losses_per_day: Option<u32>,
+        daily_stop_percent: Option<f64>,
+        drawdown_threshold_percent: Option<f64>,
+        drawdown_risk_multiplier: f64,
+        max_risk_fraction: Option<f64>,
+    ) -> Result<Self> {
+        let rules = TradingRules {
+            max_losses_per_day,
+            daily_stop_percent,
+            drawdown_threshold_percent,
+            drawdown_risk_multiplier,
+            max_risk_fraction,
+        };
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_losses_per_day == Some(0) {
+            return Err(Error::Validation("Max losses per day must be at least 1".into()));
+        }
+        if self.daily_stop_percent.is_some_and(|p| p <= 0.0 || p > 100.0) {
+            return Err(Error::Validation("Daily stop percent must be between 0 and 100".into()));
+        }
+        if self.drawdown_threshold_percent.is_some_and(|p| p <= 0.0 || p >= 100.0) {
+            return Err(Error::Validation("Drawdown threshold must be between 0 and 100 percent".into()));
+        }
+        if !(0.0..=1.0).contains(&self.drawdown_risk_multiplier) {
+            return Err(Error::Validation("Drawdown risk multiplier must be between 0 and 1".into()));
+        }
+        if self.max_risk_fraction.is_some_and(|f| f <= 0.0) {
+            return Err(Error::Validation("Max risk fraction must be positive".into()));
+        }
+        Ok(())
+    }
+
+    /// The sizing strategy's position size after the drawdown cut and the cap.
+    fn cap_size(&self, size: f64, equity: f64, peak_equity: f64) -> f64 {
+        let drawdown_percent = (peak_equity - equity) / peak_equity * 100.0;
+        let size = match self.drawdown_threshold_percent {
+            Some(threshold) if drawdown_percent >= threshold => size * self.drawdown_risk_multiplier,
+            _ => size,
+        };
+        match self.max_risk_fraction {
+            Some(cap) => size.min(equity * cap),
+            None => size,
+        }
+    }
+
+    /// Whether to sit out the rest of the day after `losses` losing trades
+    /// and a loss of `day_loss` against a daily allowance of `daily_loss_limit`.
+    fn stops_day(&self, losses: u32, day_loss: f64, daily_loss_limit: f64) -> bool {
+        self.max_losses_per_day.is_some_and(|max| losses >= max)
+            || self.daily_stop_percent.is_some_and(|p| day_loss >= daily_loss_limit * p / 100.0)
+    }
+}
+
+/// A tail event injected into the simulated days, such as a news gap or
+/// broker slippage: each day, with `probability_per_day`, one extra trade
+/// loses `loss_multiple` times the average historical loss.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct ShockEvent {
+    pub probability_per_day: f64,
+    pub loss_multiple: f64, // In average losses, one risk unit if the history has none
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ShockEvent {
+    #[new]
+    #[pyo3(signature = (probability_per_day, loss_multiple=3.0))]
+    fn py_new(probability_per_day: f64, loss_multiple: f64) -> Result<Self> {
+        ShockEvent::new(probability_per_day, loss_multiple)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl ShockEvent {
+    pub fn new(probability_per_day: f64, loss_multiple: f64) -> Result<Self> {
+        let shock = ShockEvent { probability_per_day, loss_multiple };
+        shock.validate()?;
+        Ok(shock)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.probability_per_day) {
+            return Err(Error::Validation("Shock probability per day must be between 0 and 1".into()));
+        }
+        if !(self.loss_multiple >= 0.0 && self.loss_multiple.is_finite()) {
+            return Err(Error::Validation("Shock loss multiple must be non-negative".into()));
+        }
+        Ok(())
+    }
+
+    /// The shock's return in the units of `history`.
+    fn loss(&self, history: &[f64]) -> f64 {
+        let losses: Vec<f64> = history.iter().copied().filter(|&r| r < 0.0).collect();
+        let average_loss = match losses.len() {
+            0 => -1.0,
+            n => losses.iter().sum::<f64>() / n as f64,
+        };
+        self.loss_multiple * average_loss
+    }
+}
+
+/// Per-phase sizing state: the rolling window of recent trades, the
+/// fraction last estimated from it and the recent equity curve.
+struct PositionSizing<'a> {
+    strategy: &'a SizingStrategy,
+    window: std::collections::VecDeque<f64>,
+    equity_curve: std::collections::VecDeque<f64>,
+    fraction: f64,
+    trades: usize,
+}
+
+impl<'a> PositionSizing<'a> {
+    fn new(strategy: &'a SizingStrategy, history: &[f64]) -> Self {
+        let seed = &history[history.len().saturating_sub(strategy.lookback)..];
+        PositionSizing {
+            strategy,
+            window: seed.iter().copied().collect(),
+            equity_curve: std::collections::VecDeque::new(),
+            fraction: strategy.risk_fraction,
+            trades: 0,
+        }
+    }
+
+    /// Position size for the next trade.
+    fn size(&mut self, equity: f64, account_size: f64) -> f64 {
+        let strategy = self.strategy;
+        match strategy.mode {
+            SizingMode::FixedFractional => equity * strategy.risk_fraction,
+            SizingMode::FixedLot => account_size * strategy.risk_fraction,
+            SizingMode::FixedRatio => {
+                // N units need delta * N(N-1)/2 of profit
+                let profit = (equity - account_size).max(0.0);
+                let delta = strategy.delta.unwrap_or(f64::INFINITY);
+                let units = ((1.0 + (1.0 + 8.0 * profit / delta).sqrt()) / 2.0).floor();
+                units * account_size * strategy.risk_fraction
+            }
+            SizingMode::VolatilityTarget => {
+                let (_, std) = mean_and_std(self.window.make_contiguous());
+                let target = strategy.target_volatility.unwrap_or(0.0);
+                let fraction = if std > 0.0 { target / std } else { strategy.risk_fraction };
+                equity * fraction.min(strategy.risk_fraction)
+            }
+            SizingMode::KellyRecalculated => {
+                if self.trades.is_multiple_of(strategy.recalc_interval) {
+                    self.fraction = self.kelly_fraction();
+                }
+                equity * self.fraction
+            }
+            SizingMode::EquityCurve => {
+                if self.equity_curve.len() == strategy.lookback {
+                    self.equity_curve.pop_front();
+                }
+                self.equity_curve.push_back(equity);
+                let average = self.equity_curve.iter().sum::<f64>() / self.equity_curve.len() as f64;
+                let scale = if equity < average { strategy.equity_curve_multiplier } else { 1.0 };
+                equity * strategy.risk_fraction * scale
+            }
+        }
+    }
+
+    /// Fractional Kelly of the current window, capped at `risk_fraction`.
+    fn kelly_fraction(&mut self) -> f64 {
+        let cap = self.strategy.risk_fraction;
+        let window = self.window.make_contiguous();
+        let largest_loss = window.iter().fold(0.0f64, |worst, &r| worst.min(r));
+        if largest_loss == 0.0 {
+            return cap;
+        }
+        let (kelly, _) = maximize_log_growth(window, 0.0, 0.999 / -largest_loss, 1e-9 / -largest_loss);
+        (kelly * self.strategy.kelly_multiplier).min(cap)
+    }
+
+    fn record(&mut self, ret: f64) {
+        self.trades += 1;
+        if matches!(self.strategy.mode, SizingMode::VolatilityTarget | SizingMode::KellyRecalculated) {
+            if self.window.len() == self.strategy.lookback {
+                self.window.pop_front();
+            }
+            self.window.push_back(ret);
+        }
+    }
+}
+
+/// Trading frictions applied to every trade before a simulation, in account
+/// currency per lot like MT5 commission and swap. Slippage and commission
+/// are always costs; swap may be negative for a carry credit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct CostModel {
+    pub commission_per_lot: f64, // Round trip
+    pub swap_per_lot_per_day: f64, // Per calendar day held; needs open and close times
+    pub slippage_per_lot: f64, // Fixed slippage over both fills
+    pub slippage_percent: f64, // Proportional slippage, percent of the trade's absolute profit
+    pub include_trade_costs: bool, // Also charge each trade's recorded commission and swap
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CostModel {
+    #[new]
+    #[pyo3(signature = (
+        commission_per_lot=0.0,
+        swap_per_lot_per_day=0.0,
+        slippage_per_lot=0.0,
+        slippage_percent=0.0,
+        include_trade_costs=true
+    ))]
+    fn py_new(
+        commission_per_lot: f64,
+        swap_per_lot_per_day: f64,
+        slippage_per_lot: f64,
+        slippage_percent: f64,
+        include_trade_costs: bool,
+    ) -> Result<Self> {
+        CostModel::new(commission_per_lot, swap_per_lot_per_day, slippage_per_lot, slippage_percent, include_trade_costs)
+    }
+
+    /// The trade as the simulation sees it: `profit` net of every cost, with
+    /// commission plus slippage in `commission` and holding costs in `swap`
+    /// (negative for costs, as in MT5 reports).
+    #[pyo3(name = "apply")]
+    fn py_apply(&self, trade: &Trade) -> Trade {
+        self.apply(trade)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl CostModel {
+    pub fn new(
+        commission_per_lot: f64,
+        swap_per_lot_per_day: f64,
+        slippage_per_lot: f64,
+        slippage_percent: f64,
+        include_trade_costs: bool,
+    ) -> Result<Self> {
+        if commission_per_lot < 0.0 || slippage_per_lot < 0.0 {
+            return Err(Error::Validation("Commission and slippage must not be negative".into()));
+        }
+        if !(0.0..100.0).contains(&slippage_percent) {
+            return Err(Error::Validation("Slippage percent must be between 0 and 100".into()));
+        }
+        Ok(CostModel {
+            commission_per_lot,
+            swap_per_lot_per_day,
+            slippage_per_lot,
+            slippage_percent,
+            include_trade_costs,
+        })
+    }
+
+    /// The trade as the simulation sees it: `profit` net of every cost, with
+    /// commission plus slippage in `commission` and holding costs in `swap`
+    /// (negative for costs, as in MT5 reports).
+    pub fn apply(&self, trade: &Trade) -> Trade {
+        let recorded = |cost: Option<f64>| if self.include_trade_costs { cost.unwrap_or(0.0) } else { 0.0 };
+        let days_held = match (trade.open_time, trade.close_time) {
+            (Some(open), Some(close)) => (close.div_euclid(MILLIS_PER_DAY) - open.div_euclid(MILLIS_PER_DAY)).max(0),
+            _ => 0,
+        };
+        let slippage = self.slippage_per_lot * trade.volume + trade.profit.abs() * self.slippage_percent / 100.0;
+        let commission = recorded(trade.commission) - self.commission_per_lot * trade.volume - slippage;
+        let swap = recorded(trade.swap) - self.swap_per_lot_per_day * trade.volume * days_held as f64;
+        Trade {
+            profit: trade.profit + commission + swap,
+            commission: Some(commission),
+            swap: Some(swap),
+            ..trade.clone()
+        }
+    }
+}
+
+/// Exchange rates from the currency a report is in to the challenge's
+/// account currency. Each rate applies from its time until the next one;
+/// trades closed before the first time use the first rate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ExchangeRates {
+    pub from_currency: String, // Currency the trades are reported in, e.g. "EUR"
+    pub to_currency: String, // Challenge account currency, e.g. "USD"
+    pub rates: Vec<f64>, // Units of to_currency per unit of from_currency
+    pub times: Vec<i64>, // Epoch ms each rate applies from, ascending; empty for one fixed rate
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ExchangeRates {
+    #[new]
+    #[pyo3(signature = (from_currency, to_currency, rates, times=Vec::new()))]
+    fn py_new(from_currency: String, to_currency: String, rates: Vec<f64>, times: Vec<i64>) -> Result<Self> {
+        ExchangeRates::new(from_currency, to_currency, rates, times)
+    }
+
+    /// The rate in force at `time`; any time will do for a fixed rate.
+    #[pyo3(name = "rate_at")]
+    #[pyo3(signature = (time=None))]
+    fn py_rate_at(&self, time: Option<i64>) -> Result<f64> {
+        self.rate_at(time)
+    }
+
+    /// The trades with profit, commission, swap and risk amount in the
+    /// account currency, each at the rate in force when it closed. Prices
+    /// and volumes are left alone.
+    #[pyo3(name = "convert")]
+    fn py_convert(&self, trades: Vec<Trade>) -> Result<Vec<Trade>> {
+        self.convert(&trades)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl ExchangeRates {
+    pub fn new(from_currency: String, to_currency: String, rates: Vec<f64>, times: Vec<i64>) -> Result<Self> {
+        if rates.is_empty() {
+            return Err(Error::Validation("At least one exchange rate is required".into()));
+        }
+        if rates.iter().any(|r| !r.is_finite() || *r <= 0.0) {
+            return Err(Error::Validation("Exchange rates must be positive".into()));
+        }
+        if times.is_empty() && rates.len() > 1 {
+            return Err(Error::Validation("Several exchange rates need the times they apply from".into()));
+        }
+        if !times.is_empty() && times.len() != rates.len() {
+            return Err(Error::Validation("Exchange rate times and rates must have the same length".into()));
+        }
+        if times.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::Validation("Exchange rate times must be strictly ascending".into()));
+        }
+        Ok(ExchangeRates { from_currency, to_currency, rates, times })
+    }
+
+    /// The rate in force at `time`; any time will do for a fixed rate.
+    pub fn rate_at(&self, time: Option<i64>) -> Result<f64> {
+        if self.times.is_empty() {
+            return Ok(self.rates[0]);
+        }
+        let time = time.ok_or_else(|| {
+            Error::Validation("Converting with dated exchange rates needs the close time of every trade".into())
+        })?;
+        let index = self.times.partition_point(|&t| t <= time).saturating_sub(1);
+        Ok(self.rates[index])
+    }
+
+    /// The trades with profit, commission, swap and risk amount in the
+    /// account currency, each at the rate in force when it closed. Prices
+    /// and volumes are left alone.
+    pub fn convert(&self, trades: &[Trade]) -> Result<Vec<Trade>> {
+        trades
+            .iter()
+            .map(|trade| {
+                let rate = self.rate_at(trade.close_time.or(trade.open_time))?;
+                Ok(Trade {
+                    profit: trade.profit * rate,
+                    commission: trade.commission.map(|c| c * rate),
+                    swap: trade.swap.map(|s| s * rate),
+                    risk_amount: trade.risk_amount.map(|r| r * rate),
+                    ..trade.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+/// The funded account traded after passing a challenge. Payouts withdraw
+/// everything above the account size at the end of each cycle, and a
+/// trailing drawdown starts over from the account size after each one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct FundedAccount {
+    pub profit_split: f64, // Trader's share of each payout, 0 to 1
+    pub payout_interval_days: u32, // Trading days per payout cycle
+    pub horizon_days: u32, // Funded trading days simulated after the pass
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl FundedAccount {
+    #[new]
+    #[pyo3(signature = (profit_split=0.8, payout_interval_days=TRADING_DAYS_PER_MONTH, horizon_days=12 * TRADING_DAYS_PER_MONTH))]
+    fn py_new(profit_split: f64, payout_interval_days: u32, horizon_days: u32) -> Result<Self> {
+        FundedAccount::new(profit_split, payout_interval_days, horizon_days)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl FundedAccount {
+    pub fn new(profit_split: f64, payout_interval_days: u32, horizon_days: u32) -> Result<Self> {
+        if !(0.0..=1.0).contains(&profit_split) {
+            return Err(Error::Validation("Profit split must be between 0 and 1".into()));
+        }
+        if payout_interval_days == 0 || horizon_days == 0 {
+            return Err(Error::Validation("Payout interval and horizon must be positive".into()));
+        }
+        Ok(FundedAccount {
+            profit_split,
+            payout_interval_days,
+            horizon_days,
+        })
+    }
+}
+
+/// Fitted distribution of per-trade returns, used by the parametric
+/// simulation modes instead of the historical trades themselves. The Markov
+/// chain model draws whether each trade wins from the transition
+/// probabilities and then a historical win or loss (breakevens count as
+/// losses), so streaks are as persistent as in the history. The GARCH model
+/// lets each return's variance follow the size of the previous ones, so
+/// volatile stretches cluster as they do in a daily return series.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct FittedReturnModel {
+    pub model: ReturnModel,
+    pub weights: Vec<f64>, // One component except for Gaussian mixtures
+    pub means: Vec<f64>, // Location of each component
+    pub std_devs: Vec<f64>, // Scale of each component; the Student-t scale, not its standard deviation
+    pub degrees_of_freedom: Option<f64>, // Student-t only
+    pub win_after_win: Option<f64>, // Markov chain only: P(win | previous trade won)
+    pub win_after_loss: Option<f64>, // Markov chain only: P(win | previous trade lost)
+    pub dependence_p_value: Option<f64>, // Chi-square test of independence of consecutive outcomes
+    pub wins: Vec<f64>, // Markov chain only: historical returns drawn after a simulated win
+    pub losses: Vec<f64>,
+    #[serde(default)]
+    pub garch_omega: Option<f64>, // GARCH only: variance = omega + alpha * shock^2 + beta * variance
+    #[serde(default)]
+    pub garch_alpha: Option<f64>,
+    #[serde(default)]
+    pub garch_beta: Option<f64>,
+}
+
+/// What a path's next draw depends on: the Markov chain's last outcome and
+/// the GARCH conditional variance.
+#[derive(Debug, Default)]
+struct DrawState {
+    previous_win: Option<bool>,
+    variance: Option<f64>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl FittedReturnModel {
+    /// Draws `n` returns from the fitted distribution.
+    #[pyo3(name = "sample")]
+    #[pyo3(signature = (n, seed=None))]
+    fn py_sample(&self, n: usize, seed: Option<u64>) -> Vec<f64> {
+        self.sample(n, seed)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        hash_repr(&self.py_repr())
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl FittedReturnModel {
+    /// Draws `n` returns from the fitted distribution.
+    pub fn sample(&self, n: usize, seed: Option<u64>) -> Vec<f64> {
+        let mut rng = simulation_rng(seed.unwrap_or_else(rand::random), 0);
+        let mut state = DrawState::default();
+        (0..n).map(|_| self.draw(&mut rng, &mut state)).collect()
+    }
+
+    /// Fits `model` to a return sample; `None` for bootstrapping, which
+    /// needs no fit.
+    fn fit(returns: &[f64], model: ReturnModel, components: usize) -> Result<Option<Self>> {
+        if model == ReturnModel::Bootstrap {
+            return Ok(None);
+        }
+        let (mean, std) = mean_and_std(returns);
+        if returns.len() < 2 || std == 0.0 {
+            return Err(Error::InsufficientData("Fitting a return model needs at least two distinct returns".into()));
+        }
+        let single = |std_dev: f64, degrees_of_freedom: Option<f64>| FittedReturnModel {
+            model,
+            weights: vec![1.0],
+            means: vec![mean],
+            std_devs: vec![std_dev],
+            degrees_of_freedom,
+            win_after_win: None,
+            win_after_loss: None,
+            dependence_p_value: None,
+            wins: Vec::new(),
+            losses: Vec::new(),
+            garch_omega: None,
+            garch_alpha: None,
+            garch_beta: None,
+        };
+
+        Ok(Some(match model {
+            ReturnModel::Bootstrap => unreachable!(),
+            ReturnModel::Normal => single(std, None),
+            ReturnModel::StudentT => {
+                // Method of moments: excess kurtosis 6 / (nu - 4), kept in a range with finite variance
+                let kurtosis = returns.iter().map(|r| ((r - mean) / std).powi(4)).sum::<f64>() / returns.len() as f64;
+                let excess = kurtosis - 3.0;
+                let nu = if excess > 0.0 { (6.0 / excess + 4.0).clamp(2.5, 100.0) } else { 100.0 };
+                single(std * ((nu - 2.0) / nu).sqrt(), Some(nu))
+            }
+            ReturnModel::GaussianMixture => {
+                if components == 0 || returns.len() < 2 * components {
+                    return Err(Error::InsufficientData(format!(
+                        "A {}-component mixture needs at least {} trades",
+                        components,
+                        2 * components
+                    )));
+                }
+                let (weights, means, std_devs) = gaussian_mixture(returns, components, std);
+                FittedReturnModel {
+                    weights,
+                    means,
+                    std_devs,
+                    ..single(std, None)
+                }
+            }
+            ReturnModel::MarkovChain => {
+                let (wins, losses): (Vec<f64>, Vec<f64>) = returns.iter().partition(|&&r| r > 0.0);
+                if wins.is_empty() || losses.is_empty() {
+                    return Err(Error::InsufficientData("A Markov chain model needs both wins and losses".into()));
+                }
+                // counts[previous][next], with index 1 for a win
+                let mut counts = [[0.0_f64; 2]; 2];
+                for pair in returns.windows(2) {
+                    counts[(pair[0] > 0.0) as usize][(pair[1] > 0.0) as usize] += 1.0;
+                }
+                let win_rate = wins.len() as f64 / returns.len() as f64;
+                let after = |previous: usize| {
+                    let visits = counts[previous][0] + counts[previous][1];
+                    if visits > 0.0 { counts[previous][1] / visits } else { win_rate }
+                };
+                FittedReturnModel {
+                    win_after_win: Some(after(1)),
+                    win_after_loss: Some(after(0)),
+                    dependence_p_value: independence_p_value(&counts),
+                    wins,
+                    losses,
+                    ..single(std, None)
+                }
+            }
+            ReturnModel::Garch => {
+                if returns.len() < GARCH_MIN_RETURNS {
+                    return Err(Error::InsufficientData(format!(
+                        "A GARCH model needs at least {} returns",
+                        GARCH_MIN_RETURNS
+                    )));
+                }
+                let shocks: Vec<f64> = returns.iter().map(|r| r - mean).collect();
+                let (alpha, beta) = fit_garch(&shocks);
+                let variance = shocks.iter().map(|e| e * e).sum::<f64>() / shocks.len() as f64;
+                FittedReturnModel {
+                    garch_omega: Some(variance * (1.0 - alpha - beta)),
+                    garch_alpha: Some(alpha),
+                    garch_beta: Some(beta),
+                    ..single(variance.sqrt(), None)
+                }
+            }
+        }))
+    }
+
+    /// Draws one return; `state` carries the Markov chain and GARCH state
+    /// from one trade to the next and is ignored by the other models.
+    fn draw(&self, rng: &mut impl rand::Rng, state: &mut DrawState) -> f64 {
+        use rand_distr::{Distribution, StandardNormal, StudentT};
+
+        if let (Some(after_win), Some(after_loss)) = (self.win_after_win, self.win_after_loss) {
+            let p = match state.previous_win {
+                Some(true) => after_win,
+                Some(false) => after_loss,
+                None => self.wins.len() as f64 / (self.wins.len() + self.losses.len()) as f64,
+            };
+            let win = rng.gen::<f64>() < p;
+            state.previous_win = Some(win);
+            let pool = if win { &self.wins } else { &self.losses };
+            return pool[rng.gen_range(0..pool.len())];
+        }
+
+        if let (Some(omega), Some(alpha), Some(beta)) = (self.garch_omega, self.garch_alpha, self.garch_beta) {
+            // Paths start at the unconditional variance
+            let variance = state.variance.unwrap_or(self.std_devs[0].powi(2));
+            let z: f64 = StandardNormal.sample(rng);
+            let shock = variance.sqrt() * z;
+            state.variance = Some(omega + alpha * shock * shock + beta * variance);
+            return self.means[0] + shock;
+        }
+
+        let mut component = 0;
+        if self.weights.len() > 1 {
+            let mut u: f64 = rng.gen();
+            while component + 1 < self.weights.len() && u >= self.weights[component] {
+                u -= self.weights[component];
+                component += 1;
+            }
+        }
+        let z: f64 = match self.degrees_of_freedom {
+            Some(nu) => StudentT::new(nu).expect("degrees of freedom are positive").sample(rng),
+            None => StandardNormal.sample(rng),
+        };
+        self.means[component] + self.std_devs[component] * z
+    }
+}
+
+/// Fewest returns a GARCH(1,1) is fitted to.
+const GARCH_MIN_RETURNS: usize = 20;
+
+/// Gaussian log-likelihood of demeaned returns under a GARCH(1,1) whose
+/// omega is set so the long-run variance matches the sample's.
+fn garch_log_likelihood(shocks: &[f64], sample_variance: f64, alpha: f64, beta: f64) -> f64 {
+    let omega = sample_variance * (1.0 - alpha - beta);
+    let mut variance = sample_variance;
+    let mut likelihood = 0.0;
+    for shock in shocks {
+        likelihood -= variance.ln() + shock * shock / variance;
+        variance = omega + alpha * shock * shock + beta * variance;
+    }
+    likelihood / 2.0
+}
+
+/// Maximum-likelihood alpha and beta of a GARCH(1,1) with variance
+/// targeting: a coarse grid over the stationary region, then a finer grid
+/// around its best point.
+fn fit_garch(shocks: &[f64]) -> (f64, f64) {
+    let sample_variance = shocks.iter().map(|e| e * e).sum::<f64>() / shocks.len() as f64;
+    let best_of = |grid: Vec<(f64, f64)>| {
+        grid.into_iter()
+            .filter(|&(alpha, beta)| alpha >= 0.0 && beta >= 0.0 && alpha + beta < 0.999)
+            .map(|(alpha, beta)| (garch_log_likelihood(shocks, sample_variance, alpha, beta), (alpha, beta)))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or((0.0, 0.0), |(_, point)| point)
+    };
+    let (alpha, beta) =
+        best_of((0..25).flat_map(|a| (0..50).map(move |b| (a as f64 * 0.02, b as f64 * 0.02))).collect());
+    best_of(
+        (-20..=20)
+            .flat_map(|a| (-20..=20).map(move |b| (alpha + a as f64 * 0.001, beta + b as f64 * 0.001)))
+            .collect(),
+    )
+}
+
+/// p-value of Pearson's chi-square test that a trade's outcome does not
+/// depend on the previous one, from a 2x2 transition count table. `None`
+/// when a row or column is empty and the test is undefined.
+fn independence_p_value(counts: &[[f64; 2]; 2]) -> Option<f64> {
+    let total: f64 = counts.iter().flatten().sum();
+    let rows = [counts[0][0] + counts[0][1], counts[1][0] + counts[1][1]];
+    let columns = [counts[0][0] + counts[1][0], counts[0][1] + counts[1][1]];
+    if rows.contains(&0.0) || columns.contains(&0.0) {
+        return None;
+    }
+    let mut statistic = 0.0;
+    for (i, row) in rows.iter().enumerate() {
+        for (j, column) in columns.iter().enumerate() {
+            let expected = row * column / total;
+            statistic += (counts[i][j] - expected).powi(2) / expected;
+        }
+    }
+    // With one degree of freedom the chi-square tail is erfc(sqrt(x / 2))
+    Some(erfc((statistic / 2.0).sqrt()))
+}
+
+/// Complementary error function (Numerical Recipes' Chebyshev fit,
+/// accurate to about 1.2e-7).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98 + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+/// Expectation-maximization fit of a `k`-component Gaussian mixture,
+/// started from equal-count slices of the sorted sample. Component standard
+/// deviations are floored so that no component collapses onto one point.
+fn gaussian_mixture(values: &[f64], k: usize, overall_std: f64) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let floor = overall_std * 1e-3;
+    let chunk = sorted.len().div_ceil(k);
+    let mut weights = vec![1.0 / k as f64; k];
+    let (mut means, mut std_devs): (Vec<f64>, Vec<f64>) = sorted
+        .chunks(chunk)
+        .map(|c| {
+            let (mean, std) = mean_and_std(c);
+            (mean, std.max(floor))
+        })
+        .unzip();
+    // Fewer chunks than components only happens for tiny samples; pad with the overall spread
+    while means.len() < k {
+        means.push(sorted[sorted.len() / 2]);
+        std_devs.push(overall_std);
+    }
+
+    let density = |x: f64, mean: f64, std: f64| {
+        (-0.5 * ((x - mean) / std).powi(2)).exp() / (std * (2.0 * std::f64::consts::PI).sqrt())
+    };
+    let mut previous_likelihood = f64::NEG_INFINITY;
+    let mut responsibilities = vec![vec![0.0; k]; values.len()];
+    for _ in 0..500 {
+        let mut likelihood = 0.0;
+        for (x, row) in values.iter().zip(responsibilities.iter_mut()) {
+            for j in 0..k {
+                row[j] = weights[j] * density(*x, means[j], std_devs[j]);
+            }
+            let total: f64 = row.iter().sum::<f64>().max(f64::MIN_POSITIVE);
+            row.iter_mut().for_each(|r| *r /= total);
+            likelihood += total.ln();
+        }
+
+        for j in 0..k {
+            let mass: f64 = responsibilities.iter().map(|row| row[j]).sum::<f64>().max(f64::MIN_POSITIVE);
+            let mean = values.iter().zip(&responsibilities).map(|(x, row)| row[j] * x).sum::<f64>() / mass;
+            let variance =
+                values.iter().zip(&responsibilities).map(|(x, row)| row[j] * (x - mean).powi(2)).sum::<f64>() / mass;
+            weights[j] = mass / values.len() as f64;
+            means[j] = mean;
+            std_devs[j] = variance.sqrt().max(floor);
+        }
+
+        if (likelihood - previous_likelihood).abs() < 1e-10 * likelihood.abs().max(1.0) {
+            break;
+        }
+        previous_likelihood = likelihood;
+    }
+    (weights, means, std_devs)
+}
+
+#[cfg_attr(feature = "python", pyfunction)]
+#[cfg_attr(feature = "python", pyo3(signature = (trades, model, components=2)))]
+pub fn fit_return_model(trades: Vec<Trade>, model: ReturnModel, components: usize) -> Result<Option<FittedReturnModel>> {
+    let returns: Vec<f64> = trades.iter().map(|t| t.profit).collect();
+    FittedReturnModel::fit(&returns, model, components)
+}
+
+/// Settings shared by every path of one Monte Carlo run.
+struct SimulationConfig {
+    sizing: SizingStrategy,
+    trades_per_day: Option<usize>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    progress: Option<Arc<SimulationProgress>>,
+    sample_paths: usize, // Simulations with a lower index record their equity path
+    return_model: ReturnModel,
+    mixture_components: usize,
+    horizon_days: Option<usize>, // Trading days per phase; None replays as many days as the history has
+    funded: Option<FundedAccount>, // Funded account traded after a pass
+    rules: Option<TradingRules>,
+    shock: Option<ShockEvent>,
+}
+
+impl SimulationConfig {
+    fn new(risk_fraction: f64) -> Self {
+        SimulationConfig {
+            sizing: SizingStrategy::fixed_fractional(risk_fraction),
+            trades_per_day: None,
+            resampling: ResamplingMode::Iid,
+            block_length: 1,
+            progress: None,
+            sample_paths: 0,
+            return_model: ReturnModel::Bootstrap,
+            mixture_components: 2,
+            horizon_days: None,
+            funded: None,
+            rules: None,
+            shock: None,
+        }
+    }
+}
+
+/// Lets Python stop a running simulation from another thread, e.g. a
+/// "Stop" button in a UI. Cancelled runs raise `SimulationCancelled`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CancellationToken {
+    #[new]
+    fn py_new() -> Self {
+        Self::new()
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&self) {
+        self.cancel()
+    }
+
+    #[getter(is_cancelled)]
+    fn py_is_cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Completed paths and the cancellation flag, shared between the rayon
+/// workers and the thread reporting progress.
+#[derive(Default)]
+pub struct SimulationProgress {
+    completed: AtomicUsize,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl SimulationProgress {
+    pub fn new(token: Option<&CancellationToken>) -> Self {
+        SimulationProgress {
+            completed: AtomicUsize::new(0),
+            cancelled: token.map(|t| t.cancelled.clone()).unwrap_or_default(),
+        }
+    }
+
+    /// Paths finished so far.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Runs `job` on a separate thread while the calling thread, holding the
+/// GIL only briefly, calls `callback` with the percent complete every
+/// `every` paths and checks for Ctrl-C. An interrupt or an exception from
+/// the callback cancels the run and is raised once the workers stop.
+#[cfg(feature = "python")]
+fn run_with_progress<T: Send>(
+    py: Python<'_>,
+    progress: &SimulationProgress,
+    total: usize,
+    callback: Option<&Bound<'_, PyAny>>,
+    every: usize,
+    job: impl FnOnce() -> Result<T> + Send,
+) -> PyResult<T> {
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+
+    let every = every.max(1);
+    let mut reported = 0;
+    let mut report = |completed: usize| -> PyResult<()> {
+        if let Some(callback) = callback {
+            if completed / every > reported / every || (completed == total && reported < total) {
+                reported = completed;
+                callback.call1((completed as f64 / total as f64 * 100.0,))?;
+            }
+        }
+        Ok(())
+    };
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = channel();
+        scope.spawn(move || sender.send(job()));
+        let receiver = std::sync::Mutex::new(receiver);
+
+        let mut failure = None;
+        loop {
+            let poll = std::time::Duration::from_millis(50);
+            match py.allow_threads(|| receiver.lock().expect("receiver lock").recv_timeout(poll)) {
+                Ok(result) => {
+                    return match failure {
+                        Some(error) => Err(error),
+                        None => result.map_err(PyErr::from).and_then(|value| report(total).map(|_| value)),
+                    };
+                }
+                Err(RecvTimeoutError::Timeout) if failure.is_none() => {
+                    let completed = progress.completed();
+                    if let Err(error) = py.check_signals().and_then(|_| report(completed)) {
+                        progress.cancelled.store(true, Ordering::Relaxed);
+                        failure = Some(error);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // The worker panicked; the scope re-raises the panic on exit
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(SimulationError::new_err("Simulation worker failed"));
+                }
+            }
+        }
+    })
+}
+
+/// Splits the trade history into trading sessions of per-trade values
+/// (usually returns). An explicit `trades_per_day` chunks the chronological
+/// history; otherwise trades are grouped by close date, and without
+/// timestamps every trade is treated as its own session.
+fn historical_sessions<T>(
+    trades: &[Trade],
+    trades_per_day: Option<usize>,
+    value: impl Fn(&Trade) -> T,
+) -> Vec<Vec<T>> {
+    let ordered = chronological(trades);
+    if let Some(size) = trades_per_day {
+        return ordered.chunks(size).map(|day| day.iter().map(|t| value(t)).collect()).collect();
+    }
+
+    let mut sessions: Vec<Vec<T>> = Vec::new();
+    let mut current_day = None;
+    for trade in ordered {
+        let day = trade_day(trade);
+        match sessions.last_mut() {
+            Some(session) if day.is_some() && day == current_day => session.push(value(trade)),
+            _ => sessions.push(vec![value(trade)]),
+        }
+        current_day = day;
+    }
+    sessions
+}
+
+/// Draws `len` indices into a history of length `n`. Block modes keep runs
+/// of consecutive sessions together (wrapping around the end of the history)
+/// so that clustered losing streaks survive the resampling.
+fn resample_indices(n: usize, len: usize, config: &SimulationConfig, rng: &mut impl rand::Rng) -> Vec<usize> {
+    let mut sample = Vec::with_capacity(len);
+    match config.resampling {
+        ResamplingMode::Iid => {
+            sample.extend((0..len).map(|_| rng.gen_range(0..n)));
+        }
+        ResamplingMode::Block => {
+            while sample.len() < len {
+                let start = rng.gen_range(0..n);
+                let take = config.block_length.min(len - sample.len());
+                sample.extend((start..start + take).map(|i| i % n));
+            }
+        }
+        ResamplingMode::Stationary => {
+            let restart_probability = 1.0 / config.block_length as f64;
+            let mut index = rng.gen_range(0..n);
+            sample.push(index);
+            while sample.len() < len {
+                index = if rng.gen_bool(restart_probability) { rng.gen_range(0..n) } else { (index + 1) % n };
+                sample.push(index);
+            }
+        }
+    }
+    sample
+}
+
+/// Simulates one challenge phase over an already resampled sequence of
+/// sessions, each paired with flags marking trades that break a trading
+/// restriction. `history` is the chronological trade history that seeds
+/// adaptive position sizing.
+fn simulate_path<'a>(
+    sessions: impl Iterator<Item = (&'a [f64], &'a [bool])>,
+    history: &[f64],
+    params: &ChallengeParams,
+    phase: &ChallengePhase,
+    config: &SimulationConfig,
+    record_path: bool,
+) -> PathOutcome {
+    let target_equity = params.account_size * (1.0 + phase.profit_target_percent / 100.0);
+    let max_loss = params.account_size * params.max_overall_loss_percent / 100.0;
+
+    let mut equity = params.account_size;
+    let mut peak_equity = equity;
+    let mut loss_floor = params.account_size - max_loss;
+    let mut sizing = PositionSizing::new(&config.sizing, history);
+    let consistency_rules = params.max_day_profit_percent.is_some() || params.min_profitable_days_percent.is_some();
+    let mut day_profits = Vec::new();
+
+    let mut outcome = PathOutcome {
+        failure: Some(FailureReason::Timeout),
+        final_equity: equity,
+        max_drawdown_percent: 0.0,
+        days_to_target: None,
+        target_before_min_days: false,
+        phases_passed: 0,
+        equity_path: if record_path { vec![equity] } else { Vec::new() },
+        funded_payout: 0.0,
+        funded_blowup_day: None,
+    };
+
+    'path: for (day, (session, restricted)) in sessions.enumerate() {
+        let trading_days = day as u32 + 1;
+
+        // Daily loss is measured against the balance at the start of the day
+        let day_start_equity = equity;
+        let daily_loss_limit = params.daily_loss_basis(day_start_equity) * params.max_daily_loss_percent / 100.0;
+        let mut losses = 0;
+
+        for (&ret, &restricted) in session.iter().zip(restricted) {
+            let mut position_size = sizing.size(equity, params.account_size);
+            if let Some(rules) = &config.rules {
+                position_size = rules.cap_size(position_size, equity, peak_equity);
+            }
+            let trade_pl = position_size * ret; // ret is already a profit/loss value
+            equity += trade_pl;
+            sizing.record(ret);
+            if record_path {
+                outcome.equity_path.push(equity);
+            }
+
+            if restricted {
+                outcome.failure = Some(FailureReason::Restricted);
+                break 'path;
+            }
+
+            peak_equity = peak_equity.max(equity);
+            outcome.max_drawdown_percent =
+                outcome.max_drawdown_percent.max((peak_equity - equity) / peak_equity * 100.0);
+            if params.drawdown_mode == DrawdownMode::TrailingEquity {
+                loss_floor = params.trail_floor(loss_floor, peak_equity);
+            }
+
+            // Check daily loss limit
+            if day_start_equity - equity > daily_loss_limit {
+                outcome.failure = Some(FailureReason::DailyLoss);
+                break 'path;
+            }
+
+            // Check overall loss limit
+            if equity < loss_floor {
+                outcome.failure = Some(FailureReason::OverallLoss);
+                break 'path;
+            }
+
+            // Check profit target; reaching it early means trading on until
+            // the minimum number of trading days and consistency rules are met
+            if equity >= target_equity {
+                let inconsistent = consistency_rules && {
+                    day_profits.push(equity - day_start_equity);
+                    let violation = params.consistency_violation(&day_profits);
+                    day_profits.pop();
+                    violation.is_some()
+                };
+                if inconsistent {
+                    outcome.failure = Some(FailureReason::Consistency);
+                } else if trading_days >= phase.min_trading_days {
+                    outcome.failure = None;
+                    outcome.days_to_target = Some(trading_days);
+                    outcome.phases_passed = 1;
+                    break 'path;
+                }
+                if trading_days < phase.min_trading_days {
+                    outcome.target_before_min_days = true;
+                }
+            }
+
+            losses += u32::from(ret < 0.0);
+            if config.rules.as_ref().is_some_and(|r| r.stops_day(losses, day_start_equity - equity, daily_loss_limit)) {
+                break;
+            }
+        }
+
+        if params.drawdown_mode == DrawdownMode::TrailingBalance {
+            loss_floor = params.trail_floor(loss_floor, equity);
+        }
+        if consistency_rules {
+            day_profits.push(equity - day_start_equity);
+        }
+    }
+
+    outcome.final_equity = equity;
+    outcome
+}
+
+/// Simulates every phase of one challenge attempt in order, each with a
+/// freshly resampled sequence of sessions, stopping at the first failure.
+/// With `config.funded`, a passed attempt goes on to trade the funded account.
+#[allow(clippy::too_many_arguments)]
+fn simulate_attempt(
+    sessions: &[Vec<f64>],
+    restricted: &[Vec<bool>],
+    history: &[f64],
+    params: &ChallengeParams,
+    phases: &[ChallengePhase],
+    config: &SimulationConfig,
+    fitted: Option<&FittedReturnModel>,
+    shock: Option<(f64, f64)>, // Daily probability and return of an injected shock
+    record_path: bool,
+    rng: &mut impl rand::Rng,
+) -> PathOutcome {
+    let mut attempt = PathOutcome {
+        failure: None,
+        final_equity: params.account_size,
+        max_drawdown_percent: 0.0,
+        days_to_target: Some(0),
+        target_before_min_days: false,
+        phases_passed: 0,
+        equity_path: Vec::new(),
+        funded_payout: 0.0,
+        funded_blowup_day: None,
+    };
+
+    let mut state = DrawState::default();
+    for phase in phases {
+        let days = config.horizon_days.unwrap_or(sessions.len());
+        let path = resample_sessions(sessions, restricted, days, config, fitted, shock, &mut state, rng);
+        let path = path.iter().map(|(session, flags)| (session.as_ref(), flags.as_ref()));
+        let outcome = simulate_path(path, history, params, phase, config, record_path);
+
+        attempt.final_equity = outcome.final_equity;
+        attempt.max_drawdown_percent = attempt.max_drawdown_percent.max(outcome.max_drawdown_percent);
+        attempt.target_before_min_days |= outcome.target_before_min_days;
+        attempt.equity_path.extend_from_slice(&outcome.equity_path);
+
+        match (outcome.failure, outcome.days_to_target) {
+            (None, Some(days)) => {
+                attempt.phases_passed += 1;
+                attempt.days_to_target = attempt.days_to_target.map(|total| total + days);
+            }
+            _ => {
+                attempt.failure = outcome.failure;
+                attempt.days_to_target = None;
+                break;
+            }
+        }
+    }
+
+    if let (None, Some(funded)) = (attempt.failure, &config.funded) {
+        let days = funded.horizon_days as usize;
+        let path = resample_sessions(sessions, restricted, days, config, fitted, shock, &mut state, rng);
+        let path = path.iter().map(|(session, flags)| (session.as_ref(), flags.as_ref()));
+        (attempt.funded_payout, attempt.funded_blowup_day) = simulate_funded(path, history, params, funded, config);
+    }
+
+    attempt
+}
+
+/// One resampled day's trade returns and their restriction flags.
+type SimulatedDay<'a> = (std::borrow::Cow<'a, [f64]>, std::borrow::Cow<'a, [bool]>);
+
+/// Resamples `len` days of sessions with their restriction flags. With a
+/// fitted model the sessions only set the trade counts per day, and drawn
+/// returns keep the flags of the trades they replace. A `shock` lands at a
+/// random point of the day as an extra, unrestricted trade.
+#[allow(clippy::too_many_arguments)]
+fn resample_sessions<'a>(
+    sessions: &'a [Vec<f64>],
+    restricted: &'a [Vec<bool>],
+    len: usize,
+    config: &SimulationConfig,
+    fitted: Option<&FittedReturnModel>,
+    shock: Option<(f64, f64)>,
+    state: &mut DrawState,
+    rng: &mut impl rand::Rng,
+) -> Vec<SimulatedDay<'a>> {
+    use std::borrow::Cow;
+
+    resample_indices(sessions.len(), len, config, rng)
+        .into_iter()
+        .map(|i| {
+            let mut session = match fitted {
+                Some(model) => sessions[i].iter().map(|_| model.draw(rng, state)).collect(),
+                None => Cow::Borrowed(sessions[i].as_slice()),
+            };
+            let mut flags = Cow::Borrowed(restricted[i].as_slice());
+            if let Some((probability, loss)) = shock {
+                if rng.gen_bool(probability) {
+                    let at = rng.gen_range(0..=session.len());
+                    session.to_mut().insert(at, loss);
+                    flags.to_mut().insert(at, false);
+                }
+            }
+            (session, flags)
+        })
+        .collect()
+}
+
+/// Trades the funded account after a pass: no profit target, the
+/// challenge's loss limits and restrictions, and every payout interval any
+/// equity above the account size is withdrawn and split. Returns the
+/// trader's payouts and the funded trading day the account was lost on.
+fn simulate_funded<'a>(
+    sessions: impl Iterator<Item = (&'a [f64], &'a [bool])>,
+    history: &[f64],
+    params: &ChallengeParams,
+    funded: &FundedAccount,
+    config: &SimulationConfig,
+) -> (f64, Option<u32>) {
+    let mut equity = params.account_size;
+    let mut peak_equity = equity;
+    let initial_floor = params.account_size * (1.0 - params.max_overall_loss_percent / 100.0);
+    let mut loss_floor = initial_floor;
+    let mut sizing = PositionSizing::new(&config.sizing, history);
+    let mut payouts = 0.0;
+
+    for (day, (session, restricted)) in sessions.enumerate() {
+        let trading_days = day as u32 + 1;
+        let day_start_equity = equity;
+        let daily_loss_limit = params.daily_loss_basis(day_start_equity) * params.max_daily_loss_percent / 100.0;
+
+        let mut losses = 0;
+
+        for (&ret, &restricted) in session.iter().zip(restricted) {
+            let mut position_size = sizing.size(equity, params.account_size);
+            if let Some(rules) = &config.rules {
+                position_size = rules.cap_size(position_size, equity, peak_equity);
+            }
+            equity += position_size * ret;
+            sizing.record(ret);
+            peak_equity = peak_equity.max(equity);
+            if params.drawdown_mode == DrawdownMode::TrailingEquity {
+                loss_floor = params.trail_floor(loss_floor, peak_equity);
+            }
+            if restricted || day_start_equity - equity > daily_loss_limit || equity < loss_floor {
+                return (payouts, Some(trading_days));
+            }
+            losses += u32::from(ret < 0.0);
+            if config.rules.as_ref().is_some_and(|r| r.stops_day(losses, day_start_equity - equity, daily_loss_limit)) {
+                break;
+            }
+        }
+
+        if params.drawdown_mode == DrawdownMode::TrailingBalance {
+            loss_floor = params.trail_floor(loss_floor, equity);
+        }
+        if trading_days.is_multiple_of(funded.payout_interval_days) && equity > params.account_size {
+            payouts += (equity - params.account_size) * funded.profit_split;
+            equity = params.account_size;
+            peak_equity = equity;
+            // The withdrawal takes the account back to its start, so a trailed floor must not stay above it
+            loss_floor = initial_floor;
+        }
+    }
+
+    (payouts, None)
+}
+
+/// Bootstraps `num_simulations` challenge attempts from the trade history.
+/// Each attempt draws from its own RNG stream derived from the seed and its
+/// index, so a seed gives identical results on any machine and thread count.
+fn run_simulations(
+    trades: &[Trade],
+    challenge_params: &ChallengeParams,
+    config: &SimulationConfig,
+    num_simulations: usize,
+    seed: Option<u64>,
+) -> Result<Vec<PathOutcome>> {
+    use rayon::prelude::*;
+
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if num_simulations == 0 {
+        return Err(Error::Validation("Number of simulations must be positive".into()));
+    }
+    if config.block_length == 0 {
+        return Err(Error::Validation("Block length must be positive".into()));
+    }
+    if config.trades_per_day == Some(0) {
+        return Err(Error::Validation("Trades per day must be positive".into()));
+    }
+
+    config.sizing.validate()?;
+
+    let sessions = historical_sessions(trades, config.trades_per_day, |t| t.profit);
+    let restricted =
+        historical_sessions(trades, config.trades_per_day, |t| challenge_params.trading_restriction(t).is_some());
+    let history: Vec<f64> = sessions.concat();
+    let fitted = FittedReturnModel::fit(&history, config.return_model, config.mixture_components)?;
+    let shock = match &config.shock {
+        Some(shock) => {
+            shock.validate()?;
+            Some((shock.probability_per_day, shock.loss(&history)))
+        }
+        None => None,
+    };
+    let phases = challenge_params.phase_rules();
+    let base_seed = seed.unwrap_or_else(rand::random);
+
+    let outcomes: Option<Vec<PathOutcome>> = (0..num_simulations)
+        .into_par_iter()
+        .map(|index| {
+            let progress = config.progress.as_deref();
+            if progress.is_some_and(SimulationProgress::is_cancelled) {
+                return None;
+            }
+            let mut rng = simulation_rng(base_seed, index as u64);
+            let record_path = index < config.sample_paths;
+            let outcome = simulate_attempt(
+                &sessions,
+                &restricted,
+                &history,
+                challenge_params,
+                &phases,
+                config,
+                fitted.as_ref(),
+                shock,
+                record_path,
+                &mut rng,
+            );
+            if let Some(progress) = progress {
+                progress.completed.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(outcome)
+        })
+        .collect();
+
+    outcomes.ok_or_else(|| {
+        let completed = config.progress.as_ref().map_or(0, |p| p.completed());
+        Error::Cancelled(format!(
+            "Simulation cancelled after {} of {} paths",
+            completed, num_simulations
+        ))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct MonteCarloResult {
+    #[serde(default)]
+    pub account_size: f64, // Starting equity of every simulation
+    pub num_simulations: usize,
+    pub passed_simulations: usize,
+    pub pass_rate: f64,
+    pub daily_loss_failures: usize,
+    pub overall_loss_failures: usize,
+    pub timeouts: usize,
+    #[serde(default)]
+    pub consistency_failures: usize, // Reached the target without ever meeting the consistency rules
+    #[serde(default)]
+    pub restricted_trading_failures: usize, // Drew a trade breaking the weekend or restricted-window rules
+    pub target_before_min_days: usize, // Paths that hit the target before min_trading_days
+    pub phase_pass_rates: Vec<f64>, // Share of all simulations that passed each phase
+    pub final_equity: Vec<f64>,
+    pub max_drawdown: Vec<f64>, // Percent of peak equity
+    pub days_to_target: Vec<Option<u32>>,
+    pub failure_reasons: Vec<Option<String>>, // None for passed simulations
+    pub phases_passed: Vec<usize>,
+    #[serde(default)]
+    pub sample_paths: Vec<Vec<(usize, f64)>>, // (trade number, equity) points of the first simulations
+    #[serde(default)]
+    pub funded_payouts: Vec<f64>, // Trader's funded payouts per simulation; empty without a funded phase
+    #[serde(default)]
+    pub funded_blowup_days: Vec<Option<u32>>, // Funded trading day the account was lost on, if it was
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl MonteCarloResult {
+    /// Percentile (0-100) of final equity across all simulations.
+    #[pyo3(name = "final_equity_percentile")]
+    fn py_final_equity_percentile(&self, p: f64) -> f64 {
+        self.final_equity_percentile(p)
+    }
+
+    /// Percentile (0-100) of the maximum drawdown percent across all simulations.
+    #[pyo3(name = "max_drawdown_percentile")]
+    fn py_max_drawdown_percentile(&self, p: f64) -> f64 {
+        self.max_drawdown_percentile(p)
+    }
+
+    /// Percentile (0-100) of days to target among passed simulations.
+    #[pyo3(name = "days_to_target_percentile")]
+    fn py_days_to_target_percentile(&self, p: f64) -> Option<f64> {
+        self.days_to_target_percentile(p)
+    }
+
+    /// Loss from the account size that final equity falls below in only
+    /// `1 - confidence` of the simulations (historical VaR).
+    #[pyo3(name = "value_at_risk")]
+    #[pyo3(signature = (confidence=0.95))]
+    fn py_value_at_risk(&self, confidence: f64) -> Result<f64> {
+        self.value_at_risk(confidence)
+    }
+
+    /// Average loss from the account size over the worst `1 - confidence`
+    /// of the simulations (expected shortfall).
+    #[pyo3(name = "conditional_value_at_risk")]
+    #[pyo3(signature = (confidence=0.95))]
+    fn py_conditional_value_at_risk(&self, confidence: f64) -> Result<f64> {
+        self.conditional_value_at_risk(confidence)
+    }
+
+    /// Share of simulations whose drawdown reached `threshold_percent` of peak equity.
+    #[pyo3(name = "drawdown_probability")]
+    fn py_drawdown_probability(&self, threshold_percent: f64) -> f64 {
+        self.drawdown_probability(threshold_percent)
+    }
+
+    /// Mean trader payout per attempt, counting failed attempts as zero.
+    /// None unless a funded phase was simulated.
+    #[pyo3(name = "expected_payout")]
+    fn py_expected_payout(&self) -> Option<f64> {
+        self.expected_payout()
+    }
+
+    /// Probability that a funded account is lost within `months` of
+    /// trading, among the simulations that passed.
+    #[pyo3(name = "funded_blowup_probability")]
+    fn py_funded_blowup_probability(&self, months: f64) -> Option<f64> {
+        self.funded_blowup_probability(months)
+    }
+
+    /// Headline numbers plus the usual percentiles as a flat dict.
+    #[pyo3(name = "summary")]
+    fn py_summary(&self) -> HashMap<String, f64> {
+        self.summary()
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        hash_repr(&self.py_repr())
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl MonteCarloResult {
+    /// Percentile (0-100) of final equity across all simulations.
+    pub fn final_equity_percentile(&self, p: f64) -> f64 {
+        sorted_percentile(self.final_equity.iter().copied(), p)
+    }
+
+    /// Percentile (0-100) of the maximum drawdown percent across all simulations.
+    pub fn max_drawdown_percentile(&self, p: f64) -> f64 {
+        sorted_percentile(self.max_drawdown.iter().copied(), p)
+    }
+
+    /// Percentile (0-100) of days to target among passed simulations.
+    pub fn days_to_target_percentile(&self, p: f64) -> Option<f64> {
+        if self.passed_simulations == 0 {
+            return None;
+        }
+        Some(sorted_percentile(self.days_to_target.iter().flatten().map(|&d| d as f64), p))
+    }
+
+    /// Loss from the account size that final equity falls below in only
+    /// `1 - confidence` of the simulations (historical VaR).
+    pub fn value_at_risk(&self, confidence: f64) -> Result<f64> {
+        check_confidence(confidence)?;
+        Ok(self.account_size - self.final_equity_percentile((1.0 - confidence) * 100.0))
+    }
+
+    /// Average loss from the account size over the worst `1 - confidence`
+    /// of the simulations (expected shortfall).
+    pub fn conditional_value_at_risk(&self, confidence: f64) -> Result<f64> {
+        check_confidence(confidence)?;
+        let mut sorted = self.final_equity.clone();
+        sorted.sort_by(f64::total_cmp);
+        let tail = ((1.0 - confidence) * sorted.len() as f64).round().max(1.0) as usize;
+        Ok(self.account_size - sorted[..tail].iter().sum::<f64>() / tail as f64)
+    }
+
+    /// Share of simulations whose drawdown reached `threshold_percent` of peak equity.
+    pub fn drawdown_probability(&self, threshold_percent: f64) -> f64 {
+        let hits = self.max_drawdown.iter().filter(|&&d| d >= threshold_percent).count();
+        hits as f64 / self.max_drawdown.len() as f64
+    }
+
+    /// Headline numbers plus the usual percentiles as a flat dict.
+    pub fn summary(&self) -> HashMap<String, f64> {
+        let mut summary = self.headline();
+        summary.insert("daily_loss_failures".to_string(), self.daily_loss_failures as f64);
+        summary.insert("overall_loss_failures".to_string(), self.overall_loss_failures as f64);
+        summary.insert("timeouts".to_string(), self.timeouts as f64);
+        summary.insert("consistency_failures".to_string(), self.consistency_failures as f64);
+        summary.insert("restricted_trading_failures".to_string(), self.restricted_trading_failures as f64);
+        for p in [5.0, 25.0, 50.0, 75.0, 95.0] {
+            summary.insert(format!("final_equity_p{}", p), self.final_equity_percentile(p));
+            summary.insert(format!("max_drawdown_p{}", p), self.max_drawdown_percentile(p));
+            if let Some(days) = self.days_to_target_percentile(p) {
+                summary.insert(format!("days_to_target_p{}", p), days);
+            }
+        }
+        if let Some(days) = self.days_to_target_percentile(90.0) {
+            summary.insert("days_to_target_p90".to_string(), days);
+        }
+        for confidence in [95, 99] {
+            let c = confidence as f64 / 100.0;
+            summary.insert(format!("var_{}", confidence), self.value_at_risk(c).unwrap_or(f64::NAN));
+            summary.insert(format!("cvar_{}", confidence), self.conditional_value_at_risk(c).unwrap_or(f64::NAN));
+        }
+        summary
+    }
+
+    /// Probability that a funded account is lost within `months` of
+    /// trading, among the simulations that passed.
+    pub fn funded_blowup_probability(&self, months: f64) -> Option<f64> {
+        if self.funded_blowup_days.is_empty() || self.passed_simulations == 0 {
+            return None;
+        }
+        let horizon = months * TRADING_DAYS_PER_MONTH as f64;
+        let lost = self.funded_blowup_days.iter().flatten().filter(|&&day| day as f64 <= horizon).count();
+        Some(lost as f64 / self.passed_simulations as f64)
+    }
+
+    /// Mean trader payout per attempt, counting failed attempts as zero.
+    /// None unless a funded phase was simulated.
+    pub fn expected_payout(&self) -> Option<f64> {
+        (!self.funded_payouts.is_empty())
+            .then(|| self.funded_payouts.iter().sum::<f64>() / self.funded_payouts.len() as f64)
+    }
+
+    fn from_outcomes(outcomes: Vec<PathOutcome>, params: &ChallengeParams) -> Self {
+        let num_phases = params.phase_rules().len();
+        let count = |reason| outcomes.iter().filter(|o| o.failure == Some(reason)).count();
+        let passed_simulations = outcomes.iter().filter(|o| o.failure.is_none()).count();
+        let phase_pass_rates = (1..=num_phases)
+            .map(|phase| outcomes.iter().filter(|o| o.phases_passed >= phase).count() as f64 / outcomes.len() as f64)
+            .collect();
+
+        MonteCarloResult {
+            account_size: params.account_size,
+            num_simulations: outcomes.len(),
+            passed_simulations,
+            pass_rate: passed_simulations as f64 / outcomes.len() as f64,
+            daily_loss_failures: count(FailureReason::DailyLoss),
+            overall_loss_failures: count(FailureReason::OverallLoss),
+            timeouts: count(FailureReason::Timeout),
+            consistency_failures: count(FailureReason::Consistency),
+            restricted_trading_failures: count(FailureReason::Restricted),
+            target_before_min_days: outcomes.iter().filter(|o| o.target_before_min_days).count(),
+            phase_pass_rates,
+            final_equity: outcomes.iter().map(|o| o.final_equity).collect(),
+            max_drawdown: outcomes.iter().map(|o| o.max_drawdown_percent).collect(),
+            days_to_target: outcomes.iter().map(|o| o.days_to_target).collect(),
+            failure_reasons: outcomes.iter().map(|o| o.failure.map(|f| f.as_str().to_string())).collect(),
+            phases_passed: outcomes.iter().map(|o| o.phases_passed).collect(),
+            sample_paths: Vec::new(),
+            funded_payouts: Vec::new(),
+            funded_blowup_days: Vec::new(),
+        }
+    }
+
+    /// Pass rates and counts, as returned by `run_monte_carlo_simulation`.
+    fn headline(&self) -> HashMap<String, f64> {
+        let mut headline = HashMap::new();
+        headline.insert("pass_rate".to_string(), self.pass_rate);
+        headline.insert("total_simulations".to_string(), self.num_simulations as f64);
+        headline.insert("passed_simulations".to_string(), self.passed_simulations as f64);
+        headline.insert(
+            "target_before_min_days_rate".to_string(),
+            self.target_before_min_days as f64 / self.num_simulations as f64,
+        );
+        for (i, rate) in self.phase_pass_rates.iter().enumerate() {
+            headline.insert(format!("phase_{}_pass_rate", i + 1), *rate);
+        }
+        if let Some(payout) = self.expected_payout() {
+            headline.insert("expected_payout".to_string(), payout);
+        }
+        if let Some(probability) = self.funded_blowup_probability(f64::INFINITY) {
+            headline.insert("funded_blowup_rate".to_string(), probability);
+        }
+        headline
+    }
+}
+
+fn check_confidence(confidence: f64) -> Result<()> {
+    if confidence <= 0.0 || confidence >= 1.0 {
+        return Err(Error::Validation("Confidence must be between 0 and 1".into()));
+    }
+    Ok(())
+}
+
+/// Sorts the values and returns their interpolated percentile.
+fn sorted_percentile(values: impl Iterator<Item = f64>, p: f64) -> f64 {
+    let mut sorted: Vec<f64> = values.collect();
+    sorted.sort_by(f64::total_cmp);
+    percentile(&sorted, p)
+}
+
+/// Settings of a Monte Carlo run besides the trades, the challenge, the
+/// risk fraction and the number of simulations. The defaults are those of
+/// `run_monte_carlo_detailed`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationOptions {
+    pub seed: Option<u64>,
+    pub resampling: ResamplingMode,
+    pub block_length: usize,
+    pub trades_per_day: Option<usize>,
+    pub symbol_specs: Option<SymbolRegistry>, // Revalues the trades from their prices
+    pub sizing: Option<SizingStrategy>, // None sizes at `risk_fraction` of equity
+    pub sample_paths: usize, // Simulations whose equity paths are kept
+    pub path_points: usize, // Points each kept path is thinned to
+    pub cost_model: Option<CostModel>,
+    pub return_model: ReturnModel,
+    pub mixture_components: usize,
+    pub funded: Option<FundedAccount>,
+    pub exchange_rates: Option<ExchangeRates>,
+    pub rules: Option<TradingRules>,
+    pub return_mode: ReturnMode,
+    pub shock: Option<ShockEvent>,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        SimulationOptions {
+            seed: None,
+            resampling: ResamplingMode::Iid,
+            block_length: 5,
+            trades_per_day: None,
+            symbol_specs: None,
+            sizing: None,
+            sample_paths: 0,
+            path_points: 200,
+            cost_model: None,
+            return_model: ReturnModel::Bootstrap,
+            mixture_components: 2,
+            funded: None,
+            exchange_rates: None,
+            rules: None,
+            return_mode: ReturnMode::Currency,
+            shock: None,
+        }
+    }
+}
+
+/// The headline figures of `run_monte_carlo_detailed`, which takes the same
+/// arguments apart from the sampled paths.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, risk_fraction, num_simulations, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, symbol_specs=None, sizing=None, progress=None, progress_every=1000, cancel_token=None, cost_model=None, return_model=ReturnModel::Bootstrap, mixture_components=2, funded=None, exchange_rates=None, rules=None, return_mode=ReturnMode::Currency, shock=None))]
+#[allow(clippy::too_many_arguments)]
+fn run_monte_carlo_simulation(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    symbol_specs: Option<SymbolRegistry>,
+    sizing: Option<SizingStrategy>,
+    progress: Option<&Bound<'_, PyAny>>,
+    progress_every: usize,
+    cancel_token: Option<PyRef<'_, CancellationToken>>,
+    cost_model: Option<CostModel>,
+    return_model: ReturnModel,
+    mixture_components: usize,
+    funded: Option<FundedAccount>,
+    exchange_rates: Option<ExchangeRates>,
+    rules: Option<TradingRules>,
+    return_mode: ReturnMode,
+    shock: Option<ShockEvent>,
+) -> PyResult<HashMap<String, f64>> {
+    let options = SimulationOptions {
+        seed,
+        resampling,
+        block_length,
+        trades_per_day,
+        symbol_specs,
+        sizing,
+        cost_model,
+        return_model,
+        mixture_components,
+        funded,
+        exchange_rates,
+        rules,
+        return_mode,
+        shock,
+        ..SimulationOptions::default()
+    };
+    let run = MonteCarloRun { trades, challenge_params, risk_fraction, num_simulations, options };
+    Ok(run.execute(py, progress, progress_every, cancel_token)?.headline())
+}
+
+/// With `symbol_specs`, trades are revalued from their prices and
+/// `risk_fraction` is the share of equity posted as margin on each trade.
+/// A `sizing` strategy replaces fixed-fractional sizing at `risk_fraction`.
+/// A `cost_model` first charges commission, swap and slippage on every
+/// trade. A parametric `return_model` draws returns from a distribution
+/// fitted to the trades. With `funded`, passed attempts go on to trade the
+/// funded account. `exchange_rates` converts the trades to the account
+/// currency before anything else. `rules` adds daily stops and position
+/// size caps on top of the sizing. `return_mode` says what each trade's
+/// profit is measured in, account currency by default; it is ignored with
+/// `symbol_specs`, which revalue the trades themselves. A `shock` injects a
+/// tail loss into random simulated days.
+///
+/// `progress` is called with the percent complete whenever another
+/// `progress_every` paths have finished (checked every 50 ms) and once at
+/// the end; Ctrl-C or `cancel_token` stop the run early. The equity paths
+/// of the first `sample_paths` simulations are kept, thinned to about
+/// `path_points` points each, for drawing fan charts. Seeded runs are
+/// cached, so repeating one with identical inputs returns at once.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, risk_fraction, num_simulations, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, symbol_specs=None, sizing=None, progress=None, progress_every=1000, cancel_token=None, sample_paths=0, path_points=200, cost_model=None, return_model=ReturnModel::Bootstrap, mixture_components=2, funded=None, exchange_rates=None, rules=None, return_mode=ReturnMode::Currency, shock=None))]
+#[allow(clippy::too_many_arguments)]
+fn run_monte_carlo_detailed(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    symbol_specs: Option<SymbolRegistry>,
+    sizing: Option<SizingStrategy>,
+    progress: Option<&Bound<'_, PyAny>>,
+    progress_every: usize,
+    cancel_token: Option<PyRef<'_, CancellationToken>>,
+    sample_paths: usize,
+    path_points: usize,
+    cost_model: Option<CostModel>,
+    return_model: ReturnModel,
+    mixture_components: usize,
+    funded: Option<FundedAccount>,
+    exchange_rates: Option<ExchangeRates>,
+    rules: Option<TradingRules>,
+    return_mode: ReturnMode,
+    shock: Option<ShockEvent>,
+) -> PyResult<MonteCarloResult> {
+    let options = SimulationOptions {
+        seed,
+        resampling,
+        block_length,
+        trades_per_day,
+        symbol_specs,
+        sizing,
+        sample_paths,
+        path_points,
+        cost_model,
+        return_model,
+        mixture_components,
+        funded,
+        exchange_rates,
+        rules,
+        return_mode,
+        shock,
+    };
+    let run = MonteCarloRun { trades, challenge_params, risk_fraction, num_simulations, options };
+    run.execute(py, progress, progress_every, cancel_token)
+}
+
+/// The inputs of one `monte_carlo_detailed` call, as gathered by the
+/// Python entry points.
+#[cfg(feature = "python")]
+struct MonteCarloRun {
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    options: SimulationOptions,
+}
+
+#[cfg(feature = "python")]
+impl MonteCarloRun {
+    /// Runs on a worker thread while reporting to `progress` every
+    /// `progress_every` paths; Ctrl-C or `cancel_token` stop the run.
+    fn execute(
+        self,
+        py: Python<'_>,
+        progress: Option<&Bound<'_, PyAny>>,
+        progress_every: usize,
+        cancel_token: Option<PyRef<'_, CancellationToken>>,
+    ) -> PyResult<MonteCarloResult> {
+        let monitor = Arc::new(SimulationProgress::new(cancel_token.as_deref()));
+        let job_monitor = monitor.clone();
+        let num_simulations = self.num_simulations;
+        run_with_progress(py, &monitor, num_simulations, progress, progress_every, move || {
+            self.run(Some(job_monitor))
+        })
+    }
+
+    fn run(self, progress: Option<Arc<SimulationProgress>>) -> Result<MonteCarloResult> {
+        monte_carlo_detailed(
+            self.trades,
+            self.challenge_params,
+            self.risk_fraction,
+            self.num_simulations,
+            self.options,
+            progress,
+        )
+    }
+}
+
+/// A seeded run is cached by its inputs and repeating it returns the
+/// cached result, with `progress` marked complete.
+pub fn monte_carlo_detailed(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    options: SimulationOptions,
+    progress: Option<Arc<SimulationProgress>>,
+) -> Result<MonteCarloResult> {
+    // Unseeded runs are meant to differ, so only seeded ones are reused
+    let key = options.seed.and_then(|_| {
+        cache_key(&("monte_carlo_detailed", &trades, &challenge_params, risk_fraction, num_simulations, &options))
+    });
+    let reported = progress.clone();
+    let result = cached(key, || {
+        simulate_detailed(trades, challenge_params, risk_fraction, num_simulations, options, progress)
+    })?;
+    // A cached result skipped the run, so report it as complete
+    if let Some(progress) = reported {
+        progress.completed.store(num_simulations, Ordering::Relaxed);
+    }
+    Ok(result)
+}
+
+fn simulate_detailed(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    options: SimulationOptions,
+    progress: Option<Arc<SimulationProgress>>,
+) -> Result<MonteCarloResult> {
+    if options.sample_paths > 0 && options.path_points < 2 {
+        return Err(Error::Validation("Sampled paths need at least 2 points".into()));
+    }
+    if let Some(rules) = &options.rules {
+        rules.validate()?;
+    }
+    let config = SimulationConfig {
+        sizing: options.sizing.unwrap_or_else(|| SizingStrategy::fixed_fractional(risk_fraction)),
+        trades_per_day: options.trades_per_day,
+        resampling: options.resampling,
+        block_length: options.block_length,
+        progress,
+        sample_paths: options.sample_paths,
+        return_model: options.return_model,
+        mixture_components: options.mixture_components,
+        horizon_days: None,
+        funded: options.funded,
+        rules: options.rules,
+        shock: options.shock,
+    };
+    let trades = match options.exchange_rates {
+        Some(rates) => rates.convert(&trades)?,
+        None => trades,
+    };
+    let trades = match options.cost_model {
+        Some(model) => trades.iter().map(|trade| model.apply(trade)).collect(),
+        None => trades,
+    };
+    let trades = match options.symbol_specs {
+        Some(registry) => registry.margin_returns(&trades)?,
+        None => options.return_mode.apply(trades, challenge_params.account_size)?,
+    };
+    let mut outcomes = run_simulations(&trades, &challenge_params, &config, num_simulations, options.seed)?;
+    let paths: Vec<Vec<f64>> =
+        outcomes.iter_mut().take(config.sample_paths).map(|o| std::mem::take(&mut o.equity_path)).collect();
+    let (funded_payouts, funded_blowup_days) = match config.funded {
+        Some(_) => outcomes.iter().map(|o| (o.funded_payout, o.funded_blowup_day)).unzip(),
+        None => (Vec::new(), Vec::new()),
+    };
+    Ok(MonteCarloResult {
+        sample_paths: downsample_paths(&paths, options.path_points),
+        funded_payouts,
+        funded_blowup_days,
+        ..MonteCarloResult::from_outcomes(outcomes, &challenge_params)
+    })
+}
+
+type SimulationTask = Box<dyn FnOnce(Arc<SimulationProgress>) -> Result<MonteCarloResult> + Send>;
+
+/// A Monte Carlo run on a background thread, for GUIs and notebooks that
+/// must not block: `start()` launches it, `progress()` and `result()` poll
+/// it and `cancel()` stops it early. The run itself still spreads its paths
+/// over the rayon thread pool.
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct SimulationJob {
+    num_simulations: usize,
+    progress: Arc<SimulationProgress>,
+    task: std::sync::Mutex<Option<SimulationTask>>, // Taken by start()
+    worker: std::sync::Mutex<Option<std::thread::JoinHandle<Result<MonteCarloResult>>>>,
+    outcome: std::sync::Mutex<Option<Result<MonteCarloResult>>>,
+}
+
+/// Takes the same settings as `run_monte_carlo_detailed`, without the
+/// progress callback and cancellation token that the job provides itself.
+#[cfg(feature = "python")]
+#[pymethods]
+impl SimulationJob {
+    #[new]
+    #[pyo3(signature = (trades, challenge_params, risk_fraction, num_simulations, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, symbol_specs=None, sizing=None, sample_paths=0, path_points=200, cost_model=None, return_model=ReturnModel::Bootstrap, mixture_components=2, funded=None, exchange_rates=None, rules=None, return_mode=ReturnMode::Currency, shock=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        trades: Vec<Trade>,
+        challenge_params: ChallengeParams,
+        risk_fraction: f64,
+        num_simulations: usize,
+        seed: Option<u64>,
+        resampling: ResamplingMode,
+        block_length: usize,
+        trades_per_day: Option<usize>,
+        symbol_specs: Option<SymbolRegistry>,
+        sizing: Option<SizingStrategy>,
+        sample_paths: usize,
+        path_points: usize,
+        cost_model: Option<CostModel>,
+        return_model: ReturnModel,
+        mixture_components: usize,
+        funded: Option<FundedAccount>,
+        exchange_rates: Option<ExchangeRates>,
+        rules: Option<TradingRules>,
+        return_mode: ReturnMode,
+        shock: Option<ShockEvent>,
+    ) -> Self {
+        let options = SimulationOptions {
+            seed,
+            resampling,
+            block_length,
+            trades_per_day,
+            symbol_specs,
+            sizing,
+            sample_paths,
+            path_points,
+            cost_model,
+            return_model,
+            mixture_components,
+            funded,
+            exchange_rates,
+            rules,
+            return_mode,
+            shock,
+        };
+        let run = MonteCarloRun { trades, challenge_params, risk_fraction, num_simulations, options };
+        SimulationJob::new(num_simulations, move |progress| run.run(Some(progress)))
+    }
+
+    #[pyo3(name = "start")]
+    fn py_start(&self) -> Result<()> {
+        self.start()
+    }
+
+    /// Percent of the paths finished so far.
+    #[pyo3(name = "progress")]
+    fn py_progress(&self) -> f64 {
+        self.progress()
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&self) {
+        self.cancel()
+    }
+
+    #[getter(is_done)]
+    fn py_is_done(&self) -> bool {
+        self.is_done()
+    }
+
+    /// Waits up to `timeout` seconds, forever by default, without holding
+    /// the GIL, and returns the result or raises the run's error. Raises
+    /// `TimeoutError` if the run is still going when the time is up.
+    #[pyo3(name = "result")]
+    #[pyo3(signature = (timeout=None))]
+    fn py_result(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<MonteCarloResult> {
+        let deadline = timeout.map(|t| std::time::Instant::now() + std::time::Duration::from_secs_f64(t.max(0.0)));
+        loop {
+            if let Some(outcome) = self.poll()? {
+                return Ok(outcome?);
+            }
+            let now = std::time::Instant::now();
+            if deadline.is_some_and(|d| now >= d) {
+                return Err(pyo3::exceptions::PyTimeoutError::new_err("The simulation is still running"));
+            }
+            let pause = deadline.map_or(JOB_POLL_INTERVAL, |d| (d - now).min(JOB_POLL_INTERVAL));
+            py.allow_threads(|| std::thread::sleep(pause));
+            py.check_signals()?;
+        }
+    }
+}
+
+/// How often a waiting `SimulationJob` checks whether its run has finished.
+const JOB_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+impl SimulationJob {
+    /// A job that will run `task`, which should report its completed paths
+    /// and check for cancellation through the progress it is given.
+    pub fn new(
+        num_simulations: usize,
+        task: impl FnOnce(Arc<SimulationProgress>) -> Result<MonteCarloResult> + Send + 'static,
+    ) -> Self {
+        SimulationJob {
+            num_simulations,
+            progress: Arc::new(SimulationProgress::new(None)),
+            task: std::sync::Mutex::new(Some(Box::new(task))),
+            worker: std::sync::Mutex::new(None),
+            outcome: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Launches the run on a background thread; a job runs only once.
+    pub fn start(&self) -> Result<()> {
+        let task = self.task.lock().expect("task lock").take();
+        let Some(task) = task else {
+            return Err(Error::Simulation("The simulation job has already been started".into()));
+        };
+        let progress = self.progress.clone();
+        *self.worker.lock().expect("worker lock") = Some(std::thread::spawn(move || task(progress)));
+        Ok(())
+    }
+
+    /// Percent of the paths finished so far.
+    pub fn progress(&self) -> f64 {
+        if self.num_simulations == 0 {
+            return 0.0;
+        }
+        self.progress.completed() as f64 / self.num_simulations as f64 * 100.0
+    }
+
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the run has finished, successfully or not.
+    pub fn is_done(&self) -> bool {
+        self.outcome.lock().expect("outcome lock").is_some()
+            || self.worker.lock().expect("worker lock").as_ref().is_some_and(|w| w.is_finished())
+    }
+
+    /// Blocks until the run finishes and returns its result.
+    pub fn wait(&self) -> Result<MonteCarloResult> {
+        loop {
+            if let Some(outcome) = self.poll()? {
+                return outcome;
+            }
+            std::thread::sleep(JOB_POLL_INTERVAL);
+        }
+    }
+
+    /// The run's outcome once it has finished, `None` while it is running,
+    /// or an error if it was never started.
+    fn poll(&self) -> Result<Option<Result<MonteCarloResult>>> {
+        let mut outcome = self.outcome.lock().expect("outcome lock");
+        if outcome.is_none() {
+            let mut worker = self.worker.lock().expect("worker lock");
+            match worker.take() {
+                None => return Err(Error::Simulation("The simulation job has not been started".into())),
+                Some(handle) if !handle.is_finished() => {
+                    *worker = Some(handle);
+                    return Ok(None);
+                }
+                Some(handle) => {
+                    let result = handle.join();
+                    *outcome = Some(result.unwrap_or_else(|_| Err(Error::Simulation("Simulation worker failed".into()))));
+                }
+            }
+        }
+        Ok(outcome.as_ref().map(|result| match result {
+            Ok(value) => Ok(value.clone()),
+            Err(error) => Err(error.duplicate()),
+        }))
+    }
+}
+
+/// Thins equity paths onto a shared grid of trade numbers, so that the
+/// longest path keeps about `points` points, always keeping each path's
+/// final point. Multi-phase paths restart from the account size each phase.
+fn downsample_paths(paths: &[Vec<f64>], points: usize) -> Vec<Vec<(usize, f64)>> {
+    let longest = paths.iter().map(Vec::len).max().unwrap_or(0);
+    let step = longest.saturating_sub(1).div_ceil(points.saturating_sub(1).max(1)).max(1);
+    paths
+        .iter()
+        .map(|path| {
+            let mut sampled: Vec<(usize, f64)> = path.iter().copied().enumerate().step_by(step).collect();
+            if (path.len() - 1) % step != 0 {
+                sampled.push((path.len() - 1, path[path.len() - 1]));
+            }
+            sampled
+        })
+        .collect()
+}
+
+/// Several strategies traded on one challenge account. Whole historical
+/// days are resampled at once, so trades the strategies closed on the same
+/// day stay together and their correlation carries into the simulation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct MultiStrategyResult {
+    pub combined: MonteCarloResult,
+    pub standalone_pass_rates: Vec<f64>, // Each strategy alone at its own allocation, on the same seed
+    pub allocations: Vec<f64>,
+    pub days: usize, // Historical days with a trade from any strategy
+}
+
+/// Each strategy risks its allocation times `risk_fraction` per unit of
+/// its returns, after `return_mode` has converted them to risk units.
+/// Every trade needs a close time to be placed on its day.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (strategies, allocations, challenge_params, risk_fraction, num_simulations, seed=None, resampling=ResamplingMode::Iid, block_length=5, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn run_multi_strategy_monte_carlo(
+    py: Python<'_>,
+    strategies: Vec<Vec<Trade>>,
+    allocations: Vec<f64>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    return_mode: ReturnMode,
+) -> Result<MultiStrategyResult> {
+    py.allow_threads(|| {
+        multi_strategy_monte_carlo(
+            strategies,
+            allocations,
+            challenge_params,
+            risk_fraction,
+            num_simulations,
+            seed,
+            resampling,
+            block_length,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn multi_strategy_monte_carlo(
+    strategies: Vec<Vec<Trade>>,
+    allocations: Vec<f64>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    return_mode: ReturnMode,
+) -> Result<MultiStrategyResult> {
+    if strategies.is_empty() {
+        return Err(Error::InsufficientData("No strategies provided".into()));
+    }
+    if allocations.len() != strategies.len() {
+        return Err(Error::Validation(format!(
+            "Expected {} allocations, one per strategy, got {}",
+            strategies.len(),
+            allocations.len()
+        )));
+    }
+    if allocations.iter().any(|a| a.is_nan() || *a < 0.0) || allocations.iter().all(|&a| a == 0.0) {
+        return Err(Error::Validation("Allocations must be non-negative with at least one positive".into()));
+    }
+    if strategies.iter().flatten().any(|t| t.close_time.is_none()) {
+        return Err(Error::Validation("Multi-strategy simulation needs a close time on every trade".into()));
+    }
+
+    let mut allocated = Vec::with_capacity(strategies.len());
+    for (trades, &allocation) in strategies.into_iter().zip(&allocations) {
+        let trades = return_mode.apply(trades, challenge_params.account_size)?;
+        allocated.push(trades.into_iter().map(|t| Trade { profit: t.profit * allocation, ..t }).collect::<Vec<_>>());
+    }
+    let combined: Vec<Trade> = allocated.concat();
+
+    // The standalone runs share the combined run's seed
+    let seed = Some(seed.unwrap_or_else(rand::random));
+    let config = SimulationConfig { resampling, block_length, ..SimulationConfig::new(risk_fraction) };
+    let simulate = |trades: &[Trade]| -> Result<MonteCarloResult> {
+        let outcomes = run_simulations(trades, &challenge_params, &config, num_simulations, seed)?;
+        Ok(MonteCarloResult::from_outcomes(outcomes, &challenge_params))
+    };
+    let standalone_pass_rates = allocated
+        .iter()
+        .map(|trades| simulate(trades).map(|result| result.pass_rate))
+        .collect::<Result<Vec<f64>>>()?;
+
+    Ok(MultiStrategyResult {
+        combined: simulate(&combined)?,
+        standalone_pass_rates,
+        allocations,
+        days: historical_sessions(&combined, None, |_| ()).len(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct RiskFractionSearch {
+    pub risk_fractions: Vec<f64>,
+    pub pass_rates: Vec<f64>, // Pass rate for each entry of `risk_fractions`
+    pub best_risk_fraction: f64,
+    pub best_pass_rate: f64,
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, f_min, f_max, steps, num_simulations, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn optimize_risk_fraction(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    f_min: f64,
+    f_max: f64,
+    steps: usize,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<RiskFractionSearch> {
+    py.allow_threads(|| {
+        risk_fraction_search(
+            trades,
+            challenge_params,
+            f_min,
+            f_max,
+            steps,
+            num_simulations,
+            seed,
+            resampling,
+            block_length,
+            trades_per_day,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn risk_fraction_search(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    f_min: f64,
+    f_max: f64,
+    steps: usize,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<RiskFractionSearch> {
+    use rayon::prelude::*;
+
+    if steps == 0 {
+        return Err(Error::Validation("Steps must be positive".into()));
+    }
+    if f_min <= 0.0 || f_max < f_min {
+        return Err(Error::Validation("Risk fraction range must satisfy 0 < f_min <= f_max".into()));
+    }
+
+    let trades = return_mode.apply(trades, challenge_params.account_size)?;
+    let risk_fractions: Vec<f64> = match steps {
+        1 => vec![f_min],
+        _ => (0..steps)
+            .map(|i| f_min + (f_max - f_min) * i as f64 / (steps - 1) as f64)
+            .collect(),
+    };
+    // Every fraction replays the same resampled paths so the curve is not
+    // dominated by sampling noise between grid points
+    let seed = Some(seed.unwrap_or_else(rand::random));
+
+    let pass_rates = risk_fractions
+        .par_iter()
+        .map(|&risk_fraction| {
+            let config = SimulationConfig {
+                trades_per_day,
+                resampling,
+                block_length,
+                ..SimulationConfig::new(risk_fraction)
+            };
+            let outcomes = run_simulations(&trades, &challenge_params, &config, num_simulations, seed)?;
+            Ok(MonteCarloResult::from_outcomes(outcomes, &challenge_params).pass_rate)
+        })
+        .collect::<Result<Vec<f64>>>()?;
+
+    // The first of equally good fractions is the least aggressive one
+    let (best, best_pass_rate) = pass_rates
+        .iter()
+        .enumerate()
+        .fold((0, pass_rates[0]), |best, (i, &rate)| if rate > best.1 { (i, rate) } else { best });
+
+    Ok(RiskFractionSearch {
+        best_risk_fraction: risk_fractions[best],
+        best_pass_rate,
+        risk_fractions,
+        pass_rates,
+    })
+}
+
+/// A deliberately worse version of the backtest, since live results rarely
+/// match it: fewer winners, smaller wins and larger losses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all, set_all))]
+pub struct StressScenario {
+    pub win_rate_reduction: f64, // Percentage points of win rate lost, e.g. 5.0 turns 55% into 50%
+    pub avg_win_reduction_percent: f64, // Every win shrinks by this percentage
+    pub avg_loss_increase_percent: f64, // Every loss grows by this percentage
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl StressScenario {
+    #[new]
+    #[pyo3(signature = (win_rate_reduction=0.0, avg_win_reduction_percent=0.0, avg_loss_increase_percent=0.0))]
+    fn py_new(win_rate_reduction: f64, avg_win_reduction_percent: f64, avg_loss_increase_percent: f64) -> Result<Self> {
+        StressScenario::new(win_rate_reduction, avg_win_reduction_percent, avg_loss_increase_percent)
+    }
+
+    /// The trades with the scenario applied, in their original order.
+    #[pyo3(name = "apply")]
+    fn py_apply(&self, trades: Vec<Trade>) -> Result<Vec<Trade>> {
+        self.apply(&trades)
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+impl StressScenario {
+    pub fn new(
+        win_rate_reduction: f64,
+        avg_win_reduction_percent: f64,
+        avg_loss_increase_percent: f64,
+    ) -> Result<Self> {
+        let scenario = StressScenario {
+            win_rate_reduction,
+            avg_win_reduction_percent,
+            avg_loss_increase_percent,
+        };
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=100.0).contains(&self.win_rate_reduction) {
+            return Err(Error::Validation("Win rate reduction must be between 0 and 100 points".into()));
+        }
+        if !(0.0..100.0).contains(&self.avg_win_reduction_percent) {
+            return Err(Error::Validation("Average win reduction must be at least 0 and below 100 percent".into()));
+        }
+        if !(self.avg_loss_increase_percent >= 0.0 && self.avg_loss_increase_percent.is_finite()) {
+            return Err(Error::Validation("Average loss increase must be a non-negative percentage".into()));
+        }
+        Ok(())
+    }
+
+    /// The trades with the scenario applied, in their original order. Wins
+    /// and losses are rescaled, then the smallest wins are turned into
+    /// average (stressed) losses until the win rate has dropped by
+    /// `win_rate_reduction` points, rounded to whole trades.
+    pub fn apply(&self, trades: &[Trade]) -> Result<Vec<Trade>> {
+        self.validate()?;
+        let win_scale = 1.0 - self.avg_win_reduction_percent / 100.0;
+        let loss_scale = 1.0 + self.avg_loss_increase_percent / 100.0;
+        let mut stressed: Vec<Trade> = trades
+            .iter()
+            .map(|t| Trade { profit: t.profit * if t.profit > 0.0 { win_scale } else { loss_scale }, ..t.clone() })
+            .collect();
+
+        let mut winners: Vec<usize> = (0..stressed.len()).filter(|&i| stressed[i].profit > 0.0).collect();
+        let losses: Vec<f64> = stressed.iter().map(|t| t.profit).filter(|&p| p < 0.0).collect();
+        // Without losses to copy, a flipped win loses what it would have won
+        let flipped_loss = match losses.len() {
+            0 => None,
+            n => Some(losses.iter().sum::<f64>() / n as f64),
+        };
+        let flips = ((self.win_rate_reduction / 100.0 * trades.len() as f64).round() as usize).min(winners.len());
+        winners.sort_by(|&a, &b| stressed[a].profit.total_cmp(&stressed[b].profit));
+        for &i in &winners[..flips] {
+            stressed[i].profit = flipped_loss.unwrap_or(-stressed[i].profit);
+        }
+        Ok(stressed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct StressTestResult {
+    pub scenario: StressScenario,
+    pub baseline_metrics: PerformanceMetrics,
+    pub stressed_metrics: PerformanceMetrics,
+    pub baseline_kelly_fraction: Option<f64>, // None without both wins and losses
+    pub stressed_kelly_fraction: Option<f64>, // None once the stressed edge is gone
+    pub risk_fraction: f64,
+    pub baseline_pass_rate: Option<f64>, // None without challenge parameters
+    pub stressed_pass_rate: Option<f64>, // Over the same resampled paths as the baseline
+}
+
+/// Compares the Kelly suggestion and, given challenge parameters, the pass
+/// rate at `risk_fraction` between the trades as recorded and under `stress`.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, stress, challenge_params=None, risk_fraction=0.01, num_simulations=1000, seed=None, trades_per_day=None, fractional_multiplier=1.0, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn run_stress_test(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    stress: StressScenario,
+    challenge_params: Option<ChallengeParams>,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    trades_per_day: Option<usize>,
+    fractional_multiplier: f64,
+    return_mode: ReturnMode,
+) -> Result<StressTestResult> {
+    py.allow_threads(|| {
+        stress_test(
+            trades,
+            stress,
+            challenge_params,
+            risk_fraction,
+            num_simulations,
+            seed,
+            trades_per_day,
+            fractional_multiplier,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn stress_test(
+    trades: Vec<Trade>,
+    stress: StressScenario,
+    challenge_params: Option<ChallengeParams>,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    trades_per_day: Option<usize>,
+    fractional_multiplier: f64,
+    return_mode: ReturnMode,
+) -> Result<StressTestResult> {
+    if risk_fraction <= 0.0 {
+        return Err(Error::Validation("Risk fraction must be positive".into()));
+    }
+    let stressed = stress.apply(&trades)?;
+    let metrics = |trades: &[Trade]| {
+        compute_performance_metrics(trades.to_vec(), 0.0, None, ProfitNormalization::Raw, BetaPrior::default())
+    };
+    let baseline_metrics = metrics(&trades)?;
+    let stressed_metrics = metrics(&stressed)?;
+    let kelly = |m: &PerformanceMetrics| {
+        calculate_kelly_criterion(m.win_probability, m.win_loss_ratio, fractional_multiplier).ok()
+    };
+
+    // Both runs replay the same resampled paths
+    let seed = Some(seed.unwrap_or_else(rand::random));
+    let config = SimulationConfig { trades_per_day, ..SimulationConfig::new(risk_fraction) };
+    let (baseline_pass_rate, stressed_pass_rate) = match &challenge_params {
+        Some(params) => {
+            let pass_rate = |trades: &[Trade]| -> Result<f64> {
+                let outcomes = run_simulations(trades, params, &config, num_simulations, seed)?;
+                Ok(MonteCarloResult::from_outcomes(outcomes, params).pass_rate)
+            };
+            // Stressed after the conversion, so both runs share the baseline's risk unit
+            let baseline = return_mode.apply(trades, params.account_size)?;
+            (Some(pass_rate(&baseline)?), Some(pass_rate(&stress.apply(&baseline)?)?))
+        }
+        None => (None, None),
+    };
+
+    Ok(StressTestResult {
+        baseline_kelly_fraction: kelly(&baseline_metrics),
+        stressed_kelly_fraction: kelly(&stressed_metrics),
+        baseline_pass_rate,
+        stressed_pass_rate,
+        scenario: stress,
+        baseline_metrics,
+        stressed_metrics,
+        risk_fraction,
+    })
+}
+
+/// Pass rate over a grid of shock frequencies and sizes, against the same
+/// simulations without shocks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ShockSensitivity {
+    pub risk_fraction: f64,
+    pub baseline_pass_rate: f64,
+    pub probabilities: Vec<f64>,
+    pub loss_multiples: Vec<f64>,
+    pub pass_rates: Vec<Vec<f64>>, // One row per probability, one column per loss multiple
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, risk_fraction, probabilities, loss_multiples, num_simulations=1000, seed=None, trades_per_day=None, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn calculate_shock_sensitivity(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    probabilities: Vec<f64>,
+    loss_multiples: Vec<f64>,
+    num_simulations: usize,
+    seed: Option<u64>,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<ShockSensitivity> {
+    py.allow_threads(|| {
+        shock_sensitivity(
+            trades,
+            challenge_params,
+            risk_fraction,
+            probabilities,
+            loss_multiples,
+            num_simulations,
+            seed,
+            trades_per_day,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn shock_sensitivity(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    probabilities: Vec<f64>,
+    loss_multiples: Vec<f64>,
+    num_simulations: usize,
+    seed: Option<u64>,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<ShockSensitivity> {
+    if probabilities.is_empty() || loss_multiples.is_empty() {
+        return Err(Error::Validation("At least one shock probability and loss multiple are required".into()));
+    }
+
+    let trades = return_mode.apply(trades, challenge_params.account_size)?;
+    // Every grid point shares one seed
+    let seed = Some(seed.unwrap_or_else(rand::random));
+    let pass_rate = |shock: Option<ShockEvent>| -> Result<f64> {
+        let config = SimulationConfig { trades_per_day, shock, ..SimulationConfig::new(risk_fraction) };
+        let outcomes = run_simulations(&trades, &challenge_params, &config, num_simulations, seed)?;
+        Ok(MonteCarloResult::from_outcomes(outcomes, &challenge_params).pass_rate)
+    };
+    let pass_rates = probabilities
+        .iter()
+        .map(|&probability| {
+            loss_multiples
+                .iter()
+                .map(|&multiple| pass_rate(Some(ShockEvent::new(probability, multiple)?)))
+                .collect::<Result<Vec<f64>>>()
+        })
+        .collect::<Result<Vec<Vec<f64>>>>()?;
+
+    Ok(ShockSensitivity {
+        risk_fraction,
+        baseline_pass_rate: pass_rate(None)?,
+        probabilities,
+        loss_multiples,
+        pass_rates,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct TargetHorizonCurve {
+    pub risk_fraction: f64,
+    pub horizons: Vec<u32>, // Trading days allowed to pass every phase
+    pub probabilities: Vec<f64>, // Share of simulations passing within each horizon
+    pub num_simulations: usize,
+}
+
+/// Probability of passing the challenge within each of `horizons` trading
+/// days (5 to 60 in steps of 5 by default), to judge whether a deadline is
+/// realistic. Every phase runs for up to the longest horizon.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, risk_fraction, num_simulations, horizons=None, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn target_probability_by_horizon(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    horizons: Option<Vec<u32>>,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<TargetHorizonCurve> {
+    py.allow_threads(|| {
+        horizon_pass_probabilities(
+            trades,
+            challenge_params,
+            risk_fraction,
+            num_simulations,
+            horizons,
+            seed,
+            resampling,
+            block_length,
+            trades_per_day,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn horizon_pass_probabilities(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    horizons: Option<Vec<u32>>,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<TargetHorizonCurve> {
+    let horizons = horizons.unwrap_or_else(|| (5..=60).step_by(5).collect());
+    if horizons.is_empty() || horizons.contains(&0) {
+        return Err(Error::Validation("Horizons must be a non-empty list of positive day counts".into()));
+    }
+    let config = SimulationConfig {
+        trades_per_day,
+        resampling,
+        block_length,
+        horizon_days: horizons.iter().max().map(|&days| days as usize),
+        ..SimulationConfig::new(risk_fraction)
+    };
+
+    let trades = return_mode.apply(trades, challenge_params.account_size)?;
+    let outcomes = run_simulations(&trades, &challenge_params, &config, num_simulations, seed)?;
+    let probabilities = horizons
+        .iter()
+        .map(|&horizon| {
+            let passed = outcomes.iter().filter(|o| o.days_to_target.is_some_and(|days| days <= horizon)).count();
+            passed as f64 / num_simulations as f64
+        })
+        .collect();
+
+    Ok(TargetHorizonCurve {
+        risk_fraction,
+        horizons,
+        probabilities,
+        num_simulations,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ResetStrategy {
+    pub attempts: u32, // Attempts the budget pays for: the first fee, then resets
+    pub risk_fractions: Vec<f64>,
+    pub pass_rates: Vec<f64>, // Per attempt
+    pub funded_probabilities: Vec<f64>, // Passing within the budget
+    pub expected_costs: Vec<f64>, // Fees spent on average, stopping at the first pass
+    pub best_risk_fraction: f64,
+    pub best_funded_probability: f64,
+}
+
+/// Finds the risk fraction most likely to get funded when `budget` pays for
+/// a first attempt at `challenge_fee` and further attempts at `reset_fee`
+/// each. Between equally likely fractions the cheaper one wins.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, budget, challenge_fee, reset_fee, f_min, f_max, steps, num_simulations, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn optimize_reset_strategy(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    budget: f64,
+    challenge_fee: f64,
+    reset_fee: f64,
+    f_min: f64,
+    f_max: f64,
+    steps: usize,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<ResetStrategy> {
+    py.allow_threads(|| {
+        reset_strategy_search(
+            trades,
+            challenge_params,
+            budget,
+            challenge_fee,
+            reset_fee,
+            f_min,
+            f_max,
+            steps,
+            num_simulations,
+            seed,
+            resampling,
+            block_length,
+            trades_per_day,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn reset_strategy_search(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    budget: f64,
+    challenge_fee: f64,
+    reset_fee: f64,
+    f_min: f64,
+    f_max: f64,
+    steps: usize,
+    num_simulations: usize,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<ResetStrategy> {
+    if challenge_fee < 0.0 || reset_fee <= 0.0 {
+        return Err(Error::Validation("Challenge fee must not be negative and reset fee must be positive".into()));
+    }
+    if budget < challenge_fee {
+        return Err(Error::Validation("Budget does not cover the challenge fee".into()));
+    }
+    let attempts = 1 + ((budget - challenge_fee) / reset_fee).floor() as u32;
+
+    let search = risk_fraction_search(
+        trades,
+        challenge_params,
+        f_min,
+        f_max,
+        steps,
+        num_simulations,
+        seed,
+        resampling,
+        block_length,
+        trades_per_day,
+        return_mode,
+    )?;
+
+    let funded_probabilities: Vec<f64> =
+        search.pass_rates.iter().map(|&p| 1.0 - (1.0 - p).powi(attempts as i32)).collect();
+    // Reset number i is only bought after i failures in a row
+    let expected_costs: Vec<f64> = search
+        .pass_rates
+        .iter()
+        .map(|&p| challenge_fee + reset_fee * (1..attempts).map(|i| (1.0 - p).powi(i as i32)).sum::<f64>())
+        .collect();
+    let best = (0..funded_probabilities.len())
+        .reduce(|best, i| {
+            let better = funded_probabilities[i] > funded_probabilities[best]
+                || (funded_probabilities[i] == funded_probabilities[best] && expected_costs[i] < expected_costs[best]);
+            if better { i } else { best }
+        })
+        .unwrap_or(0);
+
+    Ok(ResetStrategy {
+        attempts,
+        best_risk_fraction: search.risk_fractions[best],
+        best_funded_probability: funded_probabilities[best],
+        risk_fractions: search.risk_fractions,
+        pass_rates: search.pass_rates,
+        funded_probabilities,
+        expected_costs,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct ChallengeValue {
+    pub pass_probability: f64, // Per attempt
+    pub expected_payout_if_passed: f64, // Trader's funded payouts, given a pass
+    pub funded_blowup_rate: f64, // Funded accounts lost within the funded horizon, given a pass
+    pub expected_attempts: f64, // Fees paid on average; infinite if retrying forever without a chance to pass
+    pub expected_retries: f64,
+    pub eventual_pass_probability: f64, // Passing within `max_attempts`
+    pub expected_value: f64, // Expected payouts minus expected fees, in account currency
+}
+
+/// Expected money made by paying `challenge_fee` and attempting the
+/// challenge at `risk_fraction`, retrying after each failure up to
+/// `max_attempts` times (None retries until passing). A pass is followed by
+/// `funded_days` of funded trading paid out every `payout_interval_days`.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, risk_fraction, challenge_fee, profit_split, num_simulations, max_attempts=None, payout_interval_days=TRADING_DAYS_PER_MONTH, funded_days=12 * TRADING_DAYS_PER_MONTH, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn challenge_expected_value(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    challenge_fee: f64,
+    profit_split: f64,
+    num_simulations: usize,
+    max_attempts: Option<u32>,
+    payout_interval_days: u32,
+    funded_days: u32,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<ChallengeValue> {
+    py.allow_threads(|| {
+        expected_challenge_value(
+            trades,
+            challenge_params,
+            risk_fraction,
+            challenge_fee,
+            profit_split,
+            num_simulations,
+            max_attempts,
+            payout_interval_days,
+            funded_days,
+            seed,
+            resampling,
+            block_length,
+            trades_per_day,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn expected_challenge_value(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    challenge_fee: f64,
+    profit_split: f64,
+    num_simulations: usize,
+    max_attempts: Option<u32>,
+    payout_interval_days: u32,
+    funded_days: u32,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<ChallengeValue> {
+    if challenge_fee < 0.0 {
+        return Err(Error::Validation("Challenge fee must not be negative".into()));
+    }
+    if max_attempts == Some(0) {
+        return Err(Error::Validation("Max attempts must be positive".into()));
+    }
+    let config = SimulationConfig {
+        trades_per_day,
+        resampling,
+        block_length,
+        funded: Some(FundedAccount::new(profit_split, payout_interval_days, funded_days)?),
+        ..SimulationConfig::new(risk_fraction)
+    };
+    let trades = return_mode.apply(trades, challenge_params.account_size)?;
+    let outcomes = run_simulations(&trades, &challenge_params, &config, num_simulations, seed)?;
+
+    let passed: Vec<&PathOutcome> = outcomes.iter().filter(|o| o.failure.is_none()).collect();
+    let pass_probability = passed.len() as f64 / outcomes.len() as f64;
+    let (expected_payout_if_passed, funded_blowup_rate) = match passed.len() {
+        0 => (0.0, 0.0),
+        n => (
+            passed.iter().map(|o| o.funded_payout).sum::<f64>() / n as f64,
+            passed.iter().filter(|o| o.funded_blowup_day.is_some()).count() as f64 / n as f64,
+        ),
+    };
+
+    // Attempts are independent, so the number needed is geometric
+    let fail_probability = 1.0 - pass_probability;
+    let (eventual_pass_probability, expected_attempts) = match max_attempts {
+        _ if pass_probability == 0.0 => (0.0, max_attempts.map_or(f64::INFINITY, f64::from)),
+        Some(limit) => {
+            let eventual = 1.0 - fail_probability.powi(limit as i32);
+            (eventual, eventual / pass_probability)
+        }
+        None => (1.0, 1.0 / pass_probability),
+    };
+
+    Ok(ChallengeValue {
+        pass_probability,
+        expected_payout_if_passed,
+        funded_blowup_rate,
+        expected_attempts,
+        expected_retries: expected_attempts - 1.0,
+        eventual_pass_probability,
+        expected_value: eventual_pass_probability * expected_payout_if_passed - challenge_fee * expected_attempts,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct StrategyComparison {
+    pub labels: Vec<String>,
+    pub strategies: Vec<SizingStrategy>,
+    pub results: Vec<MonteCarloResult>, // One result per strategy, over the same resampled paths
+    pub pass_rates: Vec<f64>,
+    pub best_index: usize, // First strategy with the highest pass rate
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl StrategyComparison {
+    /// One summary row per strategy, labelled with a `label` key.
+    fn table(&self, py: Python<'_>) -> Vec<HashMap<String, PyObject>> {
+        self.labels
+            .iter()
+            .zip(&self.results)
+            .map(|(label, result)| {
+                let mut row: HashMap<String, PyObject> =
+                    result.summary().into_iter().map(|(key, value)| (key, value.into_py(py))).collect();
+                row.insert("label".to_string(), label.into_py(py));
+                row
+            })
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        self.py_repr()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        hash_repr(&self.py_repr())
+    }
+
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+        reduce_to_state(slf)
+    }
+
+    #[classmethod]
+    fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+        from_state(state)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+
+    #[classmethod]
+    fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+        from_json(json)
+    }
+}
+
+/// Runs every sizing strategy over identical resampled paths (common random
+/// numbers), so differences between them reflect the strategy rather than
+/// sampling noise.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, challenge_params, strategies, num_simulations, labels=None, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn compare_strategies(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    strategies: Vec<SizingStrategy>,
+    num_simulations: usize,
+    labels: Option<Vec<String>>,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<StrategyComparison> {
+    py.allow_threads(|| {
+        strategy_comparison(
+            trades,
+            challenge_params,
+            strategies,
+            num_simulations,
+            labels,
+            seed,
+            resampling,
+            block_length,
+            trades_per_day,
+            return_mode,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn strategy_comparison(
+    trades: Vec<Trade>,
+    challenge_params: ChallengeParams,
+    strategies: Vec<SizingStrategy>,
+    num_simulations: usize,
+    labels: Option<Vec<String>>,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    return_mode: ReturnMode,
+) -> Result<StrategyComparison> {
+    use rayon::prelude::*;
+
+    if strategies.is_empty() {
+        return Err(Error::Validation("No strategies provided".into()));
+    }
+    let labels = match labels {
+        Some(labels) if labels.len() != strategies.len() => {
+            return Err(Error::Validation("Labels must match the number of strategies".into()));
+        }
+        Some(labels) => labels,
+        None => strategies.iter().map(|s| format!("{:?} {}", s.mode, s.risk_fraction)).collect(),
+    };
+
+    let trades = return_mode.apply(trades, challenge_params.account_size)?;
+    let seed = Some(seed.unwrap_or_else(rand::random));
+
+    let results = strategies
+        .par_iter()
+        .map(|strategy| {
+            let config = SimulationConfig {
+                sizing: strategy.clone(),
+                trades_per_day,
+                resampling,
+                block_length,
+                progress: None,
+                sample_paths: 0,
+                return_model: ReturnModel::Bootstrap,
+                mixture_components: 2,
+                horizon_days: None,
+                funded: None,
+                rules: None,
+                shock: None,
+            };
+            let outcomes = run_simulations(&trades, &challenge_params, &config, num_simulations, seed)?;
+            Ok(MonteCarloResult::from_outcomes(outcomes, &challenge_params))
+        })
+        .collect::<Result<Vec<MonteCarloResult>>>()?;
+
+    let pass_rates: Vec<f64> = results.iter().map(|r| r.pass_rate).collect();
+    let best_index = pass_rates
+        .iter()
+        .enumerate()
+        .fold(0, |best, (i, &rate)| if rate > pass_rates[best] { i } else { best });
+
+    Ok(StrategyComparison {
+        labels,
+        strategies,
+        results,
+        pass_rates,
+        best_index,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct RiskOfRuinResult {
+    pub risk_fraction: f64,
+    pub ruin_threshold_percent: f64,
+    pub analytical_probability: f64, // Diffusion approximation over an unlimited horizon
+    pub simulated_probability: f64, // Bootstrap estimate within `horizon_trades`
+    pub num_simulations: usize,
+    pub horizon_trades: usize,
+}
+
+/// Probability of ever losing `threshold` (a fraction of the starting equity)
+/// when each trade compounds equity by `1 + risk_fraction * profit`. Treats
+/// log equity as Brownian motion with the drift and variance of the trade
+/// log returns, for which hitting a barrier `b` below has probability
+/// `exp(-2 * drift * b / variance)`. Real trades overshoot the barrier, so
+/// the approximation tends to overstate the risk slightly.
+fn analytical_risk_of_ruin(profits: &[f64], risk_fraction: f64, threshold: f64) -> f64 {
+    let growth: Vec<f64> = profits.iter().map(|p| 1.0 + risk_fraction * p).collect();
+    // A trade that wipes out the account is bound to recur eventually
+    if growth.iter().any(|&g| g <= 0.0) {
+        return 1.0;
+    }
+
+    let log_returns: Vec<f64> = growth.iter().map(|g| g.ln()).collect();
+    let drift = log_returns.iter().sum::<f64>() / log_returns.len() as f64;
+    if drift <= 0.0 {
+        return 1.0;
+    }
+    let variance = log_returns.iter().map(|r| (r - drift).powi(2)).sum::<f64>() / log_returns.len() as f64;
+    if variance == 0.0 {
+        return 0.0;
+    }
+
+    let barrier = -(1.0 - threshold).ln();
+    (-2.0 * drift * barrier / variance).exp().min(1.0)
+}
+
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (trades, risk_fraction, ruin_threshold_percent, num_simulations=10000, horizon_trades=1000, seed=None))]
+fn calculate_risk_of_ruin(
+    py: Python<'_>,
+    trades: Vec<Trade>,
+    risk_fraction: f64,
+    ruin_threshold_percent: f64,
+    num_simulations: usize,
+    horizon_trades: usize,
+    seed: Option<u64>,
+) -> Result<RiskOfRuinResult> {
+    py.allow_threads(|| {
+        risk_of_ruin(
+            trades,
+            risk_fraction,
+            ruin_threshold_percent,
+            num_simulations,
+            horizon_trades,
+            seed,
+        )
+    })
+}
+
+pub fn risk_of_ruin(
+    trades: Vec<Trade>,
+    risk_fraction: f64,
+    ruin_threshold_percent: f64,
+    num_simulations: usize,
+    horizon_trades: usize,
+    seed: Option<u64>,
+) -> Result<RiskOfRuinResult> {
+    use rand::Rng;
+    use rayon::prelude::*;
+
+    if trades.is_empty() {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    }
+    if risk_fraction <= 0.0 {
+        return Err(Error::Validation("Risk fraction must be positive".into()));
+    }
+    if ruin_threshold_percent <= 0.0 || ruin_threshold_percent > 100.0 {
+        return Err(Error::Validation("Ruin threshold must be between 0 and 100 percent".into()));
+    }
+    if num_simulations == 0 {
+        return Err(Error::Validation("Number of simulations must be positive".into()));
+    }
+    if horizon_trades == 0 {
+        return Err(Error::Validation("Horizon must be at least one trade".into()));
+    }
+
+    let profits: Vec<f64> = trades.iter().map(|t| t.profit).collect();
+    let threshold = ruin_threshold_percent / 100.0;
+    let ruin_equity = 1.0 - threshold;
+    let base_seed = seed.unwrap_or_else(rand::random);
+
+    let ruined = (0..num_simulations)
+        .into_par_iter()
+        .filter(|&index| {
+            let mut rng = simulation_rng(base_seed, index as u64);
+            let mut equity = 1.0;
+            (0..horizon_trades).any(|_| {
+                equity *= 1.0 + risk_fraction * profits[rng.gen_range(0..profits.len())];
+                equity <= ruin_equity
+            })
+        })
+        .count();
+
+    Ok(RiskOfRuinResult {
+        risk_fraction,
+        ruin_threshold_percent,
+        analytical_probability: analytical_risk_of_ruin(&profits, risk_fraction, threshold),
+        simulated_probability: ruined as f64 / num_simulations as f64,
+        num_simulations,
+        horizon_trades,
+    })
+}
+
+/// Metrics, Kelly and challenge pass rate of one account in a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct AccountAnalysis {
+    pub index: usize, // Position of the account in the batch
+    pub metrics: Option<PerformanceMetrics>,
+    pub kelly: Option<KellyResult>, // None without both wins and losses
+    pub pass_rate: Option<f64>,
+    pub error: Option<String>, // Why the account could not be analyzed; the other fields are then None
+}
+
+/// Analyzes many accounts in parallel in one call. An account that cannot
+/// be analyzed reports its error instead of failing the whole batch.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (accounts, challenge_params, risk_fraction, num_simulations=1000, seed=None, fractional_multiplier=1.0, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn analyze_batch(
+    py: Python<'_>,
+    accounts: Vec<Vec<Trade>>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    fractional_multiplier: f64,
+    return_mode: ReturnMode,
+) -> Vec<AccountAnalysis> {
+    py.allow_threads(|| {
+        batch_analysis(
+            accounts,
+            challenge_params,
+            risk_fraction,
+            num_simulations,
+            seed,
+            fractional_multiplier,
+            return_mode,
+        )
+    })
+}
+
+pub fn batch_analysis(
+    accounts: Vec<Vec<Trade>>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    seed: Option<u64>,
+    fractional_multiplier: f64,
+    return_mode: ReturnMode,
+) -> Vec<AccountAnalysis> {
+    use rayon::prelude::*;
+
+    let config = SimulationConfig::new(risk_fraction);
+    accounts
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, trades)| {
+            let analysis = || -> Result<AccountAnalysis> {
+                let prior = BetaPrior::default();
+                let metrics = compute_performance_metrics(trades.clone(), 0.0, None, ProfitNormalization::Raw, prior)?;
+                let units = return_mode.apply(trades.clone(), challenge_params.account_size)?;
+                let outcomes = run_simulations(&units, &challenge_params, &config, num_simulations, seed)?;
+                Ok(AccountAnalysis {
+                    index,
+                    metrics: Some(metrics),
+                    kelly: calculate_kelly_from_trades(trades.clone(), fractional_multiplier).ok(),
+                    pass_rate: Some(MonteCarloResult::from_outcomes(outcomes, &challenge_params).pass_rate),
+                    error: None,
+                })
+            };
+            analysis().unwrap_or_else(|err| AccountAnalysis {
+                index,
+                metrics: None,
+                kelly: None,
+                pass_rate: None,
+                error: Some(err.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Combined risk of several accounts traded together, e.g. funded accounts
+/// stacked through copy trading. Accounts are aligned on the days from the
+/// first to the last day any of them traded, skipping weekends nothing was
+/// traded on. The breach probabilities replay `horizon_days` historical days
+/// drawn at random, the same day for every account so their correlation is
+/// kept; an account that breaks the daily loss limit stops trading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine", get_all))]
+pub struct PortfolioAnalysis {
+    pub accounts: Vec<String>, // Input order; the row and column order of `correlation`
+    pub account_sizes: Vec<f64>,
+    pub days: usize,
+    pub daily_pnl: Vec<Vec<f64>>, // Per account, aligned on the same days
+    pub correlation: Vec<Vec<f64>>, // Pearson correlation of daily P&L; 0 against a flat account
+    pub combined_equity: Vec<f64>, // End-of-day equity of all accounts together, from their combined size
+    pub combined_max_drawdown: f64,
+    pub combined_max_drawdown_percent: f64,
+    pub daily_loss_breaches: Vec<usize>, // Days on which each account's history broke its daily loss limit
+    pub simultaneous_breach_days: usize, // Days on which two or more accounts broke it together
+    pub simultaneous_breach_probability: f64, // Two or more accounts breaching on the same day within the horizon
+    pub all_breach_probability: f64, // Every account breaching on the same day within the horizon
+    pub independent_breach_probability: f64, // `simultaneous_breach_probability` with uncorrelated accounts
+    pub num_simulations: usize,
+    pub horizon_days: usize,
+}
+
+/// Net P&L and lowest point below the day's starting equity (0 or less) of
+/// one account, by day since the Unix epoch.
+type AccountDays = HashMap<i64, (f64, f64)>;
+
+/// Combined drawdown, correlation and simultaneous daily-loss breaches of
+/// accounts given as trade lists. Each account starts at its entry in
+/// `account_sizes`, or at the challenge account size.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (accounts, challenge_params, account_sizes=None, names=None, num_simulations=10000, horizon_days=20, seed=None))]
+#[allow(clippy::too_many_arguments)]
+fn analyze_portfolio(
+    py: Python<'_>,
+    accounts: Vec<Vec<Trade>>,
+    challenge_params: ChallengeParams,
+    account_sizes: Option<Vec<f64>>,
+    names: Option<Vec<String>>,
+    num_simulations: usize,
+    horizon_days: usize,
+    seed: Option<u64>,
+) -> Result<PortfolioAnalysis> {
+    py.allow_threads(|| {
+        portfolio_analysis(accounts, challenge_params, account_sizes, names, num_simulations, horizon_days, seed)
+    })
+}
+
+/// `analyze_portfolio` for accounts given as `(epoch ms, equity)` samples,
+/// e.g. polled from live terminals. Each account starts at its first sample,
+/// and intraday samples let the daily loss include open positions.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (equity_series, challenge_params, names=None, num_simulations=10000, horizon_days=20, seed=None))]
+fn analyze_portfolio_equity(
+    py: Python<'_>,
+    equity_series: Vec<Vec<(i64, f64)>>,
+    challenge_params: ChallengeParams,
+    names: Option<Vec<String>>,
+    num_simulations: usize,
+    horizon_days: usize,
+    seed: Option<u64>,
+) -> Result<PortfolioAnalysis> {
+    py.allow_threads(|| {
+        portfolio_equity_analysis(equity_series, challenge_params, names, num_simulations, horizon_days, seed)
+    })
+}
+
+pub fn portfolio_analysis(
+    accounts: Vec<Vec<Trade>>,
+    challenge_params: ChallengeParams,
+    account_sizes: Option<Vec<f64>>,
+    names: Option<Vec<String>>,
+    num_simulations: usize,
+    horizon_days: usize,
+    seed: Option<u64>,
+) -> Result<PortfolioAnalysis> {
+    let account_sizes = account_sizes.unwrap_or_else(|| vec![challenge_params.account_size; accounts.len()]);
+    if account_sizes.len() != accounts.len() {
+        return Err(Error::Validation("Provide one account size per account".into()));
+    }
+    let days = accounts
+        .iter()
+        .map(|trades| {
+            let mut days = AccountDays::new();
+            for trade in chronological(trades) {
+                let day = trade_day(trade)
+                    .ok_or_else(|| Error::InsufficientData("Portfolio analysis requires close timestamps".into()))?;
+                let (pnl, low) = days.entry(day).or_insert((0.0, 0.0));
+                *pnl += trade.profit;
+                *low = low.min(*pnl);
+            }
+            Ok(days)
+        })
+        .collect::<Result<Vec<AccountDays>>>()?;
+    analyze_account_days(days, account_sizes, challenge_params, names, num_simulations, horizon_days, seed)
+}
+
+pub fn portfolio_equity_analysis(
+    equity_series: Vec<Vec<(i64, f64)>>,
+    challenge_params: ChallengeParams,
+    names: Option<Vec<String>>,
+    num_simulations: usize,
+    horizon_days: usize,
+    seed: Option<u64>,
+) -> Result<PortfolioAnalysis> {
+    let mut account_sizes = Vec::with_capacity(equity_series.len());
+    let mut days = Vec::with_capacity(equity_series.len());
+    for mut samples in equity_series {
+        samples.sort_by_key(|&(time, _)| time);
+        let Some(&(_, first_equity)) = samples.first() else {
+            return Err(Error::InsufficientData("Every account needs at least one equity sample".into()));
+        };
+        if first_equity <= 0.0 {
+            return Err(Error::Validation("Starting equity must be positive".into()));
+        }
+        let mut account_days = AccountDays::new();
+        let mut day_start_equity = first_equity;
+        for day_samples in samples.chunk_by(|a, b| a.0.div_euclid(MILLIS_PER_DAY) == b.0.div_euclid(MILLIS_PER_DAY)) {
+            let low = day_samples.iter().map(|&(_, equity)| equity).fold(day_start_equity, f64::min);
+            let close = day_samples[day_samples.len() - 1].1;
+            account_days.insert(
+                day_samples[0].0.div_euclid(MILLIS_PER_DAY),
+                (close - day_start_equity, low - day_start_equity),
+            );
+            day_start_equity = close;
+        }
+        account_sizes.push(first_equity);
+        days.push(account_days);
+    }
+    analyze_account_days(days, account_sizes, challenge_params, names, num_simulations, horizon_days, seed)
+}
+
+fn analyze_account_days(
+    accounts: Vec<AccountDays>,
+    account_sizes: Vec<f64>,
+    params: ChallengeParams,
+    names: Option<Vec<String>>,
+    num_simulations: usize,
+    horizon_days: usize,
+    seed: Option<u64>,
+) -> Result<PortfolioAnalysis> {
+    use rayon::prelude::*;
+
+    if accounts.len() < 2 {
+        return Err(Error::InsufficientData("At least two accounts are required".into()));
+    }
+    let names = names.unwrap_or_else(|| (1..=accounts.len()).map(|i| format!("account {}", i)).collect());
+    if names.len() != accounts.len() {
+        return Err(Error::Validation("Provide one name per account".into()));
+    }
+    if account_sizes.iter().any(|&size| size <= 0.0) {
+        return Err(Error::Validation("Account sizes must be positive".into()));
+    }
+    if num_simulations == 0 || horizon_days == 0 {
+        return Err(Error::Validation("Simulations and horizon days must be positive".into()));
+    }
+
+    let traded_days: std::collections::HashSet<i64> = accounts.iter().flat_map(|a| a.keys().copied()).collect();
+    let (Some(&first), Some(&last)) = (traded_days.iter().min(), traded_days.iter().max()) else {
+        return Err(Error::InsufficientData("No trades provided".into()));
+    };
+    let days: Vec<i64> = (first..=last).filter(|day| !is_weekend(*day) || traded_days.contains(day)).collect();
+    if days.len() < 2 {
+        return Err(Error::InsufficientData("At least two trading days are required".into()));
+    }
+    let daily: Vec<Vec<(f64, f64)>> = accounts
+        .iter()
+        .map(|account| days.iter().map(|day| account.get(day).copied().unwrap_or((0.0, 0.0))).collect())
+        .collect();
+    let daily_pnl: Vec<Vec<f64>> = daily.iter().map(|d| d.iter().map(|&(pnl, _)| pnl).collect()).collect();
+
+    // Each account's limits are percentages of its own size
+    let account_params: Vec<ChallengeParams> = account_sizes
+        .iter()
+        .map(|&account_size| ChallengeParams { account_size, ..params.clone() })
+        .collect();
+
+    let mut equity = account_sizes.clone();
+    let mut combined_equity = Vec::with_capacity(days.len());
+    let mut daily_loss_breaches = vec![0; accounts.len()];
+    let mut simultaneous_breach_days = 0;
+    for day in 0..days.len() {
+        let mut breaches = 0;
+        for (((account_days, params), account_equity), account_breaches) in
+            daily.iter().zip(&account_params).zip(equity.iter_mut()).zip(daily_loss_breaches.iter_mut())
+        {
+            let (pnl, low) = account_days[day];
+            if breaks_daily_loss(params, *account_equity, low) {
+                *account_breaches += 1;
+                breaches += 1;
+            }
+            *account_equity += pnl;
+        }
+        if breaches >= 2 {
+            simultaneous_breach_days += 1;
+        }
+        combined_equity.push(equity.iter().sum::<f64>());
+    }
+    let mut peak = account_sizes.iter().sum::<f64>();
+    let (mut combined_max_drawdown, mut combined_max_drawdown_percent) = (0.0_f64, 0.0_f64);
+    for &value in &combined_equity {
+        peak = peak.max(value);
+        combined_max_drawdown = combined_max_drawdown.max(peak - value);
+        combined_max_drawdown_percent = combined_max_drawdown_percent.max((peak - value) / peak * 100.0);
+    }
+
+    let base_seed = seed.unwrap_or_else(rand::random);
+    let (simultaneous, all, independent) = (0..num_simulations)
+        .into_par_iter()
+        .map(|index| {
+            let mut rng = simulation_rng(base_seed, index as u64);
+            let joint = most_simultaneous_breaches(&daily, &account_params, horizon_days, true, &mut rng);
+            let independent = most_simultaneous_breaches(&daily, &account_params, horizon_days, false, &mut rng);
+            ((joint >= 2) as usize, (joint == accounts.len()) as usize, (independent >= 2) as usize)
+        })
+        .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+
+    Ok(PortfolioAnalysis {
+        accounts: names,
+        account_sizes,
+        days: days.len(),
+        correlation: correlation_matrix(&daily_pnl),
+        daily_pnl,
+        combined_equity,
+        combined_max_drawdown,
+        combined_max_drawdown_percent,
+        daily_loss_breaches,
+        simultaneous_breach_days,
+        simultaneous_breach_probability: simultaneous as f64 / num_simulations as f64,
+        all_breach_probability: all as f64 / num_simulations as f64,
+        independent_breach_probability: independent as f64 / num_simulations as f64,
+        num_simulations,
+        horizon_days,
+    })
+}
+
+/// Whether a day starting at `day_start_equity` whose equity dipped `low`
+/// below its start broke the daily loss limit.
+fn breaks_daily_loss(params: &ChallengeParams, day_start_equity: f64, low: f64) -> bool {
+    -low > params.daily_loss_basis(day_start_equity) * params.max_daily_loss_percent / 100.0
+}
+
+/// Replays `horizon_days` randomly drawn days and returns the most accounts
+/// that broke the daily loss limit on the same day. A `joint` draw gives
+/// every account the same day, keeping their correlation; otherwise each
+/// account draws its own.
+fn most_simultaneous_breaches(
+    daily: &[Vec<(f64, f64)>],
+    account_params: &[ChallengeParams],
+    horizon_days: usize,
+    joint: bool,
+    rng: &mut impl rand::Rng,
+) -> usize {
+    let days = daily[0].len();
+    let mut equity: Vec<f64> = account_params.iter().map(|params| params.account_size).collect();
+    let mut active = vec![true; daily.len()];
+    let mut most = 0;
+    for _ in 0..horizon_days {
+        let shared_day = rng.gen_range(0..days);
+        let mut breaches = 0;
+        for (account, account_days) in daily.iter().enumerate() {
+            if !active[account] {
+                continue;
+            }
+            let (pnl, low) = account_days[if joint { shared_day } else { rng.gen_range(0..days) }];
+            if breaks_daily_loss(&account_params[account], equity[account], low) {
+                active[account] = false;
+                breaches += 1;
+            }
+            equity[account] += pnl;
+        }
+        most = most.max(breaches);
+    }
+    most
+}
+
+/// One trade history analysed on demand: metrics, the Kelly variants and
+/// optimal f are computed on first use and then kept, so callers need not
+/// pass intermediate results around or recompute them.
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct RiskAnalysis {
+    trades: Vec<Trade>,
+    fractional_multiplier: f64, // Applied to every Kelly variant
+    initial_balance: Option<f64>, // Lets the metrics include return-based ratios
+    metrics: std::sync::OnceLock<Result<PerformanceMetrics>>,
+    kelly: std::sync::OnceLock<Result<KellyResult>>,
+    empirical_kelly: std::sync::OnceLock<Result<f64>>,
+    bayesian_kelly: std::sync::OnceLock<Result<BayesianKellyResult>>,
+    optimal_f: std::sync::OnceLock<Result<OptimalFResult>>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RiskAnalysis {
+    #[new]
+    #[pyo3(signature = (trades, fractional_multiplier=1.0, initial_balance=None))]
+    fn py_new(trades: Vec<Trade>, fractional_multiplier: f64, initial_balance: Option<f64>) -> Result<Self> {
+        Self::new(trades, fractional_multiplier, initial_balance)
+    }
+
+    #[getter(trades)]
+    fn py_trades(&self) -> Vec<Trade> {
+        self.trades.clone()
+    }
+
+    #[getter(fractional_multiplier)]
+    fn py_fractional_multiplier(&self) -> f64 {
+        self.fractional_multiplier
+    }
+
+    #[getter(initial_balance)]
+    fn py_initial_balance(&self) -> Option<f64> {
+        self.initial_balance
+    }
+
+    #[getter(metrics)]
+    fn py_metrics(&self, py: Python<'_>) -> Result<PerformanceMetrics> {
+        py.allow_threads(|| self.metrics())
+    }
+
+    #[getter(kelly)]
+    fn py_kelly(&self, py: Python<'_>) -> Result<KellyResult> {
+        py.allow_threads(|| self.kelly())
+    }
+
+    #[getter(empirical_kelly)]
+    fn py_empirical_kelly(&self, py: Python<'_>) -> Result<f64> {
+        py.allow_threads(|| self.empirical_kelly())
+    }
+
+    #[getter(bayesian_kelly)]
+    fn py_bayesian_kelly(&self, py: Python<'_>) -> Result<BayesianKellyResult> {
+        py.allow_threads(|| self.bayesian_kelly())
+    }
+
+    #[getter(optimal_f)]
+    fn py_optimal_f(&self, py: Python<'_>) -> Result<OptimalFResult> {
+        py.allow_threads(|| self.optimal_f())
+    }
+
+    /// Runs the challenge at `risk_fraction`, by default the Kelly fraction.
+    #[pyo3(name = "simulate")]
+    #[pyo3(signature = (challenge_params, risk_fraction=None, num_simulations=1000, seed=None))]
+    fn py_simulate(
+        &self,
+        py: Python<'_>,
+        challenge_params: ChallengeParams,
+        risk_fraction: Option<f64>,
+        num_simulations: usize,
+        seed: Option<u64>,
+    ) -> Result<MonteCarloResult> {
+        py.allow_threads(|| self.simulate(challenge_params, risk_fraction, num_simulations, seed))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "RiskAnalysis(trades={}, fractional_multiplier={})",
+            self.trades.len(),
+            self.fractional_multiplier
+        )
+    }
+
+    fn __len__(&self) -> usize {
+        self.trades.len()
+    }
+}
+
+impl RiskAnalysis {
+    pub fn new(trades: Vec<Trade>, fractional_multiplier: f64, initial_balance: Option<f64>) -> Result<Self> {
+        if trades.is_empty() {
+            return Err(Error::InsufficientData("No trades provided".into()));
+        }
+        if fractional_multiplier <= 0.0 {
+            return Err(Error::Validation("Fractional multiplier must be positive".into()));
+        }
+        if initial_balance.is_some_and(|b| b <= 0.0) {
+            return Err(Error::Validation("Initial balance must be positive".into()));
+        }
+        Ok(RiskAnalysis {
+            trades,
+            fractional_multiplier,
+            initial_balance,
+            metrics: Default::default(),
+            kelly: Default::default(),
+            empirical_kelly: Default::default(),
+            bayesian_kelly: Default::default(),
+            optimal_f: Default::default(),
+        })
+    }
+
+    pub fn trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    pub fn metrics(&self) -> Result<PerformanceMetrics> {
+        computed_once(&self.metrics, || {
+            cached_performance_metrics(
+                self.trades.clone(),
+                0.0,
+                self.initial_balance,
+                ProfitNormalization::Raw,
+                BetaPrior::default(),
+            )
+        })
+    }
+
+    pub fn kelly(&self) -> Result<KellyResult> {
+        computed_once(&self.kelly, || calculate_kelly_from_trades(self.trades.clone(), self.fractional_multiplier))
+    }
+
+    pub fn empirical_kelly(&self) -> Result<f64> {
+        computed_once(&self.empirical_kelly, || {
+            calculate_kelly_empirical(self.trades.clone(), self.fractional_multiplier)
+        })
+    }
+
+    /// Bayesian Kelly under the default uniform prior on the win rate.
+    pub fn bayesian_kelly(&self) -> Result<BayesianKellyResult> {
+        computed_once(&self.bayesian_kelly, || {
+            calculate_kelly_bayesian(self.trades.clone(), self.fractional_multiplier, 0.5, 2.0)
+        })
+    }
+
+    pub fn optimal_f(&self) -> Result<OptimalFResult> {
+        computed_once(&self.optimal_f, || optimal_f(self.trades.clone(), 1000, 1e-6, 0.0, 1.0, true))
+    }
+
+    /// Monte Carlo pass rate of the challenge at `risk_fraction`, or at the
+    /// (fractional) Kelly fraction when none is given. Profits are read as
+    /// account currency, so an average loss risks exactly `risk_fraction`.
+    /// Seeded runs are cached like those of `monte_carlo_detailed`.
+    pub fn simulate(
+        &self,
+        challenge_params: ChallengeParams,
+        risk_fraction: Option<f64>,
+        num_simulations: usize,
+        seed: Option<u64>,
+    ) -> Result<MonteCarloResult> {
+        let risk_fraction = match risk_fraction {
+            Some(fraction) => fraction,
+            None => self.kelly()?.kelly_fraction,
+        };
+        if risk_fraction <= 0.0 {
+            return Err(Error::Validation(
+                "The risk fraction must be positive; the trades show no edge to size from".into(),
+            ));
+        }
+        monte_carlo_detailed(
+            self.trades.clone(),
+            challenge_params,
+            risk_fraction,
+            num_simulations,
+            SimulationOptions { seed, ..SimulationOptions::default() },
+            None,
+        )
+    }
+}
+
+/// The value in `cell`, computing it on first use; errors are kept too,
+/// so a failing computation is not retried.
+fn computed_once<T: Clone>(cell: &std::sync::OnceLock<Result<T>>, compute: impl FnOnce() -> Result<T>) -> Result<T> {
+    match cell.get_or_init(compute) {
+        Ok(value) => Ok(value.clone()),
+        Err(error) => Err(error.duplicate()),
+    }
+}
+
+/// Output of `generate_report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub enum OutputFormat {
+    Markdown,
+    Html, // A standalone page
+    Json, // The report's numbers, for other tools
+}
+
+/// Multiples of full Kelly listed in a report's Kelly ladder.
+const REPORT_KELLY_LADDER: [f64; 4] = [0.25, 0.5, 0.75, 1.0];
+
+/// Drawdown whose probability the Kelly ladder reports, in percent.
+const REPORT_DRAWDOWN_THRESHOLD: f64 = 20.0;
+
+#[derive(Serialize)]
+struct RiskReport {
+    trades: usize,
+    metrics: PerformanceMetrics,
+    kelly: Option<KellyResult>, // None without both wins and losses
+    kelly_ladder: Option<KellyTradeoff>, // None without an edge to size
+    simulation: Option<SimulationSummary>, // Only with challenge parameters
+}
+
+#[derive(Serialize)]
+struct SimulationSummary {
+    risk_fraction: f64,
+    #[serde(flatten)]
+    summary: std::collections::BTreeMap<String, f64>, // MonteCarloResult::summary, sorted for stable output
+}
+
+/// A table of a rendered report.
+struct ReportSection {
+    title: &'static str,
+    header: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+/// Renders the analysis as a report with a metrics table, the Kelly
+/// fractions, a ladder of growth and drawdown at fractions of full Kelly
+/// and, given `challenge_params`, a summary of a challenge simulation at
+/// `risk_fraction` (the analysis' Kelly fraction by default).
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (analysis, format=OutputFormat::Markdown, challenge_params=None, risk_fraction=None, num_simulations=1000, seed=None))]
+fn generate_report(
+    py: Python<'_>,
+    analysis: PyRef<'_, RiskAnalysis>,
+    format: OutputFormat,
+    challenge_params: Option<ChallengeParams>,
+    risk_fraction: Option<f64>,
+    num_simulations: usize,
+    seed: Option<u64>,
+) -> Result<String> {
+    let analysis = &*analysis;
+    py.allow_threads(|| risk_report(analysis, format, challenge_params, risk_fraction, num_simulations, seed))
+}
+
+pub fn risk_report(
+    analysis: &RiskAnalysis,
+    format: OutputFormat,
+    challenge_params: Option<ChallengeParams>,
+    risk_fraction: Option<f64>,
+    num_simulations: usize,
+    seed: Option<u64>,
+) -> Result<String> {
+    let kelly_ladder = match kelly_tradeoff(
+        analysis.trades().to_vec(),
+        Some(REPORT_KELLY_LADDER.to_vec()),
+        num_simulations,
+        None,
+        REPORT_DRAWDOWN_THRESHOLD,
+        seed,
+    ) {
+        Ok(ladder) => Some(ladder),
+        Err(Error::InsufficientData(_)) => None,
+        Err(error) => return Err(error),
+    };
+    let simulation = match challenge_params {
+        Some(params) => {
+            let result = analysis.simulate(params, risk_fraction, num_simulations, seed)?;
+            let risk_fraction = match risk_fraction {
+                Some(fraction) => fraction,
+                None => analysis.kelly()?.kelly_fraction,
+            };
+            Some(SimulationSummary { risk_fraction, summary: result.summary().into_iter().collect() })
+        }
+        None => None,
+    };
+    let report = RiskReport {
+        trades: analysis.trades().len(),
+        metrics: analysis.metrics()?,
+        kelly: analysis.kelly().ok(),
+        kelly_ladder,
+        simulation,
+    };
+    Ok(match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(&report).map_err(|err| Error::Simulation(err.to_string()))?
+        }
+        OutputFormat::Markdown => render_markdown(&report_sections(&report)),
+        OutputFormat::Html => render_html(&report_sections(&report)),
+    })
+}
+
+fn report_sections(report: &RiskReport) -> Vec<ReportSection> {
+    let number = |value: f64| format!("{value:.2}");
+    let optional = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{v:.2}"));
+    let percent = |value: f64| format!("{:.2}%", value * 100.0);
+    let pair = |label: &str, value: String| vec![label.to_string(), value];
+
+    let m = &report.metrics;
+    let mut sections = vec![ReportSection {
+        title: "Performance",
+        header: vec!["Metric", "Value"],
+        rows: vec![
+            pair("Trades", report.trades.to_string()),
+            pair("Win rate", percent(m.win_probability)),
+            pair("Average win", number(m.avg_win)),
+            pair("Average loss", number(m.avg_loss)),
+            pair("Win/loss ratio", number(m.win_loss_ratio)),
+            pair("Profit factor", number(m.profit_factor)),
+            pair("Expectancy", number(m.expectancy)),
+            pair("Max drawdown", number(m.max_drawdown)),
+            pair("Sharpe ratio", optional(m.sharpe_ratio)),
+            pair("Sortino ratio", optional(m.sortino_ratio)),
+            pair("SQN", optional(m.sqn)),
+        ],
+    }];
+    if let Some(kelly) = &report.kelly {
+        sections.push(ReportSection {
+            title: "Kelly",
+            header: vec!["Estimate", "Risk per trade"],
+            rows: vec![
+                pair("Kelly fraction", percent(kelly.kelly_fraction)),
+                pair("Continuous Kelly", percent(kelly.continuous_kelly_fraction)),
+                pair("Fractional multiplier", number(kelly.fractional_multiplier)),
+            ],
+        });
+    }
+    if let Some(ladder) = &report.kelly_ladder {
+        sections.push(ReportSection {
+            title: "Kelly ladder",
+            header: vec![
+                "Multiple of Kelly",
+                "Risk per trade",
+                "Growth per trade",
+                "Median max drawdown",
+                "Drawdown of 20% or more",
+            ],
+            rows: ladder
+                .rows
+                .iter()
+                .map(|row| {
+                    vec![
+                        format!("{:.2}x", row.multiplier),
+                        percent(row.fraction),
+                        percent(row.growth_rate),
+                        format!("{:.1}%", row.median_max_drawdown_percent),
+                        percent(row.drawdown_probability),
+                    ]
+                })
+                .collect(),
+        });
+    }
+    if let Some(simulation) = &report.simulation {
+        let value = |key: &str| simulation.summary.get(key).copied().filter(|v| v.is_finite());
+        let count = |key: &str| value(key).map_or("-".to_string(), |v| format!("{v:.0}"));
+        sections.push(ReportSection {
+            title: "Challenge simulation",
+            header: vec!["Statistic", "Value"],
+            rows: vec![
+                pair("Risk per trade", percent(simulation.risk_fraction)),
+                pair("Simulations", count("total_simulations")),
+                pair("Pass rate", value("pass_rate").map_or("-".to_string(), percent)),
+                pair("Daily loss failures", count("daily_loss_failures")),
+                pair("Overall loss failures", count("overall_loss_failures")),
+                pair("Timeouts", count("timeouts")),
+                pair("Median days to target", optional(value("days_to_target_p50"))),
+                pair("Median max drawdown", value("max_drawdown_p50").map_or("-".to_string(), |v| format!("{v:.1}%"))),
+                pair("95% value at risk", optional(value("var_95"))),
+            ],
+        });
+    }
+    sections
+}
+
+fn render_markdown(sections: &[ReportSection]) -> String {
+    let mut out = String::from("# Risk report\n");
+    for section in sections {
+        out.push_str(&format!("\n## {}\n\n", section.title));
+        out.push_str(&format!("| {} |\n", section.header.join(" | ")));
+        out.push_str(&format!("|{}\n", " --- |".repeat(section.header.len())));
+        for row in &section.rows {
+            out.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+    }
+    out
+}
+
+fn render_html(sections: &[ReportSection]) -> String {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let cells = |tag: &str, values: Vec<String>| {
+        values.iter().map(|value| format!("<{tag}>{}</{tag}>", escape(value))).collect::<String>()
+    };
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Risk report</title>\n</head>\n<body>\n\
+         <h1>Risk report</h1>\n",
+    );
+    for section in sections {
+        out.push_str(&format!("<h2>{}</h2>\n<table>\n", escape(section.title)));
+        let header = section.header.iter().map(|h| h.to_string()).collect();
+        out.push_str(&format!("<tr>{}</tr>\n", cells("th", header)));
+        for row in &section.rows {
+            out.push_str(&format!("<tr>{}</tr>\n", cells("td", row.clone())));
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// A column of an exported table. Missing values become empty CSV fields
+/// and Parquet nulls.
+enum ExportColumn {
+    Integer(Vec<Option<i64>>),
+    Timestamp(Vec<Option<i64>>), // Unix epoch milliseconds
+    Float(Vec<Option<f64>>),
+    Boolean(Vec<bool>),
+    Text(Vec<Option<String>>),
+}
+
+impl ExportColumn {
+    fn field(&self, row: usize) -> String {
+        match self {
+            ExportColumn::Integer(values) | ExportColumn::Timestamp(values) => {
+                values[row].map_or(String::new(), |v| v.to_string())
+            }
+            ExportColumn::Float(values) => values[row].map_or(String::new(), |v| v.to_string()),
+            ExportColumn::Boolean(values) => values[row].to_string(),
+            ExportColumn::Text(values) => values[row].clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Rows written straight to a file, so that millions of simulations never
+/// have to cross into Python.
+struct ExportTable {
+    rows: usize,
+    columns: Vec<(&'static str, ExportColumn)>,
+}
+
+impl ExportTable {
+    /// Writes a `.csv` or `.parquet` file, chosen by the extension, and
+    /// returns the number of rows written.
+    fn write(&self, path: &std::path::Path) -> Result<usize> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("csv") => self.write_csv(path)?,
+            Some("parquet") => self.write_parquet(path)?,
+            _ => return Err(Error::Validation("Export files must be .csv or .parquet".into())),
+        }
+        Ok(self.rows)
+    }
+
+    fn write_csv(&self, path: &std::path::Path) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path).map_err(std::io::Error::from)?;
+        writer.write_record(self.columns.iter().map(|(name, _)| name)).map_err(std::io::Error::from)?;
+        for row in 0..self.rows {
+            let fields = self.columns.iter().map(|(_, column)| column.field(row));
+            writer.write_record(fields).map_err(std::io::Error::from)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The table as one Arrow record batch; times become UTC timestamps.
+    #[cfg(feature = "arrow")]
+    fn record_batch(&self) -> Result<arrow_array::RecordBatch> {
+        use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+
+        let arrays = self.columns.iter().map(|(name, column)| {
+            let array: ArrayRef = match column {
+                ExportColumn::Integer(values) => Arc::new(Int64Array::from(values.clone())),
+                ExportColumn::Timestamp(values) => Arc::new(
+                    arrow_array::TimestampMillisecondArray::from(values.clone()).with_timezone("UTC"),
+                ),
+                ExportColumn::Float(values) => Arc::new(Float64Array::from(values.clone())),
+                ExportColumn::Boolean(values) => Arc::new(BooleanArray::from(values.clone())),
+                ExportColumn::Text(values) => Arc::new(StringArray::from(values.clone())),
+            };
+            (*name, array)
+        });
+        Ok(RecordBatch::try_from_iter(arrays).map_err(std::io::Error::other)?)
+    }
+
+    #[cfg(feature = "parquet")]
+    fn write_parquet(&self, path: &std::path::Path) -> Result<()> {
+        use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+        let batch = self.record_batch()?;
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let file = std::fs::File::create(path)?;
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(properties)).map_err(std::io::Error::other)?;
+        writer.write(&batch).map_err(std::io::Error::other)?;
+        writer.close().map_err(std::io::Error::other)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn write_parquet(&self, _path: &std::path::Path) -> Result<()> {
+        Err(Error::Validation("Parquet export needs the `parquet` feature".into()))
+    }
+}
+
+/// Writes one row per simulation of `result` to a `.csv` or `.parquet`
+/// file: whether it passed, final equity, max drawdown, days to target,
+/// phases passed and the failure reason, plus the funded payout and blow-up
+/// day when a funded phase was simulated. Returns the rows written.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn export_simulation_results(
+    py: Python<'_>,
+    result: PyRef<'_, MonteCarloResult>,
+    path: std::path::PathBuf,
+) -> Result<usize> {
+    let result = &*result;
+    py.allow_threads(|| write_simulation_results(result, &path))
+}
+
+pub fn write_simulation_results(result: &MonteCarloResult, path: &std::path::Path) -> Result<usize> {
+    simulation_results_table(result).write(path)
+}
+
+fn simulation_results_table(result: &MonteCarloResult) -> ExportTable {
+    let integers = |values: &[Option<u32>]| values.iter().map(|v| v.map(i64::from)).collect();
+    let mut columns = vec![
+        ("simulation", ExportColumn::Integer((0..result.num_simulations as i64).map(Some).collect())),
+        ("passed", ExportColumn::Boolean(result.failure_reasons.iter().map(Option::is_none).collect())),
+        ("final_equity", ExportColumn::Float(result.final_equity.iter().copied().map(Some).collect())),
+        ("max_drawdown_percent", ExportColumn::Float(result.max_drawdown.iter().copied().map(Some).collect())),
+        ("days_to_target", ExportColumn::Integer(integers(&result.days_to_target))),
+        ("phases_passed", ExportColumn::Integer(result.phases_passed.iter().map(|&p| Some(p as i64)).collect())),
+        ("failure_reason", ExportColumn::Text(result.failure_reasons.clone())),
+    ];
+    if !result.funded_payouts.is_empty() {
+        columns.push(("funded_payout", ExportColumn::Float(result.funded_payouts.iter().copied().map(Some).collect())));
+        columns.push(("funded_blowup_day", ExportColumn::Integer(integers(&result.funded_blowup_days))));
+    }
+    ExportTable { rows: result.num_simulations, columns }
+}
+
+/// Writes one row per window of `rolling` to a `.csv` or `.parquet` file:
+/// the window's last trade and close time, its headline metrics and its
+/// Kelly fraction. Returns the rows written.
+#[cfg(feature = "python")]
+#[pyfunction]
+fn export_rolling_metrics(
+    py: Python<'_>,
+    rolling: PyRef<'_, RollingMetrics>,
+    path: std::path::PathBuf,
+) -> Result<usize> {
+    let rolling = &*rolling;
+    py.allow_threads(|| write_rolling_metrics(rolling, &path))
+}
+
+pub fn write_rolling_metrics(rolling: &RollingMetrics, path: &std::path::Path) -> Result<usize> {
+    rolling_metrics_table(rolling).write(path)
+}
+
+fn rolling_metrics_table(rolling: &RollingMetrics) -> ExportTable {
+    let metric = |value: fn(&PerformanceMetrics) -> Option<f64>| {
+        ExportColumn::Float(rolling.metrics.iter().map(value).collect())
+    };
+    let columns = vec![
+        ("end_index", ExportColumn::Integer(rolling.end_indices.iter().map(|&i| Some(i as i64)).collect())),
+        ("close_time", ExportColumn::Timestamp(rolling.close_times.clone())),
+        ("total_trades", ExportColumn::Integer(rolling.metrics.iter().map(|m| Some(m.total_trades as i64)).collect())),
+        ("win_probability", metric(|m| Some(m.win_probability))),
+        ("avg_win", metric(|m| Some(m.avg_win))),
+        ("avg_loss", metric(|m| Some(m.avg_loss))),
+        ("win_loss_ratio", metric(|m| Some(m.win_loss_ratio))),
+        ("profit_factor", metric(|m| Some(m.profit_factor))),
+        ("expectancy", metric(|m| Some(m.expectancy))),
+        ("max_drawdown", metric(|m| Some(m.max_drawdown))),
+        ("sharpe_ratio", metric(|m| m.sharpe_ratio)),
+        ("sortino_ratio", metric(|m| m.sortino_ratio)),
+        ("sqn", metric(|m| m.sqn)),
+        ("kelly_fraction", ExportColumn::Float(rolling.kelly_fraction.clone())),
+    ];
+    ExportTable { rows: rolling.end_indices.len(), columns }
+}
+
+/// An Arrow table handed to Python without copying: `pyarrow.table(t)`,
+/// `polars.DataFrame(t)` and other consumers of the Arrow PyCapsule
+/// interface read its buffers directly.
+#[cfg(feature = "arrow")]
+#[cfg_attr(feature = "python", pyclass(module = "risk_optima_engine"))]
+pub struct ArrowTable {
+    batch: arrow_array::RecordBatch,
+}
+
+#[cfg(all(feature = "python", feature = "arrow"))]
+#[pymethods]
+impl ArrowTable {
+    #[getter(num_rows)]
+    fn py_num_rows(&self) -> usize {
+        self.batch.num_rows()
+    }
+
+    #[getter(column_names)]
+    fn py_column_names(&self) -> Vec<String> {
+        self.column_names()
+    }
+
+    /// Exports the table as an Arrow C stream; `requested_schema` is not
+    /// supported and ignored, as the protocol allows.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, pyo3::types::PyCapsule>> {
+        use arrow_array::{ffi_stream::FFI_ArrowArrayStream, RecordBatchIterator};
+
+        let _ = requested_schema;
+        let reader = RecordBatchIterator::new([Ok(self.batch.clone())], self.batch.schema());
+        let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+        let name = std::ffi::CString::new("arrow_array_stream").expect("capsule name");
+        // Dropping the capsule releases the stream unless a consumer has moved it out
+        pyo3::types::PyCapsule::new_bound(py, stream, Some(name))
+    }
+
+    fn __len__(&self) -> usize {
+        self.batch.num_rows()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ArrowTable(num_rows={}, columns={:?})", self.batch.num_rows(), self.column_names())
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl ArrowTable {
+    pub fn new(batch: arrow_array::RecordBatch) -> Self {
+        ArrowTable { batch }
+    }
+
+    pub fn record_batch(&self) -> &arrow_array::RecordBatch {
+        &self.batch
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        self.batch.schema().fields().iter().map(|field| field.name().clone()).collect()
+    }
+}
+
+/// Builds trades from any Arrow table or stream exposing
+/// `__arrow_c_stream__` (a pyarrow `Table` or `RecordBatchReader`, a polars
+/// `DataFrame`, ...), with one column per `Trade` field. Columns are read
+/// in Rust without creating a Python object per value; times may be
+/// timestamps or epoch milliseconds.
+#[cfg(all(feature = "python", feature = "arrow"))]
+#[pyfunction]
+#[pyo3(signature = (data, allow_break_even=false))]
+fn trades_from_arrow(data: &Bound<'_, PyAny>, allow_break_even: bool) -> PyResult<Vec<Trade>> {
+    use arrow_array::ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream};
+
+    if !data.hasattr("__arrow_c_stream__")? {
+        return Err(ValidationError::new_err("Expected an Arrow table or stream exposing __arrow_c_stream__"));
+    }
+    let capsule = data.call_method0("__arrow_c_stream__")?;
+    let capsule = capsule.downcast::<pyo3::types::PyCapsule>()?;
+    if capsule.name()?.and_then(|name| name.to_str().ok()) != Some("arrow_array_stream") {
+        return Err(ValidationError::new_err("__arrow_c_stream__ did not return an Arrow stream capsule"));
+    }
+    // SAFETY: a capsule named "arrow_array_stream" holds an ArrowArrayStream;
+    // from_raw moves it out and leaves a released stream for the capsule to drop
+    let reader = unsafe { ArrowArrayStreamReader::from_raw(capsule.pointer() as *mut FFI_ArrowArrayStream) }
+        .map_err(|err| ParseError::new_err(err.to_string()))?;
+    let batches = reader.collect::<Result<Vec<_>, _>>().map_err(|err| ParseError::new_err(err.to_string()))?;
+    Ok(data.py().allow_threads(|| trades_from_record_batches(&batches, allow_break_even))?)
+}
+
+/// The trades as an Arrow table with one column per field and the times
+/// as UTC timestamps, ready for `pyarrow.table(...)` or `polars.DataFrame(...)`.
+#[cfg(all(feature = "python", feature = "arrow"))]
+#[pyfunction]
+fn trades_to_arrow(py: Python<'_>, trades: Vec<Trade>) -> Result<ArrowTable> {
+    py.allow_threads(|| Ok(ArrowTable::new(trades_record_batch(&trades)?)))
+}
+
+/// The per-simulation rows of `export_simulation_results` as an Arrow table.
+#[cfg(all(feature = "python", feature = "arrow"))]
+#[pyfunction]
+fn simulation_results_to_arrow(py: Python<'_>, result: PyRef<'_, MonteCarloResult>) -> Result<ArrowTable> {
+    let result = &*result;
+    py.allow_threads(|| Ok(ArrowTable::new(simulation_results_table(result).record_batch()?)))
+}
+
+/// The per-window rows of `export_rolling_metrics` as an Arrow table.
+#[cfg(all(feature = "python", feature = "arrow"))]
+#[pyfunction]
+fn rolling_metrics_to_arrow(py: Python<'_>, rolling: PyRef<'_, RollingMetrics>) -> Result<ArrowTable> {
+    let rolling = &*rolling;
+    py.allow_threads(|| Ok(ArrowTable::new(rolling_metrics_table(rolling).record_batch()?)))
+}
+
+#[cfg(feature = "arrow")]
+pub fn trades_record_batch(trades: &[Trade]) -> Result<arrow_array::RecordBatch> {
+    let numbers = |value: fn(&Trade) -> Option<f64>| ExportColumn::Float(trades.iter().map(value).collect());
+    let times = |value: fn(&Trade) -> Option<i64>| ExportColumn::Timestamp(trades.iter().map(value).collect());
+    let texts = |value: fn(&Trade) -> Option<String>| ExportColumn::Text(trades.iter().map(value).collect());
+    let columns = vec![
+        ("symbol", texts(|t| Some(t.symbol.clone()))),
+        ("trade_type", texts(|t| Some(t.trade_type.as_str().to_string()))),
+        ("volume", numbers(|t| Some(t.volume))),
+        ("open_price", numbers(|t| Some(t.open_price))),
+        ("close_price", numbers(|t| Some(t.close_price))),
+        ("profit", numbers(|t| Some(t.profit))),
+        ("commission", numbers(|t| t.commission)),
+        ("swap", numbers(|t| t.swap)),
+        ("open_time", times(|t| t.open_time)),
+        ("close_time", times(|t| t.close_time)),
+        ("risk_amount", numbers(|t| t.risk_amount)),
+        ("tag", texts(|t| t.tag.clone())),
+        ("max_favorable_excursion", numbers(|t| t.max_favorable_excursion)),
+        ("max_adverse_excursion", numbers(|t| t.max_adverse_excursion)),
+        ("ticket", ExportColumn::Integer(trades.iter().map(|t| t.ticket.map(|ticket| ticket as i64)).collect())),
+    ];
+    ExportTable { rows: trades.len(), columns }.record_batch()
+}
+
+/// Reads trades from record batches with one column per `Trade` field.
+/// `symbol`, `trade_type`, `volume`, the prices and `profit` are required;
+/// numbers may be of any numeric type and times timestamps of any unit or
+/// epoch milliseconds. `allow_break_even` admits trades closed at their
+/// open price.
+#[cfg(feature = "arrow")]
+pub fn trades_from_record_batches(batches: &[arrow_array::RecordBatch], allow_break_even: bool) -> Result<Vec<Trade>> {
+    use arrow_array::{cast::AsArray, types, Array, ArrayRef};
+    use arrow_schema::{DataType, TimeUnit};
+
+    let cast = |array: &ArrayRef, to: &DataType, name: &str| {
+        arrow_cast::cast(array, to).map_err(|err| Error::Parse(format!("Column {} is not usable: {}", name, err)))
+    };
+    let mut trades = Vec::with_capacity(batches.iter().map(|b| b.num_rows()).sum());
+    for batch in batches {
+        let first_row = trades.len();
+        let column = |name: &str| batch.column_by_name(name);
+        let required = |name: &str| {
+            column(name).ok_or_else(|| Error::Parse(format!("Arrow data is missing the {} column", name)))
+        };
+        let numbers = |array: Option<&ArrayRef>, name: &str| -> Result<Option<ArrayRef>> {
+            array.map(|a| cast(a, &DataType::Float64, name)).transpose()
+        };
+        let texts = |array: Option<&ArrayRef>, name: &str| -> Result<Option<ArrayRef>> {
+            array.map(|a| cast(a, &DataType::Utf8, name)).transpose()
+        };
+        let times = |name: &str| -> Result<Option<ArrayRef>> {
+            column(name)
+                .map(|a| match a.data_type() {
+                    DataType::Timestamp(..) | DataType::Date32 | DataType::Date64 => {
+                        let millis = cast(a, &DataType::Timestamp(TimeUnit::Millisecond, None), name)?;
+                        cast(&millis, &DataType::Int64, name)
+                    }
+                    _ => cast(a, &DataType::Int64, name),
+                })
+                .transpose()
+        };
+
+        let symbol = texts(Some(required("symbol")?), "symbol")?.expect("required column");
+        let trade_type = texts(Some(required("trade_type")?), "trade_type")?.expect("required column");
+        let [volume, open_price, close_price, profit] = ["volume", "open_price", "close_price", "profit"]
+            .map(|name| required(name).and_then(|a| numbers(Some(a), name)).map(|a| a.expect("required column")));
+        let (volume, open_price, close_price, profit) = (volume?, open_price?, close_price?, profit?);
+        let optional = ["commission", "swap", "risk_amount", "max_favorable_excursion", "max_adverse_excursion"];
+        let [commission, swap, risk_amount, favorable, adverse] = optional.map(|name| numbers(column(name), name));
+        let (commission, swap, risk_amount) = (commission?, swap?, risk_amount?);
+        let (favorable, adverse) = (favorable?, adverse?);
+        let (open_time, close_time) = (times("open_time")?, times("close_time")?);
+        let tag = texts(column("tag"), "tag")?;
+        let ticket = column("ticket").map(|a| cast(a, &DataType::UInt64, "ticket")).transpose()?;
+
+        let text = |array: &ArrayRef, row: usize| {
+            let values = array.as_string::<i32>();
+            values.is_valid(row).then(|| values.value(row).to_string())
+        };
+        let number = |array: &ArrayRef, row: usize| {
+            let values = array.as_primitive::<types::Float64Type>();
+            values.is_valid(row).then(|| values.value(row)).filter(|v| !v.is_nan())
+        };
+        let time = |array: &ArrayRef, row: usize| {
+            let values = array.as_primitive::<types::Int64Type>();
+            values.is_valid(row).then(|| values.value(row))
+        };
+        let optional_number = |array: &Option<ArrayRef>, row: usize| array.as_ref().and_then(|a| number(a, row));
+        let optional_time = |array: &Option<ArrayRef>, row: usize| array.as_ref().and_then(|a| time(a, row));
+        for row in 0..batch.num_rows() {
+            let index = first_row + row;
+            let missing = |name: &str| Error::Parse(format!("Row {} is missing {}", index, name));
+            let trade = Trade {
+                symbol: text(&symbol, row).ok_or_else(|| missing("symbol"))?,
+                trade_type: TradeType::parse(&text(&trade_type, row).ok_or_else(|| missing("trade_type"))?)?,
+                volume: number(&volume, row).ok_or_else(|| missing("volume"))?,
+                open_price: number(&open_price, row).ok_or_else(|| missing("open_price"))?,
+                close_price: number(&close_price, row).ok_or_else(|| missing("close_price"))?,
+                profit: number(&profit, row).ok_or_else(|| missing("profit"))?,
+                commission: optional_number(&commission, row),
+                swap: optional_number(&swap, row),
+                open_time: optional_time(&open_time, row),
+                close_time: optional_time(&close_time, row),
+                risk_amount: optional_number(&risk_amount, row),
+                tag: tag.as_ref().and_then(|a| text(a, row)),
+                max_favorable_excursion: optional_number(&favorable, row),
+                max_adverse_excursion: optional_number(&adverse, row),
+                ticket: ticket.as_ref().and_then(|a| {
+                    let values = a.as_primitive::<types::UInt64Type>();
+                    values.is_valid(row).then(|| values.value(row))
+                }),
+            };
+            trade.check(allow_break_even).map_err(|reason| Error::Validation(format!("Row {}: {}", index, reason)))?;
+            trades.push(trade);
+        }
+    }
+    Ok(trades)
 }
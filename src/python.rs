@@ -0,0 +1,740 @@
+use crate::*;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyBytes, PyType};
+use std::collections::HashMap;
+
+// Every error raised by the engine derives from RiskOptimaError, which is a
+// ValueError so that existing `except ValueError` handlers keep working.
+pyo3::create_exception!(risk_optima_core, RiskOptimaError, PyValueError, "Base class of all engine errors.");
+pyo3::create_exception!(
+    risk_optima_core,
+    ParseError,
+    RiskOptimaError,
+    "A report, record or serialized state could not be read."
+);
+pyo3::create_exception!(risk_optima_core, ValidationError, RiskOptimaError, "An argument or setting is out of range.");
+pyo3::create_exception!(
+    risk_optima_core,
+    InsufficientDataError,
+    RiskOptimaError,
+    "The trade history is empty or too small for the requested statistic."
+);
+pyo3::create_exception!(risk_optima_core, SimulationError, RiskOptimaError, "A Monte Carlo run failed.");
+pyo3::create_exception!(risk_optima_core, SimulationCancelled, SimulationError, "A Monte Carlo run was cancelled.");
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Parse(message) => ParseError::new_err(message),
+            Error::Validation(message) => ValidationError::new_err(message),
+            Error::InsufficientData(message) => InsufficientDataError::new_err(message),
+            Error::Simulation(message) => SimulationError::new_err(message),
+            Error::Cancelled(message) => SimulationCancelled::new_err(message),
+            Error::Io(error) => error.into(),
+        }
+    }
+}
+
+// Array entry points: per-trade returns arrive as any buffer of float64
+// (numpy arrays, array.array('d'), memoryview) and are read with one copy
+// instead of extracting a Python `Trade` object per trade.
+
+/// Builds anonymous trades whose profit is each return, closing at the
+/// matching epoch-millisecond timestamp when `timestamps` (int64) is given.
+fn trades_from_returns(
+    py: Python<'_>,
+    returns: &PyBuffer<f64>,
+    timestamps: Option<&PyBuffer<i64>>,
+) -> PyResult<Vec<Trade>> {
+    let returns = returns.to_vec(py)?;
+    let timestamps = match timestamps {
+        Some(buffer) => {
+            let times = buffer.to_vec(py)?;
+            if times.len() != returns.len() {
+                return Err(ValidationError::new_err("Timestamps must have the same length as returns"));
+            }
+            times.into_iter().map(Some).collect()
+        }
+        None => vec![None; returns.len()],
+    };
+
+    Ok(returns
+        .into_iter()
+        .zip(timestamps)
+        .map(|(profit, close_time)| Trade {
+            symbol: String::new(),
+            trade_type: TradeType::Buy,
+            volume: 1.0,
+            open_price: 0.0,
+            close_price: 0.0,
+            profit,
+            commission: None,
+            swap: None,
+            open_time: close_time,
+            close_time,
+            risk_amount: None,
+            tag: None,
+            max_favorable_excursion: None,
+            max_adverse_excursion: None,
+            ticket: None,
+        })
+        .collect())
+}
+
+#[pyfunction]
+#[pyo3(signature = (returns, timestamps=None, risk_free_rate=0.0, initial_balance=None))]
+fn metrics_from_returns(
+    py: Python<'_>,
+    returns: PyBuffer<f64>,
+    timestamps: Option<PyBuffer<i64>>,
+    risk_free_rate: f64,
+    initial_balance: Option<f64>,
+) -> PyResult<PerformanceMetrics> {
+    let trades = trades_from_returns(py, &returns, timestamps.as_ref())?;
+    let metrics = py.allow_threads(|| {
+        let prior = BetaPrior::default();
+        cached_performance_metrics(trades, risk_free_rate, initial_balance, ProfitNormalization::Raw, prior)
+    })?;
+    Ok(metrics)
+}
+
+#[pyfunction]
+#[pyo3(signature = (returns, fractional_multiplier=1.0))]
+fn kelly_from_returns(py: Python<'_>, returns: PyBuffer<f64>, fractional_multiplier: f64) -> PyResult<KellyResult> {
+    let trades = trades_from_returns(py, &returns, None)?;
+    Ok(calculate_kelly_from_trades(trades, fractional_multiplier)?)
+}
+
+#[pyfunction]
+#[pyo3(signature = (returns, challenge_params, risk_fraction, num_simulations, timestamps=None, seed=None, resampling=ResamplingMode::Iid, block_length=5, trades_per_day=None, sizing=None, return_model=ReturnModel::Bootstrap, return_mode=ReturnMode::Currency))]
+#[allow(clippy::too_many_arguments)]
+fn monte_carlo_from_returns(
+    py: Python<'_>,
+    returns: PyBuffer<f64>,
+    challenge_params: ChallengeParams,
+    risk_fraction: f64,
+    num_simulations: usize,
+    timestamps: Option<PyBuffer<i64>>,
+    seed: Option<u64>,
+    resampling: ResamplingMode,
+    block_length: usize,
+    trades_per_day: Option<usize>,
+    sizing: Option<SizingStrategy>,
+    return_model: ReturnModel,
+    return_mode: ReturnMode,
+) -> PyResult<MonteCarloResult> {
+    let trades = trades_from_returns(py, &returns, timestamps.as_ref())?;
+    let options = SimulationOptions {
+        seed,
+        resampling,
+        block_length,
+        trades_per_day,
+        sizing,
+        return_model,
+        return_mode,
+        ..SimulationOptions::default()
+    };
+    let run = MonteCarloRun { trades, challenge_params, risk_fraction, num_simulations, options };
+    run.execute(py, None, 1000, None)
+}
+
+// Python value semantics: repr, equality, hashing, pickling and JSON
+
+/// Formats a field value the way Python's `repr()` would.
+pub(crate) trait PyRepr {
+    fn py_repr(&self) -> String;
+}
+
+impl PyRepr for f64 {
+    fn py_repr(&self) -> String {
+        if self.is_nan() {
+            "nan".to_string()
+        } else {
+            format!("{:?}", self)
+        }
+    }
+}
+
+impl PyRepr for String {
+    fn py_repr(&self) -> String {
+        format!("'{}'", self.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+impl PyRepr for char {
+    fn py_repr(&self) -> String {
+        self.to_string().py_repr()
+    }
+}
+
+impl PyRepr for bool {
+    fn py_repr(&self) -> String {
+        if *self { "True" } else { "False" }.to_string()
+    }
+}
+
+macro_rules! py_repr_integer {
+    ($($int:ty),*) => {
+        $(impl PyRepr for $int {
+            fn py_repr(&self) -> String {
+                self.to_string()
+            }
+        })*
+    };
+}
+
+py_repr_integer!(u32, i64, u64, usize);
+
+impl<T: PyRepr> PyRepr for Option<T> {
+    fn py_repr(&self) -> String {
+        self.as_ref().map_or_else(|| "None".to_string(), PyRepr::py_repr)
+    }
+}
+
+impl<T: PyRepr> PyRepr for Vec<T> {
+    fn py_repr(&self) -> String {
+        format!("[{}]", self.iter().map(PyRepr::py_repr).collect::<Vec<_>>().join(", "))
+    }
+}
+
+impl<A: PyRepr, B: PyRepr> PyRepr for (A, B) {
+    fn py_repr(&self) -> String {
+        format!("({}, {})", self.0.py_repr(), self.1.py_repr())
+    }
+}
+
+impl<A: PyRepr, B: PyRepr, C: PyRepr> PyRepr for (A, B, C) {
+    fn py_repr(&self) -> String {
+        format!("({}, {}, {})", self.0.py_repr(), self.1.py_repr(), self.2.py_repr())
+    }
+}
+
+impl<K: PyRepr + Ord, V: PyRepr> PyRepr for HashMap<K, V> {
+    fn py_repr(&self) -> String {
+        // Sorted so that equal maps have equal reprs (and hashes)
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k.py_repr(), v.py_repr())).collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+}
+
+impl PyRepr for ColumnRef {
+    fn py_repr(&self) -> String {
+        match self {
+            ColumnRef::Index(index) => index.py_repr(),
+            ColumnRef::Name(name) => name.py_repr(),
+        }
+    }
+}
+
+pub(crate) fn from_state<T: serde::de::DeserializeOwned>(state: &[u8]) -> PyResult<T> {
+    bincode::deserialize(state).map_err(|e| ParseError::new_err(format!("Invalid pickled state: {}", e)))
+}
+
+/// `__reduce__` result rebuilding `slf` from a bincode snapshot through its
+/// class's `_from_state`.
+pub(crate) fn reduce_to_state<T>(slf: &Bound<'_, T>) -> PyResult<(PyObject, (PyObject,))>
+where
+    T: pyo3::PyClass + Serialize,
+{
+    let state = bincode::serialize(&*slf.borrow())
+        .map_err(|e| RiskOptimaError::new_err(format!("Cannot pickle {}: {}", std::any::type_name::<T>(), e)))?;
+    let from_state = slf.as_any().get_type().getattr("_from_state")?;
+    let state = PyBytes::new_bound(slf.py(), &state);
+    Ok((from_state.unbind(), (state.into_any().unbind(),)))
+}
+
+/// Serializes a value to JSON. Non-finite floats have no JSON form and
+/// are written as null.
+pub(crate) fn to_json<T: Serialize + ?Sized>(value: &T) -> PyResult<String> {
+    serde_json::to_string(value).map_err(|e| RiskOptimaError::new_err(format!("JSON serialization error: {}", e)))
+}
+
+pub(crate) fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> PyResult<T> {
+    serde_json::from_str(json).map_err(|e| {
+        let name = std::any::type_name::<T>().rsplit("::").next().unwrap_or("value");
+        ParseError::new_err(format!("Invalid {} JSON: {}", name, e))
+    })
+}
+
+pub(crate) fn hash_repr(repr: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    repr.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Python value semantics for a pyclass: `repr()` over `fields`, `==` over
+/// every field, pickling (which `copy` and `multiprocessing` also use)
+/// through a bincode snapshot, and `to_json`/`from_json`; `hashable` adds `__hash__` for classes
+/// without setters. Classes that have their own `#[pymethods]` block list
+/// `manual` and define the same methods there instead.
+macro_rules! py_value_class {
+    ($name:ident { $($field:ident),* $(,)? } $(, $flag:ident)?) => {
+        impl PyRepr for $name {
+            fn py_repr(&self) -> String {
+                let fields: Vec<String> = vec![$(format!("{}={}", stringify!($field), self.$field.py_repr())),*];
+                format!("{}({})", stringify!($name), fields.join(", "))
+            }
+        }
+
+        py_value_class!(@methods $name $($flag)?);
+    };
+    (@methods $name:ident manual) => {};
+    (@methods $name:ident hashable) => {
+        py_value_class!(@block $name
+            fn __hash__(&self) -> u64 {
+                hash_repr(&self.py_repr())
+            }
+        );
+    };
+    (@methods $name:ident) => {
+        py_value_class!(@block $name);
+    };
+    (@block $name:ident $($extra:tt)*) => {
+        #[pymethods]
+        impl $name {
+            fn __repr__(&self) -> String {
+                self.py_repr()
+            }
+
+            fn __eq__(&self, other: &Self) -> bool {
+                self == other
+            }
+
+            $($extra)*
+
+            fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+                reduce_to_state(slf)
+            }
+
+            #[classmethod]
+            fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+                from_state(state)
+            }
+
+            fn to_json(&self) -> PyResult<String> {
+                to_json(self)
+            }
+
+            #[classmethod]
+            fn from_json(_cls: &Bound<'_, PyType>, json: &str) -> PyResult<Self> {
+                from_json(json)
+            }
+        }
+    };
+}
+
+/// Pickling and hashing for fieldless pyclass enums, which already get
+/// `repr()` and `==` from PyO3.
+macro_rules! py_value_enum {
+    ($($name:ident),*) => {
+        $(impl PyRepr for $name {
+            fn py_repr(&self) -> String {
+                format!("{}.{:?}", stringify!($name), self)
+            }
+        }
+
+        #[pymethods]
+        impl $name {
+            fn __hash__(&self) -> u64 {
+                *self as u64
+            }
+
+            fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, (PyObject,))> {
+                reduce_to_state(slf)
+            }
+
+            #[classmethod]
+            fn _from_state(_cls: &Bound<'_, PyType>, state: &[u8]) -> PyResult<Self> {
+                from_state(state)
+            }
+        })*
+    };
+}
+
+impl PyRepr for TradeType {
+    fn py_repr(&self) -> String {
+        format!("TradeType.{:?}", self)
+    }
+}
+
+py_value_enum!(
+    DealEntry,
+    OperationKind,
+    DailyLossBasis,
+    DrawdownMode,
+    PnlPeriod,
+    ProfitNormalization,
+    OutlierMode,
+    ResamplingMode,
+    ReturnModel,
+    ReturnMode,
+    OutputFormat,
+    SizingMode
+);
+
+py_value_class!(Trade {
+    symbol, trade_type, volume, open_price, close_price, profit, commission, swap, open_time, close_time, risk_amount,
+    tag, max_favorable_excursion, max_adverse_excursion, ticket
+}, manual);
+py_value_class!(PerformanceMetrics {
+    total_trades, win_probability, loss_probability, avg_win, avg_loss, win_loss_ratio, profit_factor, expectancy,
+    max_drawdown, sharpe_ratio, sortino_ratio, calmar_ratio, mar_ratio, bayesian_win_probability, sqn,
+    expectancy_t_stat, expectancy_p_value, additional_trades_needed, outlier_adjustment
+}, manual);
+py_value_class!(OutlierAdjustment {
+    mode, percent, trades_adjusted, win_probability, avg_win, avg_loss, win_loss_ratio, profit_factor, expectancy,
+    raw_kelly_fraction, kelly_fraction
+}, hashable);
+py_value_class!(IncrementalMetrics {
+    initial_balance, total_trades, winning_trades, losing_trades, gross_profit, gross_loss, equity, peak, max_drawdown
+}, manual);
+py_value_class!(RiskFractionChange { previous, risk_fraction, total_trades, close_time }, hashable);
+py_value_class!(CsvSchema {
+    symbol, trade_type, volume, open_price, close_price, profit, commission, swap, open_time, close_time,
+    risk_amount, date_format, decimal_separator, delimiter, has_header, tag, max_favorable_excursion,
+    max_adverse_excursion, ticket
+}, manual);
+py_value_class!(ParseWarning { row, field, raw_value, reason, row_skipped }, hashable);
+py_value_class!(ParseResult { trades, warnings, rows_read, rows_skipped, format, encoding, operations }, hashable);
+py_value_class!(AccountOperation { time, kind, amount, comment }, hashable);
+py_value_class!(Deal {
+    time, ticket, position, order, symbol, trade_type, entry, volume, price, commission, swap, profit
+}, hashable);
+py_value_class!(TradeMerge { trades, duplicates_removed, duplicates_by_set, conflicting_tickets }, hashable);
+py_value_class!(ChallengeParams {
+    account_size, profit_target_percent, max_daily_loss_percent, max_overall_loss_percent, min_trading_days,
+    daily_loss_basis, drawdown_mode, lock_trailing_at_breakeven, phases, max_day_profit_percent,
+    min_profitable_days_percent, no_weekend_holding, restricted_windows
+}, manual);
+py_value_class!(ChallengePhase { profit_target_percent, min_trading_days }, manual);
+py_value_class!(ChallengePresets { presets }, manual);
+py_value_class!(EquityCurve { close_times, equity, peak, drawdown, drawdown_percent }, hashable);
+py_value_class!(DrawdownPeriod {
+    start_index, trough_index, recovery_index, depth, depth_percent, duration_trades, recovery_trades,
+    duration_days, recovery_days
+}, hashable);
+py_value_class!(DrawdownAnalysis {
+    drawdowns, num_drawdowns, max_drawdown, average_drawdown, time_underwater_percent
+}, hashable);
+py_value_class!(StreakAnalysis {
+    longest_winning_streak, longest_losing_streak, current_streak, winning_streaks, losing_streaks,
+    loss_probability, total_trades
+}, manual);
+py_value_class!(RMultipleMetrics {
+    trades_with_risk, r_multiples, win_probability, expectancy_r, avg_win_r, avg_loss_r, r_std, kelly_fraction,
+    histogram
+}, hashable);
+py_value_class!(ReturnDistribution {
+    trades, mean, std_dev, skewness, excess_kurtosis, jarque_bera, jarque_bera_p_value, bin_edges, counts
+}, hashable);
+py_value_class!(ExcursionMetrics {
+    trades_with_excursions, avg_mfe, avg_mae, edge_ratio, avg_mae_winners, avg_mfe_losers, suggested_stop,
+    suggested_target, win_probability, win_loss_ratio, expectancy, kelly_fraction, suggested_win_probability,
+    suggested_win_loss_ratio, suggested_expectancy, suggested_kelly_fraction
+}, hashable);
+py_value_class!(SymbolMetrics { symbol, metrics, kelly_fraction }, hashable);
+py_value_class!(DirectionMetrics { long, short, long_kelly_fraction, short_kelly_fraction }, hashable);
+py_value_class!(DurationBucket { label, min_hours, max_hours, metrics, kelly_fraction }, hashable);
+py_value_class!(HoldingTimeMetrics {
+    trades_analyzed, avg_holding_hours, median_holding_hours, avg_winner_holding_hours, avg_loser_holding_hours,
+    buckets
+}, hashable);
+py_value_class!(PeriodPnl { label, start_time, profit, trades, win_rate, max_drawdown }, hashable);
+py_value_class!(TimeBucket { label, index, trades, win_rate, expectancy, profit }, hashable);
+py_value_class!(TimeBucketAnalysis { by_hour, by_weekday, trades_analyzed, utc_offset_hours }, hashable);
+py_value_class!(RollingMetrics { window, step, end_indices, close_times, metrics, kelly_fraction }, hashable);
+py_value_class!(TagMetrics { tag, metrics, kelly_fraction, frequency }, hashable);
+py_value_class!(RegimeKelly { tags, blended_kelly_fraction }, hashable);
+py_value_class!(KellyResult {
+    win_probability, win_loss_ratio, mean_return, return_variance, fractional_multiplier, kelly_fraction,
+    continuous_kelly_fraction
+}, hashable);
+py_value_class!(BayesianKellyResult {
+    win_probability, posterior_win_probability, posterior_alpha, posterior_beta, credible_interval, win_loss_ratio,
+    fractional_multiplier, raw_kelly_fraction, kelly_fraction, conservative_kelly_fraction
+}, hashable);
+py_value_class!(WalkForwardFold {
+    train_start, test_start, test_end, kelly_fraction, in_sample_growth, out_of_sample_growth,
+    in_sample_drawdown_percent, out_of_sample_drawdown_percent
+}, hashable);
+py_value_class!(WalkForwardResult {
+    folds, in_sample_growth, out_of_sample_growth, out_of_sample_twr, out_of_sample_drawdown_percent, efficiency
+}, hashable);
+py_value_class!(KellyDistribution {
+    point_estimate, iterations, valid_samples, mean, median, percentile_5, percentile_95, conservative_kelly
+}, hashable);
+py_value_class!(OptimalFResult { optimal_f, twr, geometric_mean, largest_loss }, hashable);
+py_value_class!(SecureFResult { secure_f, optimal_f, drawdown_percent, historical_drawdown_percent, twr }, hashable);
+py_value_class!(KellyTradeoffRow {
+    multiplier, fraction, growth_rate, relative_growth, median_max_drawdown_percent, drawdown_probability
+}, hashable);
+py_value_class!(KellyTradeoff {
+    full_kelly_fraction, drawdown_threshold_percent, num_simulations, horizon_trades, rows
+}, hashable);
+py_value_class!(KellySizingRow {
+    account_balance, kelly_multiplier, risk_fraction, risk_amount, lots, lot_risk_amount, limited_by
+}, hashable);
+py_value_class!(KellySizingLadder {
+    full_kelly_fraction, account_balances, kelly_multipliers, stop_loss_points, rows
+}, manual);
+py_value_class!(DrawdownConstrainedKelly {
+    risk_fraction, full_kelly_fraction, kelly_multiplier, drawdown_percent, max_drawdown_percent, confidence,
+    num_simulations, horizon_trades
+}, hashable);
+py_value_class!(SymbolCorrelation { symbols, matrix, days, daily_returns, correlated_pairs, threshold }, hashable);
+py_value_class!(LeveragedKelly {
+    leverage, unconstrained_leverage, margin_fraction, total_margin_fraction, max_margin_fraction, constrained
+}, hashable);
+py_value_class!(SymbolSpec {
+    symbol, contract_size, tick_size, tick_value, currency, leverage, margin_rate, min_lot, lot_step, max_lot
+}, manual);
+py_value_class!(SymbolRegistry { specs }, manual);
+py_value_class!(PositionSizer { contract_size, tick_size, tick_value, min_lot, lot_step, max_lot }, manual);
+py_value_class!(PositionSize { lots, raw_lots, risk_amount, risk_fraction, limited_by }, hashable);
+py_value_class!(SizingStrategy {
+    mode, risk_fraction, delta, target_volatility, lookback, recalc_interval, kelly_multiplier, equity_curve_multiplier
+}, manual);
+py_value_class!(FittedReturnModel {
+    model, weights, means, std_devs, degrees_of_freedom, win_after_win, win_after_loss, dependence_p_value,
+    garch_omega, garch_alpha, garch_beta
+}, manual);
+py_value_class!(CostModel {
+    commission_per_lot, swap_per_lot_per_day, slippage_per_lot, slippage_percent, include_trade_costs
+}, manual);
+py_value_class!(ExchangeRates { from_currency, to_currency, rates, times }, manual);
+py_value_class!(StressScenario { win_rate_reduction, avg_win_reduction_percent, avg_loss_increase_percent }, manual);
+py_value_class!(TradingRules {
+    max_losses_per_day, daily_stop_percent, drawdown_threshold_percent, drawdown_risk_multiplier, max_risk_fraction
+}, manual);
+py_value_class!(ShockEvent { probability_per_day, loss_multiple }, manual);
+py_value_class!(FundedAccount { profit_split, payout_interval_days, horizon_days }, manual);
+py_value_class!(ComplianceReport {
+    passed, violations, target_reached, trading_days, profit_percent, worst_daily_loss_percent, max_drawdown_percent,
+    best_day_percent, profitable_days_percent
+}, hashable);
+py_value_class!(NextTradeCheck {
+    allowed, breaches_daily_limit, breaches_overall_limit, proposed_loss, daily_loss_room, overall_loss_room,
+    max_risk_percent
+}, hashable);
+py_value_class!(CopyFollower {
+    name, equity, challenge_params, day_start_equity, peak_equity, symbol_specs
+}, manual);
+py_value_class!(CopyScaling {
+    name, multiplier, proportional_multiplier, risk_percent, max_risk_percent, limited_by, symbol_multipliers
+}, hashable);
+py_value_class!(MonteCarloResult {
+    account_size, num_simulations, passed_simulations, pass_rate, daily_loss_failures, overall_loss_failures, timeouts,
+    consistency_failures, restricted_trading_failures, target_before_min_days, phase_pass_rates
+}, manual);
+py_value_class!(AccountAnalysis { index, metrics, kelly, pass_rate, error }, hashable);
+py_value_class!(PortfolioAnalysis {
+    accounts, account_sizes, days, daily_pnl, correlation, combined_equity, combined_max_drawdown,
+    combined_max_drawdown_percent, daily_loss_breaches, simultaneous_breach_days, simultaneous_breach_probability,
+    all_breach_probability, independent_breach_probability, num_simulations, horizon_days
+}, hashable);
+py_value_class!(ResetStrategy {
+    attempts, risk_fractions, pass_rates, funded_probabilities, expected_costs, best_risk_fraction,
+    best_funded_probability
+}, hashable);
+py_value_class!(ChallengeValue {
+    pass_probability, expected_payout_if_passed, funded_blowup_rate, expected_attempts, expected_retries,
+    eventual_pass_probability, expected_value
+}, hashable);
+py_value_class!(TargetHorizonCurve { risk_fraction, horizons, probabilities, num_simulations }, hashable);
+py_value_class!(RiskFractionSearch { risk_fractions, pass_rates, best_risk_fraction, best_pass_rate }, hashable);
+py_value_class!(ShockSensitivity {
+    risk_fraction, baseline_pass_rate, probabilities, loss_multiples, pass_rates
+}, hashable);
+py_value_class!(MultiStrategyResult { combined, standalone_pass_rates, allocations, days }, hashable);
+py_value_class!(StressTestResult {
+    scenario, baseline_metrics, stressed_metrics, baseline_kelly_fraction, stressed_kelly_fraction, risk_fraction,
+    baseline_pass_rate, stressed_pass_rate
+}, hashable);
+py_value_class!(StrategyComparison { labels, strategies, results, pass_rates, best_index }, manual);
+py_value_class!(RiskOfRuinResult {
+    risk_fraction, ruin_threshold_percent, analytical_probability, simulated_probability, num_simulations,
+    horizon_trades
+}, hashable);
+
+/// A Python module implemented in Rust.
+#[pymodule]
+fn risk_optima_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("RiskOptimaError", m.py().get_type_bound::<RiskOptimaError>())?;
+    m.add("ParseError", m.py().get_type_bound::<ParseError>())?;
+    m.add("ValidationError", m.py().get_type_bound::<ValidationError>())?;
+    m.add("InsufficientDataError", m.py().get_type_bound::<InsufficientDataError>())?;
+    m.add("SimulationError", m.py().get_type_bound::<SimulationError>())?;
+    m.add("SimulationCancelled", m.py().get_type_bound::<SimulationCancelled>())?;
+    m.add_class::<TradeType>()?;
+    m.add_class::<Trade>()?;
+    m.add_class::<PerformanceMetrics>()?;
+    m.add_class::<OutlierAdjustment>()?;
+    m.add_class::<IncrementalMetrics>()?;
+    m.add_class::<LiveRiskMonitor>()?;
+    m.add_class::<RiskFractionChange>()?;
+    m.add_class::<CsvSchema>()?;
+    m.add_class::<ParseWarning>()?;
+    m.add_class::<ParseResult>()?;
+    m.add_class::<Deal>()?;
+    m.add_class::<TradeMerge>()?;
+    m.add_class::<DealEntry>()?;
+    m.add_class::<AccountOperation>()?;
+    m.add_class::<OperationKind>()?;
+    m.add_class::<ChallengeParams>()?;
+    m.add_class::<ChallengePhase>()?;
+    m.add_class::<ChallengePresets>()?;
+    m.add_class::<DailyLossBasis>()?;
+    m.add_class::<DrawdownMode>()?;
+    m.add_class::<EquityCurve>()?;
+    m.add_class::<DrawdownPeriod>()?;
+    m.add_class::<DrawdownAnalysis>()?;
+    m.add_class::<StreakAnalysis>()?;
+    m.add_class::<RMultipleMetrics>()?;
+    m.add_class::<ReturnDistribution>()?;
+    m.add_class::<ExcursionMetrics>()?;
+    m.add_class::<SymbolMetrics>()?;
+    m.add_class::<DirectionMetrics>()?;
+    m.add_class::<DurationBucket>()?;
+    m.add_class::<HoldingTimeMetrics>()?;
+    m.add_class::<RollingMetrics>()?;
+    m.add_class::<PnlPeriod>()?;
+    m.add_class::<PeriodPnl>()?;
+    m.add_class::<TimeBucket>()?;
+    m.add_class::<TimeBucketAnalysis>()?;
+    m.add_class::<TagMetrics>()?;
+    m.add_class::<RegimeKelly>()?;
+    m.add_class::<KellyResult>()?;
+    m.add_class::<KellyDistribution>()?;
+    m.add_class::<BayesianKellyResult>()?;
+    m.add_class::<WalkForwardFold>()?;
+    m.add_class::<WalkForwardResult>()?;
+    m.add_class::<OptimalFResult>()?;
+    m.add_class::<SecureFResult>()?;
+    m.add_class::<KellyTradeoffRow>()?;
+    m.add_class::<KellyTradeoff>()?;
+    m.add_class::<KellySizingRow>()?;
+    m.add_class::<KellySizingLadder>()?;
+    m.add_class::<DrawdownConstrainedKelly>()?;
+    m.add_class::<SymbolCorrelation>()?;
+    m.add_class::<LeveragedKelly>()?;
+    m.add_class::<SymbolSpec>()?;
+    m.add_class::<SymbolRegistry>()?;
+    m.add_class::<PositionSizer>()?;
+    m.add_class::<PositionSize>()?;
+    m.add_class::<MonteCarloResult>()?;
+    m.add_class::<ResamplingMode>()?;
+    m.add_class::<ReturnModel>()?;
+    m.add_class::<FittedReturnModel>()?;
+    m.add_class::<ReturnMode>()?;
+    m.add_class::<SizingMode>()?;
+    m.add_class::<SizingStrategy>()?;
+    m.add_class::<CostModel>()?;
+    m.add_class::<ExchangeRates>()?;
+    m.add_class::<TradingRules>()?;
+    m.add_class::<ShockEvent>()?;
+    m.add_class::<StressScenario>()?;
+    m.add_class::<FundedAccount>()?;
+    m.add_class::<CancellationToken>()?;
+    m.add_class::<SimulationJob>()?;
+    m.add_class::<RiskAnalysis>()?;
+    m.add_class::<OutputFormat>()?;
+    m.add_class::<ProfitNormalization>()?;
+    m.add_class::<OutlierMode>()?;
+    m.add_class::<RiskFractionSearch>()?;
+    m.add_class::<StressTestResult>()?;
+    m.add_class::<MultiStrategyResult>()?;
+    m.add_class::<ShockSensitivity>()?;
+    m.add_class::<TargetHorizonCurve>()?;
+    m.add_class::<ComplianceReport>()?;
+    m.add_class::<NextTradeCheck>()?;
+    m.add_class::<CopyFollower>()?;
+    m.add_class::<CopyScaling>()?;
+    m.add_class::<ChallengeValue>()?;
+    m.add_class::<ResetStrategy>()?;
+    m.add_class::<AccountAnalysis>()?;
+    m.add_class::<PortfolioAnalysis>()?;
+    m.add_class::<StrategyComparison>()?;
+    m.add_class::<RiskOfRuinResult>()?;
+    m.add_function(wrap_pyfunction!(parse_mt5_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_mt5_csv_detailed, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_mt5_xml, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_mt5_html, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_mt5_deals, m)?)?;
+    m.add_function(wrap_pyfunction!(aggregate_deals, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_trade_sets, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_trades, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_ctrader_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_csv_with_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_report, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_report_file, m)?)?;
+    m.add_function(wrap_pyfunction!(trades_from_records, m)?)?;
+    m.add_function(wrap_pyfunction!(trades_to_records, m)?)?;
+    #[cfg(feature = "arrow")]
+    {
+        m.add_class::<ArrowTable>()?;
+        m.add_function(wrap_pyfunction!(trades_from_arrow, m)?)?;
+        m.add_function(wrap_pyfunction!(trades_to_arrow, m)?)?;
+        m.add_function(wrap_pyfunction!(simulation_results_to_arrow, m)?)?;
+        m.add_function(wrap_pyfunction!(rolling_metrics_to_arrow, m)?)?;
+    }
+    m.add_function(wrap_pyfunction!(calculate_performance_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_result_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(set_result_cache_size, m)?)?;
+    m.add_function(wrap_pyfunction!(required_sample_size, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_metrics_by_symbol, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_metrics_by_direction, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_holding_time_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(rolling_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(export_rolling_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(aggregate_pnl, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_time_buckets, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_equity_curve, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_drawdown_analysis, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_streaks, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_r_multiple_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_return_distribution, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_excursion_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_criterion, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_from_trades, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_symbol_correlation, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_portfolio, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_portfolio_with_margin, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_by_tag, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_confidence, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_empirical, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_bayesian, m)?)?;
+    m.add_function(wrap_pyfunction!(walk_forward_kelly, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_optimal_f, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_secure_f, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_kelly_tradeoff, m)?)?;
+    m.add_function(wrap_pyfunction!(kelly_sizing_ladder, m)?)?;
+    m.add_function(wrap_pyfunction!(kelly_with_drawdown_constraint, m)?)?;
+    m.add_function(wrap_pyfunction!(fit_return_model, m)?)?;
+    m.add_function(wrap_pyfunction!(run_monte_carlo_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(run_monte_carlo_detailed, m)?)?;
+    m.add_function(wrap_pyfunction!(export_simulation_results, m)?)?;
+    m.add_function(wrap_pyfunction!(optimize_risk_fraction, m)?)?;
+    m.add_function(wrap_pyfunction!(run_stress_test, m)?)?;
+    m.add_function(wrap_pyfunction!(run_multi_strategy_monte_carlo, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_shock_sensitivity, m)?)?;
+    m.add_function(wrap_pyfunction!(target_probability_by_horizon, m)?)?;
+    m.add_function(wrap_pyfunction!(check_challenge_compliance, m)?)?;
+    m.add_function(wrap_pyfunction!(evaluate_next_trade, m)?)?;
+    m.add_function(wrap_pyfunction!(copy_trade_scaling, m)?)?;
+    m.add_function(wrap_pyfunction!(challenge_expected_value, m)?)?;
+    m.add_function(wrap_pyfunction!(optimize_reset_strategy, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_portfolio, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_portfolio_equity, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_report, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_strategies, m)?)?;
+    m.add_function(wrap_pyfunction!(calculate_risk_of_ruin, m)?)?;
+    m.add_function(wrap_pyfunction!(metrics_from_returns, m)?)?;
+    m.add_function(wrap_pyfunction!(kelly_from_returns, m)?)?;
+    m.add_function(wrap_pyfunction!(monte_carlo_from_returns, m)?)?;
+    Ok(())
+}
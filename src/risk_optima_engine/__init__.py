@@ -1,13 +1,174 @@
 from risk_optima_engine._core import (
     Trade,
+    TradeType,
     PerformanceMetrics,
+    OutlierAdjustment,
+    IncrementalMetrics,
+    LiveRiskMonitor,
+    RiskFractionChange,
+    CsvSchema,
+    ParseWarning,
+    ParseResult,
+    Deal,
+    TradeMerge,
+    DealEntry,
+    AccountOperation,
+    OperationKind,
     ChallengeParams,
+    ChallengePhase,
+    ChallengePresets,
+    DailyLossBasis,
+    DrawdownMode,
+    EquityCurve,
+    DrawdownPeriod,
+    DrawdownAnalysis,
+    StreakAnalysis,
+    RMultipleMetrics,
+    ReturnDistribution,
+    ExcursionMetrics,
+    SymbolMetrics,
+    DirectionMetrics,
+    DurationBucket,
+    HoldingTimeMetrics,
+    RollingMetrics,
+    PnlPeriod,
+    PeriodPnl,
+    TimeBucket,
+    TimeBucketAnalysis,
+    TagMetrics,
+    RegimeKelly,
+    KellyResult,
+    KellyDistribution,
+    BayesianKellyResult,
+    WalkForwardFold,
+    WalkForwardResult,
+    OptimalFResult,
+    SecureFResult,
+    KellyTradeoffRow,
+    KellyTradeoff,
+    KellySizingRow,
+    KellySizingLadder,
+    DrawdownConstrainedKelly,
+    SymbolCorrelation,
+    LeveragedKelly,
+    PositionSizer,
+    SymbolSpec,
+    SymbolRegistry,
+    PositionSize,
+    MonteCarloResult,
+    ResamplingMode,
+    ReturnModel,
+    FittedReturnModel,
+    ReturnMode,
+    SizingMode,
+    SizingStrategy,
+    CostModel,
+    ExchangeRates,
+    TradingRules,
+    ShockEvent,
+    StressScenario,
+    FundedAccount,
+    CancellationToken,
+    SimulationJob,
+    ArrowTable,
+    RiskAnalysis,
+    OutputFormat,
+    SimulationCancelled,
+    SimulationError,
+    InsufficientDataError,
+    ValidationError,
+    ParseError,
+    RiskOptimaError,
+    ProfitNormalization,
+    OutlierMode,
+    RiskFractionSearch,
+    StressTestResult,
+    MultiStrategyResult,
+    ShockSensitivity,
+    TargetHorizonCurve,
+    ComplianceReport,
+    NextTradeCheck,
+    CopyFollower,
+    CopyScaling,
+    ChallengeValue,
+    ResetStrategy,
+    AccountAnalysis,
+    PortfolioAnalysis,
+    StrategyComparison,
+    RiskOfRuinResult,
     parse_mt5_csv,
+    parse_mt5_csv_detailed,
     parse_mt5_xml,
+    parse_mt5_html,
+    parse_mt5_deals,
+    aggregate_deals,
+    merge_trade_sets,
+    filter_trades,
+    parse_ctrader_csv,
+    parse_csv_with_schema,
+    parse_report,
+    parse_report_file,
+    trades_from_records,
+    trades_to_records,
+    trades_from_arrow,
+    trades_to_arrow,
+    simulation_results_to_arrow,
+    rolling_metrics_to_arrow,
     calculate_performance_metrics,
+    clear_result_cache,
+    set_result_cache_size,
+    required_sample_size,
+    calculate_metrics_by_symbol,
+    calculate_metrics_by_direction,
+    calculate_holding_time_metrics,
+    rolling_metrics,
+    export_rolling_metrics,
+    aggregate_pnl,
+    analyze_time_buckets,
+    calculate_equity_curve,
+    calculate_drawdown_analysis,
+    calculate_streaks,
+    calculate_r_multiple_metrics,
+    analyze_return_distribution,
+    calculate_excursion_metrics,
     calculate_kelly_criterion,
+    calculate_kelly_from_trades,
+    calculate_symbol_correlation,
+    calculate_kelly_portfolio,
+    calculate_kelly_portfolio_with_margin,
+    calculate_kelly_by_tag,
+    calculate_kelly_confidence,
+    calculate_kelly_empirical,
+    calculate_kelly_bayesian,
+    walk_forward_kelly,
     calculate_optimal_f,
+    calculate_secure_f,
+    calculate_kelly_tradeoff,
+    kelly_sizing_ladder,
+    kelly_with_drawdown_constraint,
     run_monte_carlo_simulation,
+    run_monte_carlo_detailed,
+    export_simulation_results,
+    fit_return_model,
+    optimize_risk_fraction,
+    run_stress_test,
+    run_multi_strategy_monte_carlo,
+    calculate_shock_sensitivity,
+    target_probability_by_horizon,
+    check_challenge_compliance,
+    evaluate_next_trade,
+    copy_trade_scaling,
+    challenge_expected_value,
+    optimize_reset_strategy,
+    analyze_batch,
+    analyze_portfolio,
+    analyze_portfolio_equity,
+    generate_report,
+    compare_strategies,
+    calculate_risk_of_ruin,
+    metrics_from_returns,
+    kelly_from_returns,
+    monte_carlo_from_returns,
 )
 
 # Import MT5 modules
@@ -17,14 +178,175 @@ from . import mt5_live_data
 __version__ = "1.1.0"
 __all__ = [
     "Trade",
+    "TradeType",
     "PerformanceMetrics",
+    "OutlierAdjustment",
+    "IncrementalMetrics",
+    "LiveRiskMonitor",
+    "RiskFractionChange",
+    "CsvSchema",
+    "ParseWarning",
+    "ParseResult",
+    "Deal",
+    "TradeMerge",
+    "DealEntry",
+    "AccountOperation",
+    "OperationKind",
     "ChallengeParams",
+    "ChallengePhase",
+    "ChallengePresets",
+    "DailyLossBasis",
+    "DrawdownMode",
+    "EquityCurve",
+    "DrawdownPeriod",
+    "DrawdownAnalysis",
+    "StreakAnalysis",
+    "RMultipleMetrics",
+    "ReturnDistribution",
+    "ExcursionMetrics",
+    "SymbolMetrics",
+    "DirectionMetrics",
+    "DurationBucket",
+    "HoldingTimeMetrics",
+    "RollingMetrics",
+    "PnlPeriod",
+    "PeriodPnl",
+    "TimeBucket",
+    "TimeBucketAnalysis",
+    "TagMetrics",
+    "RegimeKelly",
+    "KellyResult",
+    "KellyDistribution",
+    "BayesianKellyResult",
+    "WalkForwardFold",
+    "WalkForwardResult",
+    "OptimalFResult",
+    "SecureFResult",
+    "KellyTradeoffRow",
+    "KellyTradeoff",
+    "KellySizingRow",
+    "KellySizingLadder",
+    "DrawdownConstrainedKelly",
+    "SymbolCorrelation",
+    "LeveragedKelly",
+    "PositionSizer",
+    "SymbolSpec",
+    "SymbolRegistry",
+    "PositionSize",
+    "MonteCarloResult",
+    "ResamplingMode",
+    "ReturnModel",
+    "FittedReturnModel",
+    "ReturnMode",
+    "SizingMode",
+    "SizingStrategy",
+    "CostModel",
+    "ExchangeRates",
+    "TradingRules",
+    "ShockEvent",
+    "StressScenario",
+    "FundedAccount",
+    "CancellationToken",
+    "SimulationJob",
+    "ArrowTable",
+    "RiskAnalysis",
+    "OutputFormat",
+    "SimulationCancelled",
+    "SimulationError",
+    "InsufficientDataError",
+    "ValidationError",
+    "ParseError",
+    "RiskOptimaError",
+    "ProfitNormalization",
+    "OutlierMode",
+    "RiskFractionSearch",
+    "StressTestResult",
+    "MultiStrategyResult",
+    "ShockSensitivity",
+    "TargetHorizonCurve",
+    "ComplianceReport",
+    "NextTradeCheck",
+    "CopyFollower",
+    "CopyScaling",
+    "ChallengeValue",
+    "ResetStrategy",
+    "AccountAnalysis",
+    "PortfolioAnalysis",
+    "StrategyComparison",
+    "RiskOfRuinResult",
     "parse_mt5_csv",
+    "parse_mt5_csv_detailed",
     "parse_mt5_xml",
+    "parse_mt5_html",
+    "parse_mt5_deals",
+    "aggregate_deals",
+    "merge_trade_sets",
+    "filter_trades",
+    "parse_ctrader_csv",
+    "parse_csv_with_schema",
+    "parse_report",
+    "parse_report_file",
+    "trades_from_records",
+    "trades_to_records",
+    "trades_from_arrow",
+    "trades_to_arrow",
+    "simulation_results_to_arrow",
+    "rolling_metrics_to_arrow",
     "calculate_performance_metrics",
+    "clear_result_cache",
+    "set_result_cache_size",
+    "required_sample_size",
+    "calculate_metrics_by_symbol",
+    "calculate_metrics_by_direction",
+    "calculate_holding_time_metrics",
+    "rolling_metrics",
+    "export_rolling_metrics",
+    "aggregate_pnl",
+    "analyze_time_buckets",
+    "calculate_equity_curve",
+    "calculate_drawdown_analysis",
+    "calculate_streaks",
+    "calculate_r_multiple_metrics",
+    "analyze_return_distribution",
+    "calculate_excursion_metrics",
     "calculate_kelly_criterion",
+    "calculate_kelly_from_trades",
+    "calculate_symbol_correlation",
+    "calculate_kelly_portfolio",
+    "calculate_kelly_portfolio_with_margin",
+    "calculate_kelly_by_tag",
+    "calculate_kelly_confidence",
+    "calculate_kelly_empirical",
+    "calculate_kelly_bayesian",
+    "walk_forward_kelly",
     "calculate_optimal_f",
+    "calculate_secure_f",
+    "calculate_kelly_tradeoff",
+    "kelly_sizing_ladder",
+    "kelly_with_drawdown_constraint",
     "run_monte_carlo_simulation",
+    "run_monte_carlo_detailed",
+    "export_simulation_results",
+    "fit_return_model",
+    "optimize_risk_fraction",
+    "run_stress_test",
+    "run_multi_strategy_monte_carlo",
+    "calculate_shock_sensitivity",
+    "target_probability_by_horizon",
+    "check_challenge_compliance",
+    "evaluate_next_trade",
+    "copy_trade_scaling",
+    "challenge_expected_value",
+    "optimize_reset_strategy",
+    "analyze_batch",
+    "analyze_portfolio",
+    "analyze_portfolio_equity",
+    "generate_report",
+    "compare_strategies",
+    "calculate_risk_of_ruin",
+    "metrics_from_returns",
+    "kelly_from_returns",
+    "monte_carlo_from_returns",
     "mt5_integration",
     "mt5_live_data",
 ]
@@ -21,6 +21,7 @@ try:
         ChallengeParams,
         parse_mt5_csv,
         parse_mt5_xml,
+        parse_mt5_html,
         calculate_performance_metrics,
         calculate_kelly_criterion,
         calculate_optimal_f,
@@ -33,6 +34,7 @@ except ImportError:
     ChallengeParams = None
     parse_mt5_csv = None
     parse_mt5_xml = None
+    parse_mt5_html = None
     calculate_performance_metrics = None
     calculate_kelly_criterion = None
     calculate_optimal_f = None
@@ -149,6 +151,9 @@ def parse_trades_from_data(trade_data: List[Dict[str, Any]]) -> List[Trade]:
             profit=data.get("profit", 0.0),
             commission=data.get("commission"),
             swap=data.get("swap"),
+            open_time=data.get("open_time"),
+            close_time=data.get("close_time"),
+            risk_amount=data.get("risk_amount"),
         )
         trades.append(trade)
     return trades
@@ -161,19 +166,25 @@ async def upload_trade_history(
     """Upload and validate MT5 trade history file"""
     try:
         # Validate file type
-        if format not in ["csv", "xml"]:
-            raise HTTPException(status_code=400, detail="Unsupported format. Use 'csv' or 'xml'")
+        if format not in ["csv", "xml", "html"]:
+            raise HTTPException(status_code=400, detail="Unsupported format. Use 'csv', 'xml' or 'html'")
 
         # Read file content
         content = await file.read()
-        content_str = content.decode("utf-8")
 
         # Parse and validate
-        if format == "csv":
+        if format == "html":
+            # MT5 HTML reports are UTF-16, so the parser decodes the raw bytes itself
+            if parse_mt5_html is None:
+                raise HTTPException(status_code=500, detail="Rust extension not available")
+            trades = parse_mt5_html(content)
+        elif format == "csv":
+            content_str = content.decode("utf-8")
             if parse_mt5_csv is None:
                 raise HTTPException(status_code=500, detail="Rust extension not available")
             trades = parse_mt5_csv(content_str)
         else:
+            content_str = content.decode("utf-8")
             if parse_mt5_xml is None:
                 raise HTTPException(status_code=500, detail="Rust extension not available")
             trades = parse_mt5_xml(content_str)
@@ -221,16 +232,21 @@ async def analyze_performance(request: AnalysisRequest):
 
         file_path = uploaded_files[request.file_id]
 
-        # Read and parse file
-        with open(file_path, "r", encoding="utf-8") as f:
-            content = f.read()
-
         # Determine format from file extension
-        if file_path.endswith(".csv"):
+        if file_path.endswith(".html"):
+            if parse_mt5_html is None:
+                raise HTTPException(status_code=500, detail="Rust extension not available")
+            with open(file_path, "rb") as f:
+                trades = parse_mt5_html(f.read())
+        elif file_path.endswith(".csv"):
+            with open(file_path, "r", encoding="utf-8") as f:
+                content = f.read()
             if parse_mt5_csv is None:
                 raise HTTPException(status_code=500, detail="Rust extension not available")
             trades = parse_mt5_csv(content)
         else:
+            with open(file_path, "r", encoding="utf-8") as f:
+                content = f.read()
             if parse_mt5_xml is None:
                 raise HTTPException(status_code=500, detail="Rust extension not available")
             trades = parse_mt5_xml(content)
@@ -305,18 +321,11 @@ async def calculate_optimal_f_endpoint(request: OptimalFRequest):
             raise HTTPException(status_code=500, detail="Rust extension not available")
 
         trades = parse_trades_from_data(request.trade_data)
-        optimal_f = calculate_optimal_f(trades, 1000, 1e-6)
-
-        # Calculate TWR for the optimal f
-        largest_loss = min((t.profit for t in trades if t.profit < 0.0), default=-1.0, key=abs)
-
-        twr = 1.0
-        for trade in trades:
-            twr *= 1.0 + optimal_f * (-trade.profit / abs(largest_loss))
+        result = calculate_optimal_f(trades)
 
         return OptimalFResponse(
-            optimal_f=optimal_f,
-            twr=twr,
+            optimal_f=result.optimal_f,
+            twr=result.twr,
             sensitivity={}  # Placeholder for future implementation
         )
 
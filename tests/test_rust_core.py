@@ -2,17 +2,131 @@
 Tests for the Rust core computational functions
 """
 
+import math
+import random
+
 import pytest
 from risk_optima_engine import (
     Trade,
+    TradeType,
+    PnlPeriod,
     PerformanceMetrics,
+    OutlierAdjustment,
+    IncrementalMetrics,
+    LiveRiskMonitor,
+    RiskFractionChange,
+    NextTradeCheck,
+    CopyFollower,
+    CsvSchema,
+    DealEntry,
+    OperationKind,
     ChallengeParams,
+    ChallengePhase,
+    ChallengePresets,
+    DailyLossBasis,
+    DrawdownMode,
+    ResamplingMode,
+    ReturnModel,
+    FittedReturnModel,
+    ReturnMode,
+    SizingMode,
+    SizingStrategy,
+    CostModel,
+    ExchangeRates,
+    TradingRules,
+    ShockEvent,
+    StressScenario,
+    FundedAccount,
+    CancellationToken,
+    SimulationJob,
+    ArrowTable,
+    RiskAnalysis,
+    OutputFormat,
+    SimulationCancelled,
+    SimulationError,
+    InsufficientDataError,
+    ValidationError,
+    ParseError,
+    RiskOptimaError,
+    MonteCarloResult,
+    ProfitNormalization,
+    OutlierMode,
+    PositionSizer,
+    SymbolSpec,
+    SymbolRegistry,
     parse_mt5_csv,
+    parse_mt5_csv_detailed,
     parse_mt5_xml,
+    parse_mt5_html,
+    parse_mt5_deals,
+    aggregate_deals,
+    merge_trade_sets,
+    filter_trades,
+    parse_ctrader_csv,
+    parse_csv_with_schema,
+    parse_report,
+    parse_report_file,
+    trades_from_records,
+    trades_to_records,
+    trades_from_arrow,
+    trades_to_arrow,
+    simulation_results_to_arrow,
+    rolling_metrics_to_arrow,
     calculate_performance_metrics,
+    clear_result_cache,
+    set_result_cache_size,
+    required_sample_size,
+    calculate_metrics_by_symbol,
+    calculate_metrics_by_direction,
+    calculate_holding_time_metrics,
+    rolling_metrics,
+    export_rolling_metrics,
+    aggregate_pnl,
+    analyze_time_buckets,
+    calculate_equity_curve,
+    calculate_drawdown_analysis,
+    calculate_streaks,
+    calculate_r_multiple_metrics,
+    analyze_return_distribution,
+    calculate_excursion_metrics,
     calculate_kelly_criterion,
+    calculate_kelly_from_trades,
+    calculate_symbol_correlation,
+    calculate_kelly_portfolio,
+    calculate_kelly_portfolio_with_margin,
+    calculate_kelly_by_tag,
+    calculate_kelly_confidence,
+    calculate_kelly_empirical,
+    calculate_kelly_bayesian,
+    walk_forward_kelly,
     calculate_optimal_f,
+    calculate_secure_f,
+    calculate_kelly_tradeoff,
+    kelly_sizing_ladder,
+    kelly_with_drawdown_constraint,
     run_monte_carlo_simulation,
+    run_monte_carlo_detailed,
+    export_simulation_results,
+    fit_return_model,
+    optimize_risk_fraction,
+    run_stress_test,
+    run_multi_strategy_monte_carlo,
+    calculate_shock_sensitivity,
+    target_probability_by_horizon,
+    check_challenge_compliance,
+    evaluate_next_trade,
+    copy_trade_scaling,
+    challenge_expected_value,
+    optimize_reset_strategy,
+    analyze_batch,
+    analyze_portfolio,
+    analyze_portfolio_equity,
+    generate_report,
+    compare_strategies,
+    calculate_risk_of_ruin,
+    metrics_from_returns,
+    kelly_from_returns,
+    monte_carlo_from_returns,
 )
 
 
@@ -40,6 +154,19 @@ class TestDataStructures:
         assert trade.profit == 50.0
         assert trade.commission == -2.0
         assert trade.swap == 0.0
+        assert trade.open_time is None
+        assert trade.close_time is None
+
+    def test_trade_timestamps(self):
+        """Test creating a Trade with open/close timestamps"""
+        trade = Trade(
+            "EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0,
+            open_time=1705314600000,
+            close_time=1705327200000,
+        )
+
+        assert trade.open_time == 1705314600000
+        assert trade.close_time == 1705327200000
 
     def test_performance_metrics_creation(self):
         """Test creating a PerformanceMetrics object"""
@@ -76,6 +203,190 @@ class TestDataStructures:
         assert params.max_daily_loss_percent == 5.0
         assert params.max_overall_loss_percent == 10.0
         assert params.min_trading_days == 30
+        assert params.daily_loss_basis == DailyLossBasis.InitialBalance
+
+        for overrides in (
+            {"account_size": 0.0},
+            {"profit_target_percent": -5.0},
+            {"max_daily_loss_percent": 0.0},
+            {"max_overall_loss_percent": 150.0},
+            {"max_day_profit_percent": float("nan")},
+            {"min_profitable_days_percent": 120.0},
+            {"phases": [ChallengePhase(8.0), ChallengePhase(0.0)]},
+            {"restricted_windows": [(2000, 1000)]},
+        ):
+            fields = dict(account_size=100000.0, profit_target_percent=10.0, max_daily_loss_percent=5.0,
+                          max_overall_loss_percent=10.0, min_trading_days=30)
+            with pytest.raises(ValidationError):
+                ChallengeParams(**{**fields, **overrides})
+
+        # Setters bypass the constructor, so validate() re-checks
+        params.max_overall_loss_percent = -1.0
+        with pytest.raises(ValidationError, match="max_overall_loss_percent"):
+            params.validate()
+
+    def test_challenge_presets(self, tmp_path):
+        """Test loading built-in and user-defined prop-firm presets by name"""
+        params = ChallengeParams.preset("FTMO-100k")
+        assert params.account_size == 100000
+        assert params.max_daily_loss_percent == 5.0
+        assert [phase.profit_target_percent for phase in params.phases] == [10.0, 5.0]
+        assert ChallengeParams.preset("topstep-50k").drawdown_mode == DrawdownMode.TrailingBalance
+
+        tighter = ChallengeParams.preset("ftmo-100k", max_daily_loss_percent=4.0, no_weekend_holding=True)
+        assert tighter.max_daily_loss_percent == 4.0
+        assert tighter.no_weekend_holding
+        assert tighter.max_overall_loss_percent == params.max_overall_loss_percent
+        with pytest.raises(ValidationError, match="Unknown challenge parameter"):
+            ChallengeParams.preset("ftmo-100k", daily_loss=4.0)
+        with pytest.raises(ValidationError, match="No challenge preset named"):
+            ChallengeParams.preset("nonexistent-10k")
+
+        presets = ChallengePresets()
+        assert {"ftmo-10k", "the5ers-60k", "fundednext-6k", "mff-200k"} <= set(presets.names())
+        assert len(ChallengePresets(builtin=False)) == 0
+
+        preset_file = tmp_path / "presets.json"
+        preset_file.write_text(
+            '{"my-ftmo": {"base": "ftmo-100k", "max_daily_loss_percent": 3},'
+            ' "desk": {"account_size": 50000, "profit_target_percent": 6, "max_daily_loss_percent": 2,'
+            ' "max_overall_loss_percent": 4, "min_trading_days": 0, "daily_loss_basis": "DayStartBalance",'
+            ' "drawdown_mode": "TrailingEquity", "lock_trailing_at_breakeven": false, "phases": []}}'
+        )
+        presets.load(str(preset_file))
+        assert presets.get("my-ftmo") == ChallengeParams.preset("ftmo-100k", max_daily_loss_percent=3.0)
+        assert presets.get("desk", account_size=25000).account_size == 25000
+        assert "DESK" in presets
+
+        with pytest.raises(ParseError, match="unknown base preset"):
+            presets.load_json('{"x": {"base": "nope"}}')
+        with pytest.raises(ParseError):
+            presets.load_json('{"x": {"account_size": 1000}}')
+        with pytest.raises(ParseError, match="Account size must be positive"):
+            presets.load_json('{"x": {"base": "ftmo-100k", "account_size": -1}}')
+        with pytest.raises(ValidationError, match="Profit target"):
+            ChallengeParams.preset("ftmo-10k", phases=[ChallengePhase(-1.0)])
+
+    def test_pickle_and_copy(self):
+        """Test that inputs and results survive pickling and deep copies"""
+        import copy
+        import pickle
+
+        trade = Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 500.0, -7.0, None, close_time=1704067200000)
+        params = ChallengeParams(100000, 8.0, 5.0, 10.0, 4, drawdown_mode=DrawdownMode.TrailingEquity,
+                                 phases=[ChallengePhase(8.0), ChallengePhase(5.0, 2)])
+        metrics = calculate_performance_metrics([trade, Trade("GBPUSD", "Sell", 1.0, 1.3, 1.31, -200.0, None, None)])
+
+        for value in [trade, params, metrics, DrawdownMode.TrailingEquity, SizingStrategy(SizingMode.FixedLot, 0.01)]:
+            restored = pickle.loads(pickle.dumps(value))
+            assert restored == value
+            assert type(restored) is type(value)
+            assert copy.deepcopy(value) == value
+
+        # A copy is independent of the original
+        copied = copy.copy(trade)
+        copied.profit = 1.0
+        assert trade.profit == 500.0
+        assert copied != trade
+
+    def test_json_round_trip(self):
+        """Test persisting inputs and results as JSON"""
+        import json
+
+        trade = Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 500.0, -7.0, None, close_time=1704067200000)
+        assert json.loads(trade.to_json())["close_time"] == 1704067200000
+        assert Trade.from_json(trade.to_json()) == trade
+
+        params = ChallengeParams(100000, 8.0, 5.0, 10.0, 4, daily_loss_basis=DailyLossBasis.DayStartBalance,
+                                 phases=[ChallengePhase(8.0), ChallengePhase(5.0, 2)])
+        data = json.loads(params.to_json())
+        assert data["daily_loss_basis"] == "DayStartBalance"
+        assert data["phases"][1] == {"profit_target_percent": 5.0, "min_trading_days": 2}
+        assert ChallengeParams.from_json(params.to_json()) == params
+
+        trades = [trade, Trade("GBPUSD", "Sell", 1.0, 1.3, 1.31, -200.0, None, None)]
+        result = run_monte_carlo_detailed(trades, params, 0.04, 20, seed=1)
+        assert MonteCarloResult.from_json(result.to_json()) == result
+
+        registry = SymbolRegistry([SymbolSpec("XAUUSD", 100, 0.01), SymbolSpec("EURUSD", 100000, 0.00001)])
+        assert SymbolRegistry.from_json(registry.to_json()) == registry
+
+        with pytest.raises(ValueError, match="Invalid Trade JSON"):
+            Trade.from_json('{"symbol": "EURUSD"}')
+
+    def test_repr_and_hash(self):
+        """Test Python-style reprs and hashing of immutable results"""
+        trade = Trade("EURUSD", "Sell", 0.5, 1.1, 1.09, 500.0, None, None)
+        assert repr(trade) == (
+            "Trade(symbol='EURUSD', trade_type=TradeType.Sell, volume=0.5, open_price=1.1, close_price=1.09, "
+            "profit=500.0, commission=None, swap=None, open_time=None, close_time=None, risk_amount=None, tag=None, "
+            "max_favorable_excursion=None, max_adverse_excursion=None, ticket=None)"
+        )
+        assert repr(ChallengePhase(8.0)) == "ChallengePhase(profit_target_percent=8.0, min_trading_days=0)"
+
+        # Inputs with setters compare by value but are not hashable
+        assert trade == Trade("EURUSD", "Sell", 0.5, 1.1, 1.09, 500.0, None, None)
+        assert trade != "EURUSD"
+        with pytest.raises(TypeError):
+            hash(trade)
+
+        first = calculate_streaks([trade] * 3)
+        second = calculate_streaks([trade] * 3)
+        assert first == second
+        assert len({first, second, DrawdownMode.Static, DrawdownMode.Static}) == 2
+
+    def test_exception_hierarchy(self):
+        """Test that errors raise typed exceptions that are still ValueErrors"""
+        for error in (ParseError, ValidationError, InsufficientDataError, SimulationError):
+            assert issubclass(error, RiskOptimaError)
+        assert issubclass(RiskOptimaError, ValueError)
+        assert issubclass(SimulationCancelled, SimulationError)
+
+        with pytest.raises(ParseError):
+            parse_mt5_csv("Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap\nEURUSD,Buy,x,1.1,1.2,5,0,0")
+        with pytest.raises(InsufficientDataError):
+            calculate_performance_metrics([])
+        with pytest.raises(ValidationError):
+            calculate_kelly_criterion(1.5, 2.0, 1.0)
+
+    def test_trade_validation(self):
+        """Test that trades parse their direction and reject impossible values"""
+        trade = Trade("EURUSD", "buy limit", 1.0, 1.1, 1.105, 50.0, None, None)
+        assert trade.trade_type == TradeType.Buy
+        assert trade.trade_type == "Buy" and trade.trade_type != "Sell"
+        assert str(trade.trade_type) == "Buy"
+        assert hash(TradeType.Sell) == hash("Sell")
+        assert Trade("EURUSD", TradeType.Sell, 1.0, 1.1, 1.09, 10.0, None, None).trade_type == "Sell"
+
+        for args in (
+            ("EURUSD", "banana", 1.0, 1.1, 1.105, 50.0),
+            ("EURUSD", "Buy", -1.0, 1.1, 1.105, 50.0),
+            ("EURUSD", "Buy", 1.0, 0.0, 1.105, 50.0),
+            ("EURUSD", "Buy", 1.0, 1.1, 1.1, 0.0),
+            ("EURUSD", "Buy", 1.0, 1.1, 1.105, float("nan")),
+        ):
+            with pytest.raises(ValidationError):
+                Trade(*args, None, None)
+        with pytest.raises(ValidationError):
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 50.0, None, None, open_time=2000, close_time=1000)
+
+        # Break-even trades are opt-in
+        scratch = Trade("EURUSD", "Buy", 1.0, 1.1, 1.1, 0.0, None, None, allow_break_even=True)
+        with pytest.raises(ValidationError, match="allow break-even trades"):
+            scratch.validate()
+        scratch.validate(strict=True, allow_break_even=True)
+        with pytest.raises(ValidationError, match="allow break-even trades"):
+            Trade.from_dict(scratch.to_dict())
+        assert Trade.from_dict(scratch.to_dict(), allow_break_even=True) == scratch
+
+        # Setters bypass the constructor, so validate() re-checks
+        trade.volume = 0.0
+        with pytest.raises(ValidationError):
+            trade.validate()
+        losing_winner = Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, -50.0, None, None)
+        losing_winner.validate()
+        with pytest.raises(ValidationError):
+            losing_winner.validate(strict=True)
 
 
 class TestDataParsing:
@@ -97,6 +408,19 @@ GBPUSD,Sell,0.5,1.3000,1.2950,-25.0,-1.0,-0.5"""
         assert trades[1].trade_type == "Sell"
         assert trades[1].profit == -25.0
 
+    def test_parse_mt5_csv_timestamps(self):
+        """Test parsing open/close times from CSV"""
+        csv_content = """Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap,Open Time,Close Time
+EURUSD,Buy,1.0,1.1000,1.1050,50.0,-2.0,0.0,2024.01.15 10:30:00,2024.01.15 14:00:00
+GBPUSD,Sell,0.5,1.3000,1.2950,-25.0,-1.0,-0.5,,"""
+
+        trades = parse_mt5_csv(csv_content)
+
+        assert trades[0].open_time == 1705314600000
+        assert trades[0].close_time == 1705327200000
+        assert trades[1].open_time is None
+        assert trades[1].close_time is None
+
     def test_parse_mt5_csv_empty(self):
         """Test parsing empty CSV"""
         csv_content = "Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap"
@@ -108,15 +432,654 @@ GBPUSD,Sell,0.5,1.3000,1.2950,-25.0,-1.0,-0.5"""
         csv_content = """Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap
 EURUSD,Buy,invalid,1.1000,1.1050,50.0,-2.0,0.0"""
 
-        # Should handle invalid data gracefully
+        # Unparseable numbers are reported instead of silently becoming zero
+        with pytest.raises(ValueError, match="row 2 has an invalid volume"):
+            parse_mt5_csv(csv_content)
+
+    def test_parsers_validate_trades(self):
+        """Test that parsers apply the Trade rules to every row they read"""
+        header = "Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap,Open Time,Close Time\n"
+        scratch = "EURUSD,Buy,1.0,1.1000,1.1000,0.0,-2.0,0.0,2024.01.15 10:30:00,2024.01.15 14:00:00\n"
+        backwards = "EURUSD,Buy,1.0,1.1000,1.1050,50.0,-2.0,0.0,2024.01.15 14:00:00,2024.01.15 10:30:00\n"
+        negative = "EURUSD,Buy,-1.0,1.1000,1.1050,50.0,-2.0,0.0,,\n"
+
+        # A scratch trade closed at its open price is rejected unless break-even trades are allowed
+        with pytest.raises(ParseError, match="row 2: open and close prices are both 1.1"):
+            parse_mt5_csv(header + scratch)
+        with pytest.raises(ParseError, match="row 2: open and close prices are both 1.1"):
+            parse_csv_with_schema(header + scratch, CsvSchema())
+        assert parse_mt5_csv(header + scratch, allow_break_even=True)[0].profit == 0.0
+        assert parse_csv_with_schema(header + scratch, CsvSchema(), allow_break_even=True)[0].close_price == 1.1
+        assert parse_report(header + scratch, allow_break_even=True).trades[0].profit == 0.0
+
+        result = parse_mt5_csv_detailed(header + scratch + backwards + negative)
+        assert len(result.trades) == 0
+        assert result.rows_skipped == 3
+        assert [(w.row, w.field, w.row_skipped) for w in result.warnings] == [
+            (2, "trade", True),
+            (3, "trade", True),
+            (4, "trade", True),
+        ]
+        assert "open and close prices" in result.warnings[0].reason
+        assert "before open_time" in result.warnings[1].reason
+        assert "volume must be positive" in result.warnings[2].reason
+        assert len(parse_mt5_csv_detailed(header + scratch + backwards, allow_break_even=True).trades) == 1
+        with pytest.raises(ParseError, match="row 3: close_time .* is before open_time"):
+            parse_mt5_csv(header + scratch + backwards, allow_break_even=True)
+        with pytest.raises(ParseError, match="row 2: volume must be positive"):
+            parse_csv_with_schema(header + negative, CsvSchema())
+
+        ctrader = "Symbol,Direction,Closing Quantity,Entry Price,Closing Price,Net USD\nEURUSD,Buy,1.00 Lots,0,1.1,5\n"
+        with pytest.raises(ParseError, match="row 2: prices must be positive"):
+            parse_ctrader_csv(ctrader)
+        with pytest.raises(ParseError, match="row 4: prices must be positive"):
+            parse_mt5_html(self.MT5_HTML_REPORT.replace("<td>1.1000</td>", "<td>-1.1000</td>"))
+
+    def test_parse_mt5_csv_detailed_warnings(self):
+        """Test that broken rows are skipped and reported instead of raising"""
+        csv_content = """Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap,Open Time
+EURUSD,Buy,1.0,1.1000,1.1050,50.0,-2.0,0.0,2024.01.15 10:30:00
+EURUSD,Buy,invalid,1.1000,1.1050,50.0,-2.0,0.0,
+GBPUSD,Sell,0.5,1.3000,1.2950,-25.0,n/a,-0.5,yesterday
+truncated,row"""
+
+        result = parse_mt5_csv_detailed(csv_content)
+
+        assert len(result.trades) == 2
+        assert result.rows_read == 4
+        assert result.rows_skipped == 2
+        assert result.trades[1].commission is None
+
+        volume, commission, open_time, short_row = result.warnings
+        assert (volume.row, volume.field, volume.raw_value) == (3, "volume", "invalid")
+        assert volume.reason == "invalid number"
+        assert volume.row_skipped
+        assert (commission.row, commission.field, commission.raw_value) == (4, "commission", "n/a")
+        assert not commission.row_skipped
+        assert open_time.reason == "invalid timestamp"
+        assert short_row.row == 5
+        assert short_row.field is None
+
+        # The strict parser does not drop a cost it cannot read
+        header = "Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap\n"
+        with pytest.raises(ParseError, match="row 2 has an invalid commission"):
+            parse_mt5_csv(header + "EURUSD,Buy,1.0,1.1000,1.1050,50.0,\"12,3,4\",0.0")
+        with pytest.raises(ParseError, match="row 2 has an invalid swap"):
+            parse_mt5_csv(header + "EURUSD,Buy,1.0,1.1000,1.1050,50.0,-2.0,abc")
+        assert parse_mt5_csv(header + "EURUSD,Buy,1.0,1.1000,1.1050,50.0,,")[0].swap is None
+
+    def test_account_operations(self):
+        """Test that deposits, withdrawals and credits are kept apart from the trades"""
+        csv_content = """Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap,Open Time,Close Time
+,Balance,,,,10000.00,,,,2024.01.15 00:00:00
+EURUSD,Buy,1.0,1.1000,1.1050,50.0,-2.0,0.0,2024.01.15 10:30:00,2024.01.15 11:30:00
+,Credit,,,,500.00
+,Balance,,,,-2000.00,,,,2024.01.20 00:00:00"""
+
+        result = parse_report(csv_content)
+
+        assert len(result.trades) == 1
+        assert result.rows_skipped == 0
+        assert not result.warnings
+        deposit, credit, withdrawal = result.operations
+        assert (deposit.kind, deposit.amount) == (OperationKind.Deposit, 10000.0)
+        assert deposit.time == 1705276800000
+        assert (credit.kind, credit.amount, credit.time) == (OperationKind.Credit, 500.0, None)
+        assert (withdrawal.kind, withdrawal.amount) == (OperationKind.Withdrawal, -2000.0)
+        assert calculate_performance_metrics(result.trades).win_probability == 1.0
+
+        html = """<table>
+<tr><th colspan=13><b>Deals</b></th></tr>
+<tr><td>Time</td><td>Deal</td><td>Symbol</td><td>Type</td><td>Direction</td><td>Volume</td>
+<td>Price</td><td>Order</td><td>Commission</td><td>Swap</td><td>Profit</td><td>Balance</td><td>Comment</td></tr>
+<tr><td>2024.01.15 00:00:00</td><td>1</td><td></td><td>balance</td><td></td><td></td>
+<td></td><td></td><td>0.00</td><td>0.00</td><td>10 000.00</td><td>10 000.00</td><td>Initial deposit</td></tr>
+<tr><td>2024.01.15 10:30:00</td><td>2</td><td>EURUSD</td><td>buy</td><td>in</td><td>1.00</td>
+<td>1.1000</td><td>11</td><td>-1.00</td><td>0.00</td><td>0.00</td><td>9 999.00</td><td></td></tr>
+<tr><td>2024.01.15 14:00:00</td><td>3</td><td>EURUSD</td><td>sell</td><td>out</td><td>1.00</td>
+<td>1.1050</td><td>12</td><td>-1.00</td><td>0.00</td><td>500.00</td><td>10 498.00</td><td></td></tr>
+</table>"""
+
+        result = parse_report(html)
+        assert [t.profit for t in result.trades] == [500.0]
+        assert len(result.operations) == 1
+        assert result.operations[0].comment == "Initial deposit"
+        assert result.operations[0].kind == OperationKind.Deposit
+
+        # An unreadable amount is reported instead of being booked as zero
+        broken = csv_content.replace("10000.00", "n/a")
+        result = parse_mt5_csv_detailed(broken)
+        assert len(result.operations) == 2
+        assert result.rows_skipped == 1
+        (warning,) = result.warnings
+        assert (warning.row, warning.field, warning.raw_value) == (2, "amount", "n/a")
+        assert warning.row_skipped
+        with pytest.raises(ParseError, match="row 2 has an invalid amount"):
+            parse_mt5_csv(broken)
+        with pytest.raises(ParseError, match="invalid amount"):
+            parse_report(html.replace("<td>10 000.00</td><td>10 000.00</td>", "<td>n/a</td><td>10 000.00</td>"))
+        with pytest.raises(ParseError, match="row 2 has an invalid amount"):
+            parse_ctrader_csv("Symbol,Direction,Closing Quantity,Entry Price,Closing Price,Net USD\n,Deposit,,,,n/a")
+
+    def test_parse_locale_formatted_numbers(self):
+        """Test numbers with locale-specific grouping and decimal separators"""
+        csv_content = """Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap
+EURUSD,Buy,"1,00","1,10000","1,10500","1 234,56","-2,00",0
+EURUSD,Buy,1.0,1.1,1.105,"1.234,56",,
+EURUSD,Buy,1.0,1.1,1.105,"1,234.56",,
+EURUSD,Buy,1.0,1.1,1.105,"-12 345.5",,"""
+
         trades = parse_mt5_csv(csv_content)
+
+        assert [t.profit for t in trades] == [1234.56, 1234.56, 1234.56, -12345.5]
+        assert trades[0].volume == 1.0
+        assert trades[0].close_price == 1.105
+        assert trades[0].commission == -2.0
+        assert trades[1].commission is None
+
+    def test_parse_mt5_xml_positions(self):
+        """Test parsing the Positions table of an MT5 SpreadsheetML report"""
+        xml_content = """<?xml version="1.0"?>
+<Workbook xmlns="urn:schemas-microsoft-com:office:spreadsheet"
+ xmlns:ss="urn:schemas-microsoft-com:office:spreadsheet">
+ <Worksheet ss:Name="Sheet1">
+  <Table>
+   <Row><Cell><Data ss:Type="String">Trade History Report</Data></Cell></Row>
+   <Row><Cell><Data ss:Type="String">Positions</Data></Cell></Row>
+   <Row>
+    <Cell><Data ss:Type="String">Time</Data></Cell>
+    <Cell><Data ss:Type="String">Position</Data></Cell>
+    <Cell><Data ss:Type="String">Symbol</Data></Cell>
+    <Cell><Data ss:Type="String">Type</Data></Cell>
+    <Cell><Data ss:Type="String">Volume</Data></Cell>
+    <Cell><Data ss:Type="String">Price</Data></Cell>
+    <Cell><Data ss:Type="String">S / L</Data></Cell>
+    <Cell><Data ss:Type="String">T / P</Data></Cell>
+    <Cell><Data ss:Type="String">Time</Data></Cell>
+    <Cell><Data ss:Type="String">Price</Data></Cell>
+    <Cell><Data ss:Type="String">Commission</Data></Cell>
+    <Cell><Data ss:Type="String">Swap</Data></Cell>
+    <Cell><Data ss:Type="String">Profit</Data></Cell>
+   </Row>
+   <Row>
+    <Cell><Data ss:Type="String">2024.01.15 10:30:00</Data></Cell>
+    <Cell><Data ss:Type="Number">1001</Data></Cell>
+    <Cell><Data ss:Type="String">EURUSD</Data></Cell>
+    <Cell><Data ss:Type="String">buy</Data></Cell>
+    <Cell><Data ss:Type="String">1 / 1</Data></Cell>
+    <Cell><Data ss:Type="Number">1.1000</Data></Cell>
+    <Cell ss:Index="9"><Data ss:Type="String">2024.01.15 14:00:00</Data></Cell>
+    <Cell><Data ss:Type="Number">1.1050</Data></Cell>
+    <Cell><Data ss:Type="Number">-2</Data></Cell>
+    <Cell><Data ss:Type="Number">0</Data></Cell>
+    <Cell><Data ss:Type="Number">500</Data></Cell>
+   </Row>
+   <Row>
+    <Cell><Data ss:Type="String">2024.01.16 09:00:00</Data></Cell>
+    <Cell><Data ss:Type="Number">1002</Data></Cell>
+    <Cell><Data ss:Type="String">GBPUSD</Data></Cell>
+    <Cell><Data ss:Type="String">sell</Data></Cell>
+    <Cell><Data ss:Type="String">0.5 / 0.5</Data></Cell>
+    <Cell><Data ss:Type="Number">1.3000</Data></Cell>
+    <Cell ss:Index="9"><Data ss:Type="String">2024.01.16 11:00:00</Data></Cell>
+    <Cell><Data ss:Type="Number">1.3050</Data></Cell>
+    <Cell><Data ss:Type="Number">-1</Data></Cell>
+    <Cell><Data ss:Type="Number">-0.5</Data></Cell>
+    <Cell><Data ss:Type="Number">-250</Data></Cell>
+   </Row>
+   <Row><Cell><Data ss:Type="String">Orders</Data></Cell></Row>
+  </Table>
+ </Worksheet>
+</Workbook>"""
+
+        trades = parse_mt5_xml(xml_content)
+
+        assert len(trades) == 2
+        assert trades[0].symbol == "EURUSD"
+        assert trades[0].trade_type == "Buy"
+        assert trades[0].volume == 1.0
+        assert trades[0].open_price == 1.1000
+        assert trades[0].close_price == 1.1050
+        assert trades[0].profit == 500.0
+        assert trades[0].commission == -2.0
+        assert trades[1].trade_type == "Sell"
+        assert trades[1].volume == 0.5
+        assert trades[1].swap == -0.5
+        assert trades[1].profit == -250.0
+        assert trades[0].open_time == 1705314600000
+        assert trades[0].close_time == 1705327200000
+
+    def test_parse_mt5_xml_malformed(self):
+        """Test that malformed or unrelated XML documents are rejected"""
+        with pytest.raises(ValueError):
+            parse_mt5_xml("<Workbook><Row><Cell>Positions</Cell></Workbook>")
+
+        with pytest.raises(ValueError):
+            parse_mt5_xml("<Workbook><Table></Table></Workbook>")
+
+    def test_parse_ctrader_csv(self):
+        """Test parsing a cTrader history export"""
+        csv_content = """Position ID,Symbol,Opening Direction,Opening Time,Closing Time,Entry Price,Closing Price,Closing Quantity,Commission,Swap,Pips,Net USD
+1001,EURUSD,Buy,15/01/2024 10:30:00.000,15/01/2024 14:00:00.000,1.10000,1.10500,1.00 Lots,-7.00,0.00,50.0,493.00
+1002,GBPUSD,Sell,16/01/2024 09:00:00.000,16/01/2024 11:00:00.000,1.30000,1.30500,0.50 Lots,-3.50,-1.50,-50.0,-255.00
+"""
+
+        trades = parse_ctrader_csv(csv_content)
+
+        assert len(trades) == 2
+        assert trades[0].symbol == "EURUSD"
+        assert trades[0].trade_type == "Buy"
+        assert trades[0].volume == 1.0
+        assert trades[0].open_price == 1.1
+        # Net USD less commission and swap, matching the MT5 meaning of profit
+        assert trades[0].profit == pytest.approx(500.0)
+        assert trades[0].commission == -7.0
+        assert trades[0].open_time == 1705314600000
+        assert trades[0].close_time == 1705327200000
+        assert trades[1].trade_type == "Sell"
+        assert trades[1].profit == pytest.approx(-250.0)
+
+        with pytest.raises(ValueError):
+            parse_ctrader_csv("Symbol,Volume\nEURUSD,1")
+        # Optional cells may be empty, but not unreadable
+        assert parse_ctrader_csv(csv_content.replace("-7.00", ""))[0].commission is None
+        with pytest.raises(ParseError, match="row 2 has an invalid commission"):
+            parse_ctrader_csv(csv_content.replace("-7.00", "n/a"))
+
+    def test_parse_report_file_detects_format(self, tmp_path):
+        """Test encoding and format detection when reading report files"""
+        html_path = tmp_path / "ReportHistory.html"
+        html_path.write_bytes(self.MT5_HTML_REPORT.encode("utf-16"))
+
+        result = parse_report_file(str(html_path))
+        assert result.format == "mt5_html"
+        assert result.encoding == "utf-16le"
+        assert [t.profit for t in result.trades] == [1500.0, -250.0]
+
+        csv_path = tmp_path / "history.csv"
+        csv_path.write_text(
+            "Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,Swap\n"
+            "EURUSD,Buy,1.0,1.1000,1.1050,50.0,-2.0,0.0\n"
+        )
+        result = parse_report_file(csv_path)
+        assert result.format == "mt5_csv"
+        assert result.encoding == "utf-8"
+        assert len(result.trades) == 1
+
+        ctrader = parse_report(
+            "Symbol,Opening Direction,Entry Price,Closing Price,Closing Quantity,Net USD\n"
+            "EURUSD,Buy,1.1,1.105,1.00 Lots,500.00\n".encode("utf-8")
+        )
+        assert ctrader.format == "ctrader_csv"
+        assert ctrader.trades[0].profit == 500.0
+
+        with pytest.raises(FileNotFoundError):
+            parse_report_file(str(tmp_path / "missing.csv"))
+
+    def test_parse_csv_with_schema(self):
+        """Test mapping an arbitrary broker export onto trades"""
+        csv_content = """Ticket;Instrument;Side;Lots;Entry;Exit;Closed;Result
+1;EURUSD;BUY;1,00;1,10000;1,10500;15/01/2024 14:00;1.234,50
+2;GBPUSD;SELL;0,50;1,30000;1,30500;16/01/2024 11:00;-250,00
+"""
+        schema = CsvSchema(
+            symbol="Instrument",
+            trade_type="Side",
+            volume="Lots",
+            open_price="Entry",
+            close_price="Exit",
+            profit="Result",
+            close_time="Closed",
+            date_format="%d/%m/%Y %H:%M",
+            decimal_separator=",",
+            delimiter=";",
+        )
+
+        trades = parse_csv_with_schema(csv_content, schema)
+
+        assert len(trades) == 2
+        assert trades[0].symbol == "EURUSD"
+        assert trades[0].trade_type == "Buy"
+        assert trades[0].open_price == 1.1
+        assert trades[0].profit == 1234.5
+        assert trades[0].close_time == 1705327200000
+        assert trades[0].commission is None
+        assert trades[1].trade_type == "Sell"
+        assert trades[1].volume == 0.5
+
+        # Columns can also be given by position in a headerless file
+        by_index = CsvSchema(
+            symbol=0, trade_type=1, volume=2, open_price=3, close_price=4, profit=5, has_header=False
+        )
+        trades = parse_csv_with_schema("EURUSD,Buy,1.0,1.1,1.105,500\n", by_index)
+        assert trades[0].profit == 500.0
+        assert by_index.symbol == 0
+
+        with pytest.raises(ValueError):
+            parse_csv_with_schema(csv_content, CsvSchema(delimiter=";"))
+
+        schema = CsvSchema(commission="Commission", max_adverse_excursion="MAE")
+        header = "Symbol,Type,Volume,Open Price,Close Price,Profit,Commission,MAE\n"
+        trades = parse_csv_with_schema(header + "EURUSD,Buy,1.0,1.1,1.105,500,,\n", schema)
+        assert (trades[0].commission, trades[0].max_adverse_excursion) == (None, None)
+        with pytest.raises(ParseError, match="row 2 has an invalid commission"):
+            parse_csv_with_schema(header + "EURUSD,Buy,1.0,1.1,1.105,500,n/a,\n", schema)
+        with pytest.raises(ParseError, match="row 3 has an invalid max adverse excursion"):
+            parse_csv_with_schema(header + "EURUSD,Buy,1.0,1.1,1.105,500,,\nEURUSD,Buy,1,1.1,1.1,0,,-\n", schema)
+
+    MT5_HTML_REPORT = """<html><head><style>td { font: 8pt Tahoma; }</style></head><body>
+<table cellspacing=1 cellpadding=3 border=0>
+<tr align=center><th colspan=14><div style="font: 14pt Tahoma"><b>Trade History Report</b></div></th></tr>
+<tr align=center><th colspan=14 style="height: 25px"><div><b>Positions</b></div></th></tr>
+<tr bgcolor="#E5F0FC"><td nowrap><b>Time</b></td><td><b>Position</b></td><td><b>Symbol</b></td>
+<td><b>Type</b></td><td class="hidden" colspan="8"></td><td><b>Volume</b></td><td><b>Price</b></td>
+<td><b>S / L</b></td><td><b>T / P</b></td><td><b>Time</b></td><td><b>Price</b></td>
+<td><b>Commission</b></td><td><b>Swap</b></td><td colspan=2><b>Profit</b></td></tr>
+<tr bgcolor="#FFFFFF"><td>2024.01.15 10:30:00</td><td>1001</td><td>EURUSD</td><td>buy</td>
+<td class="hidden" colspan="8"></td><td>1.00</td><td>1.1000</td><td></td><td></td>
+<td>2024.01.15 14:00:00</td><td>1.1050</td><td>-2.00</td><td>0.00</td><td colspan=2>1&nbsp;500.00</td></tr>
+<tr bgcolor="#F7F7F7"><td>2024.01.16 09:00:00</td><td>1002</td><td>GBPUSD</td><td>sell</td>
+<td class="hidden" colspan="8"></td><td>0.50</td><td>1.3000</td><td></td><td></td>
+<td>2024.01.16 11:00:00</td><td>1.3050</td><td>-1.00</td><td>-0.50</td><td colspan=2>-250.00</td></tr>
+<tr><td nowrap style="height: 10px"></td></tr>
+<tr align=center><th colspan=14 style="height: 25px"><div><b>Orders</b></div></th></tr>
+</table></body></html>"""
+
+    def test_parse_mt5_html_positions(self):
+        """Test parsing the Positions table of an MT5 HTML report"""
+        trades = parse_mt5_html(self.MT5_HTML_REPORT)
+
+        assert len(trades) == 2
+        assert trades[0].symbol == "EURUSD"
+        assert trades[0].trade_type == "Buy"
+        assert trades[0].volume == 1.0
+        assert trades[0].close_price == 1.1050
+        assert trades[0].profit == 1500.0
+        assert trades[0].close_time == 1705327200000
+        assert trades[1].trade_type == "Sell"
+        assert trades[1].swap == -0.5
+
+        # MT5 writes its HTML reports as UTF-16 with a byte order mark
+        encoded = parse_mt5_html(self.MT5_HTML_REPORT.encode("utf-16"))
+        assert [t.profit for t in encoded] == [1500.0, -250.0]
+
+        with pytest.raises(ParseError, match="invalid swap"):
+            parse_mt5_html(self.MT5_HTML_REPORT.replace("<td>-0.50</td>", "<td>n/a</td>"))
+        with pytest.raises(ParseError, match="invalid position"):
+            parse_mt5_html(self.MT5_HTML_REPORT.replace("<td>1002</td>", "<td>#1002</td>"))
+
+    def test_parse_mt5_html_deals(self):
+        """Test rebuilding trades from the Deals table when Positions is absent"""
+        html = """<table>
+<tr><th colspan=13><b>Deals</b></th></tr>
+<tr><td>Time</td><td>Deal</td><td>Symbol</td><td>Type</td><td>Direction</td><td>Volume</td>
+<td>Price</td><td>Order</td><td>Commission</td><td>Fee</td><td>Swap</td><td>Profit</td><td>Balance</td></tr>
+<tr><td>2024.01.15 00:00:00</td><td>1</td><td></td><td>balance</td><td></td><td></td>
+<td></td><td></td><td>0.00</td><td>0.00</td><td>0.00</td><td>10 000.00</td><td>10 000.00</td></tr>
+<tr><td>2024.01.15 10:30:00</td><td>2</td><td>EURUSD</td><td>buy</td><td>in</td><td>1.00</td>
+<td>1.1000</td><td>11</td><td>-1.00</td><td>0.00</td><td>0.00</td><td>0.00</td><td>9 999.00</td></tr>
+<tr><td>2024.01.15 14:00:00</td><td>3</td><td>EURUSD</td><td>sell</td><td>out</td><td>1.00</td>
+<td>1.1050</td><td>12</td><td>-1.00</td><td>0.00</td><td>0.00</td><td>500.00</td><td>10 498.00</td></tr>
+<tr><td colspan=8></td><td>-2.00</td><td>0.00</td><td>0.00</td><td>500.00</td><td>10 498.00</td></tr>
+</table>"""
+
+        trades = parse_mt5_html(html)
+
         assert len(trades) == 1
-        assert trades[0].volume == 0.0  # Default for invalid parse
+        assert trades[0].trade_type == "Buy"
+        assert trades[0].open_price == 1.1000
+        assert trades[0].close_price == 1.1050
+        assert trades[0].profit == 500.0
+        assert trades[0].open_time == 1705314600000
+
+        with pytest.raises(ValueError):
+            parse_mt5_html("<html><body><table></table></body></html>")
+        with pytest.raises(ParseError, match="row 4 has an invalid commission"):
+            parse_mt5_html(html.replace("<td>-1.00</td>", "<td>n/a</td>", 1))
+        with pytest.raises(ParseError, match="invalid deal"):
+            parse_mt5_html(html.replace("<td>3</td>", "<td>D3</td>"))
+
+    def test_parse_mt5_deals_partial_closes(self):
+        """Test netting partial closes and reversals into round-trip trades"""
+        def deal(time, ticket, symbol, side, direction, volume, price, commission, profit):
+            return (f"<tr><td>2024.01.15 {time}</td><td>{ticket}</td><td>{symbol}</td><td>{side}</td>"
+                    f"<td>{direction}</td><td>{volume}</td><td>{price}</td><td></td><td>{commission}</td>"
+                    f"<td>0.00</td><td>0.00</td><td>{profit}</td><td></td></tr>")
+
+        html = "\n".join([
+            "<table><tr><th colspan=13><b>Deals</b></th></tr>",
+            "<tr><td>Time</td><td>Deal</td><td>Symbol</td><td>Type</td><td>Direction</td><td>Volume</td>"
+            "<td>Price</td><td>Order</td><td>Commission</td><td>Fee</td><td>Swap</td><td>Profit</td>"
+            "<td>Balance</td></tr>",
+            "<tr><td>2024.01.15 00:00:00</td><td>1</td><td></td><td>balance</td><td></td><td></td><td></td>"
+            "<td></td><td>0.00</td><td>0.00</td><td>0.00</td><td>10 000.00</td><td>10 000.00</td></tr>",
+            deal("09:00:00", 2, "EURUSD", "buy", "in", "1.00", "1.1000", "-1.00", "0.00"),
+            deal("10:00:00", 3, "EURUSD", "sell", "out", "0.40", "1.1050", "-0.50", "200.00"),
+            deal("11:00:00", 4, "GBPUSD", "sell", "in", "1.00", "1.3000", "0.00", "0.00"),
+            deal("12:00:00", 5, "EURUSD", "sell", "out", "0.60", "1.1100", "-0.50", "600.00"),
+            deal("13:00:00", 6, "GBPUSD", "buy", "in/out", "2.00", "1.2900", "0.00", "100.00"),
+            deal("14:00:00", 7, "GBPUSD", "sell", "out", "1.00", "1.2950", "0.00", "50.00"),
+            "</table>",
+        ])
+
+        deals = parse_mt5_deals(html)
+        assert len(deals) == 6
+        assert deals[0].ticket == 2
+        assert deals[0].entry == DealEntry.In
+        assert deals[4].entry == DealEntry.InOut
+
+        trades = aggregate_deals(deals)
+        assert [(t.symbol, t.trade_type) for t in trades] == [
+            ("EURUSD", "Buy"), ("GBPUSD", "Sell"), ("GBPUSD", "Buy"),
+        ]
+        # Both partial closes belong to one trade
+        assert trades[0].volume == 1.0
+        assert trades[0].close_price == pytest.approx(1.108)
+        assert trades[0].profit == 800.0
+        assert trades[0].commission == -2.0
+        assert trades[0].open_time == 1705309200000
+        assert trades[0].close_time == 1705320000000
+        # The reversal closes the short and opens a long with the remaining lot
+        assert trades[1].profit == 100.0
+        assert trades[2].volume == 1.0
+        assert trades[2].open_price == 1.29
+
+        assert [t.profit for t in parse_mt5_html(html)] == [800.0, 100.0, 50.0]
+        with pytest.raises(ValueError, match="Deals section not found"):
+            parse_mt5_deals("<html><body><table></table></body></html>")
+
+    def test_aggregate_hedged_deals(self):
+        """Test that concurrent positions of a hedging account are netted apart by position ID"""
+        def deal(time, ticket, position, side, direction, price, profit):
+            return (f"<tr><td>2024.01.15 {time}</td><td>{ticket}</td><td>EURUSD</td><td>{side}</td>"
+                    f"<td>{direction}</td><td>1.00</td><td>{price}</td><td>{ticket + 100}</td><td>0.00</td>"
+                    f"<td>0.00</td><td>{profit}</td><td>{position}</td></tr>")
+
+        html = "\n".join([
+            "<table><tr><th colspan=12><b>Deals</b></th></tr>",
+            "<tr><td>Time</td><td>Deal</td><td>Symbol</td><td>Type</td><td>Direction</td><td>Volume</td>"
+            "<td>Price</td><td>Order</td><td>Commission</td><td>Swap</td><td>Profit</td><td>Position</td></tr>",
+            deal("09:00:00", 2, 501, "buy", "in", "1.1000", "0.00"),
+            deal("09:30:00", 3, 502, "buy", "in", "1.1020", "0.00"),
+            deal("10:00:00", 4, 501, "sell", "out", "1.1050", "500.00"),
+            deal("11:00:00", 5, 502, "sell", "out", "1.1010", "-100.00"),
+            "</table>",
+        ])
+
+        deals = parse_mt5_deals(html)
+        assert [(d.position, d.order) for d in deals] == [(501, 102), (502, 103), (501, 104), (502, 105)]
+        trades = aggregate_deals(deals)
+        assert [(t.ticket, t.open_price, t.close_price, t.profit) for t in trades] == [
+            (501, 1.1, 1.105, 500.0), (502, 1.102, 1.101, -100.0),
+        ]
+        assert trades[0].close_time == 1705312800000
+
+
+    def test_merge_trade_sets(self):
+        """Test merging overlapping report imports by ticket into one chronological list"""
+        # Reports carry the position ticket
+        assert [t.ticket for t in parse_mt5_html(self.MT5_HTML_REPORT)] == [1001, 1002]
+        schema = CsvSchema(ticket="Ticket", close_time="Close Time")
+        parsed = parse_csv_with_schema(
+            "Ticket,Symbol,Type,Volume,Open Price,Close Price,Profit,Close Time\n"
+            "77,EURUSD,Buy,1,1.1,1.105,500,2024-01-15 10:00:00\n",
+            schema,
+        )
+        assert parsed[0].ticket == 77
+
+        day = 86400000
+        def trade(ticket, close_day, profit=100.0, swap=None):
+            return Trade("EURUSD", "Buy", 1.0, 1.1, 1.101, profit, None, swap,
+                         close_time=1704067200000 + close_day * day, ticket=ticket)
+
+        january = [trade(1, 1), trade(2, 5), trade(3, 9)]
+        february = [trade(5, 33), trade(4, 31)]
+        # The full history repeats both months, with swap booked on ticket 3 since the January export
+        history = [trade(1, 1), trade(2, 5), trade(3, 9, swap=-2.0), trade(4, 31), trade(5, 33), trade(6, 35)]
+        merged = merge_trade_sets([february, january, history])
+        assert [t.ticket for t in merged.trades] == [1, 2, 3, 4, 5, 6]
+        assert merged.duplicates_removed == 5
+        assert merged.duplicates_by_set == [0, 0, 5]
+        assert merged.conflicting_tickets == [3]
+        assert merged.trades[2].swap is None
+
+        # A Deals export of the same two positions carries their IDs in the entry deals' Order column
+        deals_html = "\n".join([
+            "<table><tr><th colspan=12><b>Deals</b></th></tr>",
+            "<tr><td>Time</td><td>Deal</td><td>Symbol</td><td>Type</td><td>Direction</td><td>Volume</td>"
+            "<td>Price</td><td>Order</td><td>Commission</td><td>Swap</td><td>Profit</td><td>Balance</td></tr>",
+            "<tr><td>2024.01.15 10:30:00</td><td>5001</td><td>EURUSD</td><td>buy</td><td>in</td><td>1.00</td>"
+            "<td>1.1000</td><td>1001</td><td>-1.00</td><td>0.00</td><td>0.00</td><td></td></tr>",
+            "<tr><td>2024.01.15 14:00:00</td><td>5002</td><td>EURUSD</td><td>sell</td><td>out</td><td>1.00</td>"
+            "<td>1.1050</td><td>1003</td><td>-1.00</td><td>0.00</td><td>1 500.00</td><td></td></tr>",
+            "<tr><td>2024.01.16 09:00:00</td><td>5003</td><td>GBPUSD</td><td>sell</td><td>in</td><td>0.50</td>"
+            "<td>1.3000</td><td>1002</td><td>-0.50</td><td>0.00</td><td>0.00</td><td></td></tr>",
+            "<tr><td>2024.01.16 11:00:00</td><td>5004</td><td>GBPUSD</td><td>buy</td><td>out</td><td>0.50</td>"
+            "<td>1.3050</td><td>1004</td><td>-0.50</td><td>-0.50</td><td>-250.00</td><td></td></tr>",
+            "</table>",
+        ])
+        from_deals = parse_mt5_html(deals_html)
+        assert [t.ticket for t in from_deals] == [1001, 1002]
+        merged = merge_trade_sets([parse_mt5_html(self.MT5_HTML_REPORT), from_deals])
+        assert len(merged.trades) == 2
+        assert merged.duplicates_by_set == [0, 2]
+        assert merged.conflicting_tickets == []
+
+        # A partial close shares its position ticket but not its close time
+        assert len(merge_trade_sets([[trade(9, 1, 50.0), trade(9, 2, 70.0)]]).trades) == 2
+
+        # Without tickets identical trades match, up to the most copies any one set has
+        untracked = Trade("EURUSD", "Buy", 1.0, 1.1, 1.101, 100.0, None, None, close_time=1704153600000)
+        merged = merge_trade_sets([[untracked, untracked], [untracked], [untracked, untracked, untracked]])
+        assert len(merged.trades) == 3
+        assert merged.duplicates_by_set == [0, 1, 2]
+        assert merge_trade_sets([]).trades == []
+
+    def test_filter_trades(self):
+        """Test slicing a history by close date, symbol, direction and volume"""
+        from datetime import datetime, timezone
+
+        day = 86400000
+        jan1 = 1704067200000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.101, 100.0, None, None, close_time=jan1),
+            Trade("GBPUSD", "Sell", 0.5, 1.3, 1.299, 50.0, None, None, close_time=jan1 + day),
+            Trade("EURUSD", "Sell", 2.0, 1.1, 1.102, -400.0, None, None, close_time=jan1 + 2 * day),
+            Trade("XAUUSD", "Buy", 1.0, 2000.0, 2001.0, 100.0, None, None, open_time=jan1 + 3 * day),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.101, 100.0, None, None),
+        ]
+        assert filter_trades(trades) == trades
+
+        # Start is inclusive and end exclusive; open time stands in for a missing close time
+        sliced = filter_trades(trades, start=jan1 + day, end=jan1 + 3 * day)
+        assert [t.close_time for t in sliced] == [jan1 + day, jan1 + 2 * day]
+        assert [t.symbol for t in filter_trades(trades, start=jan1 + 3 * day)] == ["XAUUSD"]
+        by_datetime = filter_trades(trades, end=datetime(2024, 1, 2, tzinfo=timezone.utc))
+        assert [t.close_time for t in by_datetime] == [jan1]
+
+        assert [t.volume for t in filter_trades(trades, symbols=["eurusd"], direction="Sell")] == [2.0]
+        assert len(filter_trades(trades, direction=TradeType.Buy, min_volume=1.0)) == 3
+        assert filter_trades(trades, symbols=[]) == []
+
+        with pytest.raises(ValidationError):
+            filter_trades(trades, start=jan1 + day, end=jan1)
+
+    def test_trades_from_records(self):
+        """Test building trades from DataFrame-style records and back"""
+        from datetime import datetime, timezone
+
+        records = [
+            {
+                "symbol": "EURUSD", "trade_type": "buy", "volume": 1, "open_price": 1.1,
+                "close_price": 1.105, "profit": 500.0, "commission": float("nan"),
+                "open_time": datetime(2024, 1, 2, 9, 30, tzinfo=timezone.utc),
+                "close_time": 1704190200000, "extra": "ignored",
+            },
+            {
+                "symbol": "GBPUSD", "trade_type": "Sell", "volume": 0.5, "open_price": 1.3,
+                "close_price": 1.31, "profit": -500.0, "swap": -1.5, "risk_amount": None,
+            },
+        ]
+        trades = trades_from_records(records)
+
+        assert trades[0].trade_type == "Buy"
+        assert trades[0].volume == 1.0
+        assert trades[0].commission is None
+        assert trades[0].open_time == 1704187800000
+        assert trades[0].close_time == 1704190200000
+        assert trades[1].swap == -1.5
+        assert trades[1].open_time is None
+
+        # The exported records rebuild the same trades
+        rebuilt = trades_from_records(trades_to_records(trades))
+        assert [t.to_dict() for t in rebuilt] == [t.to_dict() for t in trades]
+        assert Trade.from_dict(trades[1].to_dict()).profit == -500.0
+
+        metrics = calculate_performance_metrics(trades).to_dict()
+        assert metrics["total_trades"] == 2
+        assert metrics["sharpe_ratio"] is None
+
+        with pytest.raises(ValueError, match="Record 1 is missing profit"):
+            trades_from_records([records[0], {"symbol": "X", "trade_type": "Buy", "volume": 1,
+                                              "open_price": 1, "close_price": 1}])
+        with pytest.raises(ValueError, match="invalid volume"):
+            trades_from_records([dict(records[1], volume="lots")])
 
 
 class TestPerformanceAnalysis:
     """Test performance analysis functions"""
 
+
+    def test_arrow_interchange(self):
+        """Test handing trades and results over the Arrow C stream interface"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.12, 200.0, -7.0, None, 1704187800000, 1704190200000, 100.0, "london"),
+            Trade("GBPUSD", "Sell", 0.5, 1.3, 1.31, -500.0, None, -1.5, None, None),
+        ]
+        table = trades_to_arrow(trades)
+        assert len(table) == table.num_rows == 2
+        assert table.column_names[:6] == ["symbol", "trade_type", "volume", "open_price", "close_price", "profit"]
+        assert trades_from_arrow(table) == trades
+
+        # Any object exposing the stream protocol works, e.g. a pyarrow table or polars frame
+        class Stream:
+            def __arrow_c_stream__(self, requested_schema=None):
+                return table.__arrow_c_stream__(requested_schema)
+
+        assert trades_from_arrow(Stream()) == trades
+        with pytest.raises(ValidationError, match="__arrow_c_stream__"):
+            trades_from_arrow(trades)
+
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+        result = run_monte_carlo_detailed(trades * 10, params, 0.01, 50, seed=1)
+        results = simulation_results_to_arrow(result)
+        assert results.num_rows == 50 and "final_equity" in results.column_names
+        with pytest.raises(ParseError, match="missing the symbol column"):
+            trades_from_arrow(results)
+
+        rolling = rolling_metrics_to_arrow(rolling_metrics(trades * 10, 5, 5))
+        assert rolling.num_rows == 4 and rolling.column_names[-1] == "kelly_fraction"
+
     def test_calculate_performance_metrics_basic(self):
         """Test basic performance metrics calculation"""
         trades = [
@@ -134,6 +1097,115 @@ class TestPerformanceAnalysis:
         assert metrics.avg_loss < 0
         assert metrics.profit_factor > 1.0  # Profitable system
 
+    def test_calculate_performance_metrics_chronological_drawdown(self):
+        """Test that drawdown follows close-time order rather than input order"""
+        day = 86_400_000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 100.0, None, None, close_time=2 * day),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -80.0, None, None, close_time=3 * day),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -50.0, None, None, close_time=1 * day),
+        ]
+
+        metrics = calculate_performance_metrics(trades)
+
+        # In input order the drawdown would be 130
+        assert metrics.max_drawdown == 80.0
+
+    def test_sharpe_ratio_from_daily_returns(self):
+        """Test annualized Sharpe from timestamped trades"""
+        day = 86_400_000
+        monday = 19_737 * day  # 2024-01-15
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 100.0, None, None, close_time=monday),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -50.0, None, None, close_time=monday + day),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 150.0, None, None, close_time=monday + 3 * day),
+        ]
+
+        metrics = calculate_performance_metrics(trades)
+
+        # Wednesday has no trades and counts as a flat day
+        daily = [100.0, -50.0, 0.0, 150.0]
+        mean = sum(daily) / 4
+        std = (sum((d - mean) ** 2 for d in daily) / 3) ** 0.5
+        assert metrics.sharpe_ratio == pytest.approx(mean / std * 252 ** 0.5)
+
+    def test_sharpe_ratio_risk_free_rate(self):
+        """Test that the risk-free rate lowers Sharpe and needs a balance"""
+        day = 86_400_000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, profit, None, None, close_time=19_737 * day + i * day)
+            for i, profit in enumerate([100.0, -50.0, 80.0, 20.0])
+        ]
+
+        base = calculate_performance_metrics(trades, initial_balance=10000.0)
+        with_rate = calculate_performance_metrics(trades, risk_free_rate=0.05, initial_balance=10000.0)
+        assert with_rate.sharpe_ratio < base.sharpe_ratio
+
+        with pytest.raises(ValueError):
+            calculate_performance_metrics(trades, risk_free_rate=0.05)
+
+    def test_sortino_calmar_mar_ratios(self):
+        """Test downside and drawdown based ratios"""
+        day = 86_400_000
+        profits = [200.0, -100.0, 150.0, -50.0, 300.0]
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, profit, None, None, close_time=19_737 * day + i * day)
+            for i, profit in enumerate(profits)
+        ]
+
+        metrics = calculate_performance_metrics(trades, initial_balance=10000.0)
+
+        assert metrics.sortino_ratio > metrics.sharpe_ratio  # Upside volatility is not penalized
+        # Under 36 months of data Calmar and MAR cover the same window
+        assert metrics.calmar_ratio == pytest.approx(metrics.mar_ratio)
+
+        final = 10000.0 + sum(profits)
+        cagr = (final / 10000.0) ** (252 / 5) - 1
+        assert metrics.mar_ratio == pytest.approx(cagr / (100.0 / 10200.0))
+
+        # Drawdown ratios need a starting balance
+        assert calculate_performance_metrics(trades).calmar_ratio is None
+
+    def test_sharpe_ratio_without_timestamps(self):
+        """Test that Sharpe stays unset when trades have no timestamps"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
+            Trade("GBPUSD", "Sell", 0.5, 1.3000, 1.2950, -25.0, -1.0, -0.5),
+        ]
+
+        assert calculate_performance_metrics(trades).sharpe_ratio is None
+
+    def test_calculate_performance_metrics_normalization(self):
+        """Test measuring the edge independently of past position sizes"""
+        trades = [
+            Trade("EURUSD", "Buy", 2.0, 1.1, 1.2, 200.0, None, None, risk_amount=100.0),
+            Trade("EURUSD", "Buy", 0.5, 1.1, 1.2, -100.0, None, None, risk_amount=50.0),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 100.0, None, None, risk_amount=100.0),
+        ]
+
+        raw = calculate_performance_metrics(trades)
+        assert raw.avg_win == 150.0
+
+        per_lot = calculate_performance_metrics(trades, normalization=ProfitNormalization.PerLot)
+        assert per_lot.avg_win == 100.0
+        assert per_lot.avg_loss == -200.0
+        # Drawdown still describes the account in money
+        assert per_lot.max_drawdown == raw.max_drawdown
+
+        per_r = calculate_performance_metrics(trades, normalization=ProfitNormalization.PerRiskUnit)
+        assert per_r.avg_win == 1.5
+        assert per_r.avg_loss == -2.0
+
+        percent = calculate_performance_metrics(
+            trades, initial_balance=1000.0, normalization=ProfitNormalization.PercentOfBalance
+        )
+        # +20% of 1000, -100 of 1200, +100 of 1100
+        assert percent.avg_loss == pytest.approx(-100.0 / 1200.0 * 100.0)
+        assert percent.avg_win == pytest.approx((20.0 + 100.0 / 1100.0 * 100.0) / 2)
+
+        with pytest.raises(ValueError):
+            calculate_performance_metrics(trades, normalization=ProfitNormalization.PercentOfBalance)
+
     def test_calculate_performance_metrics_empty(self):
         """Test performance metrics with no trades"""
         with pytest.raises(Exception):  # Should raise an error for empty trades
@@ -167,107 +1239,2175 @@ class TestPerformanceAnalysis:
         assert metrics.loss_probability == 1.0
         assert metrics.avg_win == 0.0  # No wins
 
+    def test_expectancy_significance(self):
+        """Test SQN and the t-test of a positive expectancy"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
+            Trade("GBPUSD", "Sell", 0.5, 1.3000, 1.2950, -25.0, -1.0, -0.5),
+            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, 50.0, -2.0, 0.0),
+        ]
+        metrics = calculate_performance_metrics(trades)
+
+        # Mean 25, sample standard deviation 25 * sqrt(3)
+        assert metrics.expectancy_t_stat == pytest.approx(1.0)
+        assert metrics.sqn == pytest.approx(1.0)
+        assert metrics.expectancy_p_value == pytest.approx(0.2113, abs=1e-4)
+        assert not metrics.has_significant_edge()
 
-class TestKellyCriterion:
-    """Test Kelly Criterion calculations"""
+        # The same edge over many more trades is significant, but SQN stops growing at 100 trades
+        large = calculate_performance_metrics(trades * 100)
+        assert large.has_significant_edge(0.01)
+        assert large.sqn == pytest.approx(large.expectancy_t_stat * math.sqrt(100 / 300))
 
-    def test_kelly_criterion_basic(self):
-        """Test basic Kelly Criterion calculation"""
-        win_prob = 0.55
-        win_loss_ratio = 1.25
-        fractional_multiplier = 1.0
+        single = calculate_performance_metrics(trades[:1])
+        assert single.sqn is None and single.expectancy_p_value is None
+        assert not single.has_significant_edge()
 
-        kelly_fraction = calculate_kelly_criterion(win_prob, win_loss_ratio, fractional_multiplier)
+    def test_required_sample_size(self):
+        """Test the minimum sample size and the additional trades flagged in the metrics"""
+        # 1.96^2 * (1 + 1/1)^2 * 0.25 / 0.1^2 = 384.1
+        assert required_sample_size(0.5, 1.0) == 385
+        assert required_sample_size(0.5, 1.0, confidence=0.9, margin=0.2) == 68
+        # Larger wins make the Kelly fraction less sensitive to the win rate
+        assert required_sample_size(0.5, 3.0) < required_sample_size(0.5, 1.0)
 
-        expected = win_prob - ((1.0 - win_prob) / win_loss_ratio)
-        assert abs(kelly_fraction - expected) < 1e-6
+        with pytest.raises(ValueError):
+            required_sample_size(1.5, 1.0)
+        with pytest.raises(ValueError):
+            required_sample_size(0.5, 0.0)
+        with pytest.raises(ValueError):
+            required_sample_size(0.5, 1.0, margin=0.0)
 
-    def test_kelly_criterion_fractional(self):
-        """Test fractional Kelly Criterion"""
-        win_prob = 0.55
-        win_loss_ratio = 1.25
-        fractional_multiplier = 0.5  # Half Kelly
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
+            Trade("GBPUSD", "Sell", 0.5, 1.3000, 1.2950, -25.0, -1.0, -0.5),
+            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, 50.0, -2.0, 0.0),
+        ]
+        metrics = calculate_performance_metrics(trades)
+        # Win rate 2/3 and median ratio 2 need 193 trades
+        assert metrics.additional_trades_needed == 190
+        assert not metrics.has_sufficient_sample()
 
-        kelly_fraction = calculate_kelly_criterion(win_prob, win_loss_ratio, fractional_multiplier)
+        large = calculate_performance_metrics(trades * 100)
+        assert large.additional_trades_needed == 0
+        assert large.has_sufficient_sample()
 
-        full_kelly = win_prob - ((1.0 - win_prob) / win_loss_ratio)
-        expected = full_kelly * fractional_multiplier
-        assert abs(kelly_fraction - expected) < 1e-6
+        # Without any losses the sample is judged as a coin flip
+        assert calculate_performance_metrics(trades[:1]).additional_trades_needed == 384
 
-    def test_kelly_criterion_edge_cases(self):
-        """Test Kelly Criterion edge cases"""
-        # Win probability = 1.0 (always win)
-        with pytest.raises(Exception):
-            calculate_kelly_criterion(1.0, 1.25, 1.0)
+    def test_outlier_adjustment(self):
+        """Test winsorized and trimmed edge statistics next to the raw metrics"""
+        profits = [100.0, -50.0, 80.0, -60.0, 120.0, -40.0, 90.0, -50.0, 5000.0, -70.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
 
-        # Win probability = 0.0 (never win)
-        with pytest.raises(Exception):
-            calculate_kelly_criterion(0.0, 1.25, 1.0)
+        assert calculate_performance_metrics(trades).outlier_adjustment is None
 
-        # Win/loss ratio = 0 (undefined)
-        with pytest.raises(Exception):
-            calculate_kelly_criterion(0.55, 0.0, 1.0)
+        winsorized = calculate_performance_metrics(trades, outliers=OutlierMode.Winsorize, outlier_percent=10.0)
+        # The raw metrics are untouched
+        assert winsorized.avg_win == pytest.approx(1078.0)
+        adjustment = winsorized.outlier_adjustment
+        assert adjustment.mode == OutlierMode.Winsorize
+        assert adjustment.trades_adjusted == 2
+        # 5000 is clipped to 120 and -70 to -60
+        assert adjustment.avg_win == pytest.approx(102.0)
+        assert adjustment.avg_loss == pytest.approx(-52.0)
+        assert adjustment.expectancy == pytest.approx(25.0)
 
+        trimmed = calculate_performance_metrics(trades, outliers=OutlierMode.Trim, outlier_percent=10.0)
+        adjustment = trimmed.outlier_adjustment
+        assert adjustment.avg_win == pytest.approx(97.5)
+        assert adjustment.avg_loss == pytest.approx(-50.0)
+        assert adjustment.win_loss_ratio == pytest.approx(1.9)
+        assert adjustment.raw_kelly_fraction == pytest.approx(0.25)
+        assert adjustment.kelly_fraction == pytest.approx(0.5 - 0.5 / 1.9)
 
-class TestOptimalF:
-    """Test Optimal F calculations"""
+        # Too few trades for 5% of a tail leaves the sample as it is
+        small = calculate_performance_metrics(trades, outliers=OutlierMode.Trim).outlier_adjustment
+        assert small.trades_adjusted == 0
+        assert small.expectancy == pytest.approx(trimmed.expectancy)
 
-    def test_optimal_f_basic(self):
-        """Test basic Optimal F calculation"""
+        with pytest.raises(ValueError):
+            calculate_performance_metrics(trades, outliers=OutlierMode.Trim, outlier_percent=50.0)
+
+    def test_calculate_equity_curve(self):
+        """Test the equity, peak and drawdown series"""
+        day = 86_400_000
         trades = [
-            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
-            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -50.0, -2.0, 0.0),
-            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, 50.0, -2.0, 0.0),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -300.0, None, None, close_time=3 * day),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1000.0, None, None, close_time=1 * day),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -200.0, None, None, close_time=2 * day),
         ]
 
-        optimal_f = calculate_optimal_f(trades, 1000, 1e-6)
+        curve = calculate_equity_curve(trades, initial_balance=10000.0)
 
-        assert isinstance(optimal_f, float)
-        assert optimal_f >= 0.0  # Should be non-negative
+        assert curve.close_times == [day, 2 * day, 3 * day]
+        assert curve.equity == [11000.0, 10800.0, 10500.0]
+        assert curve.peak == [11000.0, 11000.0, 11000.0]
+        assert curve.drawdown == [0.0, 200.0, 500.0]
+        assert curve.drawdown_percent[2] == pytest.approx(500.0 / 11000.0 * 100.0)
+        assert max(curve.drawdown) == calculate_performance_metrics(trades).max_drawdown
 
-    def test_optimal_f_no_losses(self):
-        """Test Optimal F with no losses"""
+    def test_calculate_drawdown_analysis(self):
+        """Test drawdown periods, durations and recovery"""
+        day = 86_400_000
+        profits = [100.0, -50.0, -30.0, 100.0, -200.0, 50.0, 300.0, -10.0]
         trades = [
-            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
-            Trade("GBPUSD", "Buy", 1.0, 1.3000, 1.3050, 50.0, -2.0, 0.0),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=(i + 1) * day)
+            for i, p in enumerate(profits)
         ]
 
-        optimal_f = calculate_optimal_f(trades, 1000, 1e-6)
+        analysis = calculate_drawdown_analysis(trades, initial_balance=1000.0, top_n=2)
 
-        assert optimal_f == 0.0  # No losses means no risk management needed
+        assert analysis.num_drawdowns == 3
+        assert analysis.max_drawdown == 200.0
+        assert analysis.average_drawdown == pytest.approx((80.0 + 200.0 + 10.0) / 3)
+        assert analysis.time_underwater_percent == pytest.approx(5 / 8 * 100.0)
+        assert [d.depth for d in analysis.drawdowns] == [200.0, 80.0]
 
-    def test_optimal_f_empty_trades(self):
-        """Test Optimal F with empty trades"""
-        with pytest.raises(Exception):
-            calculate_optimal_f([], 1000, 1e-6)
+        deepest = analysis.drawdowns[0]
+        assert (deepest.start_index, deepest.trough_index, deepest.recovery_index) == (4, 4, 6)
+        assert deepest.duration_trades == 2
+        assert deepest.recovery_trades == 2
+        assert deepest.duration_days == 3.0
+        assert deepest.recovery_days == 2.0
 
+        # The last drawdown has not recovered yet
+        assert calculate_drawdown_analysis(trades, 1000.0, top_n=3).drawdowns[2].recovery_index is None
 
-class TestMonteCarloSimulation:
-    """Test Monte Carlo simulation functions"""
+    def test_calculate_streaks(self):
+        """Test streak lengths, histogram and losing-run probabilities"""
+        profits = [10.0, 20.0, -5.0, -5.0, -5.0, 10.0, 0.0, -5.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
 
-    def test_monte_carlo_basic(self):
-        """Test basic Monte Carlo simulation"""
-        trades = [
-            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
+        streaks = calculate_streaks(trades)
+
+        assert streaks.longest_winning_streak == 2
+        assert streaks.longest_losing_streak == 3
+        assert streaks.current_streak == -1
+        assert streaks.winning_streaks == {2: 1, 1: 1}
+        assert streaks.losing_streaks == {3: 1, 1: 1}
+        assert streaks.loss_probability == 0.5
+
+        assert streaks.losing_streak_probability(2, num_trades=2) == pytest.approx(0.25)
+        # Of the eight equally likely outcomes of three trades, three contain two losses in a row
+        assert streaks.losing_streak_probability(2, num_trades=3) == pytest.approx(3 / 8)
+        assert streaks.losing_streak_probability(3) > streaks.losing_streak_probability(4)
+
+    def test_calculate_r_multiple_metrics(self):
+        """Test expectancy, Kelly and histogram in R-multiples"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 200.0, None, None, risk_amount=100.0),
+            Trade("EURUSD", "Buy", 2.0, 1.1, 1.2, -200.0, None, None, risk_amount=200.0),
+            Trade("EURUSD", "Buy", 0.5, 1.1, 1.2, 150.0, None, None, risk_amount=50.0),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -50.0, None, None, risk_amount=100.0),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 999.0, None, None),  # No known risk
+        ]
+        assert trades[0].r_multiple() == 2.0
+        assert trades[4].r_multiple() is None
+
+        r = calculate_r_multiple_metrics(trades)
+
+        assert r.trades_with_risk == 4
+        assert r.r_multiples == [2.0, -1.0, 3.0, -0.5]
+        assert r.win_probability == 0.5
+        assert r.expectancy_r == pytest.approx(0.875)
+        assert r.avg_win_r == 2.5
+        assert r.avg_loss_r == -0.75
+        # 0.5 - 0.5 / (2.5 / 0.75)
+        assert r.kelly_fraction == pytest.approx(0.35)
+        assert r.histogram == [(-1.0, 1), (-0.5, 1), (2.0, 1), (3.0, 1)]
+
+        with pytest.raises(Exception):
+            calculate_r_multiple_metrics([trades[4]])
+
+    def test_analyze_return_distribution(self):
+        """Test the histogram, moments and Jarque-Bera test of trade returns"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [-1.0, -1.0, 2.0, 2.0, 2.0, 5.0]]
+
+        dist = analyze_return_distribution(trades, bins=3)
+
+        assert dist.trades == 6
+        assert dist.mean == pytest.approx(1.5)
+        # Central moments m2 = 4.25, m3 = 2, m4 = 38.0625
+        assert dist.skewness == pytest.approx(2 / 4.25**1.5)
+        assert dist.excess_kurtosis == pytest.approx(38.0625 / 4.25**2 - 3)
+        assert dist.jarque_bera == pytest.approx(1.0 * (dist.skewness**2 + dist.excess_kurtosis**2 / 4))
+        assert dist.jarque_bera_p_value == pytest.approx(math.exp(-dist.jarque_bera / 2))
+        assert dist.bin_edges == pytest.approx([-1.0, 1.0, 3.0, 5.0])
+        assert dist.counts == [2, 3, 1]
+
+        # One outsized winner among many small trades is far from normal
+        fat_tail = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [1.0, -1.0] * 50 + [50.0]]
+        dist = analyze_return_distribution(fat_tail)
+        assert dist.skewness > 5
+        assert dist.jarque_bera_p_value < 0.001
+        assert sum(dist.counts) == 101 and len(dist.bin_edges) == 21
+
+        with pytest.raises(ValueError):
+            analyze_return_distribution(trades[:1])
+
+    def test_calculate_excursion_metrics(self):
+        """Test MFE/MAE averages and the stop and target suggested from them"""
+        def trade(profit, mfe, mae):
+            return Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, profit, None, None,
+                         max_favorable_excursion=mfe, max_adverse_excursion=mae)
+
+        trades = [
+            trade(100.0, 120.0, 20.0),
+            trade(100.0, 150.0, 40.0),
+            trade(100.0, 110.0, 30.0),
+            trade(-300.0, 10.0, 300.0),
+            trade(-250.0, 20.0, 250.0),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None),  # No excursions recorded
+        ]
+        assert Trade.from_dict(trades[0].to_dict()) == trades[0]
+
+        e = calculate_excursion_metrics(trades)
+
+        assert e.trades_with_excursions == 5
+        assert e.avg_mfe == pytest.approx(82.0)
+        assert e.avg_mae == pytest.approx(128.0)
+        assert e.avg_mae_winners == pytest.approx(30.0)
+        assert e.avg_mfe_losers == pytest.approx(15.0)
+
+        # Winners never went 40 against while losers ran 250 and more, so a stop in between turns the edge positive
+        assert 40.0 < e.suggested_stop < 250.0
+        assert e.expectancy == pytest.approx(-50.0)
+        assert e.suggested_expectancy > 0.0
+        assert e.suggested_win_loss_ratio > e.win_loss_ratio
+        assert e.kelly_fraction < 0.0 < e.suggested_kelly_fraction
+
+        schema = CsvSchema(max_favorable_excursion="MFE", max_adverse_excursion="MAE")
+        csv_content = (
+            "Symbol,Type,Volume,Open Price,Close Price,Profit,MFE,MAE\n"
+            "EURUSD,Buy,1.0,1.1,1.105,500,620,-80\n"
+        )
+        parsed = parse_csv_with_schema(csv_content, schema)
+        assert parsed[0].max_favorable_excursion == 620.0
+        assert parsed[0].max_adverse_excursion == 80.0
+
+        with pytest.raises(ValueError, match="max_adverse_excursion"):
+            trade(100.0, 120.0, -20.0)
+        with pytest.raises(InsufficientDataError):
+            calculate_excursion_metrics([trades[5]])
+
+    def test_calculate_metrics_by_symbol(self):
+        """Test the per-symbol breakdown and Kelly suggestions"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.0980, -20.0, None, None),
+            Trade("EURUSD", "Sell", 1.0, 1.1000, 1.0950, 50.0, None, None),
+            Trade("GBPUSD", "Buy", 1.0, 1.3000, 1.2950, -50.0, None, None),
+        ]
+
+        breakdown = calculate_metrics_by_symbol(trades)
+
+        assert set(breakdown) == {"EURUSD", "GBPUSD"}
+        eurusd = breakdown["EURUSD"]
+        assert eurusd.symbol == "EURUSD"
+        assert eurusd.metrics.total_trades == 3
+        assert eurusd.metrics.win_probability == pytest.approx(2.0 / 3.0)
+        # 2/3 - (1/3) / 2.5
+        assert eurusd.kelly_fraction == pytest.approx(0.5333333, rel=1e-5)
+
+        # A symbol with only losses has no Kelly suggestion
+        assert breakdown["GBPUSD"].metrics.total_trades == 1
+        assert breakdown["GBPUSD"].kelly_fraction is None
+
+    def test_calculate_metrics_by_direction(self):
+        """Test the long versus short breakdown and its Kelly suggestions"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, None, None),
+            Trade("GBPUSD", "Buy", 1.0, 1.3000, 1.2980, -20.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1030, 30.0, None, None),
+            Trade("EURUSD", "Sell", 1.0, 1.1000, 1.1050, -50.0, None, None),
+            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.3040, -40.0, None, None),
+        ]
+
+        split = calculate_metrics_by_direction(trades)
+
+        assert split.long.total_trades == 3
+        assert split.long.win_probability == pytest.approx(2.0 / 3.0)
+        # 2/3 - (1/3) / (40 / 20)
+        assert split.long_kelly_fraction == pytest.approx(0.5)
+        assert split.short.total_trades == 2
+        assert split.short.expectancy == pytest.approx(-45.0)
+        assert split.short_kelly_fraction is None
+
+        # A side that was never traded has no metrics
+        longs_only = calculate_metrics_by_direction(trades[:3], fractional_multiplier=0.5)
+        assert longs_only.short is None
+        assert longs_only.long_kelly_fraction == pytest.approx(0.25)
+
+        with pytest.raises(InsufficientDataError):
+            calculate_metrics_by_direction([])
+
+    def test_calculate_holding_time_metrics(self):
+        """Test holding time statistics and metrics per duration bucket"""
+        hour = 3_600_000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, open_time=0, close_time=int(h * hour))
+            for p, h in [(-30.0, 0.25), (-20.0, 0.5), (10.0, 0.75), (50.0, 3.0), (-10.0, 5.0), (80.0, 48.0)]
+        ]
+        trades.append(Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 999.0, None, None))
+
+        holding = calculate_holding_time_metrics(trades)
+
+        assert holding.trades_analyzed == 6
+        assert holding.avg_holding_hours == pytest.approx(57.5 / 6)
+        assert holding.median_holding_hours == pytest.approx(1.875)
+        assert holding.avg_winner_holding_hours == pytest.approx(51.75 / 3)
+        assert holding.avg_loser_holding_hours == pytest.approx(5.75 / 3)
+
+        # Scalps lose money while the longer holds carry the edge
+        scalps, intraday, swing = holding.buckets
+        assert (scalps.label, scalps.min_hours, scalps.max_hours) == ("< 1h", 0.0, 1.0)
+        assert scalps.metrics.total_trades == 3
+        assert scalps.metrics.expectancy == pytest.approx(-40.0 / 3)
+        assert intraday.label == "1h to 24h"
+        # 1/2 - (1/2) / 5
+        assert intraday.kelly_fraction == pytest.approx(0.4)
+        assert (swing.label, swing.max_hours, swing.metrics.total_trades) == (">= 24h", None, 1)
+
+        custom = calculate_holding_time_metrics(trades, boundaries_hours=[0.5])
+        assert [(b.label, b.metrics.total_trades) for b in custom.buckets] == [("< 0.5h", 1), (">= 0.5h", 5)]
+
+        with pytest.raises(ValueError):
+            calculate_holding_time_metrics(trades, boundaries_hours=[24.0, 1.0])
+        with pytest.raises(InsufficientDataError):
+            calculate_holding_time_metrics([trades[-1]])
+
+    def test_incremental_metrics(self):
+        """Test that running metrics match the batch calculation trade by trade"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.0980, -20.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.0970, -30.0, None, None),
+            Trade("EURUSD", "Sell", 1.0, 1.1000, 1.0950, 50.0, None, None),
+        ]
+
+        running = IncrementalMetrics()
+        assert running.kelly_fraction() is None
+        for trade in trades[:3]:
+            running.add_trade(trade)
+        running.add_trades(trades[3:])
+
+        batch = calculate_performance_metrics(trades)
+        assert running.total_trades == 4
+        assert running.win_probability == pytest.approx(batch.win_probability)
+        assert running.expectancy == pytest.approx(batch.expectancy)
+        assert running.profit_factor == pytest.approx(batch.profit_factor)
+        assert running.max_drawdown == pytest.approx(batch.max_drawdown)
+        assert running.current_drawdown == 0.0
+        # 0.5 - 0.5 / (50 / 25), from the average rather than median win/loss ratio
+        assert running.kelly_fraction() == pytest.approx(0.25)
+        assert running.kelly_fraction(0.5) == pytest.approx(0.125)
+        assert running.to_metrics().expectancy == pytest.approx(batch.expectancy)
+        assert IncrementalMetrics.from_json(running.to_json()) == running
+
+    def test_live_risk_monitor(self):
+        """Test live ingestion of MT5 deals through a polled source with change events"""
+        from collections import namedtuple
+
+        # Shaped like the records of MetaTrader5.history_deals_get
+        TradeDeal = namedtuple(
+            "TradeDeal", "ticket time time_msc type entry volume price commission swap profit fee symbol"
+        )
+        deal = lambda ticket, kind, entry, price, profit: TradeDeal(
+            ticket, 1704067200 + ticket, (1704067200 + ticket) * 1000, kind, entry, 1.0, price, -3.0, 0.0, profit, -1.0,
+            "EURUSD",
+        )
+        batches = [
+            [TradeDeal(1, 1704067200, 1704067200000, 2, 0, 0.0, 0.0, 0.0, 0.0, 10000.0, 0.0, "")],  # Deposit
+            [deal(2, 0, 0, 1.1000, 0.0), deal(3, 1, 1, 1.1050, 500.0)],
+            [deal(3, 1, 1, 1.1050, 500.0), deal(4, 1, 0, 1.1050, 0.0), deal(5, 0, 1, 1.1080, -300.0)],
+            None,
+            [deal(6, 0, 0, 1.1000, 0.0), deal(7, 1, 1, 1.1010, 100.0)],
+        ]
+        seen = []
+        monitor = LiveRiskMonitor(
+            lambda: batches.pop(0), initial_balance=10000.0, change_threshold=0.01,
+            on_change=lambda event: seen.append((event, monitor.recommended_risk_fraction)),
+        )
+        assert monitor.poll() == [] and monitor.poll() == []
+        assert monitor.recommended_risk_fraction is None
+
+        # The repeated ticket 3 is booked once; the first recommendation raises an event
+        events = monitor.poll()
+        assert monitor.metrics.total_trades == 2
+        assert monitor.metrics.gross_profit == 500.0 and monitor.metrics.gross_loss == 300.0
+        expected = monitor.metrics.kelly_fraction()
+        assert [(e.previous, e.risk_fraction, e.total_trades) for e in events] == [(None, expected, 2)]
+        assert seen == [(events[0], expected)]
+
+        # A small win moves the recommendation by more than the threshold
+        changes = monitor.run(interval=0.0, max_polls=2)
+        assert monitor.metrics.total_trades == 3
+        assert len(changes) == 1 and changes[0].previous == expected
+        assert len(seen) == 2 and seen[1][0] == changes[0]
+        assert seen[1][0].risk_fraction == pytest.approx(monitor.metrics.kelly_fraction())
+
+        # A batch with an unreadable record is rejected whole, so retrying it loses no event
+        batch = [deal(8, 0, 0, 1.1000, 0.0), deal(9, 1, 1, 1.0950, -500.0)]
+        with pytest.raises(ParseError, match="Expected a Trade"):
+            monitor.ingest([batch[0], "not a deal", batch[1]])
+        assert monitor.metrics.total_trades == 3 and len(seen) == 2
+        changes = monitor.ingest(batch)
+        assert monitor.metrics.total_trades == 4
+        assert len(changes) == 1 and seen[2][0] == changes[0]
+
+        # Closed trades from overlapping windows are booked once per ticket and close time
+        trade = Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 200.0, None, None, close_time=1704070000000, ticket=42)
+        monitor.ingest([trade])
+        monitor.ingest([trade, Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 200.0, None, None)])
+        assert monitor.metrics.total_trades == 6
+
+    def test_rolling_metrics(self):
+        """Test metrics and Kelly over a sliding window of trades"""
+        profits = [50.0, -25.0, 50.0, -25.0, -25.0, -25.0, 50.0, -25.0]
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=1_700_000_000_000 + i * 60_000)
+            for i, p in enumerate(profits)
+        ]
+
+        rolling = rolling_metrics(list(reversed(trades)), window=4, step=2)
+
+        assert rolling.end_indices == [3, 5, 7]
+        assert rolling.close_times == [trades[i].close_time for i in (3, 5, 7)]
+        assert [m.total_trades for m in rolling.metrics] == [4, 4, 4]
+        assert [m.win_probability for m in rolling.metrics] == [0.5, 0.25, 0.25]
+        # 0.5 - 0.5 / 2, then the edge decays to 0.25 - 0.75 / 2
+        assert rolling.kelly_fraction == pytest.approx([0.25, -0.125, -0.125])
+
+        with pytest.raises(ValueError):
+            rolling_metrics(trades, window=9)
+        with pytest.raises(ValueError):
+            rolling_metrics(trades, window=4, step=0)
+
+    def test_aggregate_pnl(self):
+        """Test day, week and month P&L buckets"""
+        jan_1 = 1_704_067_200_000  # Monday 2024-01-01 00:00 UTC
+        hour, day = 3_600_000, 86_400_000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=t)
+            for p, t in [
+                (100.0, jan_1 + 10 * hour),
+                (-50.0, jan_1 + 12 * hour),
+                (-30.0, jan_1 + 14 * hour),
+                (40.0, jan_1 + 2 * day),
+                (-10.0, jan_1 + 7 * day),
+                (25.0, jan_1 + 35 * day),
+            ]
+        ]
+
+        days = aggregate_pnl(list(reversed(trades)), PnlPeriod.Day)
+        assert [d.label for d in days] == ["2024-01-01", "2024-01-03", "2024-01-08", "2024-02-05"]
+        assert days[0].start_time == jan_1
+        assert days[0].profit == pytest.approx(20.0)
+        assert days[0].trades == 3
+        assert days[0].win_rate == pytest.approx(1 / 3)
+        assert days[0].max_drawdown == pytest.approx(80.0)
+
+        weeks = aggregate_pnl(trades, PnlPeriod.Week)
+        assert [(w.label, w.profit, w.trades) for w in weeks] == [
+            ("2024-W01", 60.0, 4), ("2024-W02", -10.0, 1), ("2024-W06", 25.0, 1)
+        ]
+
+        months = aggregate_pnl(trades, PnlPeriod.Month)
+        assert [m.label for m in months] == ["2024-01", "2024-02"]
+        assert months[0].win_rate == pytest.approx(0.4)
+        assert months[1].max_drawdown == 0.0
+
+        with pytest.raises(InsufficientDataError):
+            aggregate_pnl([Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)], PnlPeriod.Day)
+
+    def test_analyze_time_buckets(self):
+        """Test win rate and expectancy by hour of day and day of week"""
+        jan_1 = 1_704_067_200_000  # Monday 2024-01-01 00:00 UTC
+        hour, day = 3_600_000, 86_400_000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, open_time=o, close_time=c)
+            for p, o, c in [
+                (100.0, jan_1 + 8 * hour, jan_1 + 9 * hour),
+                (-50.0, jan_1 + 8 * hour + day, jan_1 + 10 * hour + day),
+                (60.0, jan_1 + 14 * hour + day, jan_1 + 15 * hour + day),
+                (-20.0, None, jan_1 + 23 * hour + 4 * day),  # Only the close time is known
+            ]
+        ]
+        trades.append(Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 999.0, None, None))
+
+        buckets = analyze_time_buckets(trades)
+
+        assert buckets.trades_analyzed == 4
+        assert [(b.label, b.trades) for b in buckets.by_hour] == [("08:00", 2), ("14:00", 1), ("23:00", 1)]
+        assert buckets.by_hour[0].win_rate == 0.5
+        assert buckets.by_hour[0].expectancy == pytest.approx(25.0)
+        assert [(b.label, b.index, b.profit) for b in buckets.by_weekday] == [
+            ("Monday", 0, 100.0), ("Tuesday", 1, 10.0), ("Friday", 4, -20.0)
+        ]
+
+        # Two hours ahead of UTC, the Friday 23:00 close falls on Saturday
+        shifted = analyze_time_buckets(trades, utc_offset_hours=2.0)
+        assert [b.label for b in shifted.by_hour] == ["01:00", "10:00", "16:00"]
+        assert shifted.by_weekday[-1].label == "Saturday"
+
+        with pytest.raises(InsufficientDataError):
+            analyze_time_buckets([trades[-1]])
+
+    def test_exchange_rates(self):
+        """Test converting EUR trades to a USD challenge account"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 100.0, -2.0, 0.0, close_time=1704067200000),
+            Trade("EURUSD", "Sell", 1.0, 1.1, 1.105, -50.0, None, -1.0, close_time=1706745600000,
+                  risk_amount=50.0),
+        ]
+        rates = ExchangeRates("EUR", "USD", [1.10, 1.08], times=[1704067200000, 1706745600000])
+
+        converted = rates.convert(trades)
+        assert converted[0].profit == pytest.approx(110.0)
+        assert converted[0].commission == pytest.approx(-2.2)
+        assert converted[1].profit == pytest.approx(-54.0)
+        assert converted[1].risk_amount == pytest.approx(54.0)
+        assert converted[1].commission is None
+        assert converted[0].close_price == 1.105
+        # Before the first rate the first rate applies
+        assert rates.rate_at(0) == 1.10
+
+        metrics = calculate_performance_metrics(trades, exchange_rates=rates)
+        assert metrics.avg_win == pytest.approx(110.0)
+
+        fixed = ExchangeRates("EUR", "USD", [1.1])
+        assert fixed.rate_at() == 1.1
+        assert ExchangeRates.from_json(fixed.to_json()) == fixed
+
+        with pytest.raises(ValidationError, match="close time"):
+            rates.convert([Trade("EURUSD", "Buy", 1.0, 1.1, 1.105, 100.0, None, None)])
+        with pytest.raises(ValidationError, match="ascending"):
+            ExchangeRates("EUR", "USD", [1.1, 1.2], times=[2, 1])
+        with pytest.raises(ValidationError, match="positive"):
+            ExchangeRates("EUR", "USD", [0.0])
+
+
+class TestKellyCriterion:
+    """Test Kelly Criterion calculations"""
+
+    def test_kelly_criterion_basic(self):
+        """Test basic Kelly Criterion calculation"""
+        win_prob = 0.55
+        win_loss_ratio = 1.25
+        fractional_multiplier = 1.0
+
+        kelly_fraction = calculate_kelly_criterion(win_prob, win_loss_ratio, fractional_multiplier)
+
+        expected = win_prob - ((1.0 - win_prob) / win_loss_ratio)
+        assert abs(kelly_fraction - expected) < 1e-6
+
+    def test_kelly_criterion_fractional(self):
+        """Test fractional Kelly Criterion"""
+        win_prob = 0.55
+        win_loss_ratio = 1.25
+        fractional_multiplier = 0.5  # Half Kelly
+
+        kelly_fraction = calculate_kelly_criterion(win_prob, win_loss_ratio, fractional_multiplier)
+
+        full_kelly = win_prob - ((1.0 - win_prob) / win_loss_ratio)
+        expected = full_kelly * fractional_multiplier
+        assert abs(kelly_fraction - expected) < 1e-6
+
+    def test_kelly_criterion_edge_cases(self):
+        """Test Kelly Criterion edge cases"""
+        # Win probability = 1.0 (always win)
+        with pytest.raises(Exception):
+            calculate_kelly_criterion(1.0, 1.25, 1.0)
+
+        # Win probability = 0.0 (never win)
+        with pytest.raises(Exception):
+            calculate_kelly_criterion(0.0, 1.25, 1.0)
+
+        # Win/loss ratio = 0 (undefined)
+        with pytest.raises(Exception):
+            calculate_kelly_criterion(0.55, 0.0, 1.0)
+
+    def test_kelly_from_trades(self):
+        """Test binary and continuous Kelly computed from a trade list"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 100.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 100.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 100.0, None, None),
+            Trade("EURUSD", "Sell", 1.0, 1.1000, 1.1050, -50.0, None, None),
+            Trade("EURUSD", "Sell", 1.0, 1.1000, 1.1050, -50.0, None, None),
+        ]
+
+        result = calculate_kelly_from_trades(trades, 0.5)
+
+        assert result.win_probability == pytest.approx(0.6)
+        assert result.win_loss_ratio == pytest.approx(2.0)
+        assert result.kelly_fraction == pytest.approx((0.6 - 0.4 / 2.0) * 0.5)
+
+        returns = [2.0, 2.0, 2.0, -1.0, -1.0]
+        mean = sum(returns) / 5
+        variance = sum((r - mean) ** 2 for r in returns) / 4
+        assert result.mean_return == pytest.approx(mean)
+        assert result.return_variance == pytest.approx(variance)
+        assert result.continuous_kelly_fraction == pytest.approx(mean / variance * 0.5)
+
+    def test_kelly_from_trades_without_losses(self):
+        """Test that Kelly from trades rejects a history without losses"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, None, None)] * 3
+        with pytest.raises(Exception):
+            calculate_kelly_from_trades(trades)
+
+    def test_kelly_portfolio_uncorrelated(self):
+        """Test that uncorrelated assets get their single-asset mean/variance Kelly"""
+        returns = {
+            "EURUSD": [0.02, -0.01, 0.02, -0.01],
+            "XAUUSD": [0.01, 0.01, -0.005, -0.005],
+        }
+
+        fractions = calculate_kelly_portfolio(returns, 1.0)
+
+        for symbol, series in returns.items():
+            mean = sum(series) / len(series)
+            variance = sum((r - mean) ** 2 for r in series) / (len(series) - 1)
+            assert fractions[symbol] == pytest.approx(mean / variance)
+
+    def test_kelly_portfolio_correlated(self):
+        """Test that correlated assets are allocated less than naive per-symbol Kelly"""
+        eurusd = [0.02, -0.01, 0.015, -0.012, 0.018, -0.008]
+        gbpusd = [0.019, -0.009, 0.014, -0.011, 0.02, -0.01]
+
+        fractions = calculate_kelly_portfolio({"EURUSD": eurusd, "GBPUSD": gbpusd}, 0.5)
+        naive = calculate_kelly_portfolio({"EURUSD": eurusd}, 0.5)["EURUSD"] + \
+            calculate_kelly_portfolio({"GBPUSD": gbpusd}, 0.5)["GBPUSD"]
+
+        assert fractions["EURUSD"] + fractions["GBPUSD"] < naive
+
+    def test_kelly_portfolio_invalid(self):
+        """Test portfolio Kelly input validation"""
+        with pytest.raises(ValueError):
+            calculate_kelly_portfolio({"A": [0.01, 0.02], "B": [0.01]})
+        with pytest.raises(ValueError):
+            calculate_kelly_portfolio({"A": [0.01, 0.02, -0.01], "B": [0.01, 0.02, -0.01]})
+
+    def test_kelly_portfolio_with_margin(self):
+        """Test re-solving portfolio Kelly leverage within the account's margin"""
+        returns = {
+            "EURUSD": [0.02, -0.01, 0.02, -0.01],
+            "XAUUSD": [0.01, 0.01, -0.005, -0.005],
+        }
+        specs = SymbolRegistry([
+            SymbolSpec("EURUSD", 100000.0, 0.00001, leverage=100.0),
+            SymbolSpec("XAUUSD", 100.0, 0.01, leverage=100.0),
+        ])
+
+        # Kelly leverage of about 16.7 and 33.3 needs half of equity as margin at 1:100
+        free = calculate_kelly_portfolio_with_margin(returns, specs)
+        assert not free.constrained
+        assert free.leverage == free.unconstrained_leverage
+        assert free.leverage["EURUSD"] == pytest.approx(calculate_kelly_portfolio(returns)["EURUSD"])
+        assert free.total_margin_fraction == pytest.approx(0.5)
+
+        # Under a 20% budget the low-variance gold position gives up the most leverage
+        tight = calculate_kelly_portfolio_with_margin(returns, specs, max_margin_fraction=0.2)
+        assert tight.constrained
+        assert tight.total_margin_fraction == pytest.approx(0.2)
+        assert sum(tight.margin_fraction.values()) == pytest.approx(0.2)
+        assert tight.leverage["EURUSD"] == pytest.approx(32.0 / 3.0)
+        assert tight.leverage["XAUUSD"] == pytest.approx(28.0 / 3.0)
+
+        # Half Kelly fits the same budget without re-solving
+        half = calculate_kelly_portfolio_with_margin(returns, specs, 0.5, max_margin_fraction=0.25)
+        assert not half.constrained
+        assert half.total_margin_fraction == pytest.approx(0.25)
+
+        with pytest.raises(ValueError, match="XAUUSD"):
+            calculate_kelly_portfolio_with_margin(returns, SymbolRegistry([specs.get("EURUSD")]))
+        with pytest.raises(ValueError):
+            calculate_kelly_portfolio_with_margin(returns, specs, max_margin_fraction=0.0)
+
+    def test_symbol_correlation(self):
+        """Test the daily P&L correlation matrix between traded symbols"""
+        jan_1 = 1_704_067_200_000  # Monday 2024-01-01 00:00 UTC
+        hour, day = 3_600_000, 86_400_000
+        daily = {
+            "EURUSD": [10.0, -5.0, 8.0, -3.0, 6.0],
+            "GBPUSD": [20.0, -10.0, 16.0, -6.0, 12.0],
+            "XAUUSD": [5.0, 5.0, -5.0, -5.0, 0.0],
+        }
+        trades = [
+            Trade(symbol, "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=jan_1 + d * day + 12 * hour)
+            for symbol, profits in daily.items()
+            for d, p in enumerate(profits)
+        ]
+        trades.append(Trade("XAUUSD", "Buy", 1.0, 1.1, 1.2, 4.0, None, None, close_time=jan_1 + 7 * day))
+
+        correlation = calculate_symbol_correlation(trades, initial_balance=1000.0)
+
+        # The weekend in between is skipped; next Monday is flat for the EUR pairs
+        assert correlation.days == 6
+        assert correlation.symbols == ["EURUSD", "GBPUSD", "XAUUSD"]
+        assert correlation.daily_returns["EURUSD"] == pytest.approx([0.01, -0.005, 0.008, -0.003, 0.006, 0.0])
+        matrix = correlation.matrix
+        assert [matrix[i][i] for i in range(3)] == [1.0, 1.0, 1.0]
+        assert matrix[0][1] == pytest.approx(1.0)
+        assert matrix[0][2] == matrix[2][0]
+        assert abs(matrix[0][2]) < 0.7
+        assert [(a, b) for a, b, _ in correlation.correlated_pairs] == [("EURUSD", "GBPUSD")]
+
+        # The aligned series plug straight into portfolio Kelly
+        returns = {s: correlation.daily_returns[s] for s in ("EURUSD", "XAUUSD")}
+        assert set(calculate_kelly_portfolio(returns)) == {"EURUSD", "XAUUSD"}
+
+        with pytest.raises(InsufficientDataError):
+            calculate_symbol_correlation([Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)])
+
+    def test_kelly_confidence_distribution(self):
+        """Test bootstrap distribution of the Kelly fraction"""
+        profits = [120.0, -60.0, 80.0, -50.0, 150.0, -70.0, 90.0, 60.0, -40.0, 110.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        dist = calculate_kelly_confidence(trades, iterations=2000, seed=42)
+
+        assert dist.iterations == 2000
+        assert 0 < dist.valid_samples <= 2000
+        assert dist.percentile_5 <= dist.conservative_kelly <= dist.median <= dist.percentile_95
+        assert dist.percentile_5 < dist.point_estimate < dist.percentile_95
+
+    def test_kelly_confidence_seeded(self):
+        """Test that a seed makes the bootstrap reproducible"""
+        profits = [120.0, -60.0, 80.0, -50.0, 150.0, -70.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        first = calculate_kelly_confidence(trades, 500, 7, 0.5)
+        second = calculate_kelly_confidence(trades, 500, 7, 0.5)
+
+        assert first.mean == second.mean
+        assert first.conservative_kelly == second.conservative_kelly
+
+    def test_kelly_empirical(self):
+        """Test the log-optimal fraction over the empirical return distribution"""
+        # With binary outcomes the numerical optimum equals the closed form
+        binary = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [100.0] * 6 + [-100.0] * 4]
+        assert calculate_kelly_empirical(binary) == pytest.approx(0.2, abs=1e-6)
+        assert calculate_kelly_empirical(binary, 0.5) == pytest.approx(0.1, abs=1e-6)
+
+        # A fat left tail (measured in R) pulls the fraction well below the
+        # closed form with the same win rate and average win/loss
+        profits = [150.0] * 6 + [-100.0] * 3 + [-400.0]
+        fat_tail = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, risk_amount=100.0) for p in profits]
+        closed_form = calculate_kelly_criterion(0.6, 1.5 / 1.75, 1.0)
+        assert 0.0 < calculate_kelly_empirical(fat_tail) < closed_form / 2
+
+        # Without an edge nothing should be risked
+        losing = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [100.0, -150.0]]
+        assert calculate_kelly_empirical(losing) == 0.0
+
+        with pytest.raises(ValueError):
+            calculate_kelly_empirical(binary[:6])
+
+    def test_kelly_bayesian(self):
+        """Test shrinking the win rate towards a Beta prior before sizing"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [100.0] * 4 + [-100.0]]
+
+        # Uniform prior: Beta(1 + 4, 1 + 1)
+        result = calculate_kelly_bayesian(trades)
+        assert result.win_probability == pytest.approx(0.8)
+        assert (result.posterior_alpha, result.posterior_beta) == (5.0, 2.0)
+        assert result.posterior_win_probability == pytest.approx(5 / 7)
+        assert result.raw_kelly_fraction == pytest.approx(0.6)
+        assert result.kelly_fraction == pytest.approx(3 / 7)
+        # The Beta(5, 2) CDF is 6x^5 - 5x^6
+        lower, upper = result.credible_interval
+        assert 6 * lower**5 - 5 * lower**6 == pytest.approx(0.05, abs=1e-6)
+        assert 6 * upper**5 - 5 * upper**6 == pytest.approx(0.95, abs=1e-6)
+        assert result.conservative_kelly_fraction == pytest.approx(2 * lower - 1)
+
+        metrics = calculate_performance_metrics(trades, prior_win_rate=0.4, prior_strength=10.0)
+        assert metrics.win_probability == pytest.approx(0.8)
+        assert metrics.bayesian_win_probability == pytest.approx(8 / 15)
+
+        with pytest.raises(ValueError):
+            calculate_kelly_bayesian(trades[:4])
+        with pytest.raises(ValueError):
+            calculate_kelly_bayesian(trades, prior_win_rate=1.0)
+
+    def test_walk_forward_kelly(self):
+        """Test refitting Kelly in sample and trading it out of sample"""
+        profits = [100.0, 100.0, -100.0, 100.0] * 2 + [-100.0, -100.0, 100.0, -100.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        result = walk_forward_kelly(trades, train_window=4, test_window=4)
+
+        assert [(f.train_start, f.test_start, f.test_end) for f in result.folds] == [(0, 4, 8), (4, 8, 12)]
+        # Both training windows win 3 of 4 at 1:1, so Kelly is 0.75 - 0.25 = 0.5
+        assert [f.kelly_fraction for f in result.folds] == pytest.approx([0.5, 0.5])
+        edge = (3 * math.log(1.5) + math.log(0.5)) / 4
+        assert result.in_sample_growth == pytest.approx(edge)
+        assert result.folds[0].out_of_sample_growth == pytest.approx(edge)
+        # The edge disappears in the last window
+        assert result.folds[1].out_of_sample_growth == pytest.approx((math.log(1.5) + 3 * math.log(0.5)) / 4)
+        assert result.efficiency < 0
+        assert result.out_of_sample_twr == pytest.approx(1.5**3 * 0.5 * 1.5 * 0.5**3)
+        assert result.folds[1].out_of_sample_drawdown_percent == pytest.approx(81.25)
+
+        assert walk_forward_kelly(trades, 4, 4, 0.5).folds[0].kelly_fraction == pytest.approx(0.25)
+        with pytest.raises(ValueError):
+            walk_forward_kelly(trades, train_window=10, test_window=4)
+
+    def test_kelly_by_tag(self):
+        """Test per-tag Kelly and the frequency-weighted blend"""
+        def tagged(profits, tag):
+            return [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, tag=tag) for p in profits]
+
+        # breakout: 0.6 - 0.4 / 2 = 0.4; range: 0.25 - 0.75 / 1 < 0; plus two untagged trades
+        trades = (
+            tagged([200.0] * 3 + [-100.0] * 2, "breakout")
+            + tagged([100.0, -100.0, -100.0, -100.0], "range")
+            + tagged([50.0, -50.0], None)
+        )
+
+        result = calculate_kelly_by_tag(trades)
+
+        assert set(result.tags) == {"breakout", "range", "untagged"}
+        breakout = result.tags["breakout"]
+        assert breakout.metrics.total_trades == 5
+        assert breakout.frequency == pytest.approx(5 / 11)
+        assert breakout.kelly_fraction == pytest.approx(0.4)
+        assert result.tags["range"].kelly_fraction == pytest.approx(-0.5)
+        # Only breakout has an edge; untagged breaks even with Kelly 0
+        assert result.blended_kelly_fraction == pytest.approx(5 / 11 * 0.4)
+        assert calculate_kelly_by_tag(trades, 0.5).blended_kelly_fraction == pytest.approx(5 / 11 * 0.2)
+
+        assert Trade.from_dict(trades[0].to_dict()).tag == "breakout"
+
+
+class TestOptimalF:
+    """Test Optimal F calculations"""
+
+    def test_optimal_f_basic(self):
+        """Test basic Optimal F calculation"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
             Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -50.0, -2.0, 0.0),
+            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, 50.0, -2.0, 0.0),
         ]
 
+        result = calculate_optimal_f(trades, 1000, 1e-6)
+
+        assert result.optimal_f >= 0.0  # Should be non-negative
+        assert result.largest_loss == 50.0
+        # Two +1 and one -1 holding periods: TWR = (1 + f)^2 (1 - f) peaks at f = 1/3
+        assert result.optimal_f == pytest.approx(1.0 / 3.0, abs=1e-5)
+        assert result.twr == pytest.approx((4.0 / 3.0) ** 2 * (2.0 / 3.0))
+        assert result.geometric_mean == pytest.approx(result.twr ** (1.0 / 3.0))
+
+    def test_optimal_f_search_range(self):
+        """Test optimal f above 10% and a restricted search range"""
+        profits = [300.0, -100.0, 250.0, -100.0, 200.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        full = calculate_optimal_f(trades)
+        assert full.optimal_f > 0.1
+
+        capped = calculate_optimal_f(trades, f_max=0.1)
+        assert capped.optimal_f == pytest.approx(0.1, abs=1e-5)
+        assert capped.twr < full.twr
+
+        # The grid search agrees with the golden-section search
+        grid = calculate_optimal_f(trades, max_iterations=10001, golden_section=False)
+        assert grid.optimal_f == pytest.approx(full.optimal_f, abs=1e-3)
+
+        with pytest.raises(ValueError):
+            calculate_optimal_f(trades, f_min=0.5, f_max=0.2)
+
+    def test_secure_f(self):
+        """Test the largest f whose simulated drawdown stays under a cap"""
+        profits = [300.0, -100.0, 250.0, -100.0, 200.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        result = calculate_secure_f(trades, 20.0, num_simulations=500, seed=5)
+
+        assert 0.0 < result.secure_f < result.optimal_f
+        assert result.drawdown_percent <= 20.0
+        assert result.drawdown_percent == pytest.approx(20.0, abs=0.1)
+        # The actual sequence never has two losses in a row, one loss costs f
+        assert result.historical_drawdown_percent == pytest.approx(result.secure_f * 100.0)
+        assert result.secure_f < 0.2
+
+        again = calculate_secure_f(trades, 20.0, num_simulations=500, seed=5)
+        assert again.secure_f == result.secure_f
+
+        # A loose enough cap leaves the optimal f untouched
+        loose = calculate_secure_f(trades, 99.9, num_simulations=100, seed=5)
+        assert loose.secure_f == loose.optimal_f
+
+    def test_kelly_tradeoff(self):
+        """Test growth against drawdown across fractions of full Kelly"""
+        profits = [120.0, -100.0, 80.0, -100.0, 150.0, -100.0, 90.0, 110.0, -100.0, 60.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        table = calculate_kelly_tradeoff(trades, num_simulations=2000, horizon_trades=100, seed=3)
+
+        assert [row.multiplier for row in table.rows] == pytest.approx([i / 10 for i in range(1, 16)])
+        assert table.horizon_trades == 100
+        full = table.rows[9]
+        half = table.rows[4]
+        assert full.fraction == pytest.approx(table.full_kelly_fraction)
+        assert full.relative_growth == pytest.approx(1.0)
+        assert max(row.growth_rate for row in table.rows) == full.growth_rate
+        # Half Kelly keeps about three quarters of the growth for far less drawdown
+        assert half.relative_growth == pytest.approx(0.75, abs=0.05)
+        assert half.median_max_drawdown_percent < full.median_max_drawdown_percent / 1.5
+        drawdowns = [row.median_max_drawdown_percent for row in table.rows]
+        assert drawdowns == sorted(drawdowns)
+        assert table.rows[-1].drawdown_probability >= full.drawdown_probability >= half.drawdown_probability
+
+        again = calculate_kelly_tradeoff(trades, [0.5], num_simulations=2000, horizon_trades=100, seed=3)
+        assert again.rows[0] == half
+
+        with pytest.raises(ValidationError):
+            calculate_kelly_tradeoff(trades, [0.0])
+        with pytest.raises(InsufficientDataError):
+            calculate_kelly_tradeoff([t for t in trades if t.profit > 0])
+
+    def test_kelly_with_drawdown_constraint(self):
+        """Test the largest risk fraction that respects a drawdown cap"""
+        profits = [120.0, -100.0, 80.0, -100.0, 150.0, -100.0, 90.0, 110.0, -100.0, 60.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        result = kelly_with_drawdown_constraint(trades, 10.0, confidence=0.95, num_simulations=1000,
+                                                horizon_trades=100, seed=2)
+
+        assert 0.0 < result.risk_fraction < result.full_kelly_fraction
+        assert result.drawdown_percent == pytest.approx(10.0, abs=0.1)
+        assert result.kelly_multiplier == pytest.approx(result.risk_fraction / result.full_kelly_fraction)
+        # A lower confidence tolerates more risk
+        relaxed = kelly_with_drawdown_constraint(trades, 10.0, confidence=0.5, num_simulations=1000,
+                                                 horizon_trades=100, seed=2)
+        assert relaxed.risk_fraction > result.risk_fraction
+        # A loose enough cap leaves full Kelly untouched
+        loose = kelly_with_drawdown_constraint(trades, 99.9, num_simulations=100, seed=2)
+        assert loose.risk_fraction == loose.full_kelly_fraction
+
+        with pytest.raises(ValidationError):
+            kelly_with_drawdown_constraint(trades, 10.0, confidence=1.5)
+
+    def test_optimal_f_no_losses(self):
+        """Test Optimal F with no losses"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
+            Trade("GBPUSD", "Buy", 1.0, 1.3000, 1.3050, 50.0, -2.0, 0.0),
+        ]
+
+        result = calculate_optimal_f(trades, 1000, 1e-6)
+
+        assert result.optimal_f == 0.0  # No losses means no risk management needed
+        assert result.twr == 1.0
+
+    def test_optimal_f_empty_trades(self):
+        """Test Optimal F with empty trades"""
+        with pytest.raises(Exception):
+            calculate_optimal_f([], 1000, 1e-6)
+
+
+class TestPositionSizing:
+    """Test converting risk fractions into lot sizes"""
+
+    def test_position_sizer(self):
+        """Test lot sizing with lot step, minimum and maximum lot"""
+        # EURUSD: 1 point = 0.00001, worth $1 per standard lot
+        sizer = PositionSizer()
+        assert sizer.tick_value == pytest.approx(1.0)
+        assert sizer.risk_per_lot(200) == pytest.approx(200.0)
+
+        size = sizer.size(10000.0, 0.01, 300)
+        assert size.raw_lots == pytest.approx(1 / 3)
+        assert size.lots == pytest.approx(0.33)
+        assert size.limited_by == "lot_step"
+        assert size.risk_amount == pytest.approx(99.0)
+        assert size.risk_fraction == pytest.approx(0.0099)
+
+        exact = sizer.size(10000.0, 0.01, 100)
+        assert exact.lots == pytest.approx(1.0)
+        assert exact.limited_by is None
+
+        tiny = sizer.size(100.0, 0.01, 500)
+        assert tiny.lots == 0.0
+        assert tiny.limited_by == "min_lot"
+
+        capped = PositionSizer(max_lot=2.0).size(1_000_000.0, 0.02, 100)
+        assert capped.lots == 2.0
+        assert capped.limited_by == "max_lot"
+
+    def test_position_sizer_validation(self):
+        """Test invalid sizer parameters and stops"""
+        with pytest.raises(ValueError):
+            PositionSizer(lot_step=0.0)
+        with pytest.raises(ValueError):
+            PositionSizer(min_lot=1.0, max_lot=0.5)
+        with pytest.raises(ValueError):
+            PositionSizer().size(10000.0, 0.01, 0)
+
+
+    def test_kelly_sizing_ladder(self):
+        """Test the sizing card of risk per trade across balances and multiples of Kelly"""
+        ladder = kelly_sizing_ladder(0.04, [10000.0, 50000.0], sizer=PositionSizer(), stop_loss_points=200)
+        assert ladder.kelly_multipliers == [0.25, 0.5, 1.0]
+        assert len(ladder.rows) == 6
+        # $1 a point per lot, so 200 points risk $200 a lot
+        assert [row.risk_amount for row in ladder.rows] == pytest.approx([100, 200, 400, 500, 1000, 2000])
+        assert [row.lots for row in ladder.rows] == pytest.approx([0.5, 1.0, 2.0, 2.5, 5.0, 10.0])
+        assert ladder.rows[4].account_balance == 50000.0 and ladder.rows[4].kelly_multiplier == 0.5
+
+        card = ladder.to_markdown().splitlines()
+        assert card[0] == "| Balance | 0.25× Kelly | 0.5× Kelly | 1× Kelly |"
+        assert card[2] == "| 10000.00 | 100.00 (0.50 lots) | 200.00 (1.00 lots) | 400.00 (2.00 lots) |"
+
+        # Without a sizer only the money at risk is listed
+        plain = kelly_sizing_ladder(0.02, [100.0], kelly_multipliers=[1.0])
+        assert plain.rows[0].risk_amount == pytest.approx(2.0) and plain.rows[0].lots is None
+        # Too small an account for the minimum lot
+        tiny = kelly_sizing_ladder(0.02, [100.0], sizer=PositionSizer(), stop_loss_points=500)
+        assert tiny.rows[0].lots == 0.0 and tiny.rows[0].limited_by == "min_lot"
+
+        with pytest.raises(ValidationError, match="no edge"):
+            kelly_sizing_ladder(-0.01, [10000.0])
+        with pytest.raises(ValidationError, match="stop-loss distance"):
+            kelly_sizing_ladder(0.02, [10000.0], sizer=PositionSizer())
+
+    def test_symbol_registry(self, tmp_path):
+        """Test loading symbol specifications and sizing from them"""
+        json_file = tmp_path / "symbols.json"
+        json_file.write_text(
+            '[{"symbol": "XAUUSD", "contract_size": 100, "tick_size": 0.01, "leverage": 50, "lot_step": 0.1}]'
+        )
+        registry = SymbolRegistry.load(str(json_file))
+        assert registry.symbols() == ["XAUUSD"]
+        gold = registry.get("XAUUSD")
+        assert gold.tick_value == pytest.approx(1.0)
+        assert gold.currency == "USD"
+        assert gold.margin_per_lot(2000.0) == pytest.approx(4000.0)
+        assert gold.profit_per_lot("Sell", 2000.0, 1990.0) == pytest.approx(1000.0)
+
+        # $100 at a 500 point stop is 0.2 lots, on gold's 0.1 lot step
+        size = registry.sizer("XAUUSD").size(10000.0, 0.01, 500)
+        assert size.lots == pytest.approx(0.2)
+
+        csv_file = tmp_path / "symbols.csv"
+        csv_file.write_text(
+            "symbol,contract_size,tick_size,tick_value,currency,leverage\n"
+            "EURUSD,100000,0.00001,,USD,30\n"
+            "US30,1,1,1,,20\n"
+        )
+        registry = SymbolRegistry.load(str(csv_file))
+        assert len(registry) == 2
+        assert "US30" in registry
+        assert registry.get("EURUSD").tick_value == pytest.approx(1.0)
+        assert registry.get("GBPUSD") is None
+
+        with pytest.raises(ValueError, match="No symbol specification"):
+            registry.sizer("GBPUSD")
+        with pytest.raises(ValueError):
+            SymbolRegistry.from_json('[{"symbol": "BAD", "contract_size": 0, "tick_size": 1}]')
+
+    def test_monte_carlo_with_symbol_specs(self):
+        """Test that symbol specs revalue trades from their prices, not their profit"""
+        registry = SymbolRegistry([SymbolSpec("EURUSD", 100000, 0.00001, leverage=100)])
+        # 2 pips ($20) per lot on $1000 of margin is a 2% return on the margin posted;
+        # the recorded profits are in the wrong units for the engine
+        trades = [Trade("EURUSD", "Buy", 2.0, 1.0, 1.0002, 400.0, None, None)] * 5
+        params = ChallengeParams(100000, 8.0, 5.0, 10.0, 0)
+
+        result = run_monte_carlo_detailed(trades, params, 1.0, 50, seed=1, symbol_specs=registry)
+        assert result.pass_rate == 1.0
+        assert result.final_equity_percentile(50) == pytest.approx(100000 * 1.02**4)
+
+        with pytest.raises(ValueError, match="No symbol specification"):
+            run_monte_carlo_simulation(
+                [Trade("GBPUSD", "Buy", 1.0, 1.3, 1.31, 0.0, None, None)], params, 0.1, 10, symbol_specs=registry
+            )
+
+
+class TestMonteCarloSimulation:
+    """Test Monte Carlo simulation functions"""
+
+    def test_monte_carlo_basic(self):
+        """Test basic Monte Carlo simulation"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 50.0, -2.0, 0.0),
+            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -50.0, -2.0, 0.0),
+        ]
+
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=30,
+        )
+
+        results = run_monte_carlo_simulation(trades, challenge_params, 0.01, 100)
+
+        assert isinstance(results, dict)
+        assert "pass_rate" in results
+        assert "total_simulations" in results
+        assert "passed_simulations" in results
+        assert results["total_simulations"] == 100
+        assert 0.0 <= results["pass_rate"] <= 1.0
+
+    def test_monte_carlo_seeded(self):
+        """Test that a seed makes the simulation reproducible"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 2.0, None, None),
+            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -1.0, None, None),
+            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, 1.5, None, None),
+        ] * 5
+
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        first = run_monte_carlo_simulation(trades, challenge_params, 0.01, 500, seed=123)
+        second = run_monte_carlo_simulation(trades, challenge_params, 0.01, 500, seed=123)
+
+        assert first == second
+        assert 0.0 < first["pass_rate"] < 1.0
+
+    def test_monte_carlo_detailed_result(self):
+        """Test the per-simulation outcome distribution"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 2.0, None, None),
+            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -1.0, None, None),
+            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, 1.5, None, None),
+        ] * 5
+
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        result = run_monte_carlo_detailed(trades, challenge_params, 0.01, 400, seed=123)
+        summary = run_monte_carlo_simulation(trades, challenge_params, 0.01, 400, seed=123)
+
+        assert result.num_simulations == 400
+        assert result.pass_rate == summary["pass_rate"]
+        assert len(result.final_equity) == 400
+        assert len(result.max_drawdown) == 400
+        assert result.passed_simulations + result.daily_loss_failures + \
+            result.overall_loss_failures + result.timeouts == 400
+
+        for equity, days, reason in zip(result.final_equity, result.days_to_target, result.failure_reasons):
+            if reason is None:
+                assert equity >= 110000.0
+                assert days is not None and days >= 1
+            else:
+                assert reason in ("daily_loss", "overall_loss", "timeout")
+                assert days is None
+
+        assert result.final_equity_percentile(5) <= result.final_equity_percentile(95)
+        assert result.summary()["final_equity_p50"] == result.final_equity_percentile(50)
+
+    def test_monte_carlo_sample_paths(self):
+        """Test exporting thinned equity paths for fan charts"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 2.0, None, None),
+            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -1.0, None, None),
+        ] * 40
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        result = run_monte_carlo_detailed(trades, challenge_params, 0.002, 50, seed=7, sample_paths=5, path_points=20)
+
+        assert run_monte_carlo_detailed(trades, challenge_params, 0.002, 50, seed=7).sample_paths == []
+        assert len(result.sample_paths) == 5
+        longest = max(path[-1][0] for path in result.sample_paths)
+        for path, final_equity in zip(result.sample_paths, result.final_equity):
+            assert path[0] == (0, 100000.0)
+            assert path[-1][1] == pytest.approx(final_equity)
+            assert len(path) <= 21
+            steps = {b[0] - a[0] for a, b in zip(path, path[1:-1])}
+            assert len(steps) <= 1
+        assert longest > 20
+
+        with pytest.raises(ValueError):
+            run_monte_carlo_detailed(trades, challenge_params, 0.01, 10, sample_paths=1, path_points=1)
+
+    def test_monte_carlo_tail_risk(self):
+        """Test VaR, CVaR and drawdown probabilities of the simulated outcomes"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1000, 1.1050, 2.0, None, None),
+            Trade("GBPUSD", "Sell", 1.0, 1.3000, 1.2950, -1.0, None, None),
+            Trade("USDJPY", "Buy", 1.0, 150.00, 150.50, -1.5, None, None),
+        ] * 10
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        result = run_monte_carlo_detailed(trades, challenge_params, 0.01, 500, seed=11)
+        worst = sorted(result.final_equity)
+
+        assert result.account_size == 100000.0
+        assert result.value_at_risk(0.95) == pytest.approx(100000.0 - result.final_equity_percentile(5))
+        assert result.conditional_value_at_risk(0.95) == pytest.approx(100000.0 - sum(worst[:25]) / 25)
+        assert result.conditional_value_at_risk(0.99) >= result.value_at_risk(0.99)
+        assert result.drawdown_probability(0.0) == 1.0
+        assert result.drawdown_probability(5.0) == pytest.approx(
+            sum(d >= 5.0 for d in result.max_drawdown) / 500
+        )
+
+        summary = result.summary()
+        assert summary["var_95"] == pytest.approx(result.value_at_risk(0.95))
+        assert summary["cvar_99"] == pytest.approx(result.conditional_value_at_risk(0.99))
+        if result.passed_simulations:
+            assert summary["days_to_target_p90"] == result.days_to_target_percentile(90)
+
+        with pytest.raises(ValueError):
+            result.value_at_risk(1.0)
+
+    def test_cost_model(self):
+        """Test charging commission, swap and slippage before simulating"""
+        day = 86_400_000
+        trade = Trade("EURUSD", "Buy", 2.0, 1.1, 1.11, 100.0, -4.0, -1.0, open_time=0, close_time=3 * day)
+        model = CostModel(commission_per_lot=3.0, swap_per_lot_per_day=0.5, slippage_per_lot=1.0, slippage_percent=10.0)
+
+        net = model.apply(trade)
+        # Recorded -4 - 6 commission - 2 - 10 slippage; recorded -1 - 3 days * 2 lots * 0.5 swap
+        assert net.commission == pytest.approx(-22.0)
+        assert net.swap == pytest.approx(-4.0)
+        assert net.profit == pytest.approx(74.0)
+        assert CostModel(include_trade_costs=False).apply(trade).profit == pytest.approx(100.0)
+
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0]
+        ] * 10
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+        frictionless = run_monte_carlo_detailed(trades, challenge_params, 0.01, 300, seed=5)
+        costly = run_monte_carlo_detailed(
+            trades, challenge_params, 0.01, 300, seed=5, cost_model=CostModel(commission_per_lot=0.5)
+        )
+        assert costly.pass_rate < frictionless.pass_rate
+        assert sum(costly.final_equity) < sum(frictionless.final_equity)
+
+        with pytest.raises(ValueError):
+            CostModel(slippage_percent=150.0)
+
+    def test_parametric_return_models(self):
+        """Test fitting return distributions and simulating from them"""
+        profits = [2.0, 2.2, 1.8, 2.1, 1.9] * 4 + [-1.0, -1.1, -0.9, -1.05, -0.95] * 4
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+
+        mean = sum(profits) / len(profits)
+        normal = fit_return_model(trades, ReturnModel.Normal)
+        assert normal.means == [pytest.approx(mean)]
+        assert normal.degrees_of_freedom is None
+        assert fit_return_model(trades, ReturnModel.Bootstrap) is None
+
+        # Two well separated clusters are recovered by the mixture
+        mixture = fit_return_model(trades, ReturnModel.GaussianMixture, components=2)
+        assert sorted(mixture.means) == [pytest.approx(-1.0, abs=0.01), pytest.approx(2.0, abs=0.01)]
+        assert mixture.weights == [pytest.approx(0.5), pytest.approx(0.5)]
+        draws = mixture.sample(2000, seed=1)
+        assert sum(d > 0.5 for d in draws) / 2000 == pytest.approx(0.5, abs=0.05)
+
+        fat_tailed = trades + [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -12.0, None, None)]
+        student = fit_return_model(fat_tailed, ReturnModel.StudentT)
+        assert 2.0 < student.degrees_of_freedom < 100.0
+
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+        first = run_monte_carlo_detailed(trades, challenge_params, 0.01, 200, seed=3, return_model=ReturnModel.Normal)
+        second = run_monte_carlo_detailed(trades, challenge_params, 0.01, 200, seed=3, return_model=ReturnModel.Normal)
+        bootstrap = run_monte_carlo_detailed(trades, challenge_params, 0.01, 200, seed=3)
+        assert first.final_equity == second.final_equity
+        assert first.final_equity != bootstrap.final_equity
+
+        with pytest.raises(ValueError):
+            fit_return_model(trades[:3], ReturnModel.GaussianMixture, components=2)
+
+    def test_markov_chain_return_model(self):
+        """Test fitting win/loss transition probabilities and simulating streaks"""
+        streaky = ([2.0, 1.5, 2.5, 2.0] + [-1.0, -1.5, -1.0, -0.5]) * 5
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in streaky]
+
+        chain = fit_return_model(trades, ReturnModel.MarkovChain)
+
+        assert chain.win_after_win == pytest.approx(15 / 20)
+        assert chain.win_after_loss == pytest.approx(4 / 19)
+        assert chain.dependence_p_value < 0.01
+        assert sorted(chain.wins) == sorted(p for p in streaky if p > 0)
+
+        # Simulated outcomes repeat the previous one as often as the history does
+        draws = chain.sample(20000, seed=2)
+        repeats = sum((a > 0) == (b > 0) for a, b in zip(draws, draws[1:])) / (len(draws) - 1)
+        stationary_win = (4 / 19) / (4 / 19 + 0.25)
+        assert repeats == pytest.approx(stationary_win * 0.75 + (1 - stationary_win) * 15 / 19, abs=0.02)
+        assert set(draws) <= set(streaky)
+
+        alternating = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [1.0, -1.0] * 10]
+        assert fit_return_model(alternating, ReturnModel.MarkovChain).win_after_win == 0.0
+        with pytest.raises(ValueError):
+            fit_return_model(trades[:4], ReturnModel.MarkovChain)
+
+    def test_garch_return_model(self):
+        """Test fitting a GARCH(1,1) to daily returns and simulating clustered volatility"""
+        from array import array
+
+        rng = random.Random(7)
+        returns, variance = [], 1.0
+        for _ in range(2000):
+            shock = variance**0.5 * rng.gauss(0, 1)
+            returns.append(0.1 + shock)
+            variance = 0.05 + 0.15 * shock * shock + 0.8 * variance
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, r, None, None) for r in returns]
+
+        garch = fit_return_model(trades, ReturnModel.Garch)
+        assert garch.garch_alpha == pytest.approx(0.15, abs=0.05)
+        assert garch.garch_beta == pytest.approx(0.8, abs=0.08)
+        # Variance targeting keeps the long-run variance at the sample's
+        long_run = garch.garch_omega / (1 - garch.garch_alpha - garch.garch_beta)
+        assert long_run == pytest.approx(garch.std_devs[0] ** 2)
+
+        def squared_autocorrelation(draws):
+            squares = [d * d for d in draws]
+            mean = sum(squares) / len(squares)
+            variance = sum((s - mean) ** 2 for s in squares)
+            return sum((a - mean) * (b - mean) for a, b in zip(squares, squares[1:])) / variance
+
+        # Large moves follow large moves in GARCH draws but not in normal ones
+        assert squared_autocorrelation(garch.sample(20000, seed=1)) > 0.1
+        normal = fit_return_model(trades, ReturnModel.Normal)
+        assert abs(squared_autocorrelation(normal.sample(20000, seed=1))) < 0.05
+
+        challenge_params = ChallengeParams(100000.0, 10.0, 5.0, 10.0, 0)
+        result = monte_carlo_from_returns(
+            array("d", returns[:250]), challenge_params, 0.01, 200, seed=4, trades_per_day=1,
+            return_model=ReturnModel.Garch,
+        )
+        assert result.num_simulations == 200
+
+        with pytest.raises(ValueError):
+            fit_return_model(trades[:10], ReturnModel.Garch)
+
+    def test_monte_carlo_block_bootstrap(self):
+        """Test block resampling keeps runs of consecutive trades together"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [3.0, 2.0, -1.0, -2.0, 1.0, -0.5]]
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=1000.0,
+            max_daily_loss_percent=100.0,
+            max_overall_loss_percent=100.0,
+            min_trading_days=0,
+        )
+
+        # A single block spanning the whole history is a rotation of it, so
+        # every path compounds the same returns to the same final equity
+        rotated = run_monte_carlo_detailed(
+            trades, challenge_params, 0.01, 50, seed=1,
+            resampling=ResamplingMode.Block, block_length=len(trades),
+        )
+        assert max(rotated.final_equity) == pytest.approx(min(rotated.final_equity))
+
+        iid = run_monte_carlo_detailed(trades, challenge_params, 0.01, 50, seed=1)
+        assert max(iid.final_equity) > min(iid.final_equity)
+
+        stationary = run_monte_carlo_simulation(
+            trades, challenge_params, 0.01, 50, seed=1,
+            resampling=ResamplingMode.Stationary, block_length=3,
+        )
+        assert stationary["total_simulations"] == 50
+
+        with pytest.raises(ValueError):
+            run_monte_carlo_simulation(trades, challenge_params, 0.01, 50, resampling=ResamplingMode.Block, block_length=0)
+
+    def test_monte_carlo_trades_per_day(self):
+        """Test that daily loss is enforced over whole simulated sessions"""
+        trades = [Trade("EURUSD", "Sell", 1.0, 1.1, 1.2, -1.0, None, None)] * 9
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=2.5,
+            max_overall_loss_percent=100.0,
+            min_trading_days=0,
+        )
+
+        # Without timestamps every trade is its own day, each losing only 1%
+        separate = run_monte_carlo_detailed(trades, challenge_params, 0.01, 20, seed=3)
+        assert separate.daily_loss_failures == 0
+
+        grouped = run_monte_carlo_detailed(trades, challenge_params, 0.01, 20, seed=3, trades_per_day=3)
+        assert grouped.daily_loss_failures == 20
+
+    def test_monte_carlo_trading_rules(self):
+        """Test daily stops and risk caps applied inside the simulated sessions"""
+        trades = [Trade("EURUSD", "Sell", 1.0, 1.1, 1.2, -1.0, None, None)] * 9
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=2.5,
+            max_overall_loss_percent=100.0,
+            min_trading_days=0,
+        )
+
+        # Stopping after two losing trades keeps every day under the 2.5% limit
+        stopped = run_monte_carlo_detailed(
+            trades, challenge_params, 0.01, 20, seed=3, trades_per_day=3,
+            rules=TradingRules(max_losses_per_day=2),
+        )
+        assert stopped.daily_loss_failures == 0
+
+        by_percent = run_monte_carlo_detailed(
+            trades, challenge_params, 0.01, 20, seed=3, trades_per_day=3,
+            rules=TradingRules(daily_stop_percent=50.0),
+        )
+        assert by_percent.daily_loss_failures == 0
+
+        # Three losses at the 0.5% cap stay under the limit that 2% risk breaks
+        capped = run_monte_carlo_detailed(
+            trades, challenge_params, 0.02, 20, seed=3, trades_per_day=3,
+            rules=TradingRules(max_risk_fraction=0.005),
+        )
+        assert capped.daily_loss_failures == 0
+        plain = run_monte_carlo_detailed(trades, challenge_params, 0.02, 20, seed=3, trades_per_day=3)
+        assert plain.daily_loss_failures == 20
+
+        rules = TradingRules(max_losses_per_day=3, drawdown_threshold_percent=5.0)
+        assert TradingRules.from_json(rules.to_json()) == rules
+        with pytest.raises(ValidationError):
+            TradingRules(max_losses_per_day=0)
+        with pytest.raises(ValidationError):
+            TradingRules(drawdown_risk_multiplier=1.5)
+
+    def test_monte_carlo_return_mode(self):
+        """Test converting dollar, per-lot, percent and R profits into risk units"""
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        def run(trades, **kwargs):
+            return run_monte_carlo_detailed(trades * 10, challenge_params, 0.02, 200, seed=11, trades_per_day=1, **kwargs)
+
+        def trade(profit, volume=1.0, risk_amount=None):
+            return Trade("EURUSD", "Buy", volume, 1.1, 1.2, profit, None, None, risk_amount=risk_amount)
+
+        units_trades = [trade(2.0), trade(-1.0), trade(1.5), trade(-1.0)]
+        units = run(units_trades)
+        assert 0.0 < units.pass_rate < 1.0
+
+        # Dollar profits read as risk units decide every attempt on its first trade
+        dollars = [trade(400.0), trade(-200.0), trade(300.0), trade(-200.0)]
+        raw = run(dollars, return_mode=ReturnMode.RiskUnits)
+        assert raw.daily_loss_failures + round(raw.pass_rate * 200) == 200
+        # Account currency is the default
+        assert run(dollars) == units
+        assert run(dollars, return_mode=ReturnMode.Currency) == units
+        summary = run_monte_carlo_simulation(dollars * 10, challenge_params, 0.02, 200, seed=11, trades_per_day=1)
+        assert summary["pass_rate"] == units.pass_rate
+
+        # Without a losing trade there is no amount risked to measure dollars against
+        with pytest.raises(InsufficientDataError, match="losing trade"):
+            run([trade(400.0), trade(300.0)])
+
+        per_lot = [trade(800.0, 2.0), trade(-100.0, 0.5), trade(600.0, 2.0), trade(-400.0, 2.0)]
+        assert run(per_lot, return_mode=ReturnMode.PerLot) == units
+
+        # R-multiples are used as they are, without rescaling to the average loss
+        r = [trade(100.0, risk_amount=50.0), trade(-50.0, risk_amount=50.0),
+             trade(150.0, risk_amount=100.0), trade(-80.0, risk_amount=80.0)]
+        assert run(r, return_mode=ReturnMode.RMultiple) == units
+
+        percent = run(dollars, return_mode=ReturnMode.PercentOfBalance)
+        assert 0.0 < percent.pass_rate < 1.0
+
+        with pytest.raises(ValidationError):
+            run(dollars, return_mode=ReturnMode.RMultiple)
+
+    def test_multi_strategy_monte_carlo(self):
+        """Test joint day-level resampling of several strategies on one account"""
+        day = 86_400_000
+        challenge_params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=3.0,
+            max_overall_loss_percent=6.0,
+            min_trading_days=0,
+        )
+
+        def strategy(win_on_odd_days):
+            return [
+                Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 2.0 if (d % 2 == 1) == win_on_odd_days else -1.0, None, None,
+                      close_time=d * day + 1000)
+                for d in range(20)
+            ]
+
+        trend, hedge = strategy(True), strategy(False)
+
+        # Losing days of one strategy are winning days of the other, so no day loses
+        hedged = run_multi_strategy_monte_carlo([trend, hedge], [1.0, 1.0], challenge_params, 0.02, 500, seed=1)
+        assert hedged.days == 20
+        assert hedged.combined.pass_rate == 1.0
+        assert len(hedged.standalone_pass_rates) == 2
+        assert max(hedged.standalone_pass_rates) < 1.0
+
+        # The same strategy twice doubles every daily swing
+        doubled = run_multi_strategy_monte_carlo([trend, trend], [1.0, 1.0], challenge_params, 0.02, 500, seed=1)
+        assert doubled.combined.pass_rate < min(doubled.standalone_pass_rates)
+
+        # Splitting one strategy's allocation in two only adds compounding between the halves
+        split = run_multi_strategy_monte_carlo([trend, trend], [0.5, 0.5], challenge_params, 0.02, 500, seed=1)
+        single = run_multi_strategy_monte_carlo([trend], [1.0], challenge_params, 0.02, 500, seed=1)
+        assert split.combined.pass_rate == pytest.approx(single.combined.pass_rate, abs=0.05)
+
+        with pytest.raises(ValidationError):
+            run_multi_strategy_monte_carlo([trend, hedge], [1.0], challenge_params, 0.02, 10)
+        with pytest.raises(ValidationError):
+            untimed = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None)]
+            run_multi_strategy_monte_carlo([trend, untimed], [1.0, 1.0], challenge_params, 0.02, 10)
+
+    def test_monte_carlo_shock_events(self):
+        """Test injected tail losses and the pass rate's sensitivity to them"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0]] * 10
         challenge_params = ChallengeParams(
             account_size=100000.0,
             profit_target_percent=10.0,
             max_daily_loss_percent=5.0,
             max_overall_loss_percent=10.0,
-            min_trading_days=30,
+            min_trading_days=0,
         )
 
-        results = run_monte_carlo_simulation(trades, challenge_params, 0.01, 100)
+        # A daily loss of 100 average losses at 2% risk breaks the daily limit on day one
+        crash = run_monte_carlo_detailed(
+            trades, challenge_params, 0.02, 100, seed=2, trades_per_day=2, shock=ShockEvent(1.0, 100.0),
+        )
+        assert crash.daily_loss_failures == 100
 
-        assert isinstance(results, dict)
-        assert "pass_rate" in results
-        assert "total_simulations" in results
-        assert "passed_simulations" in results
-        assert results["total_simulations"] == 100
-        assert 0.0 <= results["pass_rate"] <= 1.0
+        sensitivity = calculate_shock_sensitivity(
+            trades, challenge_params, 0.02, [0.0, 0.1, 0.5], [1.0, 3.0], num_simulations=500, seed=2, trades_per_day=2,
+        )
+        assert sensitivity.baseline_pass_rate == 1.0
+        assert sensitivity.pass_rates[0] == [1.0, 1.0]
+        # More frequent and larger shocks both lower the pass rate
+        assert sensitivity.pass_rates[2][0] < sensitivity.pass_rates[1][0]
+        assert sensitivity.pass_rates[2][1] < sensitivity.pass_rates[1][1] < sensitivity.pass_rates[1][0]
+        assert sensitivity.pass_rates[2][1] < sensitivity.pass_rates[2][0]
+
+        # Dollar profits are measured in average losses first, so they match the unit trades
+        dollars = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, t.profit * 250.0, None, None) for t in trades]
+        in_dollars = calculate_shock_sensitivity(
+            dollars, challenge_params, 0.02, [0.0, 0.1, 0.5], [1.0, 3.0], num_simulations=500, seed=2, trades_per_day=2,
+        )
+        assert in_dollars == sensitivity
+        # Read as risk units, the first dollar win alone passes most attempts
+        as_units = calculate_shock_sensitivity(
+            dollars, challenge_params, 0.02, [0.5], [1.0], num_simulations=500, seed=2, trades_per_day=2,
+            return_mode=ReturnMode.RiskUnits,
+        )
+        assert as_units.pass_rates[0][0] > sensitivity.pass_rates[2][0]
+
+        shock = ShockEvent(0.05)
+        assert shock.loss_multiple == 3.0
+        assert ShockEvent.from_json(shock.to_json()) == shock
+        with pytest.raises(ValidationError):
+            ShockEvent(1.5)
+        with pytest.raises(ValidationError):
+            calculate_shock_sensitivity(trades, challenge_params, 0.02, [], [3.0])
+
+    def test_monte_carlo_daily_loss_basis(self):
+        """Test daily sessions keyed on close dates and the daily loss basis"""
+        day = 86_400_000
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None, close_time=19_737 * day),
+            Trade("EURUSD", "Sell", 1.0, 1.1, 1.2, -1.0, None, None, close_time=19_738 * day),
+            Trade("EURUSD", "Sell", 1.0, 1.1, 1.2, -1.0, None, None, close_time=19_738 * day + 1000),
+            Trade("EURUSD", "Sell", 1.0, 1.1, 1.2, -1.0, None, None, close_time=19_738 * day + 2000),
+        ]
+
+        # A losing day costs 2.97% of that day's starting balance
+        def params(basis):
+            return ChallengeParams(
+                account_size=100000.0,
+                profit_target_percent=1000.0,
+                max_daily_loss_percent=3.0,
+                max_overall_loss_percent=100.0,
+                min_trading_days=0,
+                daily_loss_basis=basis,
+            )
+
+        day_start = run_monte_carlo_detailed(trades, params(DailyLossBasis.DayStartBalance), 0.01, 200, seed=5)
+        initial = run_monte_carlo_detailed(trades, params(DailyLossBasis.InitialBalance), 0.01, 200, seed=5)
+
+        # After a winning day the loss exceeds 3% of the initial balance
+        assert day_start.daily_loss_failures == 0
+        assert initial.daily_loss_failures > 0
+
+    def test_monte_carlo_trailing_drawdown(self):
+        """Test static and trailing overall loss floors"""
+        def reasons(profits, mode, lock=True):
+            trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+            params = ChallengeParams(
+                account_size=100000.0,
+                profit_target_percent=10.0,
+                max_daily_loss_percent=100.0,
+                max_overall_loss_percent=5.0,
+                min_trading_days=0,
+                drawdown_mode=mode,
+                lock_trailing_at_breakeven=lock,
+            )
+            result = run_monte_carlo_detailed(
+                trades, params, 0.01, 5, seed=1, trades_per_day=len(trades), return_mode=ReturnMode.RiskUnits
+            )
+            return set(result.failure_reasons)
+
+        # One session: up to 106,090 intraday, then back down to 99,851
+        round_trip = [3.0, 3.0, -2.0, -2.0, -2.0]
+        assert reasons(round_trip, DrawdownMode.Static) == {"timeout"}
+        # The end-of-day balance never made a new high, so the floor did not move
+        assert reasons(round_trip, DrawdownMode.TrailingBalance) == {"timeout"}
+        # The intraday peak drags the floor up to breakeven
+        assert reasons(round_trip, DrawdownMode.TrailingEquity) == {"overall_loss"}
+
+        # Ends at 100,870: above the locked floor, below the unlocked 101,090
+        shallow = [3.0, 3.0, -2.0, -2.0, -1.0]
+        assert reasons(shallow, DrawdownMode.TrailingEquity) == {"timeout"}
+        assert reasons(shallow, DrawdownMode.TrailingEquity, lock=False) == {"overall_loss"}
+
+    def test_monte_carlo_min_trading_days(self):
+        """Test that passing requires the minimum number of trading days"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)] * 6
+
+        def run(min_days):
+            params = ChallengeParams(
+                account_size=100000.0,
+                profit_target_percent=10.0,
+                max_daily_loss_percent=5.0,
+                max_overall_loss_percent=10.0,
+                min_trading_days=min_days,
+            )
+            return run_monte_carlo_detailed(trades, params, 0.01, 10, seed=1, return_mode=ReturnMode.RiskUnits)
+
+        # Two +5% days reach the target
+        fast = run(0)
+        assert fast.pass_rate == 1.0
+        assert set(fast.days_to_target) == {2}
+        assert fast.target_before_min_days == 0
+
+        # The target is hit early but the path keeps trading until day 4
+        slow = run(4)
+        assert slow.pass_rate == 1.0
+        assert set(slow.days_to_target) == {4}
+        assert slow.target_before_min_days == 10
+
+        # Only six days are available, so a ten-day minimum can never be met
+        impossible = run(10)
+        assert impossible.pass_rate == 0.0
+        assert impossible.timeouts == 10
+        assert impossible.summary()["target_before_min_days_rate"] == 1.0
+
+    def test_monte_carlo_two_phase_challenge(self):
+        """Test that both phases must be passed, each from the initial balance"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)] * 6
+
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+            phases=[ChallengePhase(10.0), ChallengePhase(5.0, min_trading_days=2)],
+        )
+        assert len(params.phases) == 2
+        assert params.phases[1].min_trading_days == 2
+
+        result = run_monte_carlo_detailed(trades, params, 0.01, 10, seed=1, return_mode=ReturnMode.RiskUnits)
+        assert result.pass_rate == 1.0
+        assert result.phase_pass_rates == [1.0, 1.0]
+        assert set(result.phases_passed) == {2}
+        # Two days for the evaluation, two for the verification
+        assert set(result.days_to_target) == {4}
+
+        # The verification target cannot be reached within six days
+        params.phases = [ChallengePhase(10.0), ChallengePhase(50.0)]
+        summary = run_monte_carlo_simulation(trades, params, 0.01, 10, seed=1, return_mode=ReturnMode.RiskUnits)
+        assert summary["phase_1_pass_rate"] == 1.0
+        assert summary["phase_2_pass_rate"] == 0.0
+        assert summary["pass_rate"] == 0.0
+
+    def test_funded_payouts(self):
+        """Test the funded phase traded after passing"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)] * 6
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+        funded = FundedAccount(profit_split=0.8, payout_interval_days=21, horizon_days=42)
+
+        # +5% a day, withdrawn and split every 21 days
+        result = run_monte_carlo_detailed(trades, params, 0.01, 10, seed=1, funded=funded, return_mode=ReturnMode.RiskUnits)
+        payout = 2 * (1.05**21 - 1) * 100000.0 * 0.8
+        assert result.funded_payouts == pytest.approx([payout] * 10)
+        assert result.expected_payout() == pytest.approx(payout)
+        assert result.funded_blowup_probability(2.0) == 0.0
+        assert run_monte_carlo_detailed(trades, params, 0.01, 10, seed=1, return_mode=ReturnMode.RiskUnits).expected_payout() is None
+
+        # A 20% losing day breaks the daily loss limit sooner or later
+        risky = trades[:3] + [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -20.0, None, None)]
+        summary = run_monte_carlo_simulation(risky, params, 0.01, 200, seed=1, funded=funded, return_mode=ReturnMode.RiskUnits)
+        assert 0.0 < summary["funded_blowup_rate"] <= 1.0
+        assert summary["expected_payout"] < payout
+
+        # A payout takes the account back to its start, and a trailed floor with it
+        mostly_up = trades[:3] + [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -1.0, None, None)]
+        params.drawdown_mode = DrawdownMode.TrailingBalance
+        summary = run_monte_carlo_simulation(mostly_up, params, 0.01, 200, seed=1, funded=funded, return_mode=ReturnMode.RiskUnits)
+        assert summary["funded_blowup_rate"] == 0.0
+        assert summary["expected_payout"] > 0.0
+
+        with pytest.raises(ValidationError):
+            FundedAccount(profit_split=1.5)
+
+    def test_challenge_expected_value(self):
+        """Test the dollar value of paying for and attempting a challenge"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)] * 6
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        value = challenge_expected_value(trades, params, 0.01, 500.0, 0.8, 20, funded_days=21, seed=1, return_mode=ReturnMode.RiskUnits)
+        payout = (1.05**21 - 1) * 100000.0 * 0.8
+        assert value.pass_probability == 1.0
+        assert value.expected_attempts == 1.0 and value.expected_retries == 0.0
+        assert value.expected_payout_if_passed == pytest.approx(payout)
+        assert value.expected_value == pytest.approx(payout - 500.0)
+
+        # A strategy that cannot pass only ever pays fees
+        losing = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -1.0, None, None)] * 6
+        value = challenge_expected_value(losing, params, 0.01, 500.0, 0.8, 20, max_attempts=3, seed=1)
+        assert value.pass_probability == 0.0
+        assert value.expected_value == pytest.approx(-1500.0)
+        forever = challenge_expected_value(losing, params, 0.01, 500.0, 0.8, 20, seed=1)
+        assert math.isinf(forever.expected_attempts) and forever.expected_value == -math.inf
+
+    def test_optimize_reset_strategy(self):
+        """Test choosing a risk fraction for a budget of resets"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0, 2.5, -1.0]]
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        # 600 buys the first attempt at 300 and three resets at 100
+        plan = optimize_reset_strategy(trades, params, 600.0, 300.0, 100.0, 0.005, 0.05, 5, 200, seed=9)
+        assert plan.attempts == 4
+        for p, funded, cost in zip(plan.pass_rates, plan.funded_probabilities, plan.expected_costs):
+            assert funded == pytest.approx(1 - (1 - p) ** 4)
+            assert cost == pytest.approx(300.0 + 100.0 * sum((1 - p) ** i for i in range(1, 4)))
+        assert plan.best_funded_probability == max(plan.funded_probabilities)
+        assert plan.best_risk_fraction in plan.risk_fractions
+
+        with pytest.raises(ValidationError):
+            optimize_reset_strategy(trades, params, 200.0, 300.0, 100.0, 0.005, 0.05, 5, 200)
+
+    def test_consistency_rules(self):
+        """Test consistency rules in simulation and in the compliance audit"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 5.0, None, None)] * 6
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+            max_day_profit_percent=40.0,
+        )
+
+        # +5% a day reaches the target on day 2, but no day may exceed 40% of the profit until day 3
+        result = run_monte_carlo_detailed(trades, params, 0.01, 10, seed=1, return_mode=ReturnMode.RiskUnits)
+        assert set(result.days_to_target) == {3}
+
+        params.max_day_profit_percent = 10.0
+        result = run_monte_carlo_detailed(trades, params, 0.01, 10, seed=1, return_mode=ReturnMode.RiskUnits)
+        assert result.pass_rate == 0.0
+        assert result.consistency_failures == 10
+        assert set(result.failure_reasons) == {"consistency"}
+        assert result.target_before_min_days == 0
+
+        day = 86_400_000
+        history = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=1_704_067_200_000 + i * day)
+            for i, p in enumerate([8000.0, -500.0, 1000.0, 2000.0])
+        ]
+        params.max_day_profit_percent = 50.0
+        report = check_challenge_compliance(history, params)
+        assert report.target_reached
+        assert report.trading_days == 4
+        assert report.profit_percent == pytest.approx(10.5)
+        assert report.best_day_percent == pytest.approx(8000.0 / 10500.0 * 100.0)
+        assert report.profitable_days_percent == pytest.approx(75.0)
+        assert not report.passed
+        assert len(report.violations) == 1 and "best day" in report.violations[0]
+
+        params.max_day_profit_percent = None
+        params.min_profitable_days_percent = 80.0
+        assert not check_challenge_compliance(history, params).passed
+        params.min_profitable_days_percent = None
+        assert check_challenge_compliance(history, params).passed
+
+        # A losing history has no profit for the best day to be a share of
+        params.max_day_profit_percent = 50.0
+        losing = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=t.close_time)
+                  for t, p in zip(history, [300.0, -500.0, -200.0, -100.0])]
+        report = check_challenge_compliance(losing, params)
+        assert report.best_day_percent is None
+        assert not any("best day" in v for v in report.violations)
+        params.max_day_profit_percent = None
+
+        history[1].profit = -6000.0
+        report = check_challenge_compliance(history, params)
+        assert report.worst_daily_loss_percent == pytest.approx(6.0)
+        assert any("daily loss" in v for v in report.violations)
+
+    def test_evaluate_next_trade(self):
+        """Test the pre-trade check against the daily and overall loss limits"""
+        params = ChallengeParams(100000.0, 10.0, 5.0, 10.0, 0)
+
+        # Daily floor 95,000 and overall floor 90,000
+        check = evaluate_next_trade(98000.0, 100000.0, params, 1.0)
+        assert check.allowed
+        assert check.proposed_loss == pytest.approx(980.0)
+        assert check.daily_loss_room == pytest.approx(3000.0)
+        assert check.overall_loss_room == pytest.approx(8000.0)
+        assert check.max_risk_percent == pytest.approx(3000.0 / 98000.0 * 100.0)
+
+        check = evaluate_next_trade(98000.0, 100000.0, params, 4.0)
+        assert not check.allowed
+        assert check.breaches_daily_limit and not check.breaches_overall_limit
+
+        # A trailing floor follows the high-water mark: 105,000 - 10,000
+        params.drawdown_mode = DrawdownMode.TrailingEquity
+        check = evaluate_next_trade(97000.0, 97500.0, params, 2.5, peak_equity=105000.0)
+        assert check.overall_loss_room == pytest.approx(2000.0)
+        assert check.breaches_overall_limit and not check.breaches_daily_limit
+        assert check.max_risk_percent == pytest.approx(2000.0 / 97000.0 * 100.0)
+        # Without the peak the current balances are the best estimate
+        assert evaluate_next_trade(97000.0, 97500.0, params, 2.5).overall_loss_room == pytest.approx(7000.0)
+
+        # Once the day's limit is gone nothing more may be risked
+        params.drawdown_mode = DrawdownMode.Static
+        check = evaluate_next_trade(94000.0, 100000.0, params, 0.0)
+        assert not check.allowed and check.max_risk_percent == 0.0
+
+        with pytest.raises(ValidationError):
+            evaluate_next_trade(98000.0, 100000.0, params, -1.0)
+        with pytest.raises(ValidationError):
+            evaluate_next_trade(0.0, 100000.0, params, 1.0)
+
+    def test_copy_trade_scaling(self):
+        """Test per-follower lot multipliers that keep each account within its own limits"""
+        gold = SymbolRegistry([SymbolSpec("XAUUSD", 100, 0.01)])
+        mini_gold = SymbolRegistry([SymbolSpec("XAUUSD", 10, 0.01), SymbolSpec("EURUSD", 100000, 0.00001)])
+        followers = [
+            CopyFollower("fresh", 100000.0, ChallengeParams(100000, 10.0, 5.0, 10.0, 0), symbol_specs=mini_gold),
+            # Daily floor 47,500 - 2,500 of a 50k account leaves 1,000 of room
+            CopyFollower("bruised", 48000.0, ChallengeParams(50000, 10.0, 5.0, 10.0, 0), day_start_equity=49500.0),
+            # 1,500 above the overall floor
+            CopyFollower("deep", 91500.0, ChallengeParams(100000, 10.0, 5.0, 10.0, 0)),
+        ]
+
+        # The master loses 1,000 per stop-out; each follower must survive three in a day
+        fresh, bruised, deep = copy_trade_scaling(100000.0, 0.01, followers, gold, max_losses_per_day=3)
+        assert fresh.name == "fresh"
+        assert fresh.multiplier == pytest.approx(1.0) and fresh.limited_by is None
+        assert fresh.risk_percent == pytest.approx(1.0)
+        assert fresh.max_risk_percent == pytest.approx(5.0 / 3)
+        # Ten times the lots on a broker whose gold contract is a tenth the size
+        assert fresh.symbol_multipliers == {"XAUUSD": pytest.approx(10.0)}
+
+        assert bruised.proportional_multiplier == pytest.approx(0.48)
+        assert bruised.multiplier == pytest.approx(1 / 3)
+        assert bruised.limited_by == "daily_loss"
+        assert bruised.symbol_multipliers == {}
+
+        assert deep.multiplier == pytest.approx(0.5)
+        assert deep.limited_by == "overall_loss"
+        assert deep.risk_percent == pytest.approx(500.0 / 91500.0 * 100.0)
+
+        with pytest.raises(ValidationError):
+            copy_trade_scaling(100000.0, 0.0, followers)
+        with pytest.raises(ValidationError):
+            copy_trade_scaling(100000.0, 0.01, followers, max_losses_per_day=0)
+
+    def test_trading_restrictions(self):
+        """Test weekend-holding and restricted-window rules"""
+        monday, hour, day = 1_704_067_200_000, 3_600_000, 86_400_000
+        # Four intraday trades, then one opened on Friday and closed on Monday
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None, open_time=monday + d * day, close_time=monday + c)
+            for d, c in [(0, hour), (1, day + hour), (2, 2 * day + hour), (3, 3 * day + hour), (4, 7 * day)]
+        ]
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=50.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        assert run_monte_carlo_detailed(trades, params, 0.01, 50, seed=2, return_mode=ReturnMode.RiskUnits).restricted_trading_failures == 0
+        params.no_weekend_holding = True
+        result = run_monte_carlo_detailed(trades, params, 0.01, 50, seed=2, return_mode=ReturnMode.RiskUnits)
+        assert result.restricted_trading_failures > 0
+        assert "restricted_trading" in result.failure_reasons
+
+        report = check_challenge_compliance(trades, params)
+        assert report.violations == ["position held over the weekend on 2024-01-08"]
+
+        params.no_weekend_holding = False
+        params.restricted_windows = [(monday + 2 * day, monday + 2 * day + 30 * 60_000)]
+        report = check_challenge_compliance(trades, params)
+        assert report.violations == ["trade opened or closed in a restricted window on 2024-01-03"]
+
+    def test_analyze_batch(self):
+        """Test that a batch analyzes each account in order and reports failures per account"""
+        good = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0] * 5]
+        winners = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None)] * 5
+        params = ChallengeParams(100000.0, 10.0, 5.0, 10.0, 0)
+
+        batch = analyze_batch([good, [], winners], params, 0.02, 200, seed=5, return_mode=ReturnMode.RiskUnits)
+        assert [account.index for account in batch] == [0, 1, 2]
+
+        assert batch[0].error is None
+        assert batch[0].metrics == calculate_performance_metrics(good)
+        assert batch[0].kelly == calculate_kelly_from_trades(good, 1.0)
+        assert batch[0].pass_rate == run_monte_carlo_detailed(good, params, 0.02, 200, seed=5, return_mode=ReturnMode.RiskUnits).pass_rate
+
+        assert batch[1].error == "No trades provided"
+        assert batch[1].metrics is None and batch[1].pass_rate is None
+
+        # Kelly is undefined without losses but the rest of the account is still analyzed
+        assert batch[2].error is None and batch[2].kelly is None
+        assert batch[2].pass_rate is not None
+
+    def test_analyze_portfolio(self):
+        """Test combined drawdown, correlation and simultaneous breaches of stacked accounts"""
+        day = 86400000
+        noon = [1704110400000 + offset * day for offset in [0, 1, 2, 3, 4, 7, 8, 9, 10, 11]]  # Weekdays from Jan 1 2024
+        copied = [800.0, -400.0, 600.0, -6000.0, 900.0, 300.0, -200.0, 700.0, 500.0, -300.0]
+        own = [-200.0, 300.0, 100.0, 400.0, -100.0, 200.0, -300.0, 100.0, 200.0, -100.0]
+
+        def account(profits):
+            return [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, close_time=t) for p, t in zip(profits, noon)]
+
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+        portfolio = analyze_portfolio([account(copied), account(copied), account(own)], params, seed=3)
+        assert portfolio.accounts == ["account 1", "account 2", "account 3"]
+        assert portfolio.days == 10
+        assert portfolio.correlation[0][1] == pytest.approx(1.0)
+        assert abs(portfolio.correlation[0][2]) < 1.0
+
+        # Both copied accounts lose 6% on the same day
+        assert portfolio.daily_loss_breaches == [1, 1, 0]
+        assert portfolio.simultaneous_breach_days == 1
+        assert portfolio.combined_equity[-1] == pytest.approx(300000 + 2 * sum(copied) + sum(own))
+        assert portfolio.combined_max_drawdown == pytest.approx(12000 - 400)
+        assert portfolio.combined_max_drawdown_percent == pytest.approx(11600 / 302200 * 100)
+
+        # Copy trading makes shared breaches far likelier than for independent accounts
+        assert portfolio.simultaneous_breach_probability == pytest.approx(1 - 0.9**20, abs=0.03)
+        assert portfolio.independent_breach_probability < portfolio.simultaneous_breach_probability / 2
+        assert portfolio.all_breach_probability == 0.0
+        assert analyze_portfolio([account(copied), account(own)], params, seed=3) == analyze_portfolio(
+            [account(copied), account(own)], params, seed=3
+        )
+
+        # An intraday equity dip breaks the limit even when the day closes flat
+        equity = [(noon[0] - 1, 50000.0), (noon[0], 47000.0), (noon[0] + 1, 50000.0), (noon[1], 50500.0)]
+        steady = [(noon[0] - 1, 50000.0), (noon[0], 49800.0), (noon[1], 50200.0)]
+        live = analyze_portfolio_equity([equity, equity, steady], params, names=["a", "b", "c"], seed=1)
+        assert live.account_sizes == [50000.0, 50000.0, 50000.0]
+        assert live.daily_pnl[0] == [0.0, 500.0]
+        assert live.daily_loss_breaches == [1, 1, 0]
+
+        with pytest.raises(InsufficientDataError):
+            analyze_portfolio([account(copied)], params)
+        with pytest.raises(ValidationError, match="one account size per account"):
+            analyze_portfolio([account(copied), account(own)], params, account_sizes=[100000])
+
+    def test_export_results(self, tmp_path):
+        """Test writing per-simulation results and rolling metrics to CSV and Parquet"""
+        import csv
+
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 10]
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+        result = run_monte_carlo_detailed(trades, params, 0.01, 300, seed=2)
+
+        assert export_simulation_results(result, tmp_path / "paths.csv") == 300
+        with open(tmp_path / "paths.csv", newline="") as handle:
+            rows = list(csv.DictReader(handle))
+        assert len(rows) == 300
+        assert [float(row["final_equity"]) for row in rows] == result.final_equity
+        assert [row["passed"] == "true" for row in rows] == [reason is None for reason in result.failure_reasons]
+        assert [row["days_to_target"] or None for row in rows] == [
+            None if days is None else str(days) for days in result.days_to_target
+        ]
+
+        rolling = rolling_metrics(trades, 10, 5)
+        assert export_rolling_metrics(rolling, tmp_path / "rolling.csv") == len(rolling.end_indices)
+        with open(tmp_path / "rolling.csv", newline="") as handle:
+            rows = list(csv.DictReader(handle))
+        assert [int(row["end_index"]) for row in rows] == rolling.end_indices
+        assert rows[0]["sharpe_ratio"] == ""
+
+        for write, value in [(export_simulation_results, result), (export_rolling_metrics, rolling)]:
+            path = tmp_path / (write.__name__ + ".parquet")
+            assert write(value, path) > 0
+            content = path.read_bytes()
+            assert content[:4] == b"PAR1" and content[-4:] == b"PAR1"
+
+        with pytest.raises(ValidationError, match=".csv or .parquet"):
+            export_simulation_results(result, tmp_path / "paths.txt")
+
+    def test_reproducible_across_thread_counts(self):
+        """Test that a seed gives the same results whatever the rayon thread count"""
+        import os
+        import subprocess
+        import sys
+
+        script = (
+            "from risk_optima_engine import *\n"
+            "trades = [Trade('EURUSD', 'Buy', 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0] * 5]\n"
+            "params = ChallengeParams(100000.0, 10.0, 5.0, 10.0, 0)\n"
+            "result = run_monte_carlo_detailed(trades, params, 0.02, 300, seed=11)\n"
+            "kelly = calculate_kelly_confidence(trades, 300, 11)\n"
+            "print(result.final_equity, result.days_to_target, kelly.mean)\n"
+        )
+        outputs = set()
+        for threads in ("1", "4"):
+            env = dict(os.environ, RAYON_NUM_THREADS=threads, PYTHONPATH=os.pathsep.join(sys.path))
+            run = subprocess.run([sys.executable, "-c", script], env=env, capture_output=True, text=True, check=True)
+            outputs.add(run.stdout)
+        assert len(outputs) == 1 and "" not in outputs
+
+    def test_optimize_risk_fraction(self):
+        """Test the pass-rate curve over a grid of risk fractions"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0, 2.5, -1.0]]
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+
+        search = optimize_risk_fraction(trades, params, 0.005, 0.05, 10, 200, seed=9)
+        assert len(search.risk_fractions) == 10
+        assert search.risk_fractions[0] == pytest.approx(0.005)
+        assert search.risk_fractions[-1] == pytest.approx(0.05)
+        assert search.best_pass_rate == max(search.pass_rates)
+        assert search.best_risk_fraction in search.risk_fractions
+
+        # Each grid point matches a standalone run with the same seed
+        single = run_monte_carlo_simulation(trades, params, search.risk_fractions[3], 200, seed=9)
+        assert search.pass_rates[3] == single["pass_rate"]
+
+        with pytest.raises(Exception):
+            optimize_risk_fraction(trades, params, 0.05, 0.01, 10, 200)
+
+    def test_run_stress_test(self):
+        """Test degrading the edge before sizing and simulating"""
+        profits = [2.0, -1.0, 1.5, -1.0, 2.5, -1.0, 3.0, -1.0, 1.0, 2.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=0,
+        )
+        stress = StressScenario(win_rate_reduction=10.0, avg_win_reduction_percent=20.0, avg_loss_increase_percent=25.0)
+
+        # Wins shrink by a fifth, losses grow by a quarter and the smallest win becomes an average loss
+        stressed = [t.profit for t in stress.apply(trades)]
+        assert stressed == pytest.approx([1.6, -1.25, 1.2, -1.25, 2.0, -1.25, 2.4, -1.25, -1.25, 1.6])
+
+        result = run_stress_test(trades, stress, params, risk_fraction=0.01, num_simulations=300, seed=5)
+        assert result.scenario == stress
+        assert result.baseline_metrics.win_probability == pytest.approx(0.6)
+        assert result.stressed_metrics.win_probability == pytest.approx(0.5)
+        # Median win over median loss: 0.6 - 0.4 / 2 before, 0.5 - 0.5 / (1.6 / 1.25) after
+        assert result.baseline_kelly_fraction == pytest.approx(0.4)
+        assert result.stressed_kelly_fraction == pytest.approx(0.109375)
+        assert result.stressed_pass_rate < result.baseline_pass_rate
+
+        # Without challenge parameters only the Kelly comparison is made
+        kelly_only = run_stress_test(trades, stress)
+        assert kelly_only.baseline_pass_rate is None
+        assert kelly_only.stressed_kelly_fraction == result.stressed_kelly_fraction
+
+        with pytest.raises(ValueError):
+            StressScenario(avg_win_reduction_percent=100.0)
+
+    def test_target_probability_by_horizon(self):
+        """Test the chance of passing within a growing number of trading days"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [2.0, -1.0, 1.5, -1.0, 2.5, -1.0]]
+        params = ChallengeParams(
+            account_size=100000.0,
+            profit_target_percent=10.0,
+            max_daily_loss_percent=5.0,
+            max_overall_loss_percent=10.0,
+            min_trading_days=10,
+        )
+
+        curve = target_probability_by_horizon(trades, params, 0.02, 500, seed=4)
+        assert curve.horizons == list(range(5, 65, 5))
+        assert curve.probabilities[0] == 0.0  # Shorter than min_trading_days
+        assert curve.probabilities == sorted(curve.probabilities)
+        # Paths run past the six days of history
+        assert curve.probabilities[-1] > 0.5
+
+        custom = target_probability_by_horizon(trades, params, 0.02, 500, horizons=[30, 10], seed=4)
+        assert custom.probabilities[1] <= custom.probabilities[0]
+
+        with pytest.raises(ValidationError):
+            target_probability_by_horizon(trades, params, 0.02, 500, horizons=[0])
 
     def test_monte_carlo_empty_trades(self):
         """Test Monte Carlo with empty trades"""
@@ -295,13 +3435,389 @@ class TestMonteCarloSimulation:
         )
 
         # Test with very high risk fraction
-        results = run_monte_carlo_simulation(trades, challenge_params, 0.1, 50)
+        results = run_monte_carlo_simulation(trades, challenge_params, 0.1, 50, return_mode=ReturnMode.RiskUnits)
         assert isinstance(results, dict)
 
         # Test with very low risk fraction
-        results = run_monte_carlo_simulation(trades, challenge_params, 0.001, 50)
+        results = run_monte_carlo_simulation(trades, challenge_params, 0.001, 50, return_mode=ReturnMode.RiskUnits)
         assert isinstance(results, dict)
 
 
+
+    def test_sizing_strategies(self):
+        """Test fixed lot and fixed ratio sizing against fixed fractional"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None) for _ in range(20)]
+        params = ChallengeParams(10000, 7.5, 5.0, 10.0, 0)
+
+        compounding = run_monte_carlo_detailed(trades, params, 0.01, 10, seed=3, return_mode=ReturnMode.RiskUnits)
+        assert compounding.final_equity_percentile(50) == pytest.approx(10000 * 1.01**8)
+
+        fixed_lot = SizingStrategy(SizingMode.FixedLot, 0.01)
+        result = run_monte_carlo_detailed(trades, params, 0.5, 10, seed=3, sizing=fixed_lot, return_mode=ReturnMode.RiskUnits)
+        assert result.final_equity_percentile(50) == pytest.approx(10800.0)
+
+        # One unit of 100 until 200 of profit, two until 600, then three
+        fixed_ratio = SizingStrategy(SizingMode.FixedRatio, 0.01, delta=200.0)
+        result = run_monte_carlo_detailed(trades, params, 0.01, 10, seed=3, sizing=fixed_ratio, return_mode=ReturnMode.RiskUnits)
+        assert result.final_equity_percentile(50) == pytest.approx(10900.0)
+
+        with pytest.raises(ValueError, match="delta"):
+            SizingStrategy(SizingMode.FixedRatio, 0.01)
+
+    def test_adaptive_sizing_strategies(self):
+        """Test volatility targeting and recalculated Kelly sizing"""
+        profits = [3.0, -1.0, 2.0, -1.5, 0.5, -2.0, 4.0, -1.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+        scaled = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p * 10, None, None) for p in profits]
+        params = ChallengeParams(10000, 5.0, 5.0, 10.0, 0)
+
+        # Targeting volatility makes the outcome independent of the profit scale
+        vol = SizingStrategy(SizingMode.VolatilityTarget, 1.0, target_volatility=0.005, lookback=8)
+        small = run_monte_carlo_detailed(trades, params, 0.0, 200, seed=9, sizing=vol)
+        large = run_monte_carlo_detailed(scaled, params, 0.0, 200, seed=9, sizing=vol)
+        assert small.pass_rate == large.pass_rate
+        assert small.final_equity_percentile(50) == pytest.approx(large.final_equity_percentile(50))
+
+        # A binding cap turns recalculated Kelly into fixed fractional sizing
+        kelly = SizingStrategy(SizingMode.KellyRecalculated, 0.001, recalc_interval=5)
+        capped = run_monte_carlo_detailed(trades, params, 0.0, 200, seed=9, sizing=kelly)
+        plain = run_monte_carlo_detailed(trades, params, 0.001, 200, seed=9)
+        assert capped.pass_rate == plain.pass_rate
+        assert capped.final_equity_percentile(50) == pytest.approx(plain.final_equity_percentile(50))
+
+        kelly.risk_fraction = 1.0
+        uncapped = run_monte_carlo_detailed(trades, params, 0.0, 200, seed=9, sizing=kelly)
+        assert uncapped.pass_rate > capped.pass_rate
+
+    def test_equity_curve_sizing(self):
+        """Test cutting risk while equity trades below its moving average"""
+        wins = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None) for _ in range(20)]
+        losses = [Trade("EURUSD", "Sell", 1.0, 1.1, 1.2, -1.0, None, None) for _ in range(20)]
+        params = ChallengeParams(10000, 7.5, 50.0, 90.0, 0)
+        curve = SizingStrategy(SizingMode.EquityCurve, 0.01, lookback=5)
+
+        # A rising curve never dips below its average, so nothing changes
+        rising = run_monte_carlo_detailed(wins, params, 0.0, 10, seed=3, sizing=curve, return_mode=ReturnMode.RiskUnits)
+        assert rising.final_equity_percentile(50) == pytest.approx(10000 * 1.01**8)
+
+        # After the first loss every trade is taken at half risk
+        falling = run_monte_carlo_detailed(losses, params, 0.0, 10, seed=3, sizing=curve)
+        static = run_monte_carlo_detailed(losses, params, 0.01, 10, seed=3)
+        assert static.final_equity_percentile(50) == pytest.approx(10000 * 0.99**20)
+        assert falling.final_equity_percentile(50) == pytest.approx(10000 * 0.99 * 0.995**19)
+
+        # Sitting out resumes once the flat curve's average catches up, every fifth trade
+        curve.equity_curve_multiplier = 0.0
+        stopped = run_monte_carlo_detailed(losses, params, 0.0, 10, seed=3, sizing=curve)
+        assert stopped.final_equity_percentile(50) == pytest.approx(10000 * 0.99**4)
+
+        with pytest.raises(ValueError, match="multiplier"):
+            SizingStrategy(SizingMode.EquityCurve, 0.01, equity_curve_multiplier=1.5)
+
+
+    def test_compare_strategies(self):
+        """Test comparing sizing strategies over common random numbers"""
+        profits = [300.0, -100.0, 250.0, -100.0, 200.0, -150.0]
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in profits]
+        params = ChallengeParams(10000, 8.0, 5.0, 10.0, 0)
+        strategies = [
+            SizingStrategy(SizingMode.FixedFractional, 0.0005),
+            SizingStrategy(SizingMode.FixedLot, 0.0005),
+            SizingStrategy(SizingMode.FixedFractional, 0.0002),
+        ]
+
+        comparison = compare_strategies(trades, params, strategies, 300, seed=4)
+        assert comparison.labels == ["FixedFractional 0.0005", "FixedLot 0.0005", "FixedFractional 0.0002"]
+        assert len(comparison.results) == 3
+
+        # Each entry replays exactly what a standalone run with the same seed sees
+        alone = run_monte_carlo_detailed(trades, params, 0.0002, 300, seed=4)
+        assert comparison.pass_rates[2] == alone.pass_rate
+        assert comparison.results[2].final_equity == alone.final_equity
+        assert comparison.pass_rates[comparison.best_index] == max(comparison.pass_rates)
+
+        table = comparison.table()
+        assert [row["label"] for row in table] == comparison.labels
+        assert table[0]["pass_rate"] == comparison.pass_rates[0]
+
+        with pytest.raises(ValueError):
+            compare_strategies(trades, params, strategies, 10, labels=["only one"])
+
+
+    def test_simulation_releases_gil(self):
+        """Test that other Python threads keep running during a simulation"""
+        import threading
+        import time
+
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 50]
+        params = ChallengeParams(100000, 50.0, 50.0, 90.0, 0)
+        worker = threading.Thread(target=run_monte_carlo_simulation, args=(trades, params, 0.00001, 20000))
+
+        # Holding the GIL would stall this loop for the whole simulation
+        start = last = time.perf_counter()
+        longest_stall = 0.0
+        worker.start()
+        while worker.is_alive():
+            now = time.perf_counter()
+            longest_stall = max(longest_stall, now - last)
+            last = now
+        worker.join()
+        assert longest_stall < (time.perf_counter() - start) / 2
+
+
+    def test_progress_and_cancellation(self):
+        """Test progress callbacks and cancelling a run through a token"""
+        import threading
+
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 50]
+        params = ChallengeParams(100000, 50.0, 50.0, 90.0, 0)
+
+        reports = []
+        run_monte_carlo_detailed(trades, params, 0.00001, 500, progress=reports.append, progress_every=100)
+        assert reports[-1] == 100.0
+        assert reports == sorted(reports)
+
+        token = CancellationToken()
+        threading.Timer(0.05, token.cancel).start()
+        with pytest.raises(SimulationCancelled, match="cancelled after"):
+            run_monte_carlo_simulation(trades, params, 0.00001, 10_000_000, cancel_token=token)
+        assert token.is_cancelled
+
+        # An exception raised by the callback stops the run and propagates
+        def fail(percent):
+            raise RuntimeError("stop at %.0f%%" % percent)
+
+        with pytest.raises(RuntimeError, match="stop at"):
+            run_monte_carlo_detailed(trades, params, 0.00001, 500, progress=fail)
+
+    def test_simulation_job(self):
+        """Test running a simulation in the background and polling it"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 50]
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+
+        job = SimulationJob(trades, params, 0.01, 2000, seed=7)
+        with pytest.raises(SimulationError, match="not been started"):
+            job.result()
+        assert job.progress() == 0.0
+        job.start()
+        with pytest.raises(SimulationError, match="already been started"):
+            job.start()
+        result = job.result(timeout=60.0)
+        assert job.is_done
+        assert job.progress() == 100.0
+        assert result == run_monte_carlo_detailed(trades, params, 0.01, 2000, seed=7)
+        # The finished result can be fetched again
+        assert job.result() == result
+
+        slow = SimulationJob(trades, ChallengeParams(100000, 50.0, 50.0, 90.0, 0), 0.00001, 10_000_000)
+        slow.start()
+        with pytest.raises(TimeoutError):
+            slow.result(timeout=0.01)
+        assert not slow.is_done
+        slow.cancel()
+        with pytest.raises(SimulationCancelled, match="cancelled after"):
+            slow.result()
+        assert slow.is_done
+        assert slow.progress() < 100.0
+
+
+class TestResultCache:
+    """Test reusing results computed from identical inputs"""
+
+    def test_seeded_runs_are_cached(self):
+        """Test that a repeated seeded run returns the cached result"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 50]
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+        clear_result_cache()
+        first = run_monte_carlo_detailed(trades, params, 0.01, 1000, seed=11)
+
+        # An already cancelled token only stops a run that actually happens
+        token = CancellationToken()
+        token.cancel()
+        reports = []
+        again = run_monte_carlo_detailed(
+            trades, params, 0.01, 1000, seed=11, cancel_token=token, progress=reports.append
+        )
+        assert again == first
+        assert reports == [100.0]
+        with pytest.raises(SimulationCancelled):
+            run_monte_carlo_detailed(trades, params, 0.01, 1000, seed=12, cancel_token=token)
+        # Unseeded runs are never reused
+        with pytest.raises(SimulationCancelled):
+            run_monte_carlo_detailed(trades, params, 0.01, 1000, cancel_token=token)
+
+        assert calculate_performance_metrics(trades) == calculate_performance_metrics(trades)
+
+        # Windows of a rolling analysis are not cached, so they cannot push the run out
+        rolling_metrics([Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in range(-50, 50)], 5, 1)
+        assert run_monte_carlo_detailed(trades, params, 0.01, 1000, seed=11, cancel_token=token) == first
+        try:
+            set_result_cache_size(0)
+            with pytest.raises(SimulationCancelled):
+                run_monte_carlo_detailed(trades, params, 0.01, 1000, seed=11, cancel_token=token)
+
+            # Results larger than the byte budget are computed every time
+            set_result_cache_size(32, max_bytes=1000)
+            assert run_monte_carlo_detailed(trades, params, 0.01, 1000, seed=11) == first
+            with pytest.raises(SimulationCancelled):
+                run_monte_carlo_detailed(trades, params, 0.01, 1000, seed=11, cancel_token=token)
+            assert calculate_performance_metrics(trades) == calculate_performance_metrics(trades)
+        finally:
+            set_result_cache_size(32)
+
+
+class TestRiskOfRuin:
+    """Test risk-of-ruin estimates"""
+
+    def test_risk_of_ruin_estimates_agree(self):
+        """Test the analytical and simulated probabilities against each other"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 2.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -1.0, None, None),
+        ]
+
+        result = calculate_risk_of_ruin(trades, 0.05, 20.0, num_simulations=5000, seed=3)
+        assert 0.0 < result.analytical_probability < 1.0
+        # The continuous approximation ignores overshoot, so it errs on the high side
+        assert 0.0 < result.simulated_probability < result.analytical_probability
+        assert result.simulated_probability == pytest.approx(result.analytical_probability, abs=0.1)
+        assert result.num_simulations == 5000
+        assert result.horizon_trades == 1000
+
+        again = calculate_risk_of_ruin(trades, 0.05, 20.0, num_simulations=5000, seed=3)
+        assert again.simulated_probability == result.simulated_probability
+
+        # Smaller bets make ruin less likely
+        cautious = calculate_risk_of_ruin(trades, 0.01, 20.0, num_simulations=5000, seed=3)
+        assert cautious.analytical_probability < result.analytical_probability
+
+    def test_risk_of_ruin_losing_system(self):
+        """Test that a system with negative expectancy is ruined for certain"""
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None),
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, -2.0, None, None),
+        ]
+
+        result = calculate_risk_of_ruin(trades, 0.02, 30.0, num_simulations=200, seed=1)
+        assert result.analytical_probability == 1.0
+        assert result.simulated_probability == 1.0
+
+    def test_risk_of_ruin_invalid_inputs(self):
+        """Test input validation"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 1.0, None, None)]
+
+        with pytest.raises(Exception):
+            calculate_risk_of_ruin([], 0.01, 20.0)
+        with pytest.raises(Exception):
+            calculate_risk_of_ruin(trades, 0.0, 20.0)
+        with pytest.raises(Exception):
+            calculate_risk_of_ruin(trades, 0.01, 150.0)
+
+
+class TestRiskAnalysis:
+    """Test the session object bundling one trade history's analysis"""
+
+    def test_risk_analysis_matches_free_functions(self):
+        """Test that each lazily computed result matches its free function"""
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 25]
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+        analysis = RiskAnalysis(trades, fractional_multiplier=0.5)
+
+        assert len(analysis) == 100
+        assert analysis.metrics == calculate_performance_metrics(trades)
+        assert analysis.metrics is not analysis.metrics
+        assert analysis.kelly == calculate_kelly_from_trades(trades, 0.5)
+        assert analysis.empirical_kelly == calculate_kelly_empirical(trades, 0.5)
+        assert analysis.bayesian_kelly == calculate_kelly_bayesian(trades, 0.5)
+        assert analysis.optimal_f == calculate_optimal_f(trades)
+
+        # The default risk is the fractional Kelly suggestion
+        result = analysis.simulate(params, num_simulations=300, seed=4)
+        fraction = analysis.kelly.kelly_fraction
+        expected = run_monte_carlo_detailed(trades, params, fraction, 300, seed=4, return_mode=ReturnMode.Currency)
+        assert result == expected
+        assert analysis.simulate(params, 0.01, 300, seed=4).pass_rate >= 0.0
+
+    def test_risk_analysis_errors(self):
+        """Test validation and errors from the lazily computed results"""
+        with pytest.raises(InsufficientDataError):
+            RiskAnalysis([])
+        winners = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 100.0, None, None)] * 10
+        with pytest.raises(ValidationError):
+            RiskAnalysis(winners, fractional_multiplier=0.0)
+
+        analysis = RiskAnalysis(winners)
+        assert analysis.metrics.win_probability == 1.0
+        for _ in range(2):
+            with pytest.raises(InsufficientDataError):
+                analysis.empirical_kelly
+
+    def test_generate_report(self):
+        """Test the Markdown, HTML and JSON renderings of a report"""
+        import json
+
+        trades = [Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None) for p in [300.0, -100.0, 250.0, -150.0] * 25]
+        params = ChallengeParams(100000, 10.0, 5.0, 10.0, 0)
+        analysis = RiskAnalysis(trades)
+
+        markdown = generate_report(analysis, challenge_params=params, risk_fraction=0.01, num_simulations=200, seed=1)
+        assert markdown.startswith("# Risk report\n")
+        for title in ["## Performance", "## Kelly", "## Kelly ladder", "## Challenge simulation"]:
+            assert title in markdown
+        assert "| Win rate | 50.00% |" in markdown
+        assert "| 0.50x |" in markdown
+
+        html = generate_report(analysis, OutputFormat.Html)
+        assert html.startswith("<!DOCTYPE html>")
+        assert "<h2>Kelly ladder</h2>" in html
+        assert "Challenge simulation" not in html
+
+        report = json.loads(generate_report(analysis, OutputFormat.Json, params, 0.01, 200, seed=1))
+        assert report["trades"] == 100
+        assert report["metrics"]["win_probability"] == 0.5
+        assert [row["multiplier"] for row in report["kelly_ladder"]["rows"]] == [0.25, 0.5, 0.75, 1.0]
+        expected = analysis.simulate(params, 0.01, 200, seed=1)
+        assert report["simulation"]["pass_rate"] == expected.pass_rate
+        assert report["simulation"]["risk_fraction"] == 0.01
+
+        # Without losses there is no Kelly fraction to report or ladder to build
+        winners = RiskAnalysis([Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, 100.0, None, None)] * 5)
+        report = json.loads(generate_report(winners, OutputFormat.Json))
+        assert report["kelly"] is None and report["kelly_ladder"] is None
+        assert "## Kelly" not in generate_report(winners)
+
+
+class TestArrayEntryPoints:
+    """Test the entry points taking per-trade returns as float64 buffers"""
+
+    def test_returns_match_trade_objects(self):
+        """Test that buffer inputs give the same results as Trade objects"""
+        from array import array
+
+        profits = [300.0, -100.0, 250.0, -100.0, 200.0, -150.0]
+        times = [1704067200000 + day * 86_400_000 for day in range(len(profits))]
+        trades = [
+            Trade("EURUSD", "Buy", 1.0, 1.1, 1.2, p, None, None, open_time=t, close_time=t)
+            for p, t in zip(profits, times)
+        ]
+        returns = array("d", profits)
+
+        metrics = metrics_from_returns(returns, array("q", times), initial_balance=10000.0)
+        expected = calculate_performance_metrics(trades, initial_balance=10000.0)
+        assert metrics.expectancy == expected.expectancy
+        assert metrics.sharpe_ratio == pytest.approx(expected.sharpe_ratio)
+
+        kelly = kelly_from_returns(memoryview(returns), fractional_multiplier=0.5)
+        assert kelly.kelly_fraction == calculate_kelly_from_trades(trades, 0.5).kelly_fraction
+
+        params = ChallengeParams(10000, 8.0, 5.0, 10.0, 0)
+        result = monte_carlo_from_returns(returns, params, 0.0005, 200, seed=2)
+        assert result.final_equity == run_monte_carlo_detailed(trades, params, 0.0005, 200, seed=2).final_equity
+
+        with pytest.raises(ValueError, match="same length"):
+            metrics_from_returns(returns, array("q", times[:2]))
+        with pytest.raises(Exception):
+            kelly_from_returns(array("f", profits))  # float32 is not accepted
+
 if __name__ == "__main__":
-    pytest.main([__file__])
\ No newline at end of file
+    pytest.main([__file__])